@@ -0,0 +1,196 @@
+// Assembler and disassembler for the opcodes known to the interpreter.
+//
+// The assembly is a list of whitespace separated mnemonics, case
+// insensitive. PUSHn takes an immediate value (hex with 0x, or decimal); PUSH
+// without size uses the smallest one. `name:` defines a label, used as
+// `PUSH @name` (a PUSH2 of its position). Comments start with `;` or `//`.
+//   PUSH1 0x05 loop: JUMPDEST PUSH1 1 SWAP1 SUB DUP1 PUSH @loop JUMPI
+use super::*;
+use num_bigint::BigUint;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Instruction {
+    pub pc: usize,
+    pub opcode: u8,
+    pub name: String,
+    // data of the PUSHn instructions
+    pub immediate: Vec<u8>,
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.immediate.is_empty() {
+            write!(f, "{}", self.name)
+        } else {
+            write!(f, "{} 0x{}", self.name, hex::encode(&self.immediate))
+        }
+    }
+}
+
+// disassemble decodes the code, a truncated PUSH at the end taking the
+// remaining bytes
+pub fn disassemble(code: &[u8]) -> Vec<Instruction> {
+    let opcodes = opcodes::table();
+    let mut r = Vec::new();
+    let mut pc = 0;
+    while pc < code.len() {
+        let opcode = code[pc];
+        let name = match opcodes.get(&opcode) {
+            Some(op) => op.name.clone(),
+            None => format!("INVALID(0x{:02x})", opcode),
+        };
+        let n = push_size(opcode);
+        let end = (pc + 1 + n).min(code.len());
+        r.push(Instruction {
+            pc,
+            opcode,
+            name,
+            immediate: code[pc + 1..end].to_vec(),
+        });
+        pc += 1 + n;
+    }
+    r
+}
+
+pub fn push_size(opcode: u8) -> usize {
+    if (0x60..=0x7f).contains(&opcode) {
+        (opcode - 0x5f) as usize
+    } else {
+        0
+    }
+}
+
+// opcode_by_name returns the opcode of the mnemonic
+pub fn opcode_by_name(name: &str) -> Option<u8> {
+    let name = name.to_uppercase();
+    let name = match name.as_str() {
+        "KECCAK256" => "SHA3",
+        "SUICIDE" => "SELFDESTRUCT",
+        "PREVRANDAO" => "DIFFICULTY",
+        n => n,
+    };
+    opcodes::table()
+        .iter()
+        .find(|(_, op)| op.name == name)
+        .map(|(opcode, _)| *opcode)
+}
+
+enum Item {
+    Op(u8),
+    Push(Option<usize>, Vec<u8>),
+    PushLabel(String),
+    Label(String),
+}
+
+pub fn assemble(src: &str) -> Result<Vec<u8>, String> {
+    let mut tokens = Vec::new();
+    for line in src.lines() {
+        let line = line.split(';').next().unwrap_or("");
+        let line = line.split("//").next().unwrap_or("");
+        tokens.extend(line.split_whitespace());
+    }
+
+    let mut items = Vec::new();
+    let mut i = 0;
+    while i < tokens.len() {
+        let t = tokens[i];
+        i += 1;
+        if let Some(label) = t.strip_suffix(':') {
+            items.push(Item::Label(label.to_string()));
+            continue;
+        }
+        let upper = t.to_uppercase();
+        if let Some(size) = upper.strip_prefix("PUSH") {
+            let size = match size {
+                "" => None,
+                s => match s.parse::<usize>() {
+                    Ok(n) if (1..=32).contains(&n) => Some(n),
+                    _ => return Err(format!("asm: unknown instruction {}", t)),
+                },
+            };
+            let arg = tokens
+                .get(i)
+                .ok_or_else(|| format!("asm: {} needs a value", t))?;
+            i += 1;
+            if let Some(label) = arg.strip_prefix('@') {
+                items.push(Item::PushLabel(label.to_string()));
+                continue;
+            }
+            let value = parse_value(arg)?;
+            if let Some(n) = size {
+                if value.len() > n {
+                    return Err(format!("asm: {} does not fit in {}", arg, t));
+                }
+            }
+            items.push(Item::Push(size, value));
+            continue;
+        }
+        match opcode_by_name(t) {
+            Some(opcode) => items.push(Item::Op(opcode)),
+            None => return Err(format!("asm: unknown instruction {}", t)),
+        }
+    }
+
+    // label positions, label pushes being PUSH2
+    let mut labels = HashMap::new();
+    let mut pc = 0;
+    for item in items.iter() {
+        pc += match item {
+            Item::Op(_) => 1,
+            Item::Push(size, value) => 1 + size.unwrap_or_else(|| value.len().max(1)),
+            Item::PushLabel(_) => 3,
+            Item::Label(label) => {
+                if labels.insert(label.clone(), pc).is_some() {
+                    return Err(format!("asm: duplicate label {}", label));
+                }
+                0
+            }
+        };
+    }
+
+    let mut code = Vec::new();
+    for item in items.iter() {
+        match item {
+            Item::Op(opcode) => code.push(*opcode),
+            Item::Push(size, value) => {
+                let n = size.unwrap_or_else(|| value.len().max(1));
+                code.push(0x5f + n as u8);
+                code.extend(vec![0; n - value.len()]);
+                code.extend(value);
+            }
+            Item::PushLabel(label) => {
+                let pos = labels
+                    .get(label)
+                    .ok_or_else(|| format!("asm: unknown label {}", label))?;
+                if *pos > 0xffff {
+                    return Err(format!("asm: label {} out of range", label));
+                }
+                code.push(0x61);
+                code.extend((*pos as u16).to_be_bytes());
+            }
+            Item::Label(_) => {}
+        }
+    }
+    Ok(code)
+}
+
+// parse_value parses a hex (0x prefixed) or decimal number into its minimal
+// big endian bytes, keeping the leading zeros of hex values
+fn parse_value(s: &str) -> Result<Vec<u8>, String> {
+    if let Some(h) = s.strip_prefix("0x") {
+        let h = if h.len() % 2 == 1 {
+            format!("0{}", h)
+        } else {
+            h.to_string()
+        };
+        return hex::decode(&h).map_err(|_| format!("asm: invalid value {}", s));
+    }
+    let n = s
+        .parse::<BigUint>()
+        .map_err(|_| format!("asm: invalid value {}", s))?;
+    if n == BigUint::default() {
+        return Ok(Vec::new());
+    }
+    Ok(n.to_bytes_be())
+}
@@ -0,0 +1,526 @@
+// evm command line tool, following the interface of geth's evm:
+//   evm statetest <file or dir>...  run GeneralStateTests fixtures
+//   evm blocktest <file or dir>...  run BlockchainTests fixtures
+//   evm t8n [flags]                 state transition tool
+//   evm run [flags]                 execute bytecode
+//   evm repl [code]                 interactive debugger
+//   evm tui [code]                  terminal UI debugger
+//   evm node [flags]                local JSON-RPC node
+use evm::devnet::Devnet;
+use evm::genesis::Genesis;
+use evm::spec::{Config, Spec};
+use evm::{blocktest, debugger, json, rpc, statetest, t8n, trace, transaction, u256, Stack};
+use num_bigint::BigUint;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::io::{BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::exit;
+
+const USAGE: &str = "usage: evm <command> [arguments]
+
+commands:
+  statetest [--fork NAME] <file or dir>...   run GeneralStateTests fixtures
+  blocktest [--fork NAME] <file or dir>...   run BlockchainTests fixtures
+  t8n [flags]                                 apply transactions to a state
+  run [flags]                                 execute bytecode
+  repl [HEX or file]                          interactive debugger, see help in it
+  tui <HEX or file> [--input HEX]             terminal UI debugger
+  node [flags]                                local JSON-RPC node
+
+t8n flags (files are relative to --output.basedir for the outputs; stdin
+reads {alloc, env, txs} at once, stdout and stderr print {alloc, result,
+body}):
+  --input.alloc FILE     pre-state (alloc.json)
+  --input.env FILE       block environment (env.json)
+  --input.txs FILE       transactions, JSON or .rlp (txs.json)
+  --output.basedir DIR   output directory
+  --output.result FILE   result (result.json)
+  --output.alloc FILE    post-state (alloc.json)
+  --output.body FILE     rlp of the included transactions
+  --state.fork NAME      fork (GrayGlacier)
+  --state.chainid ID     chain id (1)
+  --state.reward WEI     mining reward, -1 to disable (0)
+
+run flags (numbers in decimal or 0x prefixed hex):
+  --code HEX             code to execute
+  --codefile FILE        file with the hex code, - for stdin
+  --input HEX            calldata
+  --inputfile FILE       file with the hex calldata
+  --gas N                gas limit (10000000000)
+  --value N              value sent, taken from the sender balance (0)
+  --sender ADDRESS       caller and origin
+  --receiver ADDRESS     address the code runs at
+  --prestate FILE        genesis or alloc file with the initial state, the
+                         genesis fields giving the default block environment
+  --state.fork NAME      fork (Prague)
+  --block.number N, --block.timestamp N, --block.coinbase ADDRESS,
+  --block.gaslimit N, --block.basefee N, --block.blobbasefee N,
+  --block.chainid N      block environment
+  --json                 print an EIP-3155 trace to stderr
+  --debug                print the machine state before each instruction
+  --dump                 print the final state, as genesis or alloc
+
+node flags:
+  --prestate FILE        genesis or alloc file with the initial state
+  --state.fork NAME      fork (Prague)
+  --port N               port listened on localhost (8545)
+  --no-automine          mine the pending transactions only on evm_mine";
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.is_empty() {
+        eprintln!("{}", USAGE);
+        exit(2);
+    }
+    let r = match args[0].as_str() {
+        "statetest" => test_cmd(&args[1..], statetest::run_file),
+        "blocktest" => test_cmd(&args[1..], blocktest::run_file),
+        "t8n" | "transition" => t8n_cmd(&args[1..]),
+        "run" => run_cmd(&args[1..]),
+        "repl" => repl_cmd(&args[1..]),
+        "node" => node_cmd(&args[1..]),
+        #[cfg(feature = "tui")]
+        "tui" => tui_cmd(&args[1..]),
+        "help" | "-h" | "--help" => {
+            println!("{}", USAGE);
+            Ok(true)
+        }
+        c => Err(format!("unknown command {}\n{}", c, USAGE)),
+    };
+    match r {
+        Ok(true) => {}
+        Ok(false) => exit(1),
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(2);
+        }
+    }
+}
+
+// json_files returns the .json files of the path, walking directories
+fn json_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .collect();
+    entries.sort();
+    for entry in entries.iter() {
+        if entry.is_dir() {
+            json_files(entry, files)?;
+        } else if entry.extension().is_some_and(|e| e == "json") {
+            files.push(entry.clone());
+        }
+    }
+    Ok(())
+}
+
+type RunFile = fn(&Path) -> Result<Vec<statetest::TestResult>, String>;
+
+// test_cmd runs the fixtures, printing the failed cases and the number of
+// passed and failed cases per fork. Returns whether all of them passed.
+fn test_cmd(args: &[String], run_file: RunFile) -> Result<bool, String> {
+    let mut fork: Option<String> = None;
+    let mut files = Vec::new();
+    let mut i = 0;
+    while i < args.len() {
+        if args[i] == "--fork" {
+            fork = Some(args.get(i + 1).ok_or("--fork needs a value")?.clone());
+            i += 2;
+            continue;
+        }
+        json_files(Path::new(&args[i]), &mut files)?;
+        i += 1;
+    }
+    if files.is_empty() {
+        return Err("no fixtures given".to_string());
+    }
+
+    let mut all_passed = true;
+    let mut results = Vec::new();
+    for file in files.iter() {
+        let mut r = match run_file(file) {
+            Ok(r) => r,
+            Err(e) => {
+                println!("ERROR {}", e);
+                all_passed = false;
+                continue;
+            }
+        };
+        if let Some(fork) = &fork {
+            r.retain(|r| &r.fork == fork);
+        }
+        for r in r.iter().filter(|r| !r.pass) {
+            println!(
+                "FAIL {} {}[{}]: {}",
+                r.name,
+                r.fork,
+                r.index,
+                r.error.clone().unwrap_or_default()
+            );
+        }
+        results.append(&mut r);
+    }
+
+    for (fork, (passed, failed)) in statetest::summary(&results).iter() {
+        println!("{}: {} passed, {} failed", fork, passed, failed);
+        all_passed &= *failed == 0;
+    }
+    Ok(all_passed)
+}
+
+// flags parses --name value and --name=value arguments, and the --name
+// arguments of the boolean flags
+fn flags(args: &[String], bools: &[&str]) -> Result<HashMap<String, String>, String> {
+    let mut flags = HashMap::new();
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i]
+            .strip_prefix("--")
+            .ok_or_else(|| format!("unexpected argument {}", args[i]))?;
+        match arg.split_once('=') {
+            Some((name, value)) => {
+                flags.insert(name.to_string(), value.to_string());
+                i += 1;
+            }
+            None if bools.contains(&arg) => {
+                flags.insert(arg.to_string(), "true".to_string());
+                i += 1;
+            }
+            None => {
+                let value = args.get(i + 1).ok_or(format!("--{} needs a value", arg))?;
+                flags.insert(arg.to_string(), value.clone());
+                i += 2;
+            }
+        }
+    }
+    Ok(flags)
+}
+
+fn read_json(path: &Path) -> Result<Value, String> {
+    let data = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    serde_json::from_str(&data).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+fn t8n_cmd(args: &[String]) -> Result<bool, String> {
+    let flags = flags(args, &[])?;
+    let flag = |name: &str, default: &str| {
+        flags
+            .get(name)
+            .cloned()
+            .unwrap_or_else(|| default.to_string())
+    };
+
+    // inputs, read from stdin at most once
+    let mut stdin: Option<Value> = None;
+    let mut input = |name: &str, default: &str| -> Result<Value, String> {
+        let path = flag(&format!("input.{}", name), default);
+        if path != "stdin" {
+            return read_json(Path::new(&path));
+        }
+        if stdin.is_none() {
+            let mut data = String::new();
+            std::io::stdin()
+                .read_to_string(&mut data)
+                .map_err(|e| format!("stdin: {}", e))?;
+            stdin = Some(serde_json::from_str(&data).map_err(|e| format!("stdin: {}", e))?);
+        }
+        let v = stdin.as_ref().unwrap();
+        match (name, v.get(name)) {
+            ("txs", None) => Ok(v.get("txsRlp").cloned().unwrap_or(Value::Null)),
+            (_, r) => Ok(r.cloned().unwrap_or(Value::Null)),
+        }
+    };
+    let alloc = input("alloc", "alloc.json")?;
+    let env = input("env", "env.json")?;
+    let txs = input("txs", "txs.json")?;
+
+    let chain_id = flag("state.chainid", "1")
+        .parse::<u64>()
+        .map_err(|_| "invalid --state.chainid".to_string())?;
+    let reward = match flag("state.reward", "0").as_str() {
+        "-1" => None,
+        r => Some(BigUint::parse_bytes(r.as_bytes(), 10).ok_or("invalid --state.reward")?),
+    };
+    let out = t8n::transition(
+        &flag("state.fork", "GrayGlacier"),
+        chain_id,
+        reward,
+        &alloc,
+        &env,
+        &txs,
+    )?;
+
+    let basedir = PathBuf::from(flag("output.basedir", ""));
+    let mut stdout = Map::new();
+    let mut stderr = Map::new();
+    let outputs = [
+        (
+            "alloc",
+            flag("output.alloc", "alloc.json"),
+            json::alloc_to_json(&out.alloc),
+        ),
+        ("result", flag("output.result", "result.json"), out.result),
+        (
+            "body",
+            flag("output.body", ""),
+            Value::from(json::hex_bytes(&out.body)),
+        ),
+    ];
+    for (name, path, value) in outputs {
+        match path.as_str() {
+            "" => {}
+            "stdout" => {
+                stdout.insert(name.to_string(), value);
+            }
+            "stderr" => {
+                stderr.insert(name.to_string(), value);
+            }
+            _ => {
+                let path = basedir.join(path);
+                let data = serde_json::to_string_pretty(&value).unwrap();
+                std::fs::write(&path, data).map_err(|e| format!("{}: {}", path.display(), e))?;
+            }
+        }
+    }
+    if !stdout.is_empty() {
+        println!("{}", serde_json::to_string_pretty(&stdout).unwrap());
+    }
+    if !stderr.is_empty() {
+        eprintln!("{}", serde_json::to_string_pretty(&stderr).unwrap());
+    }
+    Ok(true)
+}
+
+// hex_arg decodes hex data given as an argument or in a file, ignoring the 0x
+// prefix and whitespace
+fn hex_arg(data: &str) -> Result<Vec<u8>, String> {
+    let data: String = data.split_whitespace().collect();
+    let h = data.strip_prefix("0x").unwrap_or(&data);
+    hex::decode(h).map_err(|_| format!("invalid hex {}", data))
+}
+
+fn read_text(path: &str) -> Result<String, String> {
+    if path == "-" {
+        let mut data = String::new();
+        std::io::stdin()
+            .read_to_string(&mut data)
+            .map_err(|e| format!("stdin: {}", e))?;
+        return Ok(data);
+    }
+    std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))
+}
+
+// name_address pads the name on the left, as geth does for its default sender
+// and receiver
+fn name_address(name: &str) -> [u8; 20] {
+    let mut a = [0; 20];
+    a[20 - name.len()..].copy_from_slice(name.as_bytes());
+    a
+}
+
+fn run_cmd(args: &[String]) -> Result<bool, String> {
+    let flags = flags(args, &["json", "debug", "dump"])?;
+    let flag = |name: &str| flags.get(name).map(|v| Value::from(v.as_str()));
+    let number = |name: &str, default: u64| match flag(name) {
+        Some(v) => json::parse_u64(&v).map_err(|e| format!("--{}: {}", name, e)),
+        None => Ok(default),
+    };
+    let address = |name: &str, default: [u8; 20]| match flag(name) {
+        Some(v) => json::parse_address(&v).map_err(|e| format!("--{}: {}", name, e)),
+        None => Ok(default),
+    };
+
+    let code = match (flags.get("code"), flags.get("codefile")) {
+        (Some(code), _) => hex_arg(code)?,
+        (None, Some(path)) => hex_arg(&read_text(path)?)?,
+        (None, None) => return Err("--code or --codefile required".to_string()),
+    };
+    let input = match (flags.get("input"), flags.get("inputfile")) {
+        (Some(input), _) => hex_arg(input)?,
+        (None, Some(path)) => hex_arg(&read_text(path)?)?,
+        (None, None) => Vec::new(),
+    };
+    let value = match flag("value") {
+        Some(v) => json::parse_biguint(&v)?,
+        None => BigUint::default(),
+    };
+    let sender = address("sender", name_address("sender"))?;
+    let receiver = address("receiver", name_address("receiver"))?;
+    let spec = Spec::from_name(flags.get("state.fork").map_or("Prague", |f| f.as_str()))?;
+    // a genesis prestate gives the defaults of the block environment
+    let (genesis, state) = match flags.get("prestate") {
+        Some(path) => {
+            let prestate = read_json(Path::new(path))?;
+            match prestate.get("alloc") {
+                Some(_) => {
+                    let genesis = Genesis::from_json(&prestate)?;
+                    let state = genesis.alloc.clone();
+                    (Some(genesis), state)
+                }
+                None => (None, json::parse_alloc(&prestate)?),
+            }
+        }
+        None => (None, evm::state::State::new()),
+    };
+    let env = match &genesis {
+        Some(genesis) => genesis.header(spec).block_env(spec, genesis.chain_id()),
+        None => transaction::BlockEnv::default(),
+    };
+    let mut block = transaction::BlockEnv {
+        number: number("block.number", env.number)?,
+        coinbase: address("block.coinbase", env.coinbase)?,
+        timestamp: number("block.timestamp", env.timestamp)?,
+        gas_limit: number("block.gaslimit", env.gas_limit)?,
+        chain_id: number("block.chainid", env.chain_id)?,
+        ..env
+    };
+    if let Some(v) = flag("block.basefee") {
+        block.base_fee = json::parse_biguint(&v)?;
+    }
+    if let Some(v) = flag("block.blobbasefee") {
+        block.blob_base_fee = json::parse_biguint(&v)?;
+    }
+
+    let mut state = state;
+    state.account(&receiver).code = code.clone();
+    state.transfer(&sender, &receiver, &value)?;
+
+    let gas = number("gas", 10000000000)?;
+    let mut s = Stack::with_config(Config::new(spec));
+    s.gas = gas;
+    s.address = receiver;
+    s.caller = sender;
+    s.origin = sender;
+    s.value = u256::biguint_to_u256(&value);
+    s.block = block;
+    s.state = state;
+    let json_trace = flags.contains_key("json");
+    if json_trace {
+        s.tracer = Some(Box::new(|step: &trace::Step| {
+            eprintln!("{}", step.to_json())
+        }));
+    }
+
+    let r = s.execute(&code, &input, flags.contains_key("debug"));
+    let gas_used = gas - s.gas;
+    let output = r.clone().unwrap_or_default();
+    if json_trace {
+        eprintln!(
+            "{}",
+            trace::summary(&output, gas_used, r.as_ref().err().map(|e| e.as_str()))
+        );
+    }
+    println!("output: {}", json::hex_bytes(&output));
+    println!("gas used: {}", gas_used);
+    if let Err(e) = &r {
+        println!("error: {}", e);
+    }
+    s.print_stack();
+    s.print_memory();
+    s.print_storage();
+    // the final state is printed in the format of the prestate
+    if flags.contains_key("dump") {
+        let dump = match genesis {
+            Some(genesis) => Genesis {
+                alloc: s.state.clone(),
+                ..genesis
+            }
+            .to_json(),
+            None => json::alloc_to_json(&s.state),
+        };
+        println!("{}", serde_json::to_string_pretty(&dump).unwrap());
+    }
+    Ok(r.is_ok())
+}
+
+// code_arg returns the code given as hex, or in a file as hex or assembly
+fn code_arg(arg: &str) -> Result<Vec<u8>, String> {
+    if !Path::new(arg).is_file() {
+        return hex_arg(arg);
+    }
+    let text = read_text(arg)?;
+    hex_arg(&text).or_else(|_| evm::asm::assemble(&text))
+}
+
+// node_cmd serves the JSON-RPC API of a devnet until interrupted
+fn node_cmd(args: &[String]) -> Result<bool, String> {
+    let flags = flags(args, &["no-automine"])?;
+    let spec = Spec::from_name(flags.get("state.fork").map_or("Prague", |f| f.as_str()))?;
+    let genesis = match flags.get("prestate") {
+        Some(path) => {
+            let prestate = read_json(Path::new(path))?;
+            match prestate.get("alloc") {
+                Some(_) => Genesis::from_json(&prestate)?,
+                None => Genesis::new(json::parse_alloc(&prestate)?),
+            }
+        }
+        None => Genesis::default(),
+    };
+    let port = match flags.get("port") {
+        Some(port) => port
+            .parse()
+            .map_err(|_| format!("--port: invalid {}", port))?,
+        None => 8545,
+    };
+    let mut devnet = Devnet::new(&genesis, spec);
+    devnet.auto_mine = !flags.contains_key("no-automine");
+    let server = rpc::Server::bind(port)?;
+    println!(
+        "listening on http://{}, chain id {}",
+        server.local_addr()?,
+        devnet.chain_id
+    );
+    server.run(&mut devnet);
+    Ok(true)
+}
+
+// repl_cmd reads debugger commands from stdin. The code is given as hex or as
+// a file with hex or assembly.
+fn repl_cmd(args: &[String]) -> Result<bool, String> {
+    let code = match args.first() {
+        Some(arg) => code_arg(arg)?,
+        None => Vec::new(),
+    };
+    let mut d = debugger::Debugger::new(&code, &[]);
+    if !code.is_empty() {
+        println!("{}", d.disasm());
+    }
+    let stdin = std::io::stdin();
+    let mut lines = stdin.lock().lines();
+    loop {
+        print!("evm> ");
+        std::io::stdout().flush().map_err(|e| e.to_string())?;
+        let line = match lines.next() {
+            Some(line) => line.map_err(|e| format!("stdin: {}", e))?,
+            None => break,
+        };
+        if matches!(line.trim(), "quit" | "q" | "exit") {
+            break;
+        }
+        match d.command(&line) {
+            Ok(out) if out.is_empty() => {}
+            Ok(out) => println!("{}", out),
+            Err(e) => println!("error: {}", e),
+        }
+    }
+    println!();
+    Ok(true)
+}
+
+#[cfg(feature = "tui")]
+fn tui_cmd(args: &[String]) -> Result<bool, String> {
+    let code = code_arg(args.first().ok_or("code required")?)?;
+    let flags = flags(&args[1..], &[])?;
+    let input = match flags.get("input") {
+        Some(input) => hex_arg(input)?,
+        None => Vec::new(),
+    };
+    let mut tui = evm::tui::Tui::new(debugger::Debugger::new(&code, &input));
+    tui.run()?;
+    Ok(true)
+}
@@ -0,0 +1,737 @@
+// Blocks: headers, bodies, receipts and logs, and the execution of a block on
+// top of its parent state
+use super::*;
+use envelope::SignedTransaction;
+use num_bigint::BigUint;
+use num_traits::identities::Zero;
+use rlp::{Decodable, Encodable, Rlp};
+use sha2::Sha256;
+use spec::{Config, Spec};
+use state::State;
+use transaction::{BlockEnv, LEGACY_TX_TYPE};
+
+// keccak256(rlp([]))
+pub const EMPTY_OMMERS_HASH: [u8; 32] = [
+    0x1d, 0xcc, 0x4d, 0xe8, 0xde, 0xc7, 0x5d, 0x7a, 0xab, 0x85, 0xb5, 0x67, 0xb6, 0xcc, 0xd4, 0x1a,
+    0xd3, 0x12, 0x45, 0x1b, 0x94, 0x8a, 0x74, 0x13, 0xf0, 0xa1, 0x42, 0xfd, 0x40, 0xd4, 0x93, 0x47,
+];
+
+// EIP-1559
+pub const INITIAL_BASE_FEE: u64 = 1000000000;
+const BASE_FEE_MAX_CHANGE_DENOMINATOR: u64 = 8;
+const ELASTICITY_MULTIPLIER: u64 = 2;
+const GAS_LIMIT_BOUND_DIVISOR: u64 = 1024;
+const MIN_GAS_LIMIT: u64 = 5000;
+
+// EIP-4844 and EIP-7691 blob gas targets and limits
+const TARGET_BLOB_GAS_PER_BLOCK_CANCUN: u64 = 393216;
+const MAX_BLOB_GAS_PER_BLOCK_CANCUN: u64 = 786432;
+const TARGET_BLOB_GAS_PER_BLOCK_PRAGUE: u64 = 786432;
+const MAX_BLOB_GAS_PER_BLOCK_PRAGUE: u64 = 1179648;
+
+// EIP-4788 and EIP-2935 system contracts
+pub const BEACON_ROOTS_ADDRESS: [u8; 20] = [
+    0x00, 0x0f, 0x3d, 0xf6, 0xd7, 0x32, 0x80, 0x7e, 0xf1, 0x31, 0x9f, 0xb7, 0xb8, 0xbb, 0x85, 0x22,
+    0xd0, 0xbe, 0xac, 0x02,
+];
+pub const HISTORY_STORAGE_ADDRESS: [u8; 20] = [
+    0x00, 0x00, 0xf9, 0x08, 0x27, 0xf1, 0xc5, 0x3a, 0x10, 0xcb, 0x7a, 0x02, 0x33, 0x5b, 0x17, 0x53,
+    0x20, 0x00, 0x29, 0x35,
+];
+const HISTORY_BUFFER_LENGTH: u64 = 8191;
+const HISTORY_SERVE_WINDOW: u64 = 8191;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Header {
+    pub parent_hash: [u8; 32],
+    pub ommers_hash: [u8; 32],
+    pub coinbase: [u8; 20],
+    pub state_root: [u8; 32],
+    pub transactions_root: [u8; 32],
+    pub receipts_root: [u8; 32],
+    pub logs_bloom: Bloom,
+    pub difficulty: BigUint,
+    pub number: u64,
+    pub gas_limit: u64,
+    pub gas_used: u64,
+    pub timestamp: u64,
+    pub extra_data: Vec<u8>,
+    // prevrandao since Paris
+    pub mix_hash: [u8; 32],
+    pub nonce: [u8; 8],
+    // the fields added by later forks are None before them
+    pub base_fee: Option<BigUint>,
+    pub withdrawals_root: Option<[u8; 32]>,
+    pub blob_gas_used: Option<u64>,
+    pub excess_blob_gas: Option<u64>,
+    pub parent_beacon_block_root: Option<[u8; 32]>,
+    pub requests_hash: Option<[u8; 32]>,
+}
+
+impl Default for Header {
+    fn default() -> Header {
+        Header {
+            parent_hash: [0; 32],
+            ommers_hash: EMPTY_OMMERS_HASH,
+            coinbase: [0; 20],
+            state_root: trie::EMPTY_ROOT,
+            transactions_root: trie::EMPTY_ROOT,
+            receipts_root: trie::EMPTY_ROOT,
+            logs_bloom: Bloom::default(),
+            difficulty: BigUint::zero(),
+            number: 0,
+            gas_limit: 30000000,
+            gas_used: 0,
+            timestamp: 0,
+            extra_data: Vec::new(),
+            mix_hash: [0; 32],
+            nonce: [0; 8],
+            base_fee: None,
+            withdrawals_root: None,
+            blob_gas_used: None,
+            excess_blob_gas: None,
+            parent_beacon_block_root: None,
+            requests_hash: None,
+        }
+    }
+}
+
+impl Header {
+    pub fn hash(&self) -> [u8; 32] {
+        keccak256(&rlp::to_bytes(self))
+    }
+
+    // block_env returns the environment the transactions of the block are
+    // executed in
+    pub fn block_env(&self, spec: Spec, chain_id: u64) -> BlockEnv {
+        BlockEnv {
+            number: self.number,
+            coinbase: self.coinbase,
+            timestamp: self.timestamp,
+            gas_limit: self.gas_limit,
+            base_fee: self.base_fee.clone().unwrap_or_default(),
+            blob_base_fee: transaction::blob_base_fee(spec, self.excess_blob_gas.unwrap_or(0)),
+            chain_id,
+            prev_randao: match spec >= Spec::Paris {
+                true => self.mix_hash,
+                false => u256::biguint_to_u256(&self.difficulty),
+            },
+            block_hashes: HashMap::new(),
+        }
+    }
+}
+
+impl Encodable for Header {
+    fn to_rlp(&self) -> Rlp {
+        let mut fields = vec![
+            self.parent_hash.to_rlp(),
+            self.ommers_hash.to_rlp(),
+            self.coinbase.to_rlp(),
+            self.state_root.to_rlp(),
+            self.transactions_root.to_rlp(),
+            self.receipts_root.to_rlp(),
+            Rlp::Bytes(self.logs_bloom.0.to_vec()),
+            self.difficulty.to_rlp(),
+            self.number.to_rlp(),
+            self.gas_limit.to_rlp(),
+            self.gas_used.to_rlp(),
+            self.timestamp.to_rlp(),
+            self.extra_data.to_rlp(),
+            self.mix_hash.to_rlp(),
+            Rlp::Bytes(self.nonce.to_vec()),
+        ];
+        // optional fields are appended in order, up to the last present one
+        let optional = [
+            self.base_fee.as_ref().map(|f| f.to_rlp()),
+            self.withdrawals_root.map(|r| r.to_rlp()),
+            self.blob_gas_used.map(|g| g.to_rlp()),
+            self.excess_blob_gas.map(|g| g.to_rlp()),
+            self.parent_beacon_block_root.map(|r| r.to_rlp()),
+            self.requests_hash.map(|r| r.to_rlp()),
+        ];
+        for f in optional.iter() {
+            match f {
+                Some(f) => fields.push(f.clone()),
+                None => break,
+            }
+        }
+        Rlp::List(fields)
+    }
+}
+
+impl Decodable for Header {
+    fn from_rlp(item: &Rlp) -> Result<Header, String> {
+        let l = item.as_list()?;
+        if l.len() < 15 || l.len() > 21 {
+            return Err(format!("header: invalid number of fields {}", l.len()));
+        }
+        let bloom = l[6].as_bytes()?;
+        if bloom.len() != 256 {
+            return Err("header: invalid logs bloom length".to_string());
+        }
+        let nonce = l[14].as_bytes()?;
+        if nonce.len() != 8 {
+            return Err("header: invalid nonce length".to_string());
+        }
+        let mut h = Header {
+            parent_hash: l[0].as_hash()?,
+            ommers_hash: l[1].as_hash()?,
+            coinbase: l[2].as_address()?,
+            state_root: l[3].as_hash()?,
+            transactions_root: l[4].as_hash()?,
+            receipts_root: l[5].as_hash()?,
+            logs_bloom: Bloom::default(),
+            difficulty: l[7].as_biguint()?,
+            number: l[8].as_u64()?,
+            gas_limit: l[9].as_u64()?,
+            gas_used: l[10].as_u64()?,
+            timestamp: l[11].as_u64()?,
+            extra_data: l[12].as_bytes()?.to_vec(),
+            mix_hash: l[13].as_hash()?,
+            nonce: [0; 8],
+            base_fee: None,
+            withdrawals_root: None,
+            blob_gas_used: None,
+            excess_blob_gas: None,
+            parent_beacon_block_root: None,
+            requests_hash: None,
+        };
+        h.logs_bloom.0.copy_from_slice(bloom);
+        h.nonce.copy_from_slice(nonce);
+        if let Some(f) = l.get(15) {
+            h.base_fee = Some(f.as_biguint()?);
+        }
+        if let Some(f) = l.get(16) {
+            h.withdrawals_root = Some(f.as_hash()?);
+        }
+        if let Some(f) = l.get(17) {
+            h.blob_gas_used = Some(f.as_u64()?);
+        }
+        if let Some(f) = l.get(18) {
+            h.excess_blob_gas = Some(f.as_u64()?);
+        }
+        if let Some(f) = l.get(19) {
+            h.parent_beacon_block_root = Some(f.as_hash()?);
+        }
+        if let Some(f) = l.get(20) {
+            h.requests_hash = Some(f.as_hash()?);
+        }
+        Ok(h)
+    }
+}
+
+// Withdrawal is an EIP-4895 withdrawal from the beacon chain, its amount in
+// gwei
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Withdrawal {
+    pub index: u64,
+    pub validator_index: u64,
+    pub address: [u8; 20],
+    pub amount: u64,
+}
+
+impl Encodable for Withdrawal {
+    fn to_rlp(&self) -> Rlp {
+        Rlp::List(vec![
+            self.index.to_rlp(),
+            self.validator_index.to_rlp(),
+            self.address.to_rlp(),
+            self.amount.to_rlp(),
+        ])
+    }
+}
+impl Decodable for Withdrawal {
+    fn from_rlp(item: &Rlp) -> Result<Withdrawal, String> {
+        let l = item.as_list()?;
+        if l.len() != 4 {
+            return Err("invalid withdrawal".to_string());
+        }
+        Ok(Withdrawal {
+            index: l[0].as_u64()?,
+            validator_index: l[1].as_u64()?,
+            address: l[2].as_address()?,
+            amount: l[3].as_u64()?,
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Block {
+    pub header: Header,
+    pub transactions: Vec<SignedTransaction>,
+    pub ommers: Vec<Header>,
+    // None before Shanghai
+    pub withdrawals: Option<Vec<Withdrawal>>,
+}
+
+impl Block {
+    pub fn hash(&self) -> [u8; 32] {
+        self.header.hash()
+    }
+}
+
+// [header, transactions, ommers, withdrawals]
+impl Encodable for Block {
+    fn to_rlp(&self) -> Rlp {
+        let mut fields = vec![
+            self.header.to_rlp(),
+            rlp::encode_list(&self.transactions),
+            rlp::encode_list(&self.ommers),
+        ];
+        if let Some(withdrawals) = &self.withdrawals {
+            fields.push(rlp::encode_list(withdrawals));
+        }
+        Rlp::List(fields)
+    }
+}
+impl Decodable for Block {
+    fn from_rlp(item: &Rlp) -> Result<Block, String> {
+        let l = item.as_list()?;
+        if l.len() != 3 && l.len() != 4 {
+            return Err(format!("block: invalid number of fields {}", l.len()));
+        }
+        Ok(Block {
+            header: Header::from_rlp(&l[0])?,
+            transactions: rlp::decode_list(&l[1])?,
+            ommers: rlp::decode_list(&l[2])?,
+            withdrawals: match l.get(3) {
+                Some(w) => Some(rlp::decode_list(w)?),
+                None => None,
+            },
+        })
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Log {
+    pub address: [u8; 20],
+    pub topics: Vec<[u8; 32]>,
+    pub data: Vec<u8>,
+}
+
+// [address, [topics...], data]
+impl Encodable for Log {
+    fn to_rlp(&self) -> Rlp {
+        Rlp::List(vec![
+            self.address.to_rlp(),
+            rlp::encode_list(&self.topics),
+            self.data.to_rlp(),
+        ])
+    }
+}
+impl Decodable for Log {
+    fn from_rlp(item: &Rlp) -> Result<Log, String> {
+        let l = item.as_list()?;
+        if l.len() != 3 {
+            return Err("invalid log".to_string());
+        }
+        Ok(Log {
+            address: l[0].as_address()?,
+            topics: rlp::decode_list(&l[1])?,
+            data: l[2].as_bytes()?.to_vec(),
+        })
+    }
+}
+
+// Bloom is the 2048 bits bloom filter of the addresses and topics of logs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bloom(pub [u8; 256]);
+
+impl Default for Bloom {
+    fn default() -> Bloom {
+        Bloom([0; 256])
+    }
+}
+
+impl Bloom {
+    // accrue sets the 3 bits selected by the first 6 bytes of the hash of the
+    // input
+    pub fn accrue(&mut self, input: &[u8]) {
+        let h = keccak256(input);
+        for i in [0, 2, 4] {
+            let bit = (((h[i] as usize) << 8) | h[i + 1] as usize) & 2047;
+            self.0[255 - bit / 8] |= 1 << (bit % 8);
+        }
+    }
+    pub fn accrue_log(&mut self, log: &Log) {
+        self.accrue(&log.address);
+        for topic in log.topics.iter() {
+            self.accrue(topic);
+        }
+    }
+    pub fn accrue_bloom(&mut self, other: &Bloom) {
+        for i in 0..256 {
+            self.0[i] |= other.0[i];
+        }
+    }
+    pub fn contains(&self, input: &[u8]) -> bool {
+        let mut b = Bloom::default();
+        b.accrue(input);
+        (0..256).all(|i| self.0[i] & b.0[i] == b.0[i])
+    }
+}
+
+pub fn logs_bloom(logs: &[Log]) -> Bloom {
+    let mut bloom = Bloom::default();
+    for log in logs.iter() {
+        bloom.accrue_log(log);
+    }
+    bloom
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Receipt {
+    pub tx_type: u8,
+    pub success: bool,
+    // intermediate state root, used instead of the status before Byzantium
+    pub post_state: Option<[u8; 32]>,
+    pub cumulative_gas_used: u64,
+    pub logs_bloom: Bloom,
+    pub logs: Vec<Log>,
+}
+
+impl Receipt {
+    // encode returns the consensus encoding: rlp([status, cumulative_gas_used,
+    // logs_bloom, logs]), prefixed by the type for typed transactions
+    pub fn encode(&self) -> Vec<u8> {
+        let status = match self.post_state {
+            Some(root) => root.to_rlp(),
+            None => self.success.to_rlp(),
+        };
+        let r = rlp::encode(&Rlp::List(vec![
+            status,
+            self.cumulative_gas_used.to_rlp(),
+            Rlp::Bytes(self.logs_bloom.0.to_vec()),
+            rlp::encode_list(&self.logs),
+        ]));
+        if self.tx_type == LEGACY_TX_TYPE {
+            return r;
+        }
+        [vec![self.tx_type], r].concat()
+    }
+}
+
+// BlockResult is the outcome of the execution of a block, to be checked
+// against (or, when building a block, written to) its header
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BlockResult {
+    pub receipts: Vec<Receipt>,
+    // per transaction execution results, in the receipts order
+    pub results: Vec<transaction::ExecutionResult>,
+    pub gas_used: u64,
+    pub blob_gas_used: u64,
+    pub logs_bloom: Bloom,
+}
+
+impl BlockResult {
+    // push adds the receipt of an included transaction, executed on state
+    pub fn push(
+        &mut self,
+        spec: Spec,
+        state: &State,
+        tx: &transaction::Transaction,
+        r: transaction::ExecutionResult,
+    ) {
+        self.gas_used += r.gas_used;
+        self.blob_gas_used += tx.blob_gas();
+        let bloom = logs_bloom(&r.logs);
+        self.logs_bloom.accrue_bloom(&bloom);
+        self.receipts.push(Receipt {
+            tx_type: tx.tx_type,
+            success: r.success,
+            post_state: if spec < Spec::Byzantium {
+                Some(state.root())
+            } else {
+                None
+            },
+            cumulative_gas_used: self.gas_used,
+            logs_bloom: bloom,
+            logs: r.logs.clone(),
+        });
+        self.results.push(r);
+    }
+
+    pub fn receipts_root(&self) -> [u8; 32] {
+        let receipts: Vec<Vec<u8>> = self.receipts.iter().map(|r| r.encode()).collect();
+        trie::ordered_root(&receipts)
+    }
+}
+
+pub fn transactions_root(transactions: &[SignedTransaction]) -> [u8; 32] {
+    let txs: Vec<Vec<u8>> = transactions.iter().map(|tx| tx.encode()).collect();
+    trie::ordered_root(&txs)
+}
+
+pub fn withdrawals_root(withdrawals: &[Withdrawal]) -> [u8; 32] {
+    let w: Vec<Vec<u8>> = withdrawals.iter().map(rlp::to_bytes).collect();
+    trie::ordered_root(&w)
+}
+
+// requests_hash is the EIP-7685 commitment to the execution layer requests,
+// sha256(sha256(request_0) || ...), where empty requests are skipped
+pub fn requests_hash(requests: &[Vec<u8>]) -> [u8; 32] {
+    let mut h = Sha256::new();
+    for r in requests.iter() {
+        if r.len() > 1 {
+            h.update(Sha256::digest(r));
+        }
+    }
+    h.finalize().into()
+}
+
+// calc_base_fee returns the base fee of the child of the parent block
+// (EIP-1559)
+pub fn calc_base_fee(parent: &Header) -> BigUint {
+    let parent_base_fee = match &parent.base_fee {
+        Some(f) => f.clone(),
+        None => return BigUint::from(INITIAL_BASE_FEE),
+    };
+    let target = parent.gas_limit / ELASTICITY_MULTIPLIER;
+    if parent.gas_used == target || target == 0 {
+        return parent_base_fee;
+    }
+    if parent.gas_used > target {
+        let delta = &parent_base_fee * (parent.gas_used - target)
+            / target
+            / BASE_FEE_MAX_CHANGE_DENOMINATOR;
+        return &parent_base_fee + delta.max(BigUint::from(1u64));
+    }
+    let delta =
+        &parent_base_fee * (target - parent.gas_used) / target / BASE_FEE_MAX_CHANGE_DENOMINATOR;
+    if delta > parent_base_fee {
+        return BigUint::zero();
+    }
+    parent_base_fee - delta
+}
+
+// blob_gas_limits returns the target and the maximum blob gas per block
+pub fn blob_gas_limits(spec: Spec) -> (u64, u64) {
+    if spec >= Spec::Prague {
+        return (
+            TARGET_BLOB_GAS_PER_BLOCK_PRAGUE,
+            MAX_BLOB_GAS_PER_BLOCK_PRAGUE,
+        );
+    }
+    (
+        TARGET_BLOB_GAS_PER_BLOCK_CANCUN,
+        MAX_BLOB_GAS_PER_BLOCK_CANCUN,
+    )
+}
+
+// calc_excess_blob_gas returns the excess blob gas of the child of the parent
+// block (EIP-4844)
+pub fn calc_excess_blob_gas(spec: Spec, parent: &Header) -> u64 {
+    let (target, _) = blob_gas_limits(spec);
+    let used = parent.excess_blob_gas.unwrap_or(0) + parent.blob_gas_used.unwrap_or(0);
+    used.saturating_sub(target)
+}
+
+// validate_header checks the header fields that only depend on its parent
+pub fn validate_header(spec: Spec, header: &Header, parent: &Header) -> Result<(), String> {
+    if header.parent_hash != parent.hash() {
+        return Err("unknown parent".to_string());
+    }
+    if header.number != parent.number + 1 {
+        return Err("invalid block number".to_string());
+    }
+    if header.timestamp <= parent.timestamp {
+        return Err("timestamp older than parent".to_string());
+    }
+    if header.extra_data.len() > 32 {
+        return Err("extra data too long".to_string());
+    }
+    if header.gas_used > header.gas_limit {
+        return Err("gas used exceeds gas limit".to_string());
+    }
+    // the gas target is kept at the London transition
+    let mut parent_gas_limit = parent.gas_limit;
+    if spec >= Spec::London && parent.base_fee.is_none() {
+        parent_gas_limit *= ELASTICITY_MULTIPLIER;
+    }
+    let limit = parent_gas_limit / GAS_LIMIT_BOUND_DIVISOR;
+    if header.gas_limit.abs_diff(parent_gas_limit) >= limit || header.gas_limit < MIN_GAS_LIMIT {
+        return Err("invalid gas limit".to_string());
+    }
+    if spec >= Spec::London {
+        if header.base_fee != Some(calc_base_fee(parent)) {
+            return Err("invalid base fee".to_string());
+        }
+    } else if header.base_fee.is_some() {
+        return Err("unexpected base fee".to_string());
+    }
+    if spec >= Spec::Paris {
+        if header.difficulty != BigUint::zero() {
+            return Err("invalid difficulty".to_string());
+        }
+        if header.nonce != [0; 8] {
+            return Err("invalid nonce".to_string());
+        }
+        if header.ommers_hash != EMPTY_OMMERS_HASH {
+            return Err("invalid ommers hash".to_string());
+        }
+    }
+    if (spec >= Spec::Shanghai) != header.withdrawals_root.is_some() {
+        return Err("invalid withdrawals root field".to_string());
+    }
+    if spec >= Spec::Cancun {
+        match (header.blob_gas_used, header.excess_blob_gas) {
+            (Some(_), Some(excess)) => {
+                if excess != calc_excess_blob_gas(spec, parent) {
+                    return Err("invalid excess blob gas".to_string());
+                }
+            }
+            _ => return Err("missing blob gas fields".to_string()),
+        }
+        if header.parent_beacon_block_root.is_none() {
+            return Err("missing parent beacon block root".to_string());
+        }
+    } else if header.blob_gas_used.is_some() || header.excess_blob_gas.is_some() {
+        return Err("unexpected blob gas fields".to_string());
+    }
+    if (spec >= Spec::Prague) != header.requests_hash.is_some() {
+        return Err("invalid requests hash field".to_string());
+    }
+    Ok(())
+}
+
+// execute_block applies the block on top of its parent state: the system
+// calls, the transactions, the withdrawals and the mining rewards. The header
+// fields computed from the execution are not checked, see validate_block.
+pub fn execute_block(
+    config: &Config,
+    state: &mut State,
+    block: &Block,
+    chain_id: u64,
+) -> Result<BlockResult, String> {
+    let spec = config.spec;
+    let header = &block.header;
+    let env = header.block_env(spec, chain_id);
+    system_calls(spec, state, header);
+
+    let (_, max_blob_gas) = blob_gas_limits(spec);
+    let mut result = BlockResult::default();
+    for (i, stx) in block.transactions.iter().enumerate() {
+        let tx = &stx.tx;
+        if tx.gas_limit > header.gas_limit - result.gas_used {
+            return Err(format!("transaction {}: gas limit reached", i));
+        }
+        if result.blob_gas_used + tx.blob_gas() > max_blob_gas {
+            return Err(format!("transaction {}: blob gas limit reached", i));
+        }
+        let r = transaction::transact(config, state, &env, tx)
+            .map_err(|e| format!("transaction {}: {}", i, e))?;
+        result.push(spec, state, tx, r);
+    }
+
+    if let Some(withdrawals) = &block.withdrawals {
+        apply_withdrawals(state, withdrawals);
+    }
+    apply_rewards(spec, state, block);
+    Ok(result)
+}
+
+// validate_block checks the header against the execution result and the
+// post-state
+pub fn validate_block(block: &Block, result: &BlockResult, state: &State) -> Result<(), String> {
+    let header = &block.header;
+    if header.gas_used != result.gas_used {
+        return Err(format!(
+            "invalid gas used: header {}, executed {}",
+            header.gas_used, result.gas_used
+        ));
+    }
+    if header.transactions_root != transactions_root(&block.transactions) {
+        return Err("invalid transactions root".to_string());
+    }
+    if header.ommers_hash != keccak256(&rlp::encode(&rlp::encode_list(&block.ommers))) {
+        return Err("invalid ommers hash".to_string());
+    }
+    match (&block.withdrawals, header.withdrawals_root) {
+        (Some(w), Some(root)) if withdrawals_root(w) == root => {}
+        (None, None) => {}
+        _ => return Err("invalid withdrawals root".to_string()),
+    }
+    if let Some(blob_gas_used) = header.blob_gas_used {
+        if blob_gas_used != result.blob_gas_used {
+            return Err("invalid blob gas used".to_string());
+        }
+    }
+    if header.logs_bloom != result.logs_bloom {
+        return Err("invalid logs bloom".to_string());
+    }
+    if header.receipts_root != result.receipts_root() {
+        return Err("invalid receipts root".to_string());
+    }
+    // EIP-7685 requests (deposits, withdrawal and consolidation requests)
+    // are not collected, so that the blocks with some are not supported
+    if let Some(h) = header.requests_hash {
+        if h != requests_hash(&[]) {
+            return Err("unsupported block: EIP-7685 requests are not collected".to_string());
+        }
+    }
+    if header.state_root != state.root() {
+        return Err(format!(
+            "invalid state root: header 0x{}, executed 0x{}",
+            hex::encode(header.state_root),
+            hex::encode(state.root())
+        ));
+    }
+    Ok(())
+}
+
+// system_calls performs the writes of the EIP-4788 beacon roots and EIP-2935
+// block hashes system contracts made by the system calls at the beginning of
+// the block. As for the calls, nothing is done when the contract has no code.
+pub fn system_calls(spec: Spec, state: &mut State, header: &Header) {
+    if spec >= Spec::Cancun && !state.code(&BEACON_ROOTS_ADDRESS).is_empty() {
+        if let Some(root) = header.parent_beacon_block_root {
+            let i = header.timestamp % HISTORY_BUFFER_LENGTH;
+            state.set_storage(&BEACON_ROOTS_ADDRESS, &word(i), word(header.timestamp));
+            state.set_storage(
+                &BEACON_ROOTS_ADDRESS,
+                &word(i + HISTORY_BUFFER_LENGTH),
+                root,
+            );
+        }
+    }
+    if spec >= Spec::Prague && header.number > 0 && !state.code(&HISTORY_STORAGE_ADDRESS).is_empty()
+    {
+        let i = (header.number - 1) % HISTORY_SERVE_WINDOW;
+        state.set_storage(&HISTORY_STORAGE_ADDRESS, &word(i), header.parent_hash);
+    }
+}
+
+// apply_withdrawals credits the withdrawn amounts, given in gwei (EIP-4895).
+// A zero amount does not create the account, which would be empty.
+pub fn apply_withdrawals(state: &mut State, withdrawals: &[Withdrawal]) {
+    for w in withdrawals.iter().filter(|w| w.amount != 0) {
+        let amount = BigUint::from(w.amount) * BigUint::from(1000000000u64);
+        state.add_balance(&w.address, &amount);
+    }
+}
+
+fn word(n: u64) -> [u8; 32] {
+    let mut w: [u8; 32] = [0; 32];
+    w[24..].copy_from_slice(&n.to_be_bytes());
+    w
+}
+
+// apply_rewards pays the proof of work mining rewards to the block and ommers
+// coinbases
+fn apply_rewards(spec: Spec, state: &mut State, block: &Block) {
+    let reward: u64 = match spec {
+        s if s >= Spec::Paris => return,
+        s if s >= Spec::Constantinople => 2000000000000000000,
+        s if s >= Spec::Byzantium => 3000000000000000000,
+        _ => 5000000000000000000,
+    };
+    let reward = BigUint::from(reward);
+    let number = block.header.number;
+    let mut miner_reward = reward.clone();
+    for ommer in block.ommers.iter() {
+        let r = &reward * (ommer.number + 8).saturating_sub(number) / 8u64;
+        state.add_balance(&ommer.coinbase, &r);
+        miner_reward += &reward / 32u64;
+    }
+    state.add_balance(&block.header.coinbase, &miner_reward);
+}
@@ -0,0 +1,143 @@
+// Runner for the BlockchainTests fixtures of ethereum/tests and
+// execution-spec-tests: starting from the genesis block and its pre-state,
+// each block is decoded, validated against its parent and executed, and the
+// state of the last block is compared with the expected post-state.
+use super::*;
+use block::{execute_block, validate_block, validate_header, Block, Header};
+use json::{parse_alloc, parse_bytes, parse_word};
+use serde_json::Value;
+use spec::{Config, Spec};
+use state::State;
+use statetest::TestResult;
+use std::collections::HashMap;
+use std::path::Path;
+
+// blockchain tests use the mainnet chain id
+const CHAIN_ID: u64 = 1;
+
+pub fn run_file(path: &Path) -> Result<Vec<TestResult>, String> {
+    let data = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let tests: Value =
+        serde_json::from_str(&data).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let tests = tests
+        .as_object()
+        .ok_or_else(|| format!("{}: expected an object of tests", path.display()))?;
+    Ok(tests
+        .iter()
+        .map(|(name, test)| run_test(name, test))
+        .collect())
+}
+
+pub fn run_test(name: &str, test: &Value) -> TestResult {
+    let error = catch_panic(|| run(test)).err();
+    TestResult {
+        name: name.to_string(),
+        fork: test["network"].as_str().unwrap_or_default().to_string(),
+        index: 0,
+        pass: error.is_none(),
+        error,
+    }
+}
+
+fn run(test: &Value) -> Result<(), String> {
+    let network = test["network"]
+        .as_str()
+        .ok_or_else(|| "missing network".to_string())?;
+    let pre = parse_alloc(&test["pre"]).map_err(|e| format!("pre: {}", e))?;
+    let genesis: Block = rlp::from_bytes(&parse_bytes(&test["genesisRLP"])?)
+        .map_err(|e| format!("genesis: {}", e))?;
+    if genesis.header.state_root != pre.root() {
+        return Err("genesis state root mismatch".to_string());
+    }
+
+    // imported blocks by hash, with their post-state
+    let mut chain: HashMap<[u8; 32], (Header, State)> = HashMap::new();
+    chain.insert(genesis.hash(), (genesis.header, pre));
+    let blocks = test["blocks"]
+        .as_array()
+        .ok_or_else(|| "missing blocks".to_string())?;
+    for (i, b) in blocks.iter().enumerate() {
+        let expect_exception = b.get("expectException").and_then(|e| e.as_str());
+        let imported = parse_bytes(&b["rlp"]).and_then(|rlp| import_block(network, &chain, &rlp));
+        match (imported, expect_exception) {
+            (Ok((header, state)), None) => {
+                chain.insert(header.hash(), (header, state));
+            }
+            (Ok(_), Some(e)) => return Err(format!("block {}: expected exception {}", i, e)),
+            (Err(e), None) => return Err(format!("block {}: {}", i, e)),
+            (Err(_), Some(_)) => {}
+        }
+    }
+
+    let last = parse_word(&test["lastblockhash"])?;
+    let (_, state) = chain
+        .get(&last)
+        .ok_or_else(|| format!("last block 0x{} not imported", hex::encode(last)))?;
+    let expected = match test.get("postStateHash") {
+        Some(h) => parse_word(h)?,
+        None => parse_alloc(&test["postState"])?.root(),
+    };
+    if state.root() != expected {
+        return Err(format!(
+            "post state root mismatch: got 0x{}, want 0x{}",
+            hex::encode(state.root()),
+            hex::encode(expected)
+        ));
+    }
+    Ok(())
+}
+
+// import_block decodes the block and executes it on top of its parent,
+// returning its header and post-state
+fn import_block(
+    network: &str,
+    chain: &HashMap<[u8; 32], (Header, State)>,
+    rlp: &[u8],
+) -> Result<(Header, State), String> {
+    let block: Block = rlp::from_bytes(rlp)?;
+    let (parent, parent_state) = chain
+        .get(&block.header.parent_hash)
+        .ok_or_else(|| "unknown parent".to_string())?;
+    let spec = spec_at(network, block.header.number, block.header.timestamp)?;
+    let config = Config::new(spec);
+    validate_header(spec, &block.header, parent)?;
+    if spec >= Spec::Paris && !block.ommers.is_empty() {
+        return Err("ommers not allowed".to_string());
+    }
+    let mut state = parent_state.clone();
+    let result = execute_block(&config, &mut state, &block, CHAIN_ID)?;
+    validate_block(&block, &result, &state)?;
+    Ok((block.header, state))
+}
+
+// spec_at returns the fork of the block, for networks with a single fork and
+// for the transition networks, such as BerlinToLondonAt5 or
+// ShanghaiToCancunAtTime15k
+pub fn spec_at(network: &str, number: u64, timestamp: u64) -> Result<Spec, String> {
+    let (before, rest) = match network.split_once("To") {
+        Some(t) => t,
+        None => return Spec::from_name(network),
+    };
+    let (after, at) = rest
+        .split_once("At")
+        .ok_or_else(|| format!("unknown fork {}", network))?;
+    let (value, by_time) = match at.strip_prefix("Time") {
+        Some(t) => (t, true),
+        None => (at, false),
+    };
+    let value = match value.strip_suffix('k') {
+        Some(v) => v.parse::<u64>().map(|v| v * 1000),
+        None => value.parse::<u64>(),
+    }
+    .map_err(|_| format!("unknown fork {}", network))?;
+    let activated = if by_time {
+        timestamp >= value
+    } else {
+        number >= value
+    };
+    if activated {
+        Spec::from_name(after)
+    } else {
+        Spec::from_name(before)
+    }
+}
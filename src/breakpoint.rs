@@ -0,0 +1,178 @@
+// Breakpoints and watchpoints, pausing an execution run with Stack::run and
+// continued with Stack::resume:
+//   let mut r = vm.run(&code, &calldata, &breakpoints)?;
+//   while let Exit::Paused(i) = r {
+//       // inspect or edit the machine
+//       r = vm.resume(&code, &calldata, &breakpoints)?;
+//   }
+// The pc, opcode and storage breakpoints pause before the instruction, the
+// memory and gas ones after the instruction that triggered them.
+use super::*;
+use std::fmt;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Breakpoint {
+    Pc(usize),
+    Opcode(u8),
+    // slot of the executing account read with SLOAD, or written with SSTORE
+    StorageRead([u8; 32]),
+    StorageWrite([u8; 32]),
+    // memory bytes from the start to the end (excluded) changed
+    Memory(usize, usize),
+    // gas left dropped below the value
+    Gas(u64),
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Breakpoint::Pc(pc) => write!(f, "pc {}", pc),
+            Breakpoint::Opcode(op) => write!(f, "opcode 0x{:02x}", op),
+            Breakpoint::StorageRead(key) => write!(f, "sload {}", trace::hex_word(key)),
+            Breakpoint::StorageWrite(key) => write!(f, "sstore {}", trace::hex_word(key)),
+            Breakpoint::Memory(start, end) => write!(f, "memory 0x{:x}..0x{:x}", start, end),
+            Breakpoint::Gas(gas) => write!(f, "gas < {}", gas),
+        }
+    }
+}
+
+impl Breakpoint {
+    // before tells whether the breakpoint pauses before the instruction at pc
+    pub fn before(&self, vm: &Stack, code: &[u8]) -> bool {
+        let opcode = code.get(vm.pc).copied().unwrap_or(0x00);
+        match self {
+            Breakpoint::Pc(pc) => *pc == vm.pc,
+            Breakpoint::Opcode(op) => *op == opcode,
+            Breakpoint::StorageRead(key) => opcode == 0x54 && vm.stack.last() == Some(key),
+            Breakpoint::StorageWrite(key) => opcode == 0x55 && vm.stack.last() == Some(key),
+            Breakpoint::Memory(..) | Breakpoint::Gas(_) => false,
+        }
+    }
+}
+
+// Watch holds the values watched by the breakpoints before an instruction, to
+// find the ones it triggers
+pub struct Watch {
+    gas: u64,
+    // bytes of the memory breakpoints, within the memory size
+    mem: Vec<Option<Vec<u8>>>,
+}
+
+impl Watch {
+    pub fn new(breakpoints: &[Breakpoint], vm: &Stack) -> Watch {
+        Watch {
+            gas: vm.gas,
+            mem: breakpoints
+                .iter()
+                .map(|b| match b {
+                    Breakpoint::Memory(start, end) => {
+                        Some(mem_range(&vm.mem, *start, *end).to_vec())
+                    }
+                    _ => None,
+                })
+                .collect(),
+        }
+    }
+
+    // hit returns the index of the first breakpoint triggered by the
+    // instruction executed since the watch was created
+    pub fn hit(&self, breakpoints: &[Breakpoint], vm: &Stack) -> Option<usize> {
+        breakpoints.iter().enumerate().position(|(i, b)| match b {
+            Breakpoint::Memory(start, end) => match &self.mem[i] {
+                Some(old) => mem_changed(old, mem_range(&vm.mem, *start, *end)),
+                None => false,
+            },
+            Breakpoint::Gas(gas) => self.gas >= *gas && vm.gas < *gas,
+            _ => false,
+        })
+    }
+}
+
+// mem_range returns the bytes from start to end within the memory size, so
+// that a watched range is not allocated past the memory
+fn mem_range(mem: &[u8], start: usize, end: usize) -> &[u8] {
+    let end = end.min(mem.len());
+    &mem[start.min(end)..end]
+}
+
+// mem_changed compares the bytes of a range before and after an instruction,
+// the memory having grown with zeros
+fn mem_changed(old: &[u8], new: &[u8]) -> bool {
+    new.len() < old.len() || new[..old.len()] != *old || new[old.len()..].iter().any(|b| *b != 0)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Exit {
+    // the execution halted, with the output
+    Halted(Vec<u8>),
+    // paused by the breakpoint with the index
+    Paused(usize),
+}
+
+impl Stack {
+    // run executes the code from the current pc, until it halts or a
+    // breakpoint pauses it
+    pub fn run(
+        &mut self,
+        code: &[u8],
+        calldata: &[u8],
+        breakpoints: &[Breakpoint],
+    ) -> Result<Exit, String> {
+        self.run_breakpoints(code, calldata, breakpoints, false)
+    }
+
+    // resume continues a paused execution, executing the next instruction
+    // even if a breakpoint is on it
+    pub fn resume(
+        &mut self,
+        code: &[u8],
+        calldata: &[u8],
+        breakpoints: &[Breakpoint],
+    ) -> Result<Exit, String> {
+        self.run_breakpoints(code, calldata, breakpoints, true)
+    }
+
+    // run_breakpoints commits or reverts the writes through the host when the
+    // execution halts, as Stack::execute
+    fn run_breakpoints(
+        &mut self,
+        code: &[u8],
+        calldata: &[u8],
+        breakpoints: &[Breakpoint],
+        resume: bool,
+    ) -> Result<Exit, String> {
+        let r = self.run_until(code, calldata, breakpoints, resume);
+        match r {
+            Ok(Exit::Halted(_)) => self.with_host(|h| h.commit()),
+            Ok(Exit::Paused(_)) => {}
+            Err(_) => self.with_host(|h| h.revert()),
+        }
+        r
+    }
+
+    fn run_until(
+        &mut self,
+        code: &[u8],
+        calldata: &[u8],
+        breakpoints: &[Breakpoint],
+        resume: bool,
+    ) -> Result<Exit, String> {
+        let mut first = resume;
+        while self.pc < code.len() {
+            if !first {
+                if let Some(i) = breakpoints.iter().position(|b| b.before(self, code)) {
+                    return Ok(Exit::Paused(i));
+                }
+            }
+            first = false;
+            let watch = Watch::new(breakpoints, self);
+            if let Some(output) = self.step(code, calldata)? {
+                return Ok(Exit::Halted(output));
+            }
+            if let Some(i) = watch.hit(breakpoints, self) {
+                return Ok(Exit::Paused(i));
+            }
+        }
+        Ok(Exit::Halted(Vec::new()))
+    }
+}
@@ -0,0 +1,741 @@
+// Storage backends of the world state. A Database holds the accounts, the
+// code by hash, the storage slots and the block hashes, read and written by
+// the interpreter through DbHost, so that the state does not need to fit in
+// the maps of a Stack. MemoryDb keeps everything in memory, FileDb in an
+// append-only log on disk, surviving across process runs, and CacheDb in an
+// overlay over another database.
+use super::*;
+use block::Log;
+use host::{AccessStatus, CallResult, Host, Message, StorageStatus, TxContext};
+use num_bigint::BigUint;
+use num_traits::identities::Zero;
+use rlp::{Decodable, Encodable, Rlp};
+use state::{State, EMPTY_CODE_HASH};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+// AccountInfo is an account without its storage, the code being referred to
+// by hash
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountInfo {
+    pub nonce: u64,
+    pub balance: BigUint,
+    pub code_hash: [u8; 32],
+}
+
+impl Default for AccountInfo {
+    fn default() -> AccountInfo {
+        AccountInfo {
+            nonce: 0,
+            balance: BigUint::zero(),
+            code_hash: EMPTY_CODE_HASH,
+        }
+    }
+}
+
+impl AccountInfo {
+    // is_empty follows the EIP-161 definition, as state::Account::is_empty
+    pub fn is_empty(&self) -> bool {
+        self.code_hash == EMPTY_CODE_HASH && self.nonce == 0 && self.balance.is_zero()
+    }
+}
+
+impl Encodable for AccountInfo {
+    fn to_rlp(&self) -> Rlp {
+        Rlp::List(vec![
+            self.nonce.to_rlp(),
+            self.balance.to_rlp(),
+            self.code_hash.to_rlp(),
+        ])
+    }
+}
+
+impl Decodable for AccountInfo {
+    fn from_rlp(item: &Rlp) -> Result<AccountInfo, String> {
+        match item.as_list()? {
+            [nonce, balance, code_hash] => Ok(AccountInfo {
+                nonce: nonce.as_u64()?,
+                balance: balance.as_biguint()?,
+                code_hash: code_hash.as_hash()?,
+            }),
+            _ => Err("db: invalid account".to_string()),
+        }
+    }
+}
+
+pub trait Database {
+    fn account(&self, address: &[u8; 20]) -> Result<Option<AccountInfo>, String>;
+    // code_by_hash returns the code with the given hash, empty if unknown
+    fn code_by_hash(&self, hash: &[u8; 32]) -> Result<Vec<u8>, String>;
+    fn storage(&self, address: &[u8; 20], key: &[u8; 32]) -> Result<[u8; 32], String>;
+    fn block_hash(&self, number: u64) -> Result<[u8; 32], String>;
+    // set_account creates or updates the account, or deletes it when None
+    fn set_account(&mut self, address: &[u8; 20], info: Option<AccountInfo>) -> Result<(), String>;
+    // set_code stores the code, returning its hash
+    fn set_code(&mut self, code: &[u8]) -> Result<[u8; 32], String>;
+    fn set_storage(
+        &mut self,
+        address: &[u8; 20],
+        key: &[u8; 32],
+        value: [u8; 32],
+    ) -> Result<(), String>;
+    fn set_block_hash(&mut self, number: u64, hash: [u8; 32]) -> Result<(), String>;
+}
+
+// commit writes into the database the changes from the state pre, which it
+// holds, to the state post: the changed accounts with their code and storage,
+// the slots cleared, and the accounts deleted with their storage
+pub fn commit(db: &mut dyn Database, pre: &State, post: &State) -> Result<(), String> {
+    for address in pre.accounts.keys() {
+        if !post.accounts.contains_key(address) {
+            db.set_account(address, None)?;
+        }
+    }
+    for (address, account) in post.accounts.iter() {
+        let before = pre.get(address);
+        if before == Some(account) {
+            continue;
+        }
+        let code_hash = db.set_code(&account.code)?;
+        db.set_account(
+            address,
+            Some(AccountInfo {
+                nonce: account.nonce,
+                balance: account.balance.clone(),
+                code_hash,
+            }),
+        )?;
+        if let Some(before) = before {
+            for key in before.storage.keys() {
+                if !account.storage.contains_key(key) {
+                    db.set_storage(address, key, [0; 32])?;
+                }
+            }
+        }
+        for (key, value) in account.storage.iter() {
+            if before.and_then(|b| b.storage.get(key)) != Some(value) {
+                db.set_storage(address, key, *value)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MemoryDb {
+    pub accounts: HashMap<[u8; 20], AccountInfo>,
+    pub code: HashMap<[u8; 32], Vec<u8>>,
+    // slots with a zero value are not stored
+    pub storage: HashMap<([u8; 20], [u8; 32]), [u8; 32]>,
+    pub block_hashes: HashMap<u64, [u8; 32]>,
+}
+
+impl MemoryDb {
+    pub fn new() -> MemoryDb {
+        MemoryDb::default()
+    }
+}
+
+impl Database for MemoryDb {
+    fn account(&self, address: &[u8; 20]) -> Result<Option<AccountInfo>, String> {
+        Ok(self.accounts.get(address).cloned())
+    }
+    fn code_by_hash(&self, hash: &[u8; 32]) -> Result<Vec<u8>, String> {
+        Ok(self.code.get(hash).cloned().unwrap_or_default())
+    }
+    fn storage(&self, address: &[u8; 20], key: &[u8; 32]) -> Result<[u8; 32], String> {
+        Ok(self
+            .storage
+            .get(&(*address, *key))
+            .copied()
+            .unwrap_or([0; 32]))
+    }
+    fn block_hash(&self, number: u64) -> Result<[u8; 32], String> {
+        Ok(self.block_hashes.get(&number).copied().unwrap_or([0; 32]))
+    }
+    fn set_account(&mut self, address: &[u8; 20], info: Option<AccountInfo>) -> Result<(), String> {
+        match info {
+            Some(info) => {
+                self.accounts.insert(*address, info);
+            }
+            None => {
+                self.accounts.remove(address);
+                self.storage.retain(|(a, _), _| a != address);
+            }
+        }
+        Ok(())
+    }
+    fn set_code(&mut self, code: &[u8]) -> Result<[u8; 32], String> {
+        if code.is_empty() {
+            return Ok(EMPTY_CODE_HASH);
+        }
+        let hash = keccak256(code);
+        self.code.insert(hash, code.to_vec());
+        Ok(hash)
+    }
+    fn set_storage(
+        &mut self,
+        address: &[u8; 20],
+        key: &[u8; 32],
+        value: [u8; 32],
+    ) -> Result<(), String> {
+        if value == [0; 32] {
+            self.storage.remove(&(*address, *key));
+        } else {
+            self.storage.insert((*address, *key), value);
+        }
+        Ok(())
+    }
+    fn set_block_hash(&mut self, number: u64, hash: [u8; 32]) -> Result<(), String> {
+        self.block_hashes.insert(number, hash);
+        Ok(())
+    }
+}
+
+// Key identifies a record of the FileDb log
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Key {
+    Account([u8; 20]),
+    Code([u8; 32]),
+    Storage([u8; 20], [u8; 32]),
+    BlockHash(u64),
+}
+
+const ACCOUNT_RECORD: u64 = 0;
+const CODE_RECORD: u64 = 1;
+const STORAGE_RECORD: u64 = 2;
+const BLOCK_HASH_RECORD: u64 = 3;
+
+// bound of the length of a record, above the largest code
+const MAX_RECORD_SIZE: u32 = 1 << 20;
+
+impl Encodable for Key {
+    fn to_rlp(&self) -> Rlp {
+        match self {
+            Key::Account(a) => Rlp::List(vec![ACCOUNT_RECORD.to_rlp(), a.to_rlp()]),
+            Key::Code(h) => Rlp::List(vec![CODE_RECORD.to_rlp(), h.to_rlp()]),
+            Key::Storage(a, k) => Rlp::List(vec![STORAGE_RECORD.to_rlp(), a.to_rlp(), k.to_rlp()]),
+            Key::BlockHash(n) => Rlp::List(vec![BLOCK_HASH_RECORD.to_rlp(), n.to_rlp()]),
+        }
+    }
+}
+
+impl Decodable for Key {
+    fn from_rlp(item: &Rlp) -> Result<Key, String> {
+        let l = item.as_list()?;
+        match (l.first().map(|t| t.as_u64()).transpose()?, l.len()) {
+            (Some(ACCOUNT_RECORD), 2) => Ok(Key::Account(l[1].as_address()?)),
+            (Some(CODE_RECORD), 2) => Ok(Key::Code(l[1].as_hash()?)),
+            (Some(STORAGE_RECORD), 3) => Ok(Key::Storage(l[1].as_address()?, l[2].as_hash()?)),
+            (Some(BLOCK_HASH_RECORD), 2) => Ok(Key::BlockHash(l[1].as_u64()?)),
+            _ => Err("db: invalid record key".to_string()),
+        }
+    }
+}
+
+// FileDb keeps the state in an append-only log, where each write appends a
+// record [key, value], prefixed by its length as a 4 byte big endian, and is
+// synced to disk. Only the position of the last record of each key is kept in
+// memory, the values being read from the file. Deleted accounts are recorded
+// with an empty value, followed by zero values for their slots. A record torn
+// by a crash at the end of the log is dropped on open.
+pub struct FileDb {
+    path: PathBuf,
+    file: File,
+    // key => (offset, length) of its last record
+    index: HashMap<Key, (u64, u32)>,
+    len: u64,
+}
+
+impl FileDb {
+    // open opens the log at the path, creating it if it does not exist
+    pub fn open(path: &Path) -> Result<FileDb, String> {
+        let err = |e: std::io::Error| format!("{}: {}", path.display(), e);
+        let file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)
+            .map_err(err)?;
+        let mut db = FileDb {
+            path: path.to_path_buf(),
+            file,
+            index: HashMap::new(),
+            len: 0,
+        };
+        let size = db.file.metadata().map_err(err)?.len();
+        let mut reader = BufReader::new(&db.file);
+        // a record past the end of the file was torn by a crash while being
+        // appended
+        while db.len + 4 <= size {
+            let mut prefix = [0; 4];
+            reader.read_exact(&mut prefix).map_err(err)?;
+            let n = u32::from_be_bytes(prefix);
+            if n > MAX_RECORD_SIZE {
+                return Err(format!(
+                    "{}: offset {}: record too large ({} bytes)",
+                    path.display(),
+                    db.len,
+                    n
+                ));
+            }
+            if db.len + 4 + n as u64 > size {
+                break;
+            }
+            let mut record = vec![0; n as usize];
+            reader.read_exact(&mut record).map_err(err)?;
+            let (key, _) = decode_record(&record)
+                .map_err(|e| format!("{}: offset {}: {}", path.display(), db.len, e))?;
+            db.index.insert(key, (db.len + 4, n));
+            db.len += 4 + n as u64;
+        }
+        drop(reader);
+        if db.len < size {
+            db.file.set_len(db.len).map_err(err)?;
+        }
+        Ok(db)
+    }
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    fn read(&self, key: &Key) -> Result<Option<Rlp>, String> {
+        let (offset, n) = match self.index.get(key) {
+            Some(p) => *p,
+            None => return Ok(None),
+        };
+        let err = |e: std::io::Error| format!("{}: {}", self.path.display(), e);
+        let mut record = vec![0; n as usize];
+        let mut file = &self.file;
+        file.seek(SeekFrom::Start(offset)).map_err(err)?;
+        file.read_exact(&mut record).map_err(err)?;
+        Ok(Some(decode_record(&record)?.1))
+    }
+    fn write(&mut self, key: Key, value: Rlp) -> Result<(), String> {
+        let record = rlp::encode(&Rlp::List(vec![key.to_rlp(), value]));
+        let n = record.len() as u32;
+        let mut data = n.to_be_bytes().to_vec();
+        data.extend_from_slice(&record);
+        let (path, mut file) = (&self.path, &self.file);
+        let err = |e: std::io::Error| format!("{}: {}", path.display(), e);
+        file.write_all(&data).map_err(err)?;
+        file.sync_data().map_err(err)?;
+        self.index.insert(key, (self.len + 4, n));
+        self.len += data.len() as u64;
+        Ok(())
+    }
+}
+
+fn decode_record(record: &[u8]) -> Result<(Key, Rlp), String> {
+    match rlp::decode(record)?.as_list()? {
+        [key, value] => Ok((Key::from_rlp(key)?, value.clone())),
+        _ => Err("db: invalid record".to_string()),
+    }
+}
+
+impl Database for FileDb {
+    fn account(&self, address: &[u8; 20]) -> Result<Option<AccountInfo>, String> {
+        match self.read(&Key::Account(*address))? {
+            Some(Rlp::List(l)) => Ok(Some(AccountInfo::from_rlp(&Rlp::List(l))?)),
+            _ => Ok(None),
+        }
+    }
+    fn code_by_hash(&self, hash: &[u8; 32]) -> Result<Vec<u8>, String> {
+        match self.read(&Key::Code(*hash))? {
+            Some(code) => Vec::from_rlp(&code),
+            None => Ok(Vec::new()),
+        }
+    }
+    fn storage(&self, address: &[u8; 20], key: &[u8; 32]) -> Result<[u8; 32], String> {
+        match self.read(&Key::Storage(*address, *key))? {
+            Some(value) => value.as_hash(),
+            None => Ok([0; 32]),
+        }
+    }
+    fn block_hash(&self, number: u64) -> Result<[u8; 32], String> {
+        match self.read(&Key::BlockHash(number))? {
+            Some(hash) => hash.as_hash(),
+            None => Ok([0; 32]),
+        }
+    }
+    fn set_account(&mut self, address: &[u8; 20], info: Option<AccountInfo>) -> Result<(), String> {
+        if let Some(info) = info {
+            return self.write(Key::Account(*address), info.to_rlp());
+        }
+        self.write(Key::Account(*address), Rlp::Bytes(Vec::new()))?;
+        // the slots of the deleted account are zeroed
+        let slots: Vec<Key> = self
+            .index
+            .keys()
+            .filter(|k| matches!(k, Key::Storage(a, _) if a == address))
+            .copied()
+            .collect();
+        for key in slots {
+            if self.read(&key)?.is_some_and(|v| v.as_hash() != Ok([0; 32])) {
+                self.write(key, [0u8; 32].to_rlp())?;
+            }
+        }
+        Ok(())
+    }
+    fn set_code(&mut self, code: &[u8]) -> Result<[u8; 32], String> {
+        if code.is_empty() {
+            return Ok(EMPTY_CODE_HASH);
+        }
+        let hash = keccak256(code);
+        // the code of a hash never changes
+        if !self.index.contains_key(&Key::Code(hash)) {
+            self.write(Key::Code(hash), code.to_rlp())?;
+        }
+        Ok(hash)
+    }
+    fn set_storage(
+        &mut self,
+        address: &[u8; 20],
+        key: &[u8; 32],
+        value: [u8; 32],
+    ) -> Result<(), String> {
+        self.write(Key::Storage(*address, *key), value.to_rlp())
+    }
+    fn set_block_hash(&mut self, number: u64, hash: [u8; 32]) -> Result<(), String> {
+        self.write(Key::BlockHash(number), hash.to_rlp())
+    }
+}
+
+// the database shared with a DbHost, so that it is still reachable after the
+// execution (e.g. to discard the overlay of a CacheDb)
+impl<D: Database> Database for Rc<RefCell<D>> {
+    fn account(&self, address: &[u8; 20]) -> Result<Option<AccountInfo>, String> {
+        self.borrow().account(address)
+    }
+    fn code_by_hash(&self, hash: &[u8; 32]) -> Result<Vec<u8>, String> {
+        self.borrow().code_by_hash(hash)
+    }
+    fn storage(&self, address: &[u8; 20], key: &[u8; 32]) -> Result<[u8; 32], String> {
+        self.borrow().storage(address, key)
+    }
+    fn block_hash(&self, number: u64) -> Result<[u8; 32], String> {
+        self.borrow().block_hash(number)
+    }
+    fn set_account(&mut self, address: &[u8; 20], info: Option<AccountInfo>) -> Result<(), String> {
+        self.borrow_mut().set_account(address, info)
+    }
+    fn set_code(&mut self, code: &[u8]) -> Result<[u8; 32], String> {
+        self.borrow_mut().set_code(code)
+    }
+    fn set_storage(
+        &mut self,
+        address: &[u8; 20],
+        key: &[u8; 32],
+        value: [u8; 32],
+    ) -> Result<(), String> {
+        self.borrow_mut().set_storage(address, key, value)
+    }
+    fn set_block_hash(&mut self, number: u64, hash: [u8; 32]) -> Result<(), String> {
+        self.borrow_mut().set_block_hash(number, hash)
+    }
+}
+
+// CacheDb layers an in-memory overlay over a database: the writes stay in the
+// overlay, the reads of what was not written fall through to the base. The
+// overlay can be discarded, or flushed into the base.
+pub struct CacheDb<D: Database> {
+    pub base: D,
+    // written accounts, None for the deleted ones
+    pub accounts: HashMap<[u8; 20], Option<AccountInfo>>,
+    pub code: HashMap<[u8; 32], Vec<u8>>,
+    // written slots, zero values included as they shadow the base
+    pub storage: HashMap<([u8; 20], [u8; 32]), [u8; 32]>,
+    // deleted accounts, whose slots in the base are shadowed as zero
+    pub cleared: HashSet<[u8; 20]>,
+    pub block_hashes: HashMap<u64, [u8; 32]>,
+}
+
+impl<D: Database> CacheDb<D> {
+    pub fn new(base: D) -> CacheDb<D> {
+        CacheDb {
+            base,
+            accounts: HashMap::new(),
+            code: HashMap::new(),
+            storage: HashMap::new(),
+            cleared: HashSet::new(),
+            block_hashes: HashMap::new(),
+        }
+    }
+    // discard drops the writes, going back to the state of the base
+    pub fn discard(&mut self) {
+        self.accounts.clear();
+        self.code.clear();
+        self.storage.clear();
+        self.cleared.clear();
+        self.block_hashes.clear();
+    }
+    // flush writes the overlay into the base, and clears it
+    pub fn flush(&mut self) -> Result<(), String> {
+        for code in self.code.values() {
+            self.base.set_code(code)?;
+        }
+        // the deletions first, the account being possibly created again
+        for address in self.cleared.iter() {
+            self.base.set_account(address, None)?;
+        }
+        for (address, info) in self.accounts.iter() {
+            self.base.set_account(address, info.clone())?;
+        }
+        for ((address, key), value) in self.storage.iter() {
+            self.base.set_storage(address, key, *value)?;
+        }
+        for (number, hash) in self.block_hashes.iter() {
+            self.base.set_block_hash(*number, *hash)?;
+        }
+        self.discard();
+        Ok(())
+    }
+}
+
+impl<D: Database> Database for CacheDb<D> {
+    fn account(&self, address: &[u8; 20]) -> Result<Option<AccountInfo>, String> {
+        match self.accounts.get(address) {
+            Some(info) => Ok(info.clone()),
+            None => self.base.account(address),
+        }
+    }
+    fn code_by_hash(&self, hash: &[u8; 32]) -> Result<Vec<u8>, String> {
+        match self.code.get(hash) {
+            Some(code) => Ok(code.clone()),
+            None => self.base.code_by_hash(hash),
+        }
+    }
+    fn storage(&self, address: &[u8; 20], key: &[u8; 32]) -> Result<[u8; 32], String> {
+        match self.storage.get(&(*address, *key)) {
+            Some(value) => Ok(*value),
+            None if self.cleared.contains(address) => Ok([0; 32]),
+            None => self.base.storage(address, key),
+        }
+    }
+    fn block_hash(&self, number: u64) -> Result<[u8; 32], String> {
+        match self.block_hashes.get(&number) {
+            Some(hash) => Ok(*hash),
+            None => self.base.block_hash(number),
+        }
+    }
+    fn set_account(&mut self, address: &[u8; 20], info: Option<AccountInfo>) -> Result<(), String> {
+        if info.is_none() {
+            self.storage.retain(|(a, _), _| a != address);
+            self.cleared.insert(*address);
+        }
+        self.accounts.insert(*address, info);
+        Ok(())
+    }
+    fn set_code(&mut self, code: &[u8]) -> Result<[u8; 32], String> {
+        if code.is_empty() {
+            return Ok(EMPTY_CODE_HASH);
+        }
+        let hash = keccak256(code);
+        self.code.insert(hash, code.to_vec());
+        Ok(hash)
+    }
+    fn set_storage(
+        &mut self,
+        address: &[u8; 20],
+        key: &[u8; 32],
+        value: [u8; 32],
+    ) -> Result<(), String> {
+        self.storage.insert((*address, *key), value);
+        Ok(())
+    }
+    fn set_block_hash(&mut self, number: u64, hash: [u8; 32]) -> Result<(), String> {
+        self.block_hashes.insert(number, hash);
+        Ok(())
+    }
+}
+
+// fork loads a state dump (see json::parse_dump) into a CacheDb, whose writes
+// can be discarded to go back to the dumped state
+pub fn fork(path: &Path) -> Result<CacheDb<MemoryDb>, String> {
+    let data = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let state = json::parse_dump(&data).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let mut db = MemoryDb::new();
+    commit(&mut db, &State::new(), &state)?;
+    Ok(CacheDb::new(db))
+}
+
+// Undo restores a write of DbHost
+#[derive(Clone, Debug, PartialEq)]
+enum Undo {
+    Account([u8; 20], Option<AccountInfo>),
+    Storage([u8; 20], [u8; 32], [u8; 32]),
+}
+
+// DbHost is a host over a database, keeping the substate of the transaction.
+// The writes of an execution are journaled, and undone when it fails. The
+// Host interface being infallible, a failing database aborts the execution
+// with a panic.
+pub struct DbHost<D: Database> {
+    pub db: D,
+    journal: Vec<Undo>,
+    // number of logs of the committed executions
+    committed_logs: usize,
+    pub storage_committed: HashMap<([u8; 20], [u8; 32]), [u8; 32]>,
+    pub accessed_storage: HashSet<([u8; 20], [u8; 32])>,
+    pub accessed_accounts: HashSet<[u8; 20]>,
+    // accounts destructed by SELFDESTRUCT, for the caller to delete at the
+    // end of the transaction
+    pub destructed: HashSet<[u8; 20]>,
+    pub logs: Vec<Log>,
+    pub origin: [u8; 20],
+    pub gas_price: BigUint,
+    pub blob_hashes: Vec<[u8; 32]>,
+    pub block: transaction::BlockEnv,
+}
+
+impl<D: Database> DbHost<D> {
+    pub fn new(db: D) -> DbHost<D> {
+        DbHost {
+            db,
+            journal: Vec::new(),
+            committed_logs: 0,
+            storage_committed: HashMap::new(),
+            accessed_storage: HashSet::new(),
+            accessed_accounts: HashSet::new(),
+            destructed: HashSet::new(),
+            logs: Vec::new(),
+            origin: [0; 20],
+            gas_price: BigUint::zero(),
+            blob_hashes: Vec::new(),
+            block: transaction::BlockEnv::default(),
+        }
+    }
+}
+
+fn ok<T>(r: Result<T, String>) -> T {
+    r.unwrap_or_else(|e| panic!("database: {}", e))
+}
+
+impl<D: Database> DbHost<D> {
+    fn set_account(&mut self, address: &[u8; 20], info: AccountInfo) {
+        let previous = ok(self.db.account(address));
+        self.journal.push(Undo::Account(*address, previous));
+        ok(self.db.set_account(address, Some(info)));
+    }
+}
+
+impl<D: Database> Host for DbHost<D> {
+    fn account_exists(&self, address: &[u8; 20]) -> bool {
+        ok(self.db.account(address)).is_some()
+    }
+    fn get_storage(&self, address: &[u8; 20], key: &[u8; 32]) -> [u8; 32] {
+        ok(self.db.storage(address, key))
+    }
+    fn set_storage(
+        &mut self,
+        address: &[u8; 20],
+        key: &[u8; 32],
+        value: [u8; 32],
+    ) -> StorageStatus {
+        let current = self.get_storage(address, key);
+        let original = *self
+            .storage_committed
+            .entry((*address, *key))
+            .or_insert(current);
+        if ok(self.db.account(address)).is_none() {
+            self.set_account(address, AccountInfo::default());
+        }
+        self.journal.push(Undo::Storage(*address, *key, current));
+        ok(self.db.set_storage(address, key, value));
+        StorageStatus::new(&original, &current, &value)
+    }
+    fn get_balance(&self, address: &[u8; 20]) -> BigUint {
+        ok(self.db.account(address)).map_or_else(BigUint::zero, |a| a.balance)
+    }
+    fn get_code_size(&self, address: &[u8; 20]) -> usize {
+        match ok(self.db.account(address)) {
+            Some(a) => ok(self.db.code_by_hash(&a.code_hash)).len(),
+            None => 0,
+        }
+    }
+    fn get_code_hash(&self, address: &[u8; 20]) -> [u8; 32] {
+        match ok(self.db.account(address)) {
+            Some(a) if !a.is_empty() => a.code_hash,
+            _ => [0; 32],
+        }
+    }
+    fn copy_code(&self, address: &[u8; 20], offset: usize, buffer: &mut [u8]) -> usize {
+        let code = match ok(self.db.account(address)) {
+            Some(a) => ok(self.db.code_by_hash(&a.code_hash)),
+            None => return 0,
+        };
+        if offset >= code.len() {
+            return 0;
+        }
+        let n = buffer.len().min(code.len() - offset);
+        buffer[..n].copy_from_slice(&code[offset..offset + n]);
+        n
+    }
+    // selfdestruct moves the balance to the beneficiary, as LocalHost
+    fn selfdestruct(&mut self, address: &[u8; 20], beneficiary: &[u8; 20]) -> bool {
+        if let Some(mut from) = ok(self.db.account(address)) {
+            let balance = std::mem::take(&mut from.balance);
+            self.set_account(address, from);
+            let mut to = ok(self.db.account(beneficiary)).unwrap_or_default();
+            to.balance += balance;
+            self.set_account(beneficiary, to);
+        }
+        self.destructed.insert(*address)
+    }
+    fn call(&mut self, _msg: &Message) -> CallResult {
+        CallResult {
+            error: Some("calls are not supported by the database host".to_string()),
+            ..CallResult::default()
+        }
+    }
+    fn get_tx_context(&self) -> TxContext {
+        TxContext {
+            origin: self.origin,
+            gas_price: self.gas_price.clone(),
+            blob_hashes: self.blob_hashes.clone(),
+            block: self.block.clone(),
+        }
+    }
+    fn get_block_hash(&self, number: u64) -> [u8; 32] {
+        ok(self.db.block_hash(number))
+    }
+    fn emit_log(&mut self, address: &[u8; 20], topics: &[[u8; 32]], data: &[u8]) {
+        self.logs.push(Log {
+            address: *address,
+            topics: topics.to_vec(),
+            data: data.to_vec(),
+        });
+    }
+    fn access_account(&mut self, address: &[u8; 20]) -> AccessStatus {
+        if self.accessed_accounts.insert(*address) {
+            return AccessStatus::Cold;
+        }
+        AccessStatus::Warm
+    }
+    fn access_storage(&mut self, address: &[u8; 20], key: &[u8; 32]) -> AccessStatus {
+        if self.accessed_storage.insert((*address, *key)) {
+            return AccessStatus::Cold;
+        }
+        AccessStatus::Warm
+    }
+    fn commit(&mut self) {
+        self.journal.clear();
+        self.committed_logs = self.logs.len();
+    }
+    // revert undoes the writes in reverse order, the first undo of a slot or
+    // an account restoring its value before the execution
+    fn revert(&mut self) {
+        while let Some(undo) = self.journal.pop() {
+            match undo {
+                Undo::Account(address, info) => ok(self.db.set_account(&address, info)),
+                Undo::Storage(address, key, value) => {
+                    ok(self.db.set_storage(&address, &key, value))
+                }
+            }
+        }
+        self.logs.truncate(self.committed_logs);
+    }
+}
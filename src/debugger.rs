@@ -0,0 +1,428 @@
+// Interactive debugging sessions: the code is executed one instruction at a
+// time on a Stack, whose stack, memory and storage can be inspected and edited
+// between the steps. Used by the `evm repl` command, which passes each input
+// line to Debugger::command. The executed steps are recorded, so that the
+// debugger goes back to any of them without executing again.
+use super::*;
+use breakpoint::{Breakpoint, Watch};
+use serde_json::Value;
+use std::convert::TryFrom;
+use std::fmt::Write;
+
+pub struct Debugger {
+    pub vm: Stack,
+    pub code: Vec<u8>,
+    pub calldata: Vec<u8>,
+    pub breakpoints: Vec<Breakpoint>,
+    // output, or error, once the execution has halted
+    pub result: Option<Result<Vec<u8>, String>>,
+    // number of instructions executed since the start
+    pub steps: usize,
+    // world state the execution starts from
+    pub initial_state: state::State,
+    // executed steps, kept when going back to redo them
+    pub recording: recording::Recording,
+    gas: u64,
+}
+
+// size up to which mstore grows the memory, which it does without charging
+// its gas
+const MAX_MEMORY_EDIT: usize = 1 << 24;
+
+const HELP: &str = "commands:
+  load HEX               load bytecode and restart
+  asm ASSEMBLY           assemble, load and restart (e.g. asm PUSH1 1 PUSH1 2 ADD)
+  calldata HEX           set the calldata and restart
+  step [N], s [N]        execute the next N instructions (1)
+  back [N]               go back N instructions (1)
+  goto N                 go to the state before step N
+  continue, c            run until a breakpoint or the end
+  break pc N             break before the instruction at pc N
+  break op NAME          break before the opcode, by name or 0x value
+  break sload KEY        break before the slot is read
+  break sstore KEY       break before the slot is written
+  break mem START END    break after the memory bytes change
+  break gas N            break after the gas left drops below N
+  breakpoints            list the breakpoints
+  delete N               delete breakpoint N
+  reset                  restart the execution, keeping the breakpoints
+  info, i                pc, gas and next instruction
+  disasm                 disassembly, the next instruction marked with >
+  stack, memory, storage show the machine state
+  push VALUE, pop        push or pop a stack item
+  set N VALUE            set the stack item N (0 is the top)
+  mstore OFFSET HEX      write bytes to memory
+  sstore KEY VALUE       write a storage slot
+  gas N                  set the gas left";
+
+impl Debugger {
+    pub fn new(code: &[u8], calldata: &[u8]) -> Debugger {
+        let vm = Stack::new();
+        Debugger {
+            gas: vm.gas,
+            recording: recording::Recording::new(&vm, 0),
+            vm,
+            code: code.to_vec(),
+            calldata: calldata.to_vec(),
+            breakpoints: Vec::new(),
+            result: None,
+            steps: 0,
+            initial_state: state::State::new(),
+        }
+    }
+
+    // reset restarts the execution on a new machine with the same config and
+    // initial gas
+    pub fn reset(&mut self) {
+        let mut vm = Stack::with_config(self.vm.config.clone());
+        vm.gas = self.gas;
+        vm.address = self.vm.address;
+        vm.state = self.initial_state.clone();
+        self.recording = recording::Recording::new(&vm, 0);
+        self.vm = vm;
+        self.result = None;
+        self.steps = 0;
+    }
+
+    // edited restarts the recording from the current state, after a change
+    // made with the commands, the steps before being lost
+    fn edited(&mut self) {
+        self.recording = recording::Recording::new(&self.vm, self.steps);
+    }
+
+    pub fn gas_used(&self) -> u64 {
+        self.gas.saturating_sub(self.vm.gas)
+    }
+
+    pub fn halted(&self) -> bool {
+        self.result.is_some()
+    }
+
+    // step executes the next instruction, or halts at the end of the code. A
+    // step already recorded is redone from its delta.
+    pub fn step(&mut self) -> Result<(), String> {
+        if self.halted() {
+            return Err("execution halted, reset to restart".to_string());
+        }
+        if let Some(delta) = self.recording.delta(self.steps) {
+            delta.redo(&mut self.vm);
+            self.result = delta.result.clone();
+            self.steps += 1;
+            return Ok(());
+        }
+        if self.vm.pc >= self.code.len() {
+            self.result = Some(Ok(Vec::new()));
+            return Ok(());
+        }
+        match self
+            .recording
+            .step(&mut self.vm, &self.code, &self.calldata)
+        {
+            Ok(Some(output)) => self.result = Some(Ok(output)),
+            Ok(None) => {}
+            Err(e) => self.result = Some(Err(e)),
+        }
+        self.steps += 1;
+        Ok(())
+    }
+
+    // step_back goes back to the state before the last instruction, undoing
+    // its recorded delta
+    pub fn step_back(&mut self) -> Result<(), String> {
+        if self.steps == 0 {
+            return Err("at the start of the execution".to_string());
+        }
+        let delta = match self.recording.delta(self.steps - 1) {
+            Some(delta) => delta,
+            None => return Err("at the start of the recording, the state was edited".to_string()),
+        };
+        delta.undo(&mut self.vm);
+        self.result = None;
+        self.steps -= 1;
+        Ok(())
+    }
+
+    // goto moves backwards or forwards to the state before the given step
+    pub fn goto(&mut self, step: usize) -> Result<(), String> {
+        while self.steps > step {
+            self.step_back()?;
+        }
+        while self.steps < step {
+            if self.halted() {
+                return Err(format!("execution halted at step {}", self.steps));
+            }
+            self.step()?;
+        }
+        Ok(())
+    }
+
+    // breakpoint returns the index of the breakpoint on the next instruction
+    pub fn breakpoint(&self) -> Option<usize> {
+        self.breakpoints
+            .iter()
+            .position(|b| b.before(&self.vm, &self.code))
+    }
+
+    // step_watched executes the next instruction, returning the index of the
+    // memory or gas breakpoint it triggered
+    fn step_watched(&mut self) -> Result<Option<usize>, String> {
+        let watch = Watch::new(&self.breakpoints, &self.vm);
+        self.step()?;
+        Ok(watch.hit(&self.breakpoints, &self.vm))
+    }
+
+    // continue_ runs until the end, or until a breakpoint is hit, returning
+    // its index
+    pub fn continue_(&mut self) -> Result<Option<usize>, String> {
+        let mut hit = self.step_watched()?;
+        while !self.halted() {
+            if hit.is_some() {
+                return Ok(hit);
+            }
+            if let Some(i) = self.breakpoint() {
+                return Ok(Some(i));
+            }
+            hit = self.step_watched()?;
+        }
+        Ok(None)
+    }
+
+    // command runs a REPL command, returning the text to print
+    pub fn command(&mut self, line: &str) -> Result<String, String> {
+        let args: Vec<&str> = line.split_whitespace().collect();
+        let arg = |i: usize| -> Result<&str, String> {
+            args.get(i)
+                .copied()
+                .ok_or_else(|| format!("{}: missing argument", args[0]))
+        };
+        let cmd = match args.first() {
+            Some(cmd) => *cmd,
+            None => return Ok(String::new()),
+        };
+        match cmd {
+            "help" | "h" => Ok(HELP.to_string()),
+            "load" => {
+                self.code = parse_hex(arg(1)?)?;
+                self.reset();
+                Ok(self.disasm())
+            }
+            "asm" => {
+                self.code = asm::assemble(&args[1..].join(" "))?;
+                self.reset();
+                Ok(self.disasm())
+            }
+            "calldata" => {
+                self.calldata = parse_hex(arg(1)?)?;
+                self.reset();
+                Ok(String::new())
+            }
+            "step" | "s" => {
+                let n = match args.get(1) {
+                    Some(n) => n.parse::<usize>().map_err(|_| "step: invalid count")?,
+                    None => 1,
+                };
+                for i in 0..n {
+                    let hit = self.step_watched()?;
+                    if self.halted() || hit.is_some() || (i + 1 < n && self.breakpoint().is_some())
+                    {
+                        break;
+                    }
+                }
+                Ok(self.info())
+            }
+            "back" => {
+                let n = match args.get(1) {
+                    Some(n) => n.parse::<usize>().map_err(|_| "back: invalid count")?,
+                    None => 1,
+                };
+                for _ in 0..n {
+                    self.step_back()?;
+                }
+                Ok(self.info())
+            }
+            "goto" => {
+                self.goto(parse_number(arg(1)?)? as usize)?;
+                Ok(self.info())
+            }
+            "continue" | "c" => {
+                let hit = self.continue_()?;
+                let mut out = String::new();
+                if let Some(i) = hit {
+                    writeln!(out, "breakpoint {} hit", i).unwrap();
+                }
+                out.push_str(&self.info());
+                Ok(out)
+            }
+            "break" | "b" => {
+                let b = match arg(1)? {
+                    "pc" => Breakpoint::Pc(parse_number(arg(2)?)? as usize),
+                    "op" => {
+                        let op = arg(2)?;
+                        Breakpoint::Opcode(match asm::opcode_by_name(op) {
+                            Some(opcode) => opcode,
+                            None => u8::try_from(parse_number(op)?)
+                                .map_err(|_| format!("unknown opcode {}", op))?,
+                        })
+                    }
+                    "sload" => Breakpoint::StorageRead(parse_word(arg(2)?)?),
+                    "sstore" => Breakpoint::StorageWrite(parse_word(arg(2)?)?),
+                    "mem" => Breakpoint::Memory(
+                        parse_number(arg(2)?)? as usize,
+                        parse_number(arg(3)?)? as usize,
+                    ),
+                    "gas" => Breakpoint::Gas(parse_number(arg(2)?)?),
+                    kind => return Err(format!("break: unknown kind {}", kind)),
+                };
+                self.breakpoints.push(b);
+                Ok(format!("breakpoint {}", self.breakpoints.len() - 1))
+            }
+            "breakpoints" => {
+                let mut out = String::new();
+                for (i, b) in self.breakpoints.iter().enumerate() {
+                    writeln!(out, "{}: {}", i, b).unwrap();
+                }
+                Ok(out.trim_end().to_string())
+            }
+            "delete" => {
+                let i = parse_number(arg(1)?)? as usize;
+                if i >= self.breakpoints.len() {
+                    return Err(format!("delete: no breakpoint {}", i));
+                }
+                self.breakpoints.remove(i);
+                Ok(String::new())
+            }
+            "reset" => {
+                self.reset();
+                Ok(self.info())
+            }
+            "info" | "i" => Ok(self.info()),
+            "disasm" => Ok(self.disasm()),
+            "stack" => Ok(self.stack()),
+            "memory" => Ok(self.memory()),
+            "storage" => Ok(self.storage()),
+            "push" => {
+                self.vm.push(parse_word(arg(1)?)?);
+                self.edited();
+                Ok(self.stack())
+            }
+            "pop" => {
+                self.vm.pop()?;
+                self.edited();
+                Ok(self.stack())
+            }
+            "set" => {
+                let i = parse_number(arg(1)?)? as usize;
+                let l = self.vm.stack.len();
+                if i >= l {
+                    return Err(format!("set: stack has {} items", l));
+                }
+                self.vm.stack[l - 1 - i] = parse_word(arg(2)?)?;
+                self.edited();
+                Ok(self.stack())
+            }
+            "mstore" => {
+                let offset = parse_number(arg(1)?)? as usize;
+                let data = parse_hex(arg(2)?)?;
+                let end = match offset.checked_add(data.len()) {
+                    Some(end) if end <= MAX_MEMORY_EDIT || end <= self.vm.mem.len() => end,
+                    _ => return Err(format!("mstore: memory past {} bytes", MAX_MEMORY_EDIT)),
+                };
+                if self.vm.mem.len() < end {
+                    self.vm.mem.resize(end.div_ceil(32) * 32, 0);
+                }
+                self.vm.mem[offset..end].copy_from_slice(&data);
+                self.edited();
+                Ok(self.memory())
+            }
+            "sstore" => {
+                let key = parse_word(arg(1)?)?;
+                self.vm.set_storage(&key, parse_word(arg(2)?)?);
+                self.edited();
+                Ok(self.storage())
+            }
+            "gas" => {
+                // the gas used is counted from the new gas left
+                self.vm.gas = parse_number(arg(1)?)?;
+                self.gas = self.vm.gas;
+                self.edited();
+                Ok(self.info())
+            }
+            _ => Err(format!("unknown command {}, see help", cmd)),
+        }
+    }
+
+    // info describes the next instruction, or the result once halted
+    pub fn info(&self) -> String {
+        match &self.result {
+            Some(Ok(output)) => format!(
+                "halted: output 0x{}, gas used {}",
+                hex::encode(output),
+                self.gas_used()
+            ),
+            Some(Err(e)) => format!("halted: error {}", e),
+            None => {
+                let next = asm::disassemble(self.code.get(self.vm.pc..).unwrap_or(&[]))
+                    .first()
+                    .map(|i| i.to_string())
+                    .unwrap_or_else(|| "end of code".to_string());
+                format!("pc={} gas={} next: {}", self.vm.pc, self.vm.gas, next)
+            }
+        }
+    }
+
+    pub fn disasm(&self) -> String {
+        let mut out = String::new();
+        for i in asm::disassemble(&self.code).iter() {
+            let marker = if i.pc == self.vm.pc && !self.halted() {
+                ">"
+            } else {
+                " "
+            };
+            writeln!(out, "{} {:4}  {}", marker, i.pc, i).unwrap();
+        }
+        out.trim_end().to_string()
+    }
+
+    // stack lists the items from the top, with their index
+    pub fn stack(&self) -> String {
+        let mut out = format!("stack ({}):", self.vm.stack.len());
+        for (i, w) in self.vm.stack.iter().rev().enumerate() {
+            write!(out, "\n{:3}: 0x{}", i, hex::encode(w)).unwrap();
+        }
+        out
+    }
+
+    pub fn memory(&self) -> String {
+        let mut out = format!("memory ({}):", self.vm.mem.len());
+        for (i, chunk) in self.vm.mem.chunks(32).enumerate() {
+            write!(out, "\n0x{:04x}: {}", i * 32, hex::encode(chunk)).unwrap();
+        }
+        out
+    }
+
+    // storage lists the slots of the executing account, sorted by key
+    pub fn storage(&self) -> String {
+        let mut slots: Vec<([u8; 32], [u8; 32])> = match self.vm.state.get(&self.vm.address) {
+            Some(account) => account.storage.iter().map(|(k, v)| (*k, *v)).collect(),
+            None => Vec::new(),
+        };
+        slots.sort();
+        let mut out = format!("storage ({}):", slots.len());
+        for (key, value) in slots.iter() {
+            write!(out, "\n0x{}: 0x{}", hex::encode(key), hex::encode(value)).unwrap();
+        }
+        out
+    }
+}
+
+fn parse_hex(s: &str) -> Result<Vec<u8>, String> {
+    json::parse_bytes(&Value::from(s))
+}
+
+fn parse_word(s: &str) -> Result<[u8; 32], String> {
+    json::parse_word(&Value::from(s))
+}
+
+fn parse_number(s: &str) -> Result<u64, String> {
+    json::parse_u64(&Value::from(s))
+}
@@ -0,0 +1,352 @@
+// In-process local chain for development and integration tests: the sent
+// transactions are kept in a pending list and mined into blocks on top of the
+// genesis, at once when auto_mine is set. Blocks are built as the proof of
+// stake ones: no mining rewards, the base fee following EIP-1559, and the
+// hashes of the previous blocks readable through BLOCKHASH.
+use super::*;
+use block::{
+    blob_gas_limits, calc_base_fee, calc_excess_blob_gas, requests_hash, system_calls,
+    transactions_root, Block, BlockResult, Header,
+};
+use envelope::SignedTransaction;
+use genesis::Genesis;
+use num_bigint::BigUint;
+use num_traits::identities::Zero;
+use serde_json::Value;
+use simulate::Overrides;
+use spec::{Config, Spec};
+use state::State;
+use std::collections::HashMap;
+use tracers::TraceConfig;
+use transaction::{BlockEnv, ExecutionResult, Transaction};
+
+// number of previous block hashes accessible from BLOCKHASH
+const BLOCK_HASH_HISTORY: u64 = 256;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MinedBlock {
+    pub block: Block,
+    // receipts and execution results of the transactions, empty for the
+    // genesis
+    pub result: BlockResult,
+}
+
+impl MinedBlock {
+    pub fn hash(&self) -> [u8; 32] {
+        self.block.hash()
+    }
+    pub fn number(&self) -> u64 {
+        self.block.header.number
+    }
+}
+
+pub struct Devnet {
+    pub config: Config,
+    pub chain_id: u64,
+    // state at the latest block
+    pub state: State,
+    // mined blocks, from the genesis
+    pub blocks: Vec<MinedBlock>,
+    pub pending: Vec<SignedTransaction>,
+    // mine a block with each sent transaction
+    pub auto_mine: bool,
+    // seconds between the timestamps of consecutive blocks
+    pub block_time: u64,
+    pub coinbase: [u8; 20],
+    // state at the genesis, from which the blocks are replayed to trace their
+    // transactions
+    genesis: State,
+    // transaction hash => (block number, index in the block)
+    tx_index: HashMap<[u8; 32], (u64, usize)>,
+    block_index: HashMap<[u8; 32], u64>,
+}
+
+impl Devnet {
+    pub fn new(genesis: &Genesis, spec: Spec) -> Devnet {
+        let block = Block {
+            header: genesis.header(spec),
+            withdrawals: if spec >= Spec::Shanghai {
+                Some(Vec::new())
+            } else {
+                None
+            },
+            ..Block::default()
+        };
+        let mut devnet = Devnet {
+            config: Config::new(spec),
+            chain_id: genesis.chain_id(),
+            state: genesis.alloc.clone(),
+            blocks: Vec::new(),
+            pending: Vec::new(),
+            auto_mine: true,
+            block_time: 1,
+            coinbase: genesis.coinbase,
+            genesis: genesis.alloc.clone(),
+            tx_index: HashMap::new(),
+            block_index: HashMap::new(),
+        };
+        devnet.push(MinedBlock {
+            block,
+            result: BlockResult::default(),
+        });
+        devnet
+    }
+
+    pub fn latest(&self) -> &MinedBlock {
+        self.blocks.last().unwrap()
+    }
+    pub fn block_number(&self) -> u64 {
+        self.latest().number()
+    }
+    pub fn block_by_number(&self, number: u64) -> Option<&MinedBlock> {
+        let first = self.blocks[0].number();
+        self.blocks.get(number.checked_sub(first)? as usize)
+    }
+    pub fn block_by_hash(&self, hash: &[u8; 32]) -> Option<&MinedBlock> {
+        self.block_by_number(*self.block_index.get(hash)?)
+    }
+
+    // transaction returns the mined block of the transaction and its index in
+    // it
+    pub fn transaction(&self, hash: &[u8; 32]) -> Option<(&MinedBlock, usize)> {
+        let (number, i) = self.tx_index.get(hash)?;
+        Some((self.block_by_number(*number)?, *i))
+    }
+
+    // send_transaction adds the transaction to the pending ones, mining it
+    // when auto_mine is set, and returns its hash. A transaction that can not
+    // be included (invalid, or with a nonce already used) is rejected, but
+    // for the ones with a future nonce, kept pending.
+    pub fn send_transaction(&mut self, stx: SignedTransaction) -> Result<[u8; 32], String> {
+        if let Some(chain_id) = stx.tx.chain_id {
+            if chain_id != self.chain_id {
+                return Err(format!(
+                    "invalid chain id: have {}, want {}",
+                    chain_id, self.chain_id
+                ));
+            }
+        }
+        let nonce = self.state.nonce(&stx.tx.from);
+        if stx.tx.nonce < nonce {
+            return Err(format!(
+                "nonce too low: tx {} state {}",
+                stx.tx.nonce, nonce
+            ));
+        }
+        let hash = stx.hash();
+        if self.tx_index.contains_key(&hash) || self.pending.iter().any(|p| p.hash() == hash) {
+            return Err("already known".to_string());
+        }
+        if self.auto_mine {
+            // the transaction is checked against the state before being kept
+            let mut state = self.state.clone();
+            match transaction::transact(&self.config, &mut state, &self.pending_env(), &stx.tx) {
+                Ok(_) => {
+                    self.pending.push(stx);
+                    self.mine();
+                }
+                Err(e) if e.starts_with("nonce too high") => self.pending.push(stx),
+                Err(e) => return Err(e),
+            }
+        } else {
+            self.pending.push(stx);
+        }
+        Ok(hash)
+    }
+
+    // pending_header returns the header of the next block, without the fields
+    // computed from its execution
+    pub fn pending_header(&self) -> Header {
+        let parent = &self.latest().block.header;
+        let spec = self.config.spec;
+        let mut h = Header {
+            parent_hash: parent.hash(),
+            coinbase: self.coinbase,
+            number: parent.number + 1,
+            gas_limit: parent.gas_limit,
+            timestamp: parent.timestamp + self.block_time,
+            difficulty: if spec >= Spec::Paris {
+                BigUint::zero()
+            } else {
+                parent.difficulty.clone()
+            },
+            ..Header::default()
+        };
+        if spec >= Spec::London {
+            h.base_fee = Some(calc_base_fee(parent));
+        }
+        if spec >= Spec::Cancun {
+            h.excess_blob_gas = Some(calc_excess_blob_gas(spec, parent));
+            h.blob_gas_used = Some(0);
+            h.parent_beacon_block_root = Some([0; 32]);
+        }
+        h
+    }
+
+    // pending_env returns the environment the transactions of the next block
+    // are executed in
+    pub fn pending_env(&self) -> BlockEnv {
+        self.block_env(&self.pending_header())
+    }
+
+    fn block_env(&self, header: &Header) -> BlockEnv {
+        let mut env = header.block_env(self.config.spec, self.chain_id);
+        let first = header.number.saturating_sub(BLOCK_HASH_HISTORY);
+        for number in first..header.number {
+            if let Some(b) = self.block_by_number(number) {
+                env.block_hashes.insert(number, b.hash());
+            }
+        }
+        env
+    }
+
+    // mine builds a block with the pending transactions that fit in its gas
+    // limit, by nonce and then in the order they were sent. The invalid ones
+    // are dropped, but for the ones with a future nonce, which are kept
+    // pending as the ones that did not fit.
+    pub fn mine(&mut self) -> &MinedBlock {
+        let spec = self.config.spec;
+        let mut header = self.pending_header();
+        let env = self.block_env(&header);
+        system_calls(spec, &mut self.state, &header);
+
+        let (_, max_blob_gas) = blob_gas_limits(spec);
+        let mut result = BlockResult::default();
+        let mut transactions = Vec::new();
+        let mut pending = Vec::new();
+        let mut txs = std::mem::take(&mut self.pending);
+        txs.sort_by_key(|stx| stx.tx.nonce);
+        for stx in txs {
+            let tx = &stx.tx;
+            if tx.gas_limit > header.gas_limit - result.gas_used
+                || result.blob_gas_used + tx.blob_gas() > max_blob_gas
+            {
+                pending.push(stx);
+                continue;
+            }
+            match transaction::transact(&self.config, &mut self.state, &env, tx) {
+                Ok(r) => {
+                    result.push(spec, &self.state, tx, r);
+                    transactions.push(stx);
+                }
+                Err(e) if e.starts_with("nonce too high") => pending.push(stx),
+                Err(_) => {}
+            }
+        }
+        self.pending = pending;
+
+        header.state_root = self.state.root();
+        header.transactions_root = transactions_root(&transactions);
+        header.receipts_root = result.receipts_root();
+        header.logs_bloom = result.logs_bloom;
+        header.gas_used = result.gas_used;
+        let withdrawals = if spec >= Spec::Shanghai {
+            header.withdrawals_root = Some(trie::EMPTY_ROOT);
+            Some(Vec::new())
+        } else {
+            None
+        };
+        if spec >= Spec::Cancun {
+            header.blob_gas_used = Some(result.blob_gas_used);
+        }
+        if spec >= Spec::Prague {
+            header.requests_hash = Some(requests_hash(&[]));
+        }
+        self.push(MinedBlock {
+            block: Block {
+                header,
+                transactions,
+                ommers: Vec::new(),
+                withdrawals,
+            },
+            result,
+        });
+        self.latest()
+    }
+
+    // call executes the transaction on top of the latest state with the
+    // overrides, as if included in the next block, without keeping its
+    // changes (see simulate::call)
+    pub fn call(&self, tx: &Transaction, overrides: &Overrides) -> Result<ExecutionResult, String> {
+        simulate::call(
+            &self.config,
+            &self.state,
+            &self.pending_env(),
+            tx,
+            overrides,
+        )
+    }
+
+    // trace_call is call, returning the output of the tracer
+    pub fn trace_call(
+        &self,
+        tx: &Transaction,
+        overrides: &Overrides,
+        trace_config: &TraceConfig,
+    ) -> Result<Value, String> {
+        let (mut state, env, tx) =
+            simulate::prepare(&self.state, &self.pending_env(), tx, overrides);
+        tracers::trace(&self.config, &mut state, &env, &tx, trace_config)
+    }
+
+    // trace_transaction replays the mined transaction, returning the output of
+    // the tracer. The blocks are replayed from the genesis, the state of the
+    // previous blocks not being kept.
+    pub fn trace_transaction(
+        &self,
+        hash: &[u8; 32],
+        trace_config: &TraceConfig,
+    ) -> Result<Value, String> {
+        let (traced, index) = self
+            .transaction(hash)
+            .ok_or_else(|| "transaction not found".to_string())?;
+        let spec = self.config.spec;
+        let mut state = self.genesis.clone();
+        for b in self.blocks[1..].iter() {
+            let header = &b.block.header;
+            let env = self.block_env(header);
+            system_calls(spec, &mut state, header);
+            for (i, stx) in b.block.transactions.iter().enumerate() {
+                if header.number == traced.number() && i == index {
+                    return tracers::trace(&self.config, &mut state, &env, &stx.tx, trace_config);
+                }
+                transaction::transact(&self.config, &mut state, &env, &stx.tx)?;
+            }
+        }
+        Err("transaction not found".to_string())
+    }
+
+    // estimate_gas returns the lowest gas limit with which the call succeeds,
+    // up to its gas limit, or the one of the block when zero
+    pub fn estimate_gas(&self, tx: &Transaction, overrides: &Overrides) -> Result<u64, String> {
+        let mut tx = tx.clone();
+        let mut hi = match tx.gas_limit {
+            0 => self.pending_header().gas_limit,
+            gas_limit => gas_limit,
+        };
+        tx.gas_limit = hi;
+        if let Some(e) = self.call(&tx, overrides)?.error {
+            return Err(e);
+        }
+        let mut lo = tx.intrinsic_gas(self.config.spec) - 1;
+        while lo + 1 < hi {
+            tx.gas_limit = lo + (hi - lo) / 2;
+            // a gas limit too low for the transaction to be valid fails too
+            if matches!(self.call(&tx, overrides), Ok(r) if r.success) {
+                hi = tx.gas_limit;
+            } else {
+                lo = tx.gas_limit;
+            }
+        }
+        Ok(hi)
+    }
+
+    fn push(&mut self, b: MinedBlock) {
+        let number = b.number();
+        for (i, stx) in b.block.transactions.iter().enumerate() {
+            self.tx_index.insert(stx.hash(), (number, i));
+        }
+        self.block_index.insert(b.hash(), number);
+        self.blocks.push(b);
+    }
+}
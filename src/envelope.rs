@@ -0,0 +1,300 @@
+// Signed transaction envelopes: legacy (with and without EIP-155 replay
+// protection) and the EIP-2718 typed transactions (EIP-2930, EIP-1559,
+// EIP-4844 and EIP-7702)
+use super::*;
+use num_bigint::BigUint;
+use rlp::{Decodable, Encodable, Rlp};
+use transaction::{
+    Transaction, ACCESS_LIST_TX_TYPE, BLOB_TX_TYPE, DYNAMIC_FEE_TX_TYPE, LEGACY_TX_TYPE,
+    SET_CODE_TX_TYPE,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct SignedTransaction {
+    // the transaction as consumed by transact(), with the recovered sender
+    pub tx: Transaction,
+    // v for legacy transactions (27/28 or EIP-155 encoded), y parity for typed
+    // ones
+    pub v: u64,
+    pub r: BigUint,
+    pub s: BigUint,
+}
+
+// decode decodes a raw signed transaction, as found in blocks and in
+// eth_sendRawTransaction, recovering its sender. For blob transactions the
+// network form (with blobs, commitments and proofs) is also accepted.
+pub fn decode(raw: &[u8]) -> Result<SignedTransaction, String> {
+    if raw.is_empty() {
+        return Err("empty transaction".to_string());
+    }
+    let mut stx = if raw[0] >= 0xc0 {
+        decode_legacy(&rlp::decode(raw)?)?
+    } else {
+        let item = rlp::decode(&raw[1..])?;
+        match raw[0] {
+            ACCESS_LIST_TX_TYPE | DYNAMIC_FEE_TX_TYPE | SET_CODE_TX_TYPE => {
+                decode_typed(raw[0], &item)?
+            }
+            BLOB_TX_TYPE => {
+                let l = item.as_list()?;
+                if !l.is_empty() && l[0].as_list().is_ok() {
+                    // network form: [tx_payload_body, blobs, commitments, proofs]
+                    decode_typed(raw[0], &l[0])?
+                } else {
+                    decode_typed(raw[0], &item)?
+                }
+            }
+            t => return Err(format!("unsupported transaction type {}", t)),
+        }
+    };
+    stx.tx.from = stx.sender()?;
+    Ok(stx)
+}
+
+// eip155_v returns the v of a legacy transaction signed for the chain
+// (EIP-155), None when it does not fit in 64 bits
+fn eip155_v(chain_id: u64, y_parity: u64) -> Option<u64> {
+    chain_id.checked_mul(2)?.checked_add(35 + y_parity)
+}
+
+impl SignedTransaction {
+    // sign signs the transaction with the secret key, setting its sender
+    pub fn sign(tx: &Transaction, secret_key: &[u8; 32]) -> Result<SignedTransaction, String> {
+        let mut stx = SignedTransaction {
+            tx: tx.clone(),
+            v: 0,
+            r: BigUint::default(),
+            s: BigUint::default(),
+        };
+        let (y_parity, r, s) = signature::sign(&stx.signing_hash(), secret_key)?;
+        stx.v = match (tx.tx_type, tx.chain_id) {
+            (LEGACY_TX_TYPE, Some(chain_id)) => {
+                eip155_v(chain_id, y_parity as u64).ok_or_else(|| {
+                    format!("chain id {} too large for a legacy transaction", chain_id)
+                })?
+            }
+            (LEGACY_TX_TYPE, None) => y_parity as u64 + 27,
+            _ => y_parity as u64,
+        };
+        stx.r = r;
+        stx.s = s;
+        stx.tx.from = signature::secret_key_to_address(secret_key)?;
+        Ok(stx)
+    }
+
+    // encode returns the canonical encoding: rlp(fields) for legacy
+    // transactions, type || rlp(fields) for typed ones
+    pub fn encode(&self) -> Vec<u8> {
+        let mut fields = self.fields();
+        fields.push(Rlp::from_u64(self.v));
+        fields.push(Rlp::from_biguint(&self.r));
+        fields.push(Rlp::from_biguint(&self.s));
+        self.with_type(rlp::encode(&Rlp::List(fields)))
+    }
+
+    pub fn hash(&self) -> [u8; 32] {
+        keccak256(&self.encode())
+    }
+
+    // signing_hash is the hash signed by the sender
+    pub fn signing_hash(&self) -> [u8; 32] {
+        let mut fields = self.fields();
+        if self.tx.tx_type == LEGACY_TX_TYPE {
+            if let Some(chain_id) = self.tx.chain_id {
+                // EIP-155
+                fields.push(Rlp::from_u64(chain_id));
+                fields.push(Rlp::from_u64(0));
+                fields.push(Rlp::from_u64(0));
+            }
+        }
+        keccak256(&self.with_type(rlp::encode(&Rlp::List(fields))))
+    }
+
+    // sender recovers the address that signed the transaction
+    pub fn sender(&self) -> Result<[u8; 20], String> {
+        let y_parity = if self.tx.tx_type != LEGACY_TX_TYPE {
+            self.v
+        } else {
+            match self.tx.chain_id.map(|chain_id| eip155_v(chain_id, 0)) {
+                Some(Some(v)) => self.v.wrapping_sub(v),
+                Some(None) => return Err(format!("invalid signature: v {}", self.v)),
+                None => self.v.wrapping_sub(27),
+            }
+        };
+        if y_parity > 1 {
+            return Err(format!("invalid signature: v {}", self.v));
+        }
+        signature::recover_address(&self.signing_hash(), y_parity as u8, &self.r, &self.s)
+    }
+
+    fn with_type(&self, mut payload: Vec<u8>) -> Vec<u8> {
+        if self.tx.tx_type == LEGACY_TX_TYPE {
+            return payload;
+        }
+        let mut r = vec![self.tx.tx_type];
+        r.append(&mut payload);
+        r
+    }
+
+    // fields returns the transaction fields, without the signature
+    fn fields(&self) -> Vec<Rlp> {
+        let tx = &self.tx;
+        let to = match tx.to {
+            Some(to) => Rlp::Bytes(to.to_vec()),
+            None => Rlp::Bytes(Vec::new()),
+        };
+        if tx.tx_type == LEGACY_TX_TYPE {
+            return vec![
+                Rlp::from_u64(tx.nonce),
+                Rlp::from_biguint(&tx.gas_price),
+                Rlp::from_u64(tx.gas_limit),
+                to,
+                Rlp::from_biguint(&tx.value),
+                Rlp::Bytes(tx.data.clone()),
+            ];
+        }
+
+        let mut fields = vec![
+            Rlp::from_u64(tx.chain_id.unwrap_or(0)),
+            Rlp::from_u64(tx.nonce),
+        ];
+        if tx.tx_type == ACCESS_LIST_TX_TYPE {
+            fields.push(Rlp::from_biguint(&tx.gas_price));
+        } else {
+            let tip = tx.max_priority_fee_per_gas.clone().unwrap_or_default();
+            fields.push(Rlp::from_biguint(&tip));
+            fields.push(Rlp::from_biguint(&tx.gas_price));
+        }
+        fields.push(Rlp::from_u64(tx.gas_limit));
+        fields.push(to);
+        fields.push(Rlp::from_biguint(&tx.value));
+        fields.push(Rlp::Bytes(tx.data.clone()));
+        fields.push(rlp::encode_list(&tx.access_list));
+        if tx.tx_type == BLOB_TX_TYPE {
+            let fee = tx.max_fee_per_blob_gas.clone().unwrap_or_default();
+            fields.push(Rlp::from_biguint(&fee));
+            fields.push(rlp::encode_list(&tx.blob_versioned_hashes));
+        }
+        if tx.tx_type == SET_CODE_TX_TYPE {
+            fields.push(rlp::encode_list(&tx.authorization_list));
+        }
+        fields
+    }
+}
+
+// In block bodies legacy transactions are encoded as a list, and typed ones
+// as the byte string of their envelope
+impl Encodable for SignedTransaction {
+    fn to_rlp(&self) -> Rlp {
+        if self.tx.tx_type == LEGACY_TX_TYPE {
+            return rlp::decode(&self.encode()).unwrap();
+        }
+        Rlp::Bytes(self.encode())
+    }
+}
+impl Decodable for SignedTransaction {
+    fn from_rlp(item: &Rlp) -> Result<SignedTransaction, String> {
+        match item {
+            Rlp::List(_) => decode(&rlp::encode(item)),
+            Rlp::Bytes(b) => decode(b),
+        }
+    }
+}
+
+fn decode_legacy(item: &Rlp) -> Result<SignedTransaction, String> {
+    let l = item.as_list()?;
+    if l.len() != 9 {
+        return Err(format!(
+            "legacy transaction: expected 9 fields, got {}",
+            l.len()
+        ));
+    }
+    let v = l[6].as_u64()?;
+    let chain_id = match v {
+        27 | 28 => None,
+        v if v >= 35 => Some((v - 35) / 2),
+        _ => return Err(format!("invalid signature: v {}", v)),
+    };
+    let tx = Transaction {
+        tx_type: LEGACY_TX_TYPE,
+        chain_id,
+        nonce: l[0].as_u64()?,
+        gas_price: l[1].as_biguint()?,
+        gas_limit: l[2].as_u64()?,
+        to: decode_to(&l[3])?,
+        value: l[4].as_biguint()?,
+        data: l[5].as_bytes()?.to_vec(),
+        ..Transaction::default()
+    };
+    Ok(SignedTransaction {
+        tx,
+        v,
+        r: l[7].as_biguint()?,
+        s: l[8].as_biguint()?,
+    })
+}
+
+fn decode_typed(tx_type: u8, item: &Rlp) -> Result<SignedTransaction, String> {
+    let l = item.as_list()?;
+    let n_fields = match tx_type {
+        ACCESS_LIST_TX_TYPE => 11,
+        DYNAMIC_FEE_TX_TYPE => 12,
+        BLOB_TX_TYPE => 14,
+        _ => 13,
+    };
+    if l.len() != n_fields {
+        return Err(format!(
+            "type {} transaction: expected {} fields, got {}",
+            tx_type,
+            n_fields,
+            l.len()
+        ));
+    }
+    let mut tx = Transaction {
+        tx_type,
+        chain_id: Some(l[0].as_u64()?),
+        nonce: l[1].as_u64()?,
+        ..Transaction::default()
+    };
+    // position of the gas limit
+    let mut i = 2;
+    if tx_type == ACCESS_LIST_TX_TYPE {
+        tx.gas_price = l[2].as_biguint()?;
+        i += 1;
+    } else {
+        tx.max_priority_fee_per_gas = Some(l[2].as_biguint()?);
+        tx.gas_price = l[3].as_biguint()?;
+        i += 2;
+    }
+    tx.gas_limit = l[i].as_u64()?;
+    tx.to = decode_to(&l[i + 1])?;
+    tx.value = l[i + 2].as_biguint()?;
+    tx.data = l[i + 3].as_bytes()?.to_vec();
+    tx.access_list = rlp::decode_list(&l[i + 4])?;
+    i += 5;
+    if tx_type == BLOB_TX_TYPE {
+        tx.max_fee_per_blob_gas = Some(l[i].as_biguint()?);
+        tx.blob_versioned_hashes = rlp::decode_list(&l[i + 1])?;
+        i += 2;
+    }
+    if tx_type == SET_CODE_TX_TYPE {
+        tx.authorization_list = rlp::decode_list(&l[i])?;
+        i += 1;
+    }
+    if (tx_type == BLOB_TX_TYPE || tx_type == SET_CODE_TX_TYPE) && tx.to.is_none() {
+        return Err(format!("type {} transaction: missing to", tx_type));
+    }
+    Ok(SignedTransaction {
+        tx,
+        v: l[i].as_u64()?,
+        r: l[i + 1].as_biguint()?,
+        s: l[i + 2].as_biguint()?,
+    })
+}
+
+fn decode_to(item: &Rlp) -> Result<Option<[u8; 20]>, String> {
+    if item.as_bytes()?.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(item.as_address()?))
+}
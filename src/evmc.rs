@@ -0,0 +1,551 @@
+// EVMC bindings: the interpreter exposed through the EVMC C API (ABI version
+// 12, https://github.com/ethereum/evmc), so that the cdylib (libevm.so) is
+// loaded as a VM by the EVMC clients and tools, e.g.
+//   evmone-statetest --vm ./libevm.so ...
+// evmc_create_evm returns the VM, whose execute runs a message on a Stack,
+// with the external effects going to the client through EvmcHost.
+//
+// The types follow evmc.h, with the evmc_ prefix dropped and camel case.
+use super::*;
+use host::{AccessStatus, CallKind, CallResult, Host, Message, StorageStatus, TxContext};
+use num_bigint::BigUint;
+use std::convert::TryFrom;
+use std::ffi::{c_char, c_void, CStr};
+
+pub const ABI_VERSION: i32 = 12;
+const NAME: &[u8] = b"evm-rs\0";
+const VERSION: &[u8] = b"0.0.1\0";
+
+pub const CAPABILITY_EVM1: u32 = 1;
+pub const STATIC: u32 = 1;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Bytes32 {
+    pub bytes: [u8; 32],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Address {
+    pub bytes: [u8; 20],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CallKindC {
+    Call = 0,
+    DelegateCall = 1,
+    CallCode = 2,
+    Create = 3,
+    Create2 = 4,
+    EofCreate = 5,
+}
+
+#[repr(C)]
+pub struct EvmcMessage {
+    pub kind: CallKindC,
+    pub flags: u32,
+    pub depth: i32,
+    pub gas: i64,
+    pub recipient: Address,
+    pub sender: Address,
+    pub input_data: *const u8,
+    pub input_size: usize,
+    pub value: Bytes32,
+    pub create2_salt: Bytes32,
+    pub code_address: Address,
+    pub code: *const u8,
+    pub code_size: usize,
+}
+
+#[repr(C)]
+pub struct TxInitcode {
+    pub hash: Bytes32,
+    pub code: *const u8,
+    pub code_size: usize,
+}
+
+#[repr(C)]
+pub struct EvmcTxContext {
+    pub tx_gas_price: Bytes32,
+    pub tx_origin: Address,
+    pub block_coinbase: Address,
+    pub block_number: i64,
+    pub block_timestamp: i64,
+    pub block_gas_limit: i64,
+    pub block_prev_randao: Bytes32,
+    pub chain_id: Bytes32,
+    pub block_base_fee: Bytes32,
+    pub blob_base_fee: Bytes32,
+    pub blob_hashes: *const Bytes32,
+    pub blob_hashes_count: usize,
+    pub initcodes: *const TxInitcode,
+    pub initcodes_count: usize,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StatusCode {
+    Success = 0,
+    Failure = 1,
+    Revert = 2,
+    OutOfGas = 3,
+    InvalidInstruction = 4,
+    UndefinedInstruction = 5,
+    StackOverflow = 6,
+    StackUnderflow = 7,
+    BadJumpDestination = 8,
+    InvalidMemoryAccess = 9,
+    CallDepthExceeded = 10,
+    StaticModeViolation = 11,
+    PrecompileFailure = 12,
+    ContractValidationFailure = 13,
+    ArgumentOutOfRange = 14,
+    WasmUnreachableInstruction = 15,
+    WasmTrap = 16,
+    InsufficientBalance = 17,
+    InternalError = -1,
+    Rejected = -2,
+    OutOfMemory = -3,
+}
+
+#[repr(C)]
+pub struct EvmcResult {
+    pub status_code: StatusCode,
+    pub gas_left: i64,
+    pub gas_refund: i64,
+    pub output_data: *const u8,
+    pub output_size: usize,
+    pub release: Option<extern "C" fn(*const EvmcResult)>,
+    pub create_address: Address,
+    pub padding: [u8; 4],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StorageStatusC {
+    Assigned = 0,
+    Added = 1,
+    Deleted = 2,
+    Modified = 3,
+    DeletedAdded = 4,
+    ModifiedDeleted = 5,
+    DeletedRestored = 6,
+    AddedDeleted = 7,
+    ModifiedRestored = 8,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccessStatusC {
+    Cold = 0,
+    Warm = 1,
+}
+
+// HostContext is opaque, owned by the client
+pub type HostContext = c_void;
+
+#[repr(C)]
+pub struct HostInterface {
+    pub account_exists: extern "C" fn(*mut HostContext, *const Address) -> bool,
+    pub get_storage: extern "C" fn(*mut HostContext, *const Address, *const Bytes32) -> Bytes32,
+    pub set_storage: extern "C" fn(
+        *mut HostContext,
+        *const Address,
+        *const Bytes32,
+        *const Bytes32,
+    ) -> StorageStatusC,
+    pub get_balance: extern "C" fn(*mut HostContext, *const Address) -> Bytes32,
+    pub get_code_size: extern "C" fn(*mut HostContext, *const Address) -> usize,
+    pub get_code_hash: extern "C" fn(*mut HostContext, *const Address) -> Bytes32,
+    pub copy_code: extern "C" fn(*mut HostContext, *const Address, usize, *mut u8, usize) -> usize,
+    pub selfdestruct: extern "C" fn(*mut HostContext, *const Address, *const Address) -> bool,
+    pub call: extern "C" fn(*mut HostContext, *const EvmcMessage) -> EvmcResult,
+    pub get_tx_context: extern "C" fn(*mut HostContext) -> EvmcTxContext,
+    pub get_block_hash: extern "C" fn(*mut HostContext, i64) -> Bytes32,
+    pub emit_log:
+        extern "C" fn(*mut HostContext, *const Address, *const u8, usize, *const Bytes32, usize),
+    pub access_account: extern "C" fn(*mut HostContext, *const Address) -> AccessStatusC,
+    pub access_storage:
+        extern "C" fn(*mut HostContext, *const Address, *const Bytes32) -> AccessStatusC,
+    pub get_transient_storage:
+        extern "C" fn(*mut HostContext, *const Address, *const Bytes32) -> Bytes32,
+    pub set_transient_storage:
+        extern "C" fn(*mut HostContext, *const Address, *const Bytes32, *const Bytes32),
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SetOptionResult {
+    Success = 0,
+    InvalidName = 1,
+    InvalidValue = 2,
+}
+
+#[repr(C)]
+pub struct EvmcVm {
+    pub abi_version: i32,
+    pub name: *const c_char,
+    pub version: *const c_char,
+    pub destroy: extern "C" fn(*mut EvmcVm),
+    pub execute: extern "C" fn(
+        *mut EvmcVm,
+        *const HostInterface,
+        *mut HostContext,
+        i32,
+        *const EvmcMessage,
+        *const u8,
+        usize,
+    ) -> EvmcResult,
+    pub get_capabilities: extern "C" fn(*mut EvmcVm) -> u32,
+    pub set_option:
+        Option<extern "C" fn(*mut EvmcVm, *const c_char, *const c_char) -> SetOptionResult>,
+}
+
+// Vm is the EvmcVm handed to the client, followed by the options
+#[repr(C)]
+struct Vm {
+    evmc: EvmcVm,
+    // enables P256VERIFY before Osaka (RIP-7212), option "rip7212"
+    rip7212: bool,
+}
+
+#[no_mangle]
+pub extern "C" fn evmc_create_evm() -> *mut EvmcVm {
+    let vm = Box::new(Vm {
+        evmc: EvmcVm {
+            abi_version: ABI_VERSION,
+            name: NAME.as_ptr() as *const c_char,
+            version: VERSION.as_ptr() as *const c_char,
+            destroy,
+            execute,
+            get_capabilities,
+            set_option: Some(set_option),
+        },
+        rip7212: false,
+    });
+    Box::into_raw(vm) as *mut EvmcVm
+}
+
+extern "C" fn destroy(vm: *mut EvmcVm) {
+    drop(unsafe { Box::from_raw(vm as *mut Vm) });
+}
+
+extern "C" fn get_capabilities(_vm: *mut EvmcVm) -> u32 {
+    CAPABILITY_EVM1
+}
+
+extern "C" fn set_option(
+    vm: *mut EvmcVm,
+    name: *const c_char,
+    value: *const c_char,
+) -> SetOptionResult {
+    let vm = unsafe { &mut *(vm as *mut Vm) };
+    let name = unsafe { CStr::from_ptr(name) }.to_bytes();
+    let value = unsafe { CStr::from_ptr(value) }.to_bytes();
+    match name {
+        b"rip7212" => match value {
+            b"true" | b"1" => vm.rip7212 = true,
+            b"false" | b"0" => vm.rip7212 = false,
+            _ => return SetOptionResult::InvalidValue,
+        },
+        _ => return SetOptionResult::InvalidName,
+    }
+    SetOptionResult::Success
+}
+
+// spec maps the EVMC revisions, in the same order as Spec
+fn spec(revision: i32) -> Option<spec::Spec> {
+    use spec::Spec::*;
+    let specs = [
+        Frontier,
+        Homestead,
+        TangerineWhistle,
+        SpuriousDragon,
+        Byzantium,
+        Constantinople,
+        Petersburg,
+        Istanbul,
+        Berlin,
+        London,
+        Paris,
+        Shanghai,
+        Cancun,
+        Prague,
+        Osaka,
+    ];
+    usize::try_from(revision)
+        .ok()
+        .and_then(|r| specs.get(r).copied())
+}
+
+extern "C" fn execute(
+    vm: *mut EvmcVm,
+    host: *const HostInterface,
+    context: *mut HostContext,
+    revision: i32,
+    msg: *const EvmcMessage,
+    code: *const u8,
+    code_size: usize,
+) -> EvmcResult {
+    // a panic must not unwind into the client
+    catch_panic(|| {
+        Ok(execute_message(
+            vm, host, context, revision, msg, code, code_size,
+        ))
+    })
+    .unwrap_or_else(|_| failure(StatusCode::InternalError))
+}
+
+fn execute_message(
+    vm: *mut EvmcVm,
+    host: *const HostInterface,
+    context: *mut HostContext,
+    revision: i32,
+    msg: *const EvmcMessage,
+    code: *const u8,
+    code_size: usize,
+) -> EvmcResult {
+    let vm = unsafe { &*(vm as *const Vm) };
+    let msg = unsafe { &*msg };
+    let spec = match spec(revision) {
+        Some(spec) => spec,
+        None => return failure(StatusCode::Rejected),
+    };
+    let code = unsafe { slice(code, code_size) };
+    let input = unsafe { slice(msg.input_data, msg.input_size) };
+
+    let mut config = spec::Config::new(spec);
+    config.rip7212 = vm.rip7212;
+    let mut s = Stack::with_config(config);
+    s.gas = msg.gas.max(0) as u64;
+    s.address = msg.recipient.bytes;
+    s.caller = msg.sender.bytes;
+    s.value = msg.value.bytes;
+    s.is_static = msg.flags & STATIC != 0;
+    s.depth = msg.depth.max(0) as usize;
+    s.host = Some(Box::new(EvmcHost { host, context }));
+    match s.execute(code, input, false) {
+        Ok(output) => {
+            let mut r = failure(StatusCode::Success);
+            r.gas_left = s.gas as i64;
+            r.gas_refund = s.refund;
+            if !output.is_empty() {
+                r.output_size = output.len();
+                r.output_data = Box::into_raw(output.into_boxed_slice()) as *const u8;
+                r.release = Some(release_output);
+            }
+            r
+        }
+        Err(e) => failure(status_code(&e)),
+    }
+}
+
+// status_code maps the errors of the interpreter
+fn status_code(error: &str) -> StatusCode {
+    if error == "out of gas" {
+        StatusCode::OutOfGas
+    } else if error.starts_with("invalid opcode") {
+        StatusCode::UndefinedInstruction
+    } else if error == "pop err" || error == "peek err" || error == "stack underflow" {
+        StatusCode::StackUnderflow
+    } else if error.starts_with("not valid dest") {
+        StatusCode::BadJumpDestination
+    } else if error == "static mode violation" {
+        StatusCode::StaticModeViolation
+    } else {
+        StatusCode::Failure
+    }
+}
+
+fn failure(status_code: StatusCode) -> EvmcResult {
+    EvmcResult {
+        status_code,
+        gas_left: 0,
+        gas_refund: 0,
+        output_data: std::ptr::null(),
+        output_size: 0,
+        release: None,
+        create_address: Address::default(),
+        padding: [0; 4],
+    }
+}
+
+extern "C" fn release_output(r: *const EvmcResult) {
+    let r = unsafe { &*r };
+    let output = std::ptr::slice_from_raw_parts_mut(r.output_data as *mut u8, r.output_size);
+    drop(unsafe { Box::from_raw(output) });
+}
+
+unsafe fn slice<'a>(data: *const u8, size: usize) -> &'a [u8] {
+    if data.is_null() || size == 0 {
+        return &[];
+    }
+    std::slice::from_raw_parts(data, size)
+}
+
+fn biguint(b: &Bytes32) -> BigUint {
+    BigUint::from_bytes_be(&b.bytes)
+}
+
+pub fn bytes32(n: &BigUint) -> Bytes32 {
+    Bytes32 {
+        bytes: u256::biguint_to_u256(n),
+    }
+}
+
+// EvmcHost is the host of the client, called through its interface
+pub struct EvmcHost {
+    pub host: *const HostInterface,
+    pub context: *mut HostContext,
+}
+
+impl EvmcHost {
+    fn interface(&self) -> &HostInterface {
+        unsafe { &*self.host }
+    }
+}
+
+fn address(a: &[u8; 20]) -> Address {
+    Address { bytes: *a }
+}
+
+impl Host for EvmcHost {
+    fn account_exists(&self, a: &[u8; 20]) -> bool {
+        (self.interface().account_exists)(self.context, &address(a))
+    }
+    fn get_storage(&self, a: &[u8; 20], key: &[u8; 32]) -> [u8; 32] {
+        let key = Bytes32 { bytes: *key };
+        (self.interface().get_storage)(self.context, &address(a), &key).bytes
+    }
+    fn set_storage(&mut self, a: &[u8; 20], key: &[u8; 32], value: [u8; 32]) -> StorageStatus {
+        let key = Bytes32 { bytes: *key };
+        let value = Bytes32 { bytes: value };
+        match (self.interface().set_storage)(self.context, &address(a), &key, &value) {
+            StorageStatusC::Assigned => StorageStatus::Assigned,
+            StorageStatusC::Added => StorageStatus::Added,
+            StorageStatusC::Deleted => StorageStatus::Deleted,
+            StorageStatusC::Modified => StorageStatus::Modified,
+            StorageStatusC::DeletedAdded => StorageStatus::DeletedAdded,
+            StorageStatusC::ModifiedDeleted => StorageStatus::ModifiedDeleted,
+            StorageStatusC::DeletedRestored => StorageStatus::DeletedRestored,
+            StorageStatusC::AddedDeleted => StorageStatus::AddedDeleted,
+            StorageStatusC::ModifiedRestored => StorageStatus::ModifiedRestored,
+        }
+    }
+    fn get_balance(&self, a: &[u8; 20]) -> BigUint {
+        biguint(&(self.interface().get_balance)(self.context, &address(a)))
+    }
+    fn get_code_size(&self, a: &[u8; 20]) -> usize {
+        (self.interface().get_code_size)(self.context, &address(a))
+    }
+    fn get_code_hash(&self, a: &[u8; 20]) -> [u8; 32] {
+        (self.interface().get_code_hash)(self.context, &address(a)).bytes
+    }
+    fn copy_code(&self, a: &[u8; 20], offset: usize, buffer: &mut [u8]) -> usize {
+        (self.interface().copy_code)(
+            self.context,
+            &address(a),
+            offset,
+            buffer.as_mut_ptr(),
+            buffer.len(),
+        )
+    }
+    fn selfdestruct(&mut self, a: &[u8; 20], beneficiary: &[u8; 20]) -> bool {
+        (self.interface().selfdestruct)(self.context, &address(a), &address(beneficiary))
+    }
+    fn call(&mut self, msg: &Message) -> CallResult {
+        let m = EvmcMessage {
+            kind: match msg.kind {
+                CallKind::Call => CallKindC::Call,
+                CallKind::DelegateCall => CallKindC::DelegateCall,
+                CallKind::CallCode => CallKindC::CallCode,
+                CallKind::Create => CallKindC::Create,
+                CallKind::Create2 => CallKindC::Create2,
+            },
+            flags: if msg.is_static { STATIC } else { 0 },
+            depth: msg.depth as i32,
+            gas: msg.gas as i64,
+            recipient: address(&msg.recipient),
+            sender: address(&msg.sender),
+            input_data: msg.input.as_ptr(),
+            input_size: msg.input.len(),
+            value: Bytes32 { bytes: msg.value },
+            create2_salt: Bytes32 { bytes: msg.salt },
+            code_address: address(&msg.code_address),
+            code: std::ptr::null(),
+            code_size: 0,
+        };
+        let r = (self.interface().call)(self.context, &m);
+        let output = unsafe { slice(r.output_data, r.output_size) }.to_vec();
+        let result = CallResult {
+            error: match r.status_code {
+                StatusCode::Success => None,
+                code => Some(format!("{:?}", code)),
+            },
+            output,
+            gas_left: r.gas_left.max(0) as u64,
+            gas_refund: r.gas_refund,
+            create_address: match msg.kind {
+                CallKind::Create | CallKind::Create2 => Some(r.create_address.bytes),
+                _ => None,
+            },
+        };
+        if let Some(release) = r.release {
+            release(&r);
+        }
+        result
+    }
+    fn get_tx_context(&self) -> TxContext {
+        let c = (self.interface().get_tx_context)(self.context);
+        let blob_hashes = unsafe { slice(c.blob_hashes as *const u8, c.blob_hashes_count * 32) };
+        TxContext {
+            origin: c.tx_origin.bytes,
+            gas_price: biguint(&c.tx_gas_price),
+            blob_hashes: blob_hashes
+                .chunks(32)
+                .map(|h| <[u8; 32]>::try_from(h).unwrap())
+                .collect(),
+            block: transaction::BlockEnv {
+                number: c.block_number as u64,
+                coinbase: c.block_coinbase.bytes,
+                timestamp: c.block_timestamp as u64,
+                gas_limit: c.block_gas_limit as u64,
+                base_fee: biguint(&c.block_base_fee),
+                blob_base_fee: biguint(&c.blob_base_fee),
+                chain_id: u256::u256_to_u64(c.chain_id.bytes),
+                prev_randao: c.block_prev_randao.bytes,
+                // read on demand through get_block_hash
+                block_hashes: HashMap::new(),
+            },
+        }
+    }
+    fn get_block_hash(&self, number: u64) -> [u8; 32] {
+        (self.interface().get_block_hash)(self.context, number as i64).bytes
+    }
+    fn emit_log(&mut self, a: &[u8; 20], topics: &[[u8; 32]], data: &[u8]) {
+        let topics: Vec<Bytes32> = topics.iter().map(|t| Bytes32 { bytes: *t }).collect();
+        (self.interface().emit_log)(
+            self.context,
+            &address(a),
+            data.as_ptr(),
+            data.len(),
+            topics.as_ptr(),
+            topics.len(),
+        )
+    }
+    fn access_account(&mut self, a: &[u8; 20]) -> AccessStatus {
+        match (self.interface().access_account)(self.context, &address(a)) {
+            AccessStatusC::Cold => AccessStatus::Cold,
+            AccessStatusC::Warm => AccessStatus::Warm,
+        }
+    }
+    fn access_storage(&mut self, a: &[u8; 20], key: &[u8; 32]) -> AccessStatus {
+        let key = Bytes32 { bytes: *key };
+        match (self.interface().access_storage)(self.context, &address(a), &key) {
+            AccessStatusC::Cold => AccessStatus::Cold,
+            AccessStatusC::Warm => AccessStatus::Warm,
+        }
+    }
+}
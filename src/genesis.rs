@@ -0,0 +1,215 @@
+// Genesis is geth's genesis.json: the chain config, the fields of the genesis
+// block, and the alloc with the initial world state. The numbers are read as
+// hex or decimal, and written as hex, as geth does.
+use super::*;
+use block::Header;
+use json::{
+    alloc_to_json, hex_biguint, hex_bytes, hex_u64, parse_address, parse_alloc, parse_biguint,
+    parse_bytes, parse_u64, parse_word,
+};
+use num_bigint::BigUint;
+use serde_json::{Map, Value};
+use spec::Spec;
+use state::State;
+use std::path::Path;
+
+// defaults of geth for the missing fields
+pub const GENESIS_GAS_LIMIT: u64 = 4712388;
+pub const GENESIS_DIFFICULTY: u64 = 131072;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Genesis {
+    // the chain config, kept as is, of which only the chain id is used
+    pub config: Value,
+    pub nonce: u64,
+    pub timestamp: u64,
+    pub extra_data: Vec<u8>,
+    pub gas_limit: u64,
+    pub difficulty: BigUint,
+    pub mix_hash: [u8; 32],
+    pub coinbase: [u8; 20],
+    pub number: u64,
+    pub base_fee: Option<BigUint>,
+    pub excess_blob_gas: Option<u64>,
+    pub blob_gas_used: Option<u64>,
+    pub alloc: State,
+}
+
+impl Default for Genesis {
+    fn default() -> Genesis {
+        Genesis {
+            config: Value::Object(Map::new()),
+            nonce: 0,
+            timestamp: 0,
+            extra_data: Vec::new(),
+            gas_limit: GENESIS_GAS_LIMIT,
+            difficulty: BigUint::from(GENESIS_DIFFICULTY),
+            mix_hash: [0; 32],
+            coinbase: [0; 20],
+            number: 0,
+            base_fee: None,
+            excess_blob_gas: None,
+            blob_gas_used: None,
+            alloc: State::new(),
+        }
+    }
+}
+
+impl Genesis {
+    pub fn new(alloc: State) -> Genesis {
+        Genesis {
+            alloc,
+            ..Genesis::default()
+        }
+    }
+
+    pub fn from_json(v: &Value) -> Result<Genesis, String> {
+        let field = |name: &str| v.get(name).filter(|f| !f.is_null());
+        let mut g = Genesis::default();
+        if let Some(config) = field("config") {
+            g.config = config.clone();
+        }
+        if let Some(f) = field("nonce") {
+            g.nonce = parse_u64(f)?;
+        }
+        if let Some(f) = field("timestamp") {
+            g.timestamp = parse_u64(f)?;
+        }
+        if let Some(f) = field("extraData") {
+            g.extra_data = parse_bytes(f)?;
+        }
+        if let Some(f) = field("gasLimit") {
+            g.gas_limit = parse_u64(f)?;
+        }
+        if let Some(f) = field("difficulty") {
+            g.difficulty = parse_biguint(f)?;
+        }
+        if let Some(f) = field("mixHash") {
+            g.mix_hash = parse_word(f)?;
+        }
+        if let Some(f) = field("coinbase") {
+            g.coinbase = parse_address(f)?;
+        }
+        if let Some(f) = field("number") {
+            g.number = parse_u64(f)?;
+        }
+        if let Some(f) = field("baseFeePerGas") {
+            g.base_fee = Some(parse_biguint(f)?);
+        }
+        if let Some(f) = field("excessBlobGas") {
+            g.excess_blob_gas = Some(parse_u64(f)?);
+        }
+        if let Some(f) = field("blobGasUsed") {
+            g.blob_gas_used = Some(parse_u64(f)?);
+        }
+        if let Some(alloc) = field("alloc") {
+            g.alloc = parse_alloc(alloc)?;
+        }
+        Ok(g)
+    }
+
+    pub fn load(path: &Path) -> Result<Genesis, String> {
+        let data =
+            std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let v: Value =
+            serde_json::from_str(&data).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Genesis::from_json(&v).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn to_json(&self) -> Value {
+        let mut g = Map::new();
+        g.insert("config".to_string(), self.config.clone());
+        g.insert("nonce".to_string(), Value::from(hex_u64(self.nonce)));
+        g.insert(
+            "timestamp".to_string(),
+            Value::from(hex_u64(self.timestamp)),
+        );
+        g.insert(
+            "extraData".to_string(),
+            Value::from(hex_bytes(&self.extra_data)),
+        );
+        g.insert("gasLimit".to_string(), Value::from(hex_u64(self.gas_limit)));
+        g.insert(
+            "difficulty".to_string(),
+            Value::from(hex_biguint(&self.difficulty)),
+        );
+        g.insert(
+            "mixHash".to_string(),
+            Value::from(hex_bytes(&self.mix_hash)),
+        );
+        g.insert(
+            "coinbase".to_string(),
+            Value::from(hex_bytes(&self.coinbase)),
+        );
+        g.insert("number".to_string(), Value::from(hex_u64(self.number)));
+        if let Some(base_fee) = &self.base_fee {
+            g.insert(
+                "baseFeePerGas".to_string(),
+                Value::from(hex_biguint(base_fee)),
+            );
+        }
+        if let Some(excess_blob_gas) = self.excess_blob_gas {
+            g.insert(
+                "excessBlobGas".to_string(),
+                Value::from(hex_u64(excess_blob_gas)),
+            );
+        }
+        if let Some(blob_gas_used) = self.blob_gas_used {
+            g.insert(
+                "blobGasUsed".to_string(),
+                Value::from(hex_u64(blob_gas_used)),
+            );
+        }
+        g.insert("alloc".to_string(), alloc_to_json(&self.alloc));
+        Value::Object(g)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let data = serde_json::to_string_pretty(&self.to_json()).map_err(|e| e.to_string())?;
+        std::fs::write(path, data).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // chain_id returns the chainId of the config, 1 when missing
+    pub fn chain_id(&self) -> u64 {
+        self.config
+            .get("chainId")
+            .and_then(|c| parse_u64(c).ok())
+            .unwrap_or(1)
+    }
+
+    // header returns the genesis block, with the fields of the later forks
+    // set to their defaults as geth does
+    pub fn header(&self, spec: Spec) -> Header {
+        let mut h = Header {
+            coinbase: self.coinbase,
+            state_root: self.alloc.root(),
+            difficulty: self.difficulty.clone(),
+            number: self.number,
+            gas_limit: self.gas_limit,
+            timestamp: self.timestamp,
+            extra_data: self.extra_data.clone(),
+            mix_hash: self.mix_hash,
+            nonce: self.nonce.to_be_bytes(),
+            ..Header::default()
+        };
+        if spec >= Spec::London {
+            h.base_fee = Some(
+                self.base_fee
+                    .clone()
+                    .unwrap_or_else(|| BigUint::from(block::INITIAL_BASE_FEE)),
+            );
+        }
+        if spec >= Spec::Shanghai {
+            h.withdrawals_root = Some(trie::EMPTY_ROOT);
+        }
+        if spec >= Spec::Cancun {
+            h.blob_gas_used = Some(self.blob_gas_used.unwrap_or(0));
+            h.excess_blob_gas = Some(self.excess_blob_gas.unwrap_or(0));
+            h.parent_beacon_block_root = Some([0; 32]);
+        }
+        if spec >= Spec::Prague {
+            h.requests_hash = Some(block::requests_hash(&[]));
+        }
+        h
+    }
+}
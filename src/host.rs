@@ -0,0 +1,354 @@
+// Host interface, after EVMC's: the external effects of the interpreter (world
+// state, substate, context and calls) go through a Host, so that it runs
+// against another state backend, or inside another node. A Stack without a
+// host set uses its own world state, through LocalHost.
+//
+// The interpreter runs the precompiles itself, and CALL and STATICCALL to the
+// other accounts through Host::call. LocalHost and DbHost do not run nested
+// frames, so that these calls fail with them, and the EVMC host forwards them
+// to the client. CREATE and the other message calls are not implemented.
+use super::*;
+use num_bigint::BigUint;
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccessStatus {
+    Cold,
+    Warm,
+}
+
+// StorageStatus is the effect of a write on a slot, from its original value
+// (at the start of the transaction), current value and new value. The
+// interpreter charges the gas and updates the refund counter from it
+// (EIP-2200, EIP-3529).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StorageStatus {
+    // the slot is dirty, or the value unchanged
+    Assigned,
+    // X -> X -> Y, with X zero, Y non zero, and deleted when Y is zero
+    Added,
+    Deleted,
+    Modified,
+    // X -> 0 -> Z
+    DeletedAdded,
+    // X -> Y -> 0
+    ModifiedDeleted,
+    // X -> 0 -> X
+    DeletedRestored,
+    // 0 -> Y -> 0
+    AddedDeleted,
+    // X -> Y -> X
+    ModifiedRestored,
+}
+
+impl StorageStatus {
+    pub fn new(original: &[u8; 32], current: &[u8; 32], new: &[u8; 32]) -> StorageStatus {
+        let zero = [0; 32];
+        if current == new {
+            StorageStatus::Assigned
+        } else if original == current {
+            if *original == zero {
+                StorageStatus::Added
+            } else if *new == zero {
+                StorageStatus::Deleted
+            } else {
+                StorageStatus::Modified
+            }
+        } else if original == new {
+            if *original == zero {
+                StorageStatus::AddedDeleted
+            } else if *current == zero {
+                StorageStatus::DeletedRestored
+            } else {
+                StorageStatus::ModifiedRestored
+            }
+        } else if *original != zero && *current == zero {
+            StorageStatus::DeletedAdded
+        } else if *original != zero && *new == zero {
+            StorageStatus::ModifiedDeleted
+        } else {
+            StorageStatus::Assigned
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TxContext {
+    pub origin: [u8; 20],
+    // effective gas price of the transaction
+    pub gas_price: BigUint,
+    // versioned hashes of its blobs (EIP-4844)
+    pub blob_hashes: Vec<[u8; 32]>,
+    pub block: transaction::BlockEnv,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CallKind {
+    Call,
+    DelegateCall,
+    CallCode,
+    Create,
+    Create2,
+}
+
+// Message is a call, or a contract creation, made by the interpreter
+#[derive(Clone, Debug, PartialEq)]
+pub struct Message {
+    pub kind: CallKind,
+    pub is_static: bool,
+    pub depth: usize,
+    pub gas: u64,
+    pub recipient: [u8; 20],
+    pub sender: [u8; 20],
+    pub input: Vec<u8>,
+    pub value: [u8; 32],
+    // account whose code is executed, the recipient but for DELEGATECALL and
+    // CALLCODE
+    pub code_address: [u8; 20],
+    pub salt: [u8; 32],
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CallResult {
+    pub error: Option<String>,
+    pub output: Vec<u8>,
+    pub gas_left: u64,
+    pub gas_refund: i64,
+    pub create_address: Option<[u8; 20]>,
+}
+
+pub trait Host {
+    fn account_exists(&self, address: &[u8; 20]) -> bool;
+    fn get_storage(&self, address: &[u8; 20], key: &[u8; 32]) -> [u8; 32];
+    fn set_storage(&mut self, address: &[u8; 20], key: &[u8; 32], value: [u8; 32])
+        -> StorageStatus;
+    fn get_balance(&self, address: &[u8; 20]) -> BigUint;
+    fn get_code_size(&self, address: &[u8; 20]) -> usize;
+    fn get_code_hash(&self, address: &[u8; 20]) -> [u8; 32];
+    // copy_code copies the code from the offset into the buffer, returning the
+    // number of bytes copied
+    fn copy_code(&self, address: &[u8; 20], offset: usize, buffer: &mut [u8]) -> usize;
+    // selfdestruct returns false if the account was already destructed in the
+    // transaction. The account is kept until the end of the transaction, when
+    // the caller deletes it, before Cancun or if created in the transaction
+    // (EIP-6780).
+    fn selfdestruct(&mut self, address: &[u8; 20], beneficiary: &[u8; 20]) -> bool;
+    fn call(&mut self, msg: &Message) -> CallResult;
+    fn get_tx_context(&self) -> TxContext;
+    fn get_block_hash(&self, number: u64) -> [u8; 32];
+    fn emit_log(&mut self, address: &[u8; 20], topics: &[[u8; 32]], data: &[u8]);
+    fn access_account(&mut self, address: &[u8; 20]) -> AccessStatus;
+    fn access_storage(&mut self, address: &[u8; 20], key: &[u8; 32]) -> AccessStatus;
+    // commit and revert end an execution of Stack::execute, keeping or
+    // undoing its writes. They do nothing for the hosts reverted by their
+    // caller, as the EVMC client.
+    fn commit(&mut self) {}
+    fn revert(&mut self) {}
+}
+
+// JournalEntry is the undo record of a write of LocalHost, kept in the Stack
+// so that it is saved with it, and a resumed execution can still be reverted
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum JournalEntry {
+    // the account did not exist before the write
+    Created([u8; 20]),
+    // previous value of the slot, and whether the write recorded its
+    // original value
+    Storage {
+        address: [u8; 20],
+        key: [u8; 32],
+        previous: [u8; 32],
+        first_write: bool,
+    },
+    Balance {
+        address: [u8; 20],
+        previous: BigUint,
+    },
+    Log,
+    AccessedAccount([u8; 20]),
+    AccessedStorage([u8; 20], [u8; 32]),
+    Destructed([u8; 20]),
+}
+
+// LocalHost is the host of a Stack without one set, over its world state and
+// substate
+pub struct LocalHost<'a> {
+    pub state: &'a mut state::State,
+    pub storage_committed: &'a mut HashMap<([u8; 20], [u8; 32]), [u8; 32]>,
+    pub accessed_storage: &'a mut HashSet<([u8; 20], [u8; 32])>,
+    pub accessed_accounts: &'a mut HashSet<[u8; 20]>,
+    pub logs: &'a mut Vec<block::Log>,
+    pub destructed: &'a mut HashSet<[u8; 20]>,
+    pub journal: &'a mut Vec<JournalEntry>,
+    pub origin: [u8; 20],
+    pub gas_price: &'a BigUint,
+    pub blob_hashes: &'a [[u8; 32]],
+    pub block: &'a transaction::BlockEnv,
+}
+
+impl LocalHost<'_> {
+    // touch journals the creation of the account by the next write
+    fn touch(&mut self, address: &[u8; 20]) {
+        if self.state.get(address).is_none() {
+            self.journal.push(JournalEntry::Created(*address));
+        }
+    }
+}
+
+impl Host for LocalHost<'_> {
+    fn account_exists(&self, address: &[u8; 20]) -> bool {
+        self.state.get(address).is_some()
+    }
+    fn get_storage(&self, address: &[u8; 20], key: &[u8; 32]) -> [u8; 32] {
+        self.state.storage(address, key)
+    }
+    fn set_storage(
+        &mut self,
+        address: &[u8; 20],
+        key: &[u8; 32],
+        value: [u8; 32],
+    ) -> StorageStatus {
+        let current = self.state.storage(address, key);
+        // the original value is the one at the beginning of the transaction,
+        // which is the current one until the slot is first written
+        let first_write = !self.storage_committed.contains_key(&(*address, *key));
+        let original = *self
+            .storage_committed
+            .entry((*address, *key))
+            .or_insert(current);
+        self.touch(address);
+        self.journal.push(JournalEntry::Storage {
+            address: *address,
+            key: *key,
+            previous: current,
+            first_write,
+        });
+        self.state.set_storage(address, key, value);
+        StorageStatus::new(&original, &current, &value)
+    }
+    fn get_balance(&self, address: &[u8; 20]) -> BigUint {
+        self.state.balance(address)
+    }
+    fn get_code_size(&self, address: &[u8; 20]) -> usize {
+        self.state.get(address).map_or(0, |a| a.code.len())
+    }
+    fn get_code_hash(&self, address: &[u8; 20]) -> [u8; 32] {
+        match self.state.get(address) {
+            Some(a) if !a.is_empty() => a.code_hash(),
+            _ => [0; 32],
+        }
+    }
+    fn copy_code(&self, address: &[u8; 20], offset: usize, buffer: &mut [u8]) -> usize {
+        let code = self.state.code(address);
+        if offset >= code.len() {
+            return 0;
+        }
+        let n = buffer.len().min(code.len() - offset);
+        buffer[..n].copy_from_slice(&code[offset..offset + n]);
+        n
+    }
+    // selfdestruct moves the balance to the beneficiary, and marks the
+    // account as destructed
+    fn selfdestruct(&mut self, address: &[u8; 20], beneficiary: &[u8; 20]) -> bool {
+        let balance = self.state.balance(address);
+        for a in [address, beneficiary].iter() {
+            self.touch(a);
+            self.journal.push(JournalEntry::Balance {
+                address: **a,
+                previous: self.state.balance(a),
+            });
+        }
+        // can not fail, the balance being the one of the account
+        let _ = self.state.transfer(address, beneficiary, &balance);
+        if !self.destructed.insert(*address) {
+            return false;
+        }
+        self.journal.push(JournalEntry::Destructed(*address));
+        true
+    }
+    fn call(&mut self, _msg: &Message) -> CallResult {
+        CallResult {
+            error: Some("calls are not supported by the local host".to_string()),
+            ..CallResult::default()
+        }
+    }
+    fn get_tx_context(&self) -> TxContext {
+        TxContext {
+            origin: self.origin,
+            gas_price: self.gas_price.clone(),
+            blob_hashes: self.blob_hashes.to_vec(),
+            block: self.block.clone(),
+        }
+    }
+    // get_block_hash returns the hash given in the block environment, zero
+    // for the unknown blocks
+    fn get_block_hash(&self, number: u64) -> [u8; 32] {
+        self.block
+            .block_hashes
+            .get(&number)
+            .copied()
+            .unwrap_or([0; 32])
+    }
+    fn emit_log(&mut self, address: &[u8; 20], topics: &[[u8; 32]], data: &[u8]) {
+        self.journal.push(JournalEntry::Log);
+        self.logs.push(block::Log {
+            address: *address,
+            topics: topics.to_vec(),
+            data: data.to_vec(),
+        });
+    }
+    fn access_account(&mut self, address: &[u8; 20]) -> AccessStatus {
+        if self.accessed_accounts.insert(*address) {
+            self.journal.push(JournalEntry::AccessedAccount(*address));
+            return AccessStatus::Cold;
+        }
+        AccessStatus::Warm
+    }
+    fn access_storage(&mut self, address: &[u8; 20], key: &[u8; 32]) -> AccessStatus {
+        if self.accessed_storage.insert((*address, *key)) {
+            self.journal
+                .push(JournalEntry::AccessedStorage(*address, *key));
+            return AccessStatus::Cold;
+        }
+        AccessStatus::Warm
+    }
+    fn commit(&mut self) {
+        self.journal.clear();
+    }
+    // revert undoes the journaled writes, from the last one
+    fn revert(&mut self) {
+        while let Some(entry) = self.journal.pop() {
+            match entry {
+                JournalEntry::Created(address) => {
+                    self.state.accounts.remove(&address);
+                }
+                JournalEntry::Storage {
+                    address,
+                    key,
+                    previous,
+                    first_write,
+                } => {
+                    self.state.set_storage(&address, &key, previous);
+                    if first_write {
+                        self.storage_committed.remove(&(address, key));
+                    }
+                }
+                JournalEntry::Balance { address, previous } => {
+                    self.state.account(&address).balance = previous;
+                }
+                JournalEntry::Log => {
+                    self.logs.pop();
+                }
+                JournalEntry::AccessedAccount(address) => {
+                    self.accessed_accounts.remove(&address);
+                }
+                JournalEntry::AccessedStorage(address, key) => {
+                    self.accessed_storage.remove(&(address, key));
+                }
+                JournalEntry::Destructed(address) => {
+                    self.destructed.remove(&address);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+// Resumable execution: the Interpreter holds the machine together with the code
+// and the calldata it executes, and is serializable between two steps, so that
+// a long execution can be checkpointed to disk and resumed later.
+//   let mut i = Interpreter::new(vm, &code, &calldata);
+//   i.run_until(|i| i.steps == 1000)?;
+//   i.save(path)?;
+//   let output = Interpreter::load(path)?.run()?;
+// The saved state is the frame (code, calldata, pc, stack, memory, gas and
+// return data), the world state and the substate of the Stack (refund, warm
+// slots, original values and logs), and the journal of the writes made so
+// far, undone when the execution fails, even after being resumed. There is a
+// single frame, as the local host makes no nested calls (see host.rs): a
+// call to a precompile runs within the step of its CALL.
+use super::*;
+
+#[derive(Serialize, Deserialize)]
+pub struct Interpreter {
+    pub vm: Stack,
+    pub code: Vec<u8>,
+    pub calldata: Vec<u8>,
+    // number of instructions executed
+    pub steps: u64,
+    // output, or error, once the execution has halted
+    pub result: Option<Result<Vec<u8>, String>>,
+}
+
+impl Interpreter {
+    pub fn new(vm: Stack, code: &[u8], calldata: &[u8]) -> Interpreter {
+        Interpreter {
+            vm,
+            code: code.to_vec(),
+            calldata: calldata.to_vec(),
+            steps: 0,
+            result: None,
+        }
+    }
+
+    pub fn halted(&self) -> bool {
+        self.result.is_some()
+    }
+
+    // step executes the next instruction, or halts at the end of the code
+    pub fn step(&mut self) -> Result<(), String> {
+        if self.halted() {
+            return Err("execution halted".to_string());
+        }
+        if self.vm.pc >= self.code.len() {
+            self.result = Some(Ok(Vec::new()));
+            self.vm.with_host(|h| h.commit());
+            return Ok(());
+        }
+        match self.vm.step(&self.code, &self.calldata) {
+            Ok(Some(output)) => self.result = Some(Ok(output)),
+            Ok(None) => {}
+            Err(e) => self.result = Some(Err(e)),
+        }
+        self.steps += 1;
+        // as Stack::execute, keeps or undoes the writes once halted
+        match self.result {
+            Some(Ok(_)) => self.vm.with_host(|h| h.commit()),
+            Some(Err(_)) => self.vm.with_host(|h| h.revert()),
+            None => {}
+        }
+        Ok(())
+    }
+
+    // run_until executes until the execution halts, returning true, or until
+    // `stop` returns true before an instruction
+    pub fn run_until<F>(&mut self, mut stop: F) -> Result<bool, String>
+    where
+        F: FnMut(&Interpreter) -> bool,
+    {
+        while !self.halted() {
+            if stop(self) {
+                return Ok(false);
+            }
+            self.step()?;
+        }
+        Ok(true)
+    }
+
+    // run executes until the end, returning the output
+    pub fn run(&mut self) -> Result<Vec<u8>, String> {
+        self.run_until(|_| false)?;
+        self.result.clone().unwrap_or_else(|| Ok(Vec::new()))
+    }
+
+    pub fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string(self).map_err(|e| e.to_string())
+    }
+
+    pub fn from_json(s: &str) -> Result<Interpreter, String> {
+        serde_json::from_str(s).map_err(|e| format!("interpreter: {}", e))
+    }
+
+    // save writes the interpreter to the file, as JSON
+    pub fn save(&self, path: &std::path::Path) -> Result<(), String> {
+        std::fs::write(path, self.to_json()?).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn load(path: &std::path::Path) -> Result<Interpreter, String> {
+        let s = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        Interpreter::from_json(&s)
+    }
+}
@@ -0,0 +1,276 @@
+// JSON helpers for the hex encoded quantities and data used by the test
+// fixtures and geth's tools
+use super::*;
+use num_bigint::BigUint;
+use serde_json::{Map, Value};
+use state::{Account, State};
+use transaction::{AccessList, Authorization};
+
+// entries (de)serializes a map as a list of [key, value] pairs, for the maps
+// whose keys are not strings: #[serde(with = "json::entries")]
+pub mod entries {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use std::collections::HashMap;
+    use std::hash::Hash;
+
+    pub fn serialize<K, V, S>(map: &HashMap<K, V>, s: S) -> Result<S::Ok, S::Error>
+    where
+        K: Serialize,
+        V: Serialize,
+        S: Serializer,
+    {
+        s.collect_seq(map.iter())
+    }
+
+    pub fn deserialize<'de, K, V, D>(d: D) -> Result<HashMap<K, V>, D::Error>
+    where
+        K: Deserialize<'de> + Eq + Hash,
+        V: Deserialize<'de>,
+        D: Deserializer<'de>,
+    {
+        let entries: Vec<(K, V)> = Vec::deserialize(d)?;
+        Ok(entries.into_iter().collect())
+    }
+}
+
+fn as_str(v: &Value) -> Result<&str, String> {
+    v.as_str()
+        .ok_or_else(|| format!("json: expected string, found {}", v))
+}
+
+// parse_biguint accepts hex (0x prefixed) and decimal strings, and numbers
+pub fn parse_biguint(v: &Value) -> Result<BigUint, String> {
+    if let Some(n) = v.as_u64() {
+        return Ok(BigUint::from(n));
+    }
+    let s = as_str(v)?;
+    let r = match s.strip_prefix("0x") {
+        Some("") => Some(BigUint::default()),
+        Some(h) => BigUint::parse_bytes(h.as_bytes(), 16),
+        None => BigUint::parse_bytes(s.as_bytes(), 10),
+    };
+    r.ok_or_else(|| format!("json: invalid number {}", s))
+}
+
+pub fn parse_u64(v: &Value) -> Result<u64, String> {
+    let n = parse_biguint(v)?;
+    let d = n.to_u64_digits();
+    if d.len() > 1 {
+        return Err(format!("json: number overflows u64 {}", v));
+    }
+    Ok(d.first().copied().unwrap_or(0))
+}
+
+// parse_bytes decodes 0x prefixed hex data. The ":raw " prefix of the test
+// fillers is accepted too.
+pub fn parse_bytes(v: &Value) -> Result<Vec<u8>, String> {
+    let s = as_str(v)?;
+    let s = s.strip_prefix(":raw ").unwrap_or(s);
+    let h = s.strip_prefix("0x").unwrap_or(s);
+    hex::decode(h).map_err(|_| format!("json: invalid hex {}", s))
+}
+
+pub fn parse_address(v: &Value) -> Result<[u8; 20], String> {
+    let b = parse_bytes(v)?;
+    if b.len() != 20 {
+        return Err(format!("json: invalid address {}", v));
+    }
+    let mut a: [u8; 20] = [0; 20];
+    a.copy_from_slice(&b);
+    Ok(a)
+}
+
+// parse_word parses a 32 byte value, such as a hash or a storage key, given
+// either as hex data or as a number
+pub fn parse_word(v: &Value) -> Result<[u8; 32], String> {
+    let b = parse_biguint(v)?.to_bytes_be();
+    if b.len() > 32 {
+        return Err(format!("json: value overflows 32 bytes {}", v));
+    }
+    let mut w: [u8; 32] = [0; 32];
+    w[32 - b.len()..].copy_from_slice(&b);
+    Ok(w)
+}
+
+pub fn hex_u64(n: u64) -> String {
+    format!("0x{:x}", n)
+}
+
+pub fn hex_biguint(n: &BigUint) -> String {
+    format!("0x{:x}", n)
+}
+
+pub fn hex_bytes(b: &[u8]) -> String {
+    format!("0x{}", hex::encode(b))
+}
+
+// parse_alloc parses the accounts of a state, in the format used by the test
+// fixtures and geth's genesis alloc: address => {balance, nonce, code,
+// storage}, all fields optional
+pub fn parse_alloc(v: &Value) -> Result<State, String> {
+    let accounts = v
+        .as_object()
+        .ok_or_else(|| "json: alloc must be an object".to_string())?;
+    let mut state = State::new();
+    for (address, a) in accounts.iter() {
+        let address = parse_address(&Value::String(address.clone()))?;
+        let mut account = Account::default();
+        if let Some(balance) = a.get("balance") {
+            account.balance = parse_biguint(balance)?;
+        }
+        if let Some(nonce) = a.get("nonce") {
+            account.nonce = parse_u64(nonce)?;
+        }
+        if let Some(code) = a.get("code") {
+            account.code = parse_bytes(code)?;
+        }
+        if let Some(storage) = a.get("storage").and_then(|s| s.as_object()) {
+            for (key, value) in storage.iter() {
+                let value = parse_word(value)?;
+                if value != [0; 32] {
+                    account
+                        .storage
+                        .insert(parse_word(&Value::String(key.clone()))?, value);
+                }
+            }
+        }
+        state.accounts.insert(address, account);
+    }
+    Ok(state)
+}
+
+// parse_dump parses a state dump: geth's `dump` output, as an object
+// {root, accounts} or one account per line with its address (--iterative),
+// anvil's dumped state {block, accounts, ...}, or a plain alloc.
+// geth only knows the address of an account from the preimage of its hashed
+// key, and dumps the accounts without one as pre(<key>), or without address
+// when iterative: such dumps are rejected, as are the accounts whose code
+// does not match their codeHash, as dumped with --nocode.
+pub fn parse_dump(data: &str) -> Result<State, String> {
+    let accounts = match serde_json::from_str::<Value>(data) {
+        Ok(mut v) if v.get("accounts").is_some() => v["accounts"].take(),
+        Ok(v) => v,
+        Err(_) => {
+            let mut accounts = Map::new();
+            for line in data.lines().filter(|l| !l.trim().is_empty()) {
+                let v: Value = serde_json::from_str(line).map_err(|e| format!("json: {}", e))?;
+                let address = match (v.get("address"), v.get("key")) {
+                    (Some(Value::String(address)), _) => address.clone(),
+                    (None, Some(Value::String(key))) => format!("pre({})", key),
+                    // the line of the state root
+                    _ => continue,
+                };
+                accounts.insert(address, v);
+            }
+            Value::Object(accounts)
+        }
+    };
+    let missing: Vec<&str> = accounts
+        .as_object()
+        .into_iter()
+        .flat_map(|a| a.keys())
+        .filter_map(|k| k.strip_prefix("pre(")?.strip_suffix(')'))
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!(
+            "dump: {} accounts without address, whose keys have no preimage (first {}): \
+             dump from a node recording them (geth --cache.preimages)",
+            missing.len(),
+            missing[0]
+        ));
+    }
+    let state = parse_alloc(&accounts)?;
+    for (address, a) in accounts.as_object().into_iter().flatten() {
+        if let Some(code_hash) = a.get("codeHash") {
+            let address = parse_address(&Value::String(address.clone()))?;
+            if state.accounts[&address].code_hash() != parse_word(code_hash)? {
+                return Err(format!(
+                    "dump: code of {} does not match its codeHash",
+                    hex_bytes(&address)
+                ));
+            }
+        }
+    }
+    Ok(state)
+}
+
+// alloc_to_json is the inverse of parse_alloc, omitting the empty fields
+pub fn alloc_to_json(state: &State) -> Value {
+    let mut addresses: Vec<&[u8; 20]> = state.accounts.keys().collect();
+    addresses.sort();
+    let mut alloc = Map::new();
+    for address in addresses {
+        let account = &state.accounts[address];
+        let mut a = Map::new();
+        if !account.code.is_empty() {
+            a.insert("code".to_string(), Value::from(hex_bytes(&account.code)));
+        }
+        if !account.storage.is_empty() {
+            let mut keys: Vec<&[u8; 32]> = account.storage.keys().collect();
+            keys.sort();
+            let mut storage = Map::new();
+            for key in keys {
+                storage.insert(
+                    hex_bytes(key),
+                    Value::from(hex_bytes(&account.storage[key])),
+                );
+            }
+            a.insert("storage".to_string(), Value::Object(storage));
+        }
+        a.insert(
+            "balance".to_string(),
+            Value::from(hex_biguint(&account.balance)),
+        );
+        if account.nonce != 0 {
+            a.insert("nonce".to_string(), Value::from(hex_u64(account.nonce)));
+        }
+        alloc.insert(hex_bytes(address), Value::Object(a));
+    }
+    Value::Object(alloc)
+}
+
+// log_to_json returns the address, topics and data of the log, to which the
+// callers add the block and transaction fields
+pub fn log_to_json(log: &block::Log) -> Value {
+    let topics: Vec<Value> = log
+        .topics
+        .iter()
+        .map(|t| Value::from(hex_bytes(t)))
+        .collect();
+    let mut l = Map::new();
+    l.insert("address".to_string(), Value::from(hex_bytes(&log.address)));
+    l.insert("topics".to_string(), Value::Array(topics));
+    l.insert("data".to_string(), Value::from(hex_bytes(&log.data)));
+    Value::Object(l)
+}
+
+// parse_access_list parses [{address, storageKeys}]
+pub fn parse_access_list(v: &Value) -> Result<AccessList, String> {
+    let entries = v
+        .as_array()
+        .ok_or_else(|| "access list must be an array".to_string())?;
+    let mut access_list = Vec::new();
+    for entry in entries.iter() {
+        let keys = match entry["storageKeys"].as_array() {
+            Some(keys) => keys.iter().map(parse_word).collect::<Result<_, _>>()?,
+            None => Vec::new(),
+        };
+        access_list.push((parse_address(&entry["address"])?, keys));
+    }
+    Ok(access_list)
+}
+
+pub fn parse_authorization(v: &Value) -> Result<Authorization, String> {
+    let y_parity = match v.get("yParity") {
+        Some(y) => parse_u64(y)?,
+        None => parse_u64(&v["v"])?,
+    };
+    Ok(Authorization {
+        chain_id: parse_biguint(&v["chainId"])?,
+        address: parse_address(&v["address"])?,
+        nonce: parse_u64(&v["nonce"])?,
+        y_parity: y_parity.min(255) as u8,
+        r: parse_biguint(&v["r"])?,
+        s: parse_biguint(&v["s"])?,
+    })
+}
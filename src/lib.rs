@@ -1,37 +1,141 @@
 #![allow(dead_code)]
 
-use std::collections::HashMap;
+use num_bigint::BigUint;
+use num_traits::identities::Zero;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+use std::collections::{HashMap, HashSet};
+pub mod asm;
+pub mod block;
+pub mod blocktest;
+pub mod breakpoint;
+pub mod db;
+pub mod debugger;
+pub mod devnet;
+pub mod envelope;
+pub mod evmc;
+pub mod genesis;
+pub mod host;
+pub mod interpreter;
+pub mod json;
 pub mod opcodes;
+pub mod precompiles;
+pub mod proof;
+pub mod recording;
+pub mod rlp;
+pub mod rpc;
+pub mod signature;
+pub mod simulate;
+pub mod spec;
+pub mod state;
+pub mod statetest;
+pub mod t8n;
+pub mod trace;
+pub mod tracers;
+pub mod transaction;
+pub mod trie;
+#[cfg(feature = "tui")]
+pub mod tui;
 pub mod u256;
 
-#[derive(Default)]
+// Stack is the machine state of the interpreter, serializable so that an
+// execution can be saved and resumed (see interpreter::Interpreter). The
+// opcode table and the precompiles are not part of it, being derived from the
+// config.
+#[derive(Default, Serialize, Deserialize)]
 pub struct Stack {
     pub pc: usize,
     pub calldata_i: usize,
     pub calldata_size: usize,
     pub stack: Vec<[u8; 32]>,
-    pub storage_committed: HashMap<[u8; 32], Vec<u8>>,
-    pub storage: HashMap<[u8; 32], Vec<u8>>,
+    // account whose code is being executed
+    pub address: [u8; 20],
+    // world state, where the storage of the executing account is read from
+    // and written to
+    pub state: state::State,
+    // storage values at the beginning of the transaction, for the slots
+    // written by it (EIP-2200)
+    #[serde(with = "json::entries")]
+    pub storage_committed: HashMap<([u8; 20], [u8; 32]), [u8; 32]>,
+    // slots accessed in the transaction (EIP-2929)
+    pub accessed_storage: HashSet<([u8; 20], [u8; 32])>,
+    pub accessed_accounts: HashSet<[u8; 20]>,
+    // accounts destructed by SELFDESTRUCT, deleted at the end of the
+    // transaction before Cancun
+    pub destructed: HashSet<[u8; 20]>,
+    // refund counter, applied at the end of the transaction
+    pub refund: i64,
+    // message and block context
+    pub caller: [u8; 20],
+    pub origin: [u8; 20],
+    // effective gas price and blob hashes of the transaction
+    pub gas_price: BigUint,
+    pub blob_hashes: Vec<[u8; 32]>,
+    pub value: [u8; 32],
+    // static call, in which the state can not be modified (EIP-214)
+    pub is_static: bool,
+    // call depth of the frame, 0 for the one of the transaction
+    pub depth: usize,
+    pub block: transaction::BlockEnv,
+    // called after each executed instruction
+    #[serde(skip)]
+    pub tracer: Option<trace::Tracer>,
+    // whether the steps given to the tracer hold a copy of the memory
+    #[serde(skip)]
+    pub trace_memory: bool,
+    // host of the external effects, the world state above when not set
+    #[serde(skip)]
+    pub host: Option<Box<dyn host::Host>>,
+    pub logs: Vec<block::Log>,
+    // output of the last call made by the executing code (EIP-211)
+    pub return_data: Vec<u8>,
+    // undo records of the writes to the world state and substate above,
+    // until the execution ends
+    pub journal: Vec<host::JournalEntry>,
     pub mem: Vec<u8>,
     pub gas: u64,
-    pub opcodes: HashMap<u8, opcodes::Opcode>,
+    pub config: spec::Config,
 }
 
 impl Stack {
     pub fn new() -> Stack {
-        let mut s = Stack {
+        Stack::with_config(spec::Config::default())
+    }
+    pub fn with_config(config: spec::Config) -> Stack {
+        Stack {
             pc: 0,
             calldata_i: 0,
             calldata_size: 32,
             stack: Vec::new(),
+            address: [0; 20],
+            state: state::State::new(),
             storage_committed: HashMap::new(),
-            storage: HashMap::new(),
+            accessed_storage: HashSet::new(),
+            accessed_accounts: HashSet::new(),
+            destructed: HashSet::new(),
+            refund: 0,
+            caller: [0; 20],
+            origin: [0; 20],
+            gas_price: BigUint::zero(),
+            blob_hashes: Vec::new(),
+            value: [0; 32],
+            is_static: false,
+            depth: 0,
+            block: transaction::BlockEnv::default(),
+            tracer: None,
+            trace_memory: false,
+            host: None,
+            logs: Vec::new(),
+            return_data: Vec::new(),
+            journal: Vec::new(),
             mem: Vec::new(),
             gas: 10000000000,
-            opcodes: HashMap::new(),
-        };
-        s.opcodes = opcodes::new_opcodes();
-        s
+            config,
+        }
+    }
+    // precompiles returns the precompiles enabled by the config
+    pub fn precompiles(&self) -> HashMap<[u8; 20], precompiles::Precompile> {
+        precompiles::new_precompiles(&self.config)
     }
     pub fn print_stack(&self) {
         println!("stack ({}):", self.stack.len());
@@ -47,9 +151,13 @@ impl Stack {
         }
     }
     pub fn print_storage(&self) {
-        if !self.storage.is_empty() {
-            println!("storage ({}):", self.storage.len());
-            for (key, value) in self.storage.iter() {
+        let storage = match self.state.get(&self.address) {
+            Some(account) => &account.storage,
+            None => return,
+        };
+        if !storage.is_empty() {
+            println!("storage ({}):", storage.len());
+            for (key, value) in storage.iter() {
                 println!(
                     "{:?}: {:?}",
                     vec_u8_to_hex(key.to_vec()),
@@ -62,9 +170,9 @@ impl Stack {
         self.stack.push(b);
     }
     // push_arbitrary performs a push, but first converting the arbitrary-length
-    // input into a 32 byte array
+    // input into a 32 byte array, keeping the low 256 bits of longer inputs
     pub fn push_arbitrary(&mut self, b: &[u8]) {
-        // TODO if b.len()>32 return error
+        let b = &b[b.len().saturating_sub(32)..];
         let mut d: [u8; 32] = [0; 32];
         d[32 - b.len()..].copy_from_slice(b);
         self.stack.push(d);
@@ -89,6 +197,44 @@ impl Stack {
         }
         Ok(self.stack[self.stack.len() - 1])
     }
+    // with_host calls f with the host, or with a LocalHost over the world
+    // state when none is set
+    pub fn with_host<R>(&mut self, f: impl FnOnce(&mut dyn host::Host) -> R) -> R {
+        match self.host.as_deref_mut() {
+            Some(h) => f(h),
+            None => f(&mut host::LocalHost {
+                state: &mut self.state,
+                storage_committed: &mut self.storage_committed,
+                accessed_storage: &mut self.accessed_storage,
+                accessed_accounts: &mut self.accessed_accounts,
+                destructed: &mut self.destructed,
+                logs: &mut self.logs,
+                journal: &mut self.journal,
+                origin: self.origin,
+                gas_price: &self.gas_price,
+                blob_hashes: &self.blob_hashes,
+                block: &self.block,
+            }),
+        }
+    }
+    // get_storage returns the value of the slot of the executing account
+    pub fn get_storage(&self, key: &[u8; 32]) -> [u8; 32] {
+        match &self.host {
+            Some(h) => h.get_storage(&self.address, key),
+            None => self.state.storage(&self.address, key),
+        }
+    }
+    // set_storage writes the slot of the executing account, outside of the
+    // execution (the original value of the slot is not recorded)
+    pub fn set_storage(&mut self, key: &[u8; 32], value: [u8; 32]) {
+        let address = self.address;
+        match self.host.as_deref_mut() {
+            Some(h) => {
+                h.set_storage(&address, key, value);
+            }
+            None => self.state.set_storage(&address, key, value),
+        }
+    }
     pub fn substract_gas(&mut self, val: u64) -> Result<(), String> {
         if self.gas < val {
             return Err("out of gas".to_string());
@@ -97,142 +243,320 @@ impl Stack {
         Ok(())
     }
 
+    // call_precompile runs the precompile at the given address with the gas left
+    // in the stack. A failing precompile consumes all the gas.
+    pub fn call_precompile(&mut self, address: &[u8; 20], input: &[u8]) -> Result<Vec<u8>, String> {
+        let precompiles = self.precompiles();
+        let precompile = match precompiles.get(address) {
+            Some(p) => p,
+            None => {
+                return Err(format!(
+                    "no precompile at {}",
+                    vec_u8_to_hex(address.to_vec())
+                ))
+            }
+        };
+        match (precompile.run)(input, self.gas) {
+            Ok((gas_used, out)) => {
+                self.gas -= gas_used;
+                Ok(out)
+            }
+            Err(e) => {
+                self.gas = 0;
+                Err(e)
+            }
+        }
+    }
+
+    // execute runs the code until it halts, committing the writes made
+    // through the host, or reverting them on failure
     pub fn execute(
         &mut self,
         code: &[u8],
         calldata: &[u8],
         debug: bool,
+    ) -> Result<Vec<u8>, String> {
+        let r = self.execute_code(code, calldata, debug);
+        match r {
+            Ok(_) => self.with_host(|h| h.commit()),
+            Err(_) => self.with_host(|h| h.revert()),
+        }
+        r
+    }
+
+    fn execute_code(
+        &mut self,
+        code: &[u8],
+        calldata: &[u8],
+        debug: bool,
     ) -> Result<Vec<u8>, String> {
         self.pc = 0;
         self.calldata_i = 0;
         let l = code.len();
 
         while self.pc < l {
-            let opcode = code[self.pc];
-            if !self.opcodes.contains_key(&opcode) {
-                return Err(format!("invalid opcode {:x}", opcode));
-            }
-
             if debug {
-                println!(
-                    "{} (0x{:x}): pc={:?} gas={:?}",
-                    self.opcodes.get(&opcode).unwrap().name,
-                    opcode,
-                    self.pc,
-                    self.gas,
-                );
+                let opcode = code[self.pc];
+                if let Some(op) = opcodes::table().get(&opcode) {
+                    println!(
+                        "{} (0x{:x}): pc={:?} gas={:?}",
+                        op.name, opcode, self.pc, self.gas,
+                    );
+                }
                 self.print_stack();
                 self.print_memory();
                 self.print_storage();
                 println!();
             }
-            match opcode & 0xf0 {
-                0x00 => {
-                    // arithmetic
-                    match opcode {
-                        0x00 => {
-                            println!("0x00: STOP");
-                            return Ok(Vec::new());
-                        }
-                        0x01 => self.add()?,
-                        0x02 => self.mul()?,
-                        0x03 => self.sub()?,
-                        0x04 => self.div()?,
-                        0x05 => self.sdiv()?,
-                        0x06 => self.modulus()?,
-                        0x07 => self.smod()?,
-                        0x08 => self.add_mod()?,
-                        0x09 => self.mul_mod()?,
-                        0x0a => self.exp()?,
-                        // 0x0b => self.sign_extend(),
-                        _ => return Err(format!("unimplemented {:x}", opcode)),
-                    }
-                    self.pc += 1;
+            if let Some(output) = self.step(code, calldata)? {
+                if debug && output.is_empty() && code.get(self.pc) == Some(&0x00) {
+                    println!("0x00: STOP");
                 }
-                0x10 => {
-                    // arithmetic
-                    match opcode {
-                        0x10 => self.lt()?,
-                        0x11 => self.gt()?,
-                        // 0x12 => self.slt()?,
-                        // 0x13 => self.sgt()?,
-                        0x14 => self.eq()?,
-                        0x15 => self.is_zero()?,
-                        0x16 => self.and()?,
-                        0x17 => self.or()?,
-                        0x18 => self.xor()?,
-                        0x19 => self.not()?,
-                        // 0x1a => self.byte()?,
-                        _ => return Err(format!("unimplemented {:x}", opcode)),
-                    }
-                    self.pc += 1;
+                return Ok(output);
+            }
+        }
+        Ok(Vec::new())
+    }
+
+    // step executes the instruction at pc, calling the tracer. Returns the
+    // output when the execution halts.
+    pub fn step(&mut self, code: &[u8], calldata: &[u8]) -> Result<Option<Vec<u8>>, String> {
+        // running past the end of the code is a STOP
+        let opcode = match code.get(self.pc) {
+            Some(opcode) => *opcode,
+            None => return Ok(Some(Vec::new())),
+        };
+        let storage = match (opcode, self.stack.last()) {
+            _ if self.tracer.is_none() => None,
+            (0x54, Some(key)) => Some((*key, self.get_storage(key))),
+            (0x55, Some(key)) if self.stack.len() > 1 => {
+                Some((*key, self.stack[self.stack.len() - 2]))
+            }
+            _ => None,
+        };
+        let before = self.tracer.as_ref().map(|_| trace::Step {
+            pc: self.pc,
+            op: opcode,
+            gas: self.gas,
+            gas_cost: 0,
+            mem_size: self.mem.len(),
+            stack: self.stack.clone(),
+            depth: self.depth + 1,
+            refund: self.refund,
+            op_name: opcodes::table()
+                .get(&opcode)
+                .map(|o| o.name.clone())
+                .unwrap_or_else(|| format!("opcode 0x{:x}", opcode)),
+            error: None,
+            memory: match self.trace_memory {
+                true => self.mem.clone(),
+                false => Vec::new(),
+            },
+            storage,
+        });
+        let r = self.exec_opcode(code, calldata, opcode);
+        if let (Some(mut step), Some(tracer)) = (before, self.tracer.as_mut()) {
+            step.gas_cost = step.gas.saturating_sub(self.gas);
+            step.error = r.as_ref().err().cloned();
+            tracer(&step);
+        }
+        r
+    }
+
+    fn exec_opcode(
+        &mut self,
+        code: &[u8],
+        calldata: &[u8],
+        opcode: u8,
+    ) -> Result<Option<Vec<u8>>, String> {
+        if !opcodes::table().contains_key(&opcode) {
+            return Err(format!("invalid opcode {:x}", opcode));
+        }
+        match opcode & 0xf0 {
+            0x00 => {
+                // arithmetic
+                match opcode {
+                    0x00 => return Ok(Some(Vec::new())),
+                    0x01 => self.add()?,
+                    0x02 => self.mul()?,
+                    0x03 => self.sub()?,
+                    0x04 => self.div()?,
+                    0x05 => self.sdiv()?,
+                    0x06 => self.modulus()?,
+                    0x07 => self.smod()?,
+                    0x08 => self.add_mod()?,
+                    0x09 => self.mul_mod()?,
+                    0x0a => self.exp()?,
+                    // 0x0b => self.sign_extend(),
+                    _ => return Err(format!("unimplemented {:x}", opcode)),
                 }
-                0x30 => {
-                    match opcode {
-                        0x35 => self.calldata_load(&calldata)?,
-                        0x36 => self.calldata_size(&calldata),
-                        0x39 => self.code_copy(&code)?,
-                        _ => return Err(format!("unimplemented {:x}", opcode)),
-                    }
-                    self.pc += 1;
+                self.pc += 1;
+            }
+            0x10 => {
+                // arithmetic
+                match opcode {
+                    0x10 => self.lt()?,
+                    0x11 => self.gt()?,
+                    // 0x12 => self.slt()?,
+                    // 0x13 => self.sgt()?,
+                    0x14 => self.eq()?,
+                    0x15 => self.is_zero()?,
+                    0x16 => self.and()?,
+                    0x17 => self.or()?,
+                    0x18 => self.xor()?,
+                    0x19 => self.not()?,
+                    // 0x1a => self.byte()?,
+                    _ => return Err(format!("unimplemented {:x}", opcode)),
                 }
-                0x50 => {
-                    self.pc += 1;
-                    match opcode {
-                        0x51 => self.mload()?,
-                        0x52 => self.mstore()?,
-                        0x55 => self.sstore()?,
-                        0x56 => self.jump(code)?,
-                        0x57 => self.jump_i(code)?,
-                        0x5b => self.jump_dest()?,
-                        _ => return Err(format!("unimplemented {:x}", opcode)),
+                self.pc += 1;
+            }
+            0x30 => {
+                match opcode {
+                    0x30 => self.push_arbitrary(&self.address.clone()),
+                    0x32 => {
+                        let ctx = self.with_host(|h| h.get_tx_context());
+                        self.push_arbitrary(&ctx.origin)
+                    }
+                    0x33 => self.push_arbitrary(&self.caller.clone()),
+                    0x34 => self.push(self.value),
+                    0x35 => self.calldata_load(calldata)?,
+                    0x36 => self.calldata_size(calldata),
+                    0x3a => {
+                        let ctx = self.with_host(|h| h.get_tx_context());
+                        self.push_arbitrary(&ctx.gas_price.to_bytes_be())
                     }
+                    0x31 => self.balance()?,
+                    0x39 => self.code_copy(code)?,
+                    0x3b => self.ext_code_size()?,
+                    0x3c => self.ext_code_copy()?,
+                    0x3d => self.return_data_size()?,
+                    0x3e => self.return_data_copy()?,
+                    0x3f => self.ext_code_hash()?,
+                    _ => return Err(format!("unimplemented {:x}", opcode)),
                 }
-                0x60 | 0x70 => {
-                    // push
-                    let n = (opcode - 0x5f) as usize;
-                    self.push_arbitrary(&code[self.pc + 1..self.pc + 1 + n]);
-                    self.pc += 1 + n;
+                self.pc += 1;
+            }
+            0x40 => {
+                // block context
+                let block = self.with_host(|h| h.get_tx_context()).block;
+                match opcode {
+                    0x40 => self.block_hash(block.number)?,
+                    0x41 => self.push_arbitrary(&block.coinbase),
+                    0x42 => self.push(u256::u64_to_u256(block.timestamp)),
+                    0x43 => self.push(u256::u64_to_u256(block.number)),
+                    // DIFFICULTY, PREVRANDAO from Paris (EIP-4399)
+                    0x44 => self.push(block.prev_randao),
+                    0x45 => self.push(u256::u64_to_u256(block.gas_limit)),
+                    0x46 => self.push(u256::u64_to_u256(block.chain_id)),
+                    0x47 => self.self_balance(),
+                    0x48 => self.push_arbitrary(&block.base_fee.to_bytes_be()),
+                    0x49 => self.blob_hash()?,
+                    0x4a => self.push_arbitrary(&block.blob_base_fee.to_bytes_be()),
+                    _ => return Err(format!("unimplemented {:x}", opcode)),
                 }
-                0x80 => {
-                    // 0x8x dup
-                    let l = self.stack.len();
-                    if opcode > 0x7f {
-                        self.stack.push(self.stack[l - (opcode - 0x7f) as usize]);
-                    } else {
-                        self.stack.push(self.stack[(0x7f - opcode) as usize]);
-                    }
-                    self.pc += 1;
+                self.pc += 1;
+            }
+            0x50 => {
+                self.pc += 1;
+                match opcode {
+                    0x51 => self.mload()?,
+                    0x52 => self.mstore()?,
+                    0x54 => self.sload()?,
+                    0x55 => self.sstore()?,
+                    0x56 => self.jump(code)?,
+                    0x57 => self.jump_i(code)?,
+                    0x5b => self.jump_dest()?,
+                    _ => return Err(format!("unimplemented {:x}", opcode)),
                 }
-                0x90 => {
-                    // 0x9x swap
-                    let l = self.stack.len();
-                    let pos;
-                    if opcode > 0x8e {
-                        pos = l - (opcode - 0x8e) as usize;
-                    } else {
-                        pos = (0x8e - opcode) as usize;
-                    }
-                    self.stack.swap(pos, l - 1);
-                    self.pc += 1;
+            }
+            0x60 | 0x70 => {
+                // push, the data past the end of the code being zeros
+                let n = (opcode - 0x5f) as usize;
+                let start = (self.pc + 1).min(code.len());
+                let end = (self.pc + 1 + n).min(code.len());
+                let mut data = vec![0; n];
+                data[..end - start].copy_from_slice(&code[start..end]);
+                self.push_arbitrary(&data);
+                self.pc += 1 + n;
+            }
+            0x80 => {
+                // 0x8x dup
+                let n = (opcode - 0x7f) as usize;
+                let l = self.stack.len();
+                if l < n {
+                    return Err("stack underflow".to_string());
                 }
-                0xf0 => {
-                    if opcode == 0xf3 {
-                        let pos_to_return = u256::u256_to_u64(self.pop()?) as usize;
-                        let len_to_return = u256::u256_to_u64(self.pop()?) as usize;
-                        return Ok(self.mem[pos_to_return..pos_to_return + len_to_return].to_vec());
-                    }
+                self.stack.push(self.stack[l - n]);
+                self.pc += 1;
+            }
+            0x90 => {
+                // 0x9x swap
+                let n = (opcode - 0x8f) as usize;
+                let l = self.stack.len();
+                if l < n + 1 {
+                    return Err("stack underflow".to_string());
+                }
+                self.stack.swap(l - 1 - n, l - 1);
+                self.pc += 1;
+            }
+            0xa0 => {
+                if opcode > 0xa4 {
+                    return Err(format!("unimplemented {:x}", opcode));
                 }
-                _ => {
+                self.log((opcode - 0xa0) as usize)?;
+                self.pc += 1;
+            }
+            0xf0 => {
+                if opcode == 0xff {
+                    self.selfdestruct()?;
+                    return Ok(Some(Vec::new()));
+                }
+                if opcode == 0xf1 || opcode == 0xfa {
+                    self.call(opcode)?;
+                    self.pc += 1;
+                    return Ok(None);
+                }
+                // CREATE and the other message calls are not implemented
+                if opcode != 0xf3 {
                     return Err(format!("unimplemented {:x}", opcode));
                 }
+                let offset = self.pop()?;
+                let size = self.pop()?;
+                let (offset, size) = self.mem_range(offset, size)?;
+                return Ok(Some(self.mem[offset..offset + size].to_vec()));
+            }
+            _ => {
+                return Err(format!("unimplemented {:x}", opcode));
             }
-            self.substract_gas(self.opcodes.get(&opcode).unwrap().gas)?;
         }
-        Ok(Vec::new())
+        self.substract_gas(opcodes::table().get(&opcode).unwrap().gas)?;
+        Ok(None)
     }
 }
 pub fn vec_u8_to_hex(bytes: Vec<u8>) -> String {
     let strs: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
     strs.join("")
 }
+// catch_panic runs f, turning a panic into an error, so that a bug hit by
+// one test or request does not bring down the whole process
+pub fn catch_panic<R>(f: impl FnOnce() -> Result<R, String>) -> Result<R, String> {
+    match std::panic::catch_unwind(std::panic::AssertUnwindSafe(f)) {
+        Ok(r) => r,
+        Err(payload) => {
+            let msg = match (
+                payload.downcast_ref::<&str>(),
+                payload.downcast_ref::<String>(),
+            ) {
+                (Some(s), _) => s.to_string(),
+                (_, Some(s)) => s.clone(),
+                _ => "unknown".to_string(),
+            };
+            Err(format!("panic: {}", msg))
+        }
+    }
+}
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut r: [u8; 32] = [0; 32];
+    r.copy_from_slice(&Keccak256::digest(data));
+    r
+}
@@ -1,6 +1,9 @@
 use super::*;
+use host::StorageStatus;
 use num_bigint::BigUint;
 use num_traits::identities::Zero;
+use spec::Spec;
+use std::sync::OnceLock;
 
 // Non-opcode gas prices
 const GDEFAULT: usize = 1;
@@ -11,24 +14,43 @@ const GSTORAGEKILL: usize = 5000;
 const GSTORAGEMOD: usize = 5000;
 const GSTORAGEADD: usize = 20000;
 
-const NETSSTORENOOPGAS: u64 = 200;
-const NETSSTOREINITGAS: u64 = 20000;
-const NETSSTORECLEARREFUND: u64 = 15000;
-const NETSSTORERESETCLEARREFUND: u64 = 19800;
-const NETSSTORERESETREFUND: u64 = 4800;
-const NETSSTORECLEANGAS: u64 = 5000;
-const NETSSTOREDIRTYGAS: u64 = 200;
+// SLOAD costs before Berlin
+const SLOAD_GAS_FRONTIER: u64 = 50;
+const SLOAD_GAS_EIP150: u64 = 200;
+const SLOAD_GAS_EIP1884: u64 = 800;
+
+// EIP-2929 and EIP-3529 storage costs
+const COLD_SLOAD_COST: u64 = 2100;
+const WARM_STORAGE_READ_COST: u64 = 100;
+const SSTORE_SET_GAS: u64 = 20000;
+const SSTORE_RESET_GAS: u64 = 5000 - COLD_SLOAD_COST;
+const SSTORE_CLEARS_SCHEDULE: i64 = 4800;
+const SSTORE_SENTRY_GAS: u64 = 2300; // EIP-2200
+const COLD_ACCOUNT_ACCESS_COST: u64 = 2600;
+const WARM_ACCOUNT_ACCESS_COST: u64 = 100;
+const SELFDESTRUCT_GAS: u64 = 5000; // EIP-150
 
 const GEXPONENTBYTE: usize = 10; // cost of EXP exponent per byte
 const EXP_SUPPLEMENTAL_GAS: usize = 40;
 const GCOPY: usize = 3; // cost to copy one 32 byte word
-const GCONTRACTBYTE: usize = 200; // one byte of code in contract creation
+pub const GCONTRACTBYTE: usize = 200; // one byte of code in contract creation
 const GCALLVALUETRANSFER: usize = 9000; // non-zero-valued call
 const GLOGBYTE: usize = 8; // cost of a byte of logdata
 
-const GTXCOST: usize = 21000; // TX BASE GAS COST
-const GTXDATAZERO: usize = 4; // TX DATA ZERO BYTE GAS COST
-const GTXDATANONZERO: usize = 68; // TX DATA NON ZERO BYTE GAS COST
+pub const GTXCOST: usize = 21000; // TX BASE GAS COST
+pub const GTXCREATE: usize = 32000; // TX CONTRACT CREATION GAS COST (homestead)
+pub const GTXDATAZERO: usize = 4; // TX DATA ZERO BYTE GAS COST
+pub const GTXDATANONZERO: usize = 68; // TX DATA NON ZERO BYTE GAS COST
+pub const GTXDATANONZERO_EIP2028: usize = 16; // TX DATA NON ZERO BYTE GAS COST (istanbul)
+pub const GTXACCESSLISTADDRESS: usize = 2400; // EIP-2930 access list address
+pub const GTXACCESSLISTSTORAGEKEY: usize = 1900; // EIP-2930 access list storage key
+pub const GINITCODEWORD: usize = 2; // EIP-3860 cost per word of initcode
+pub const MAX_INITCODE_SIZE: usize = 49152; // EIP-3860
+pub const MAX_CODE_SIZE: usize = 24576; // EIP-170
+pub const PER_EMPTY_ACCOUNT_COST: usize = 25000; // EIP-7702 cost per authorization
+pub const PER_AUTH_BASE_COST: usize = 12500; // EIP-7702 cost per existing authority
+pub const STANDARD_TOKEN_COST: usize = 4; // EIP-7623 cost per calldata token
+pub const TOTAL_COST_FLOOR_PER_TOKEN: usize = 10; // EIP-7623
 const GSHA3WORD: usize = 6; // Cost of SHA3 per word
 const GSHA256BASE: usize = 60; // Base c of SHA256
 const GSHA256WORD: usize = 12; // Cost of SHA256 per word
@@ -41,6 +63,7 @@ const GECRECOVER: usize = 3000; // Cost of ecrecover op
 const GSTIPEND: usize = 2300;
 
 const GCALLNEWACCOUNT: usize = 25000;
+const CALL_DEPTH_LIMIT: usize = 1024;
 const GSUICIDEREFUND: usize = 24000;
 
 pub struct Opcode {
@@ -59,6 +82,62 @@ pub fn new_opcode(name: &str, ins: u32, outs: u32, gas: u64) -> Opcode {
     }
 }
 
+// sload_gas is the cost of SLOAD before Berlin, which is also the cost of an
+// SSTORE leaving a slot unchanged with EIP-1283 and EIP-2200
+fn sload_gas(spec: Spec) -> u64 {
+    match spec {
+        s if s >= Spec::Istanbul => SLOAD_GAS_EIP1884,
+        s if s >= Spec::TangerineWhistle => SLOAD_GAS_EIP150,
+        _ => SLOAD_GAS_FRONTIER,
+    }
+}
+
+// sstore_cost returns the gas and the refund of a write, without the cost of
+// a cold slot. Before Constantinople, and from Petersburg which removed
+// EIP-1283 until Istanbul brought it back as EIP-2200, they follow from the
+// current and new values only. Otherwise the write is net metered, from its
+// status, with the costs of EIP-2929 from Berlin and the refund of EIP-3529
+// from London.
+fn sstore_cost(
+    spec: Spec,
+    status: StorageStatus,
+    current: &[u8; 32],
+    value: &[u8; 32],
+) -> (u64, i64) {
+    if spec < Spec::Constantinople || spec == Spec::Petersburg {
+        return match (*current == [0; 32], *value == [0; 32]) {
+            (true, false) => (GSTORAGEADD as u64, 0),
+            (false, true) => (GSTORAGEKILL as u64, GSTORAGEREFUND as i64),
+            _ => (GSTORAGEMOD as u64, 0),
+        };
+    }
+    let (noop, reset) = match spec {
+        s if s >= Spec::Berlin => (WARM_STORAGE_READ_COST, SSTORE_RESET_GAS),
+        _ => (sload_gas(spec), GSTORAGEMOD as u64),
+    };
+    let clears = match spec {
+        s if s >= Spec::London => SSTORE_CLEARS_SCHEDULE,
+        _ => GSTORAGEREFUND as i64,
+    };
+    match status {
+        StorageStatus::Assigned => (noop, 0),
+        StorageStatus::Added => (SSTORE_SET_GAS, 0),
+        StorageStatus::Deleted => (reset, clears),
+        StorageStatus::Modified => (reset, 0),
+        StorageStatus::DeletedAdded => (noop, -clears),
+        StorageStatus::ModifiedDeleted => (noop, clears),
+        StorageStatus::DeletedRestored => (noop, (reset - noop) as i64 - clears),
+        StorageStatus::AddedDeleted => (noop, (SSTORE_SET_GAS - noop) as i64),
+        StorageStatus::ModifiedRestored => (noop, (reset - noop) as i64),
+    }
+}
+
+// table returns the opcodes known to the interpreter, built once
+pub fn table() -> &'static HashMap<u8, Opcode> {
+    static TABLE: OnceLock<HashMap<u8, Opcode>> = OnceLock::new();
+    TABLE.get_or_init(new_opcodes)
+}
+
 pub fn new_opcodes() -> HashMap<u8, Opcode> {
     let mut opcodes: HashMap<u8, Opcode> = HashMap::new();
 
@@ -94,7 +173,7 @@ pub fn new_opcodes() -> HashMap<u8, Opcode> {
 
     // contract context
     opcodes.insert(0x30, new_opcode("ADDRESS", 0, 1, 2));
-    opcodes.insert(0x31, new_opcode("BALANCE", 1, 1, 20));
+    opcodes.insert(0x31, new_opcode("BALANCE", 1, 1, 0));
     opcodes.insert(0x32, new_opcode("ORIGIN", 0, 1, 2));
     opcodes.insert(0x33, new_opcode("CALLER", 0, 1, 2));
     opcodes.insert(0x34, new_opcode("CALLVALUE", 0, 1, 2));
@@ -104,8 +183,11 @@ pub fn new_opcodes() -> HashMap<u8, Opcode> {
     opcodes.insert(0x38, new_opcode("CODESIZE", 0, 1, 2));
     opcodes.insert(0x39, new_opcode("CODECOPY", 3, 0, 3));
     opcodes.insert(0x3a, new_opcode("GASPRICE", 0, 1, 2));
-    opcodes.insert(0x3b, new_opcode("EXTCODESIZE", 1, 1, 20));
-    opcodes.insert(0x3c, new_opcode("EXTCODECOPY", 4, 0, 20));
+    opcodes.insert(0x3b, new_opcode("EXTCODESIZE", 1, 1, 0));
+    opcodes.insert(0x3c, new_opcode("EXTCODECOPY", 4, 0, 0));
+    opcodes.insert(0x3d, new_opcode("RETURNDATASIZE", 0, 1, 2));
+    opcodes.insert(0x3e, new_opcode("RETURNDATACOPY", 3, 0, 3));
+    opcodes.insert(0x3f, new_opcode("EXTCODEHASH", 1, 1, 0));
 
     // blockchain context
     opcodes.insert(0x40, new_opcode("BLOCKHASH", 1, 1, 20));
@@ -114,13 +196,18 @@ pub fn new_opcodes() -> HashMap<u8, Opcode> {
     opcodes.insert(0x43, new_opcode("NUMBER", 0, 1, 2));
     opcodes.insert(0x44, new_opcode("DIFFICULTY", 0, 1, 2));
     opcodes.insert(0x45, new_opcode("GASLIMIT", 0, 1, 2));
+    opcodes.insert(0x46, new_opcode("CHAINID", 0, 1, 2));
+    opcodes.insert(0x47, new_opcode("SELFBALANCE", 0, 1, 5));
+    opcodes.insert(0x48, new_opcode("BASEFEE", 0, 1, 2));
+    opcodes.insert(0x49, new_opcode("BLOBHASH", 1, 1, 3));
+    opcodes.insert(0x4a, new_opcode("BLOBBASEFEE", 0, 1, 2));
 
     // storage and execution
     opcodes.insert(0x50, new_opcode("POP", 1, 0, 2));
     opcodes.insert(0x51, new_opcode("MLOAD", 1, 1, 3));
     opcodes.insert(0x52, new_opcode("MSTORE", 2, 0, 3));
     opcodes.insert(0x53, new_opcode("MSTORE8", 2, 0, 3));
-    opcodes.insert(0x54, new_opcode("SLOAD", 1, 1, 50));
+    opcodes.insert(0x54, new_opcode("SLOAD", 1, 1, 0));
     opcodes.insert(0x55, new_opcode("SSTORE", 2, 0, 0));
     opcodes.insert(0x56, new_opcode("JUMP", 1, 0, 8));
     opcodes.insert(0x57, new_opcode("JUMPI", 2, 0, 10));
@@ -138,11 +225,12 @@ pub fn new_opcodes() -> HashMap<u8, Opcode> {
 
     // closures
     opcodes.insert(0xf0, new_opcode("CREATE", 3, 1, 32000));
-    opcodes.insert(0xf1, new_opcode("CALL", 7, 1, 40));
+    opcodes.insert(0xf1, new_opcode("CALL", 7, 1, 0));
     opcodes.insert(0xf2, new_opcode("CALLCODE", 7, 1, 40));
     opcodes.insert(0xf3, new_opcode("RETURN", 2, 0, 0));
     opcodes.insert(0xf4, new_opcode("DELEGATECALL", 6, 0, 40));
-    opcodes.insert(0xff, new_opcode("SUICIDE", 1, 0, 0));
+    opcodes.insert(0xfa, new_opcode("STATICCALL", 6, 1, 0));
+    opcodes.insert(0xff, new_opcode("SELFDESTRUCT", 1, 0, 0));
 
     for i in 1..33 {
         let name = format!("PUSH{}", i);
@@ -194,6 +282,11 @@ impl Stack {
     pub fn div(&mut self) -> Result<(), String> {
         let b0 = BigUint::from_bytes_be(&self.pop()?[..]);
         let b1 = BigUint::from_bytes_be(&self.pop()?[..]);
+        // a division by zero is zero
+        if b1.is_zero() {
+            self.push([0; 32]);
+            return Ok(());
+        }
         self.push_arbitrary(&(b0 / b1).to_bytes_be());
         Ok(())
     }
@@ -203,6 +296,10 @@ impl Stack {
     pub fn modulus(&mut self) -> Result<(), String> {
         let b0 = BigUint::from_bytes_be(&self.pop()?[..]);
         let b1 = BigUint::from_bytes_be(&self.pop()?[..]);
+        if b1.is_zero() {
+            self.push([0; 32]);
+            return Ok(());
+        }
         self.push_arbitrary(&(b0 % b1).to_bytes_be());
         Ok(())
     }
@@ -213,13 +310,21 @@ impl Stack {
         let b0 = BigUint::from_bytes_be(&self.pop()?[..]);
         let b1 = BigUint::from_bytes_be(&self.pop()?[..]);
         let b2 = BigUint::from_bytes_be(&self.pop()?[..]);
-        self.push_arbitrary(&(b0 + b1 % b2).to_bytes_be());
+        if b2.is_zero() {
+            self.push([0; 32]);
+            return Ok(());
+        }
+        self.push_arbitrary(&((b0 + b1) % b2).to_bytes_be());
         Ok(())
     }
     pub fn mul_mod(&mut self) -> Result<(), String> {
         let b0 = BigUint::from_bytes_be(&self.pop()?[..]);
         let b1 = BigUint::from_bytes_be(&self.pop()?[..]);
         let b2 = BigUint::from_bytes_be(&self.pop()?[..]);
+        if b2.is_zero() {
+            self.push([0; 32]);
+            return Ok(());
+        }
         self.push_arbitrary(&(b0 * b1 % b2).to_bytes_be());
         Ok(())
     }
@@ -241,16 +346,15 @@ impl Stack {
                 // is odd
                 r = r * exp.clone() % field.clone();
             }
-            exp = exp.clone() * exp.clone();
+            exp = exp.clone() * exp.clone() % field.clone();
             rem >>= 1;
         }
         self.push_arbitrary(&r.to_bytes_be());
 
-        let n_bytes = &e.to_bytes_be().len();
+        let n_bytes = e.to_bytes_be().len();
         let mut exp_fee = n_bytes * GEXPONENTBYTE;
         exp_fee += EXP_SUPPLEMENTAL_GAS * n_bytes;
-        self.gas -= exp_fee as u64;
-        Ok(())
+        self.substract_gas(exp_fee as u64)
     }
 
     // boolean
@@ -312,7 +416,7 @@ impl Stack {
     pub fn calldata_load(&mut self, calldata: &[u8]) -> Result<(), String> {
         let mut start = self.calldata_i;
         if !self.stack.is_empty() {
-            start = u256::u256_to_u64(self.peek()?) as usize;
+            start = u256::u256_to_u64_saturating(self.peek()?) as usize;
         }
         let l = calldata.len();
         if start > l {
@@ -330,20 +434,54 @@ impl Stack {
         self.calldata_size = calldata.len();
         self.push(u256::usize_to_u256(self.calldata_size));
     }
-    fn spend_gas_data_copy(&mut self, length: usize) {
+    pub fn self_balance(&mut self) {
+        let address = self.address;
+        let balance = self.with_host(|h| h.get_balance(&address));
+        self.push_arbitrary(&balance.to_bytes_be());
+    }
+    // block_hash pushes the hash of one of the 256 most recent blocks, zero
+    // for the other ones
+    pub fn block_hash(&mut self, current: u64) -> Result<(), String> {
+        let number = self.pop()?;
+        let n = u256::u256_to_u64(number);
+        let hash = if number[..24] == [0; 24] && n < current && current - n <= 256 {
+            self.with_host(|h| h.get_block_hash(n))
+        } else {
+            [0; 32]
+        };
+        self.push(hash);
+        Ok(())
+    }
+    // blob_hash pushes the versioned hash of the blob of the transaction at
+    // the index, zero past the last one (EIP-4844)
+    pub fn blob_hash(&mut self) -> Result<(), String> {
+        if self.config.spec < Spec::Cancun {
+            return Err("invalid opcode 49".to_string());
+        }
+        let index = self.pop()?;
+        let hashes = self.with_host(|h| h.get_tx_context()).blob_hashes;
+        let i = u256::u256_to_u64_saturating(index);
+        let hash = match i < hashes.len() as u64 {
+            true => hashes[i as usize],
+            false => [0; 32],
+        };
+        self.push(hash);
+        Ok(())
+    }
+    fn spend_gas_data_copy(&mut self, length: usize) -> Result<(), String> {
         let length32 = upper_multiple_of_32(length);
-        self.gas -= ((GCOPY * length32) / 32) as u64;
+        self.substract_gas(((GCOPY * length32) / 32) as u64)
     }
     pub fn code_copy(&mut self, code: &[u8]) -> Result<(), String> {
-        let dest_offset = u256::u256_to_u64(self.pop()?) as usize;
-        let offset = u256::u256_to_u64(self.pop()?) as usize;
-        let length = u256::u256_to_u64(self.pop()?) as usize;
+        let dest_offset = self.pop()?;
+        let offset = u256::u256_to_u64_saturating(self.pop()?) as usize;
+        let length = self.pop()?;
 
-        self.extend_mem(dest_offset, length);
-        self.spend_gas_data_copy(length);
+        let (dest_offset, length) = self.mem_range(dest_offset, length)?;
+        self.spend_gas_data_copy(length)?;
 
         for i in 0..length {
-            if offset + i < code.len() {
+            if offset.saturating_add(i) < code.len() {
                 self.mem[dest_offset + i] = code[offset + i];
             } else {
                 self.mem[dest_offset + i] = 0;
@@ -353,104 +491,399 @@ impl Stack {
         Ok(())
     }
 
+    fn pop_address(&mut self) -> Result<[u8; 20], String> {
+        let mut address = [0; 20];
+        address.copy_from_slice(&self.pop()?[12..]);
+        Ok(address)
+    }
+    // account_access returns the cost of accessing the account, the EIP-2929
+    // cold or warm one from Berlin, and the given one of the older specs
+    fn account_access(&mut self, address: &[u8; 20], legacy: u64) -> u64 {
+        if self.config.spec < Spec::Berlin {
+            return legacy;
+        }
+        match self.with_host(|h| h.access_account(address)) {
+            host::AccessStatus::Cold => COLD_ACCOUNT_ACCESS_COST,
+            host::AccessStatus::Warm => WARM_ACCOUNT_ACCESS_COST,
+        }
+    }
+    // ext_legacy_cost is the pre-Berlin cost of EXTCODESIZE and EXTCODECOPY
+    fn ext_legacy_cost(&self) -> u64 {
+        match self.config.spec {
+            s if s >= Spec::TangerineWhistle => 700,
+            _ => 20,
+        }
+    }
+    pub fn balance(&mut self) -> Result<(), String> {
+        let address = self.pop_address()?;
+        let legacy = match self.config.spec {
+            s if s >= Spec::Istanbul => 700,
+            s if s >= Spec::TangerineWhistle => 400,
+            _ => 20,
+        };
+        let cost = self.account_access(&address, legacy);
+        self.substract_gas(cost)?;
+        let balance = self.with_host(|h| h.get_balance(&address));
+        self.push(u256::biguint_to_u256(&balance));
+        Ok(())
+    }
+    pub fn ext_code_size(&mut self) -> Result<(), String> {
+        let address = self.pop_address()?;
+        let cost = self.account_access(&address, self.ext_legacy_cost());
+        self.substract_gas(cost)?;
+        let size = self.with_host(|h| h.get_code_size(&address));
+        self.push(u256::usize_to_u256(size));
+        Ok(())
+    }
+    pub fn ext_code_copy(&mut self) -> Result<(), String> {
+        let address = self.pop_address()?;
+        let dest_offset = self.pop()?;
+        let offset = u256::u256_to_u64_saturating(self.pop()?) as usize;
+        let length = self.pop()?;
+
+        let cost = self.account_access(&address, self.ext_legacy_cost());
+        self.substract_gas(cost)?;
+        let (dest_offset, length) = self.mem_range(dest_offset, length)?;
+        self.spend_gas_data_copy(length)?;
+        // the bytes past the end of the code are zeros
+        let mut buffer = vec![0; length];
+        self.with_host(|h| h.copy_code(&address, offset, &mut buffer));
+        self.mem[dest_offset..dest_offset + length].copy_from_slice(&buffer);
+        Ok(())
+    }
+    // ext_code_hash pushes the code hash of the account, zero for the
+    // non-existent or empty ones (EIP-1052)
+    pub fn ext_code_hash(&mut self) -> Result<(), String> {
+        if self.config.spec < Spec::Constantinople {
+            return Err("invalid opcode 3f".to_string());
+        }
+        let address = self.pop_address()?;
+        let legacy = match self.config.spec {
+            s if s >= Spec::Istanbul => 700,
+            _ => 400,
+        };
+        let cost = self.account_access(&address, legacy);
+        self.substract_gas(cost)?;
+        let hash = self.with_host(|h| h.get_code_hash(&address));
+        self.push(hash);
+        Ok(())
+    }
+
+    pub fn return_data_size(&mut self) -> Result<(), String> {
+        if self.config.spec < Spec::Byzantium {
+            return Err("invalid opcode 3d".to_string());
+        }
+        self.push(u256::usize_to_u256(self.return_data.len()));
+        Ok(())
+    }
+    pub fn return_data_copy(&mut self) -> Result<(), String> {
+        if self.config.spec < Spec::Byzantium {
+            return Err("invalid opcode 3e".to_string());
+        }
+        let dest_offset = self.pop()?;
+        let offset = u256::u256_to_u64_saturating(self.pop()?) as usize;
+        let length = self.pop()?;
+        let end = (u256::u256_to_u64_saturating(length) as usize).saturating_add(offset);
+        if end > self.return_data.len() {
+            return Err("return data out of bounds".to_string());
+        }
+        let (dest_offset, length) = self.mem_range(dest_offset, length)?;
+        self.spend_gas_data_copy(length)?;
+        self.mem[dest_offset..dest_offset + length]
+            .copy_from_slice(&self.return_data[offset..offset + length]);
+        Ok(())
+    }
+
+    // call runs CALL and STATICCALL. The interpreter runs the precompiles
+    // called without value, and the host the other calls. A failing call
+    // consumes the gas passed to it, and pushes 0.
+    pub fn call(&mut self, opcode: u8) -> Result<(), String> {
+        let spec = self.config.spec;
+        if opcode == 0xfa && spec < Spec::Byzantium {
+            return Err("invalid opcode fa".to_string());
+        }
+        let gas = self.pop()?;
+        let address = self.pop_address()?;
+        let value = match opcode {
+            0xf1 => self.pop()?,
+            _ => [0; 32],
+        };
+        let has_value = value != [0; 32];
+        if has_value && self.is_static {
+            return Err("static mode violation".to_string());
+        }
+        let (args_offset, args_size) = (self.pop()?, self.pop()?);
+        let (ret_offset, ret_size) = (self.pop()?, self.pop()?);
+
+        let legacy = match spec {
+            s if s >= Spec::TangerineWhistle => 700,
+            _ => 40,
+        };
+        let mut cost = self.account_access(&address, legacy);
+        if has_value {
+            cost += GCALLVALUETRANSFER as u64;
+        }
+        // creating the recipient, only when sending it value from Spurious
+        // Dragon (EIP-161)
+        if opcode == 0xf1
+            && (has_value || spec < Spec::SpuriousDragon)
+            && !self.with_host(|h| h.account_exists(&address))
+        {
+            cost += GCALLNEWACCOUNT as u64;
+        }
+        let (args_offset, args_size) = self.mem_range(args_offset, args_size)?;
+        let (ret_offset, ret_size) = self.mem_range(ret_offset, ret_size)?;
+        self.substract_gas(cost)?;
+        // all but one 64th of the gas left can be passed from EIP-150
+        let requested = u256::u256_to_u64_saturating(gas);
+        let mut call_gas = match spec {
+            s if s >= Spec::TangerineWhistle => requested.min(self.gas - self.gas / 64),
+            _ => requested,
+        };
+        self.substract_gas(call_gas)?;
+        if has_value {
+            call_gas += GSTIPEND as u64;
+        }
+
+        let input = self.mem[args_offset..args_offset + args_size].to_vec();
+        self.return_data = Vec::new();
+        // the call fails without executing, the gas passed being kept, past
+        // the depth limit or without the balance to send
+        let own = self.address;
+        if self.depth >= CALL_DEPTH_LIMIT
+            || (has_value
+                && self.with_host(|h| h.get_balance(&own)) < BigUint::from_bytes_be(&value))
+        {
+            self.gas += call_gas;
+            self.push([0; 32]);
+            return Ok(());
+        }
+        let precompile = match has_value {
+            false => self.precompiles().get(&address).map(|p| p.run),
+            true => None,
+        };
+        let (success, output) = match precompile {
+            Some(run) => match run(&input, call_gas) {
+                Ok((gas_used, output)) => {
+                    self.gas += call_gas.saturating_sub(gas_used);
+                    (true, output)
+                }
+                Err(_) => (false, Vec::new()),
+            },
+            None => {
+                let msg = host::Message {
+                    kind: host::CallKind::Call,
+                    is_static: self.is_static || opcode == 0xfa,
+                    depth: self.depth + 1,
+                    gas: call_gas,
+                    recipient: address,
+                    sender: own,
+                    input,
+                    value,
+                    code_address: address,
+                    salt: [0; 32],
+                };
+                let r = self.with_host(|h| h.call(&msg));
+                self.gas += r.gas_left.min(call_gas);
+                if r.error.is_none() {
+                    self.refund += r.gas_refund;
+                }
+                (r.error.is_none(), r.output)
+            }
+        };
+        let n = ret_size.min(output.len());
+        self.mem[ret_offset..ret_offset + n].copy_from_slice(&output[..n]);
+        self.return_data = output;
+        self.push(u256::usize_to_u256(success as usize));
+        Ok(())
+    }
+
     // blockchain context
 
     // storage and execution
-    pub fn extend_mem(&mut self, start: usize, size: usize) {
-        if size <= self.mem.len() || start + size <= self.mem.len() {
-            return;
+    // mem_range converts the offset and the size of a memory range, expanding
+    // the memory to it. A range past 2^64 is out of gas, as its expansion
+    // cost would be.
+    pub fn mem_range(
+        &mut self,
+        offset: [u8; 32],
+        size: [u8; 32],
+    ) -> Result<(usize, usize), String> {
+        if size == [0; 32] {
+            return Ok((0, 0));
+        }
+        if offset[..24] != [0; 24] || size[..24] != [0; 24] {
+            return Err("out of gas".to_string());
+        }
+        let offset = u256::u256_to_u64(offset) as usize;
+        let size = u256::u256_to_u64(size) as usize;
+        self.extend_mem(offset, size)?;
+        Ok((offset, size))
+    }
+    pub fn extend_mem(&mut self, start: usize, size: usize) -> Result<(), String> {
+        let end = start
+            .checked_add(size)
+            .ok_or_else(|| "out of gas".to_string())?;
+        if size == 0 || end <= self.mem.len() {
+            return Ok(());
         }
         let old_size = self.mem.len() / 32;
-        let new_size = upper_multiple_of_32(start + size) / 32;
-        let old_total_fee = old_size * GMEMORY + old_size.pow(2) / GQUADRATICMEMDENOM;
-        let new_total_fee = new_size * GMEMORY + new_size.pow(2) / GQUADRATICMEMDENOM;
-        let mem_fee = new_total_fee - old_total_fee;
-        self.gas -= mem_fee as u64;
+        let new_size = upper_multiple_of_32(end) / 32;
+        let fee = |words: usize| {
+            let quadratic = words.checked_mul(words)? / GQUADRATICMEMDENOM;
+            words.checked_mul(GMEMORY)?.checked_add(quadratic)
+        };
+        let new_total_fee = fee(new_size).ok_or_else(|| "out of gas".to_string())?;
+        let mem_fee = new_total_fee - fee(old_size).unwrap_or(0);
+        self.substract_gas(mem_fee as u64)?;
         let mut new_bytes: Vec<u8> = vec![0; (new_size - old_size) * 32];
         self.mem.append(&mut new_bytes);
+        Ok(())
     }
     pub fn mload(&mut self) -> Result<(), String> {
-        let pos = u256::u256_to_u64(self.pop()?) as usize;
-        self.extend_mem(pos as usize, 32);
+        let pos = self.pop()?;
+        let (pos, _) = self.mem_range(pos, u256::usize_to_u256(32))?;
         let mem32 = self.mem[pos..pos + 32].to_vec();
         self.push_arbitrary(&mem32);
         Ok(())
     }
     pub fn mstore(&mut self) -> Result<(), String> {
-        let pos = u256::u256_to_u64(self.pop()?);
+        let pos = self.pop()?;
         let val = self.pop()?;
-        self.extend_mem(pos as usize, 32);
+        let (pos, _) = self.mem_range(pos, u256::usize_to_u256(32))?;
 
-        self.mem[pos as usize..].copy_from_slice(&val);
+        self.mem[pos..pos + 32].copy_from_slice(&val);
+        Ok(())
+    }
+    // access_storage marks the slot as warm, returning the EIP-2929 cost of
+    // the access, or the cost of SLOAD before Berlin
+    fn access_storage(&mut self, key: [u8; 32]) -> u64 {
+        if self.config.spec < Spec::Berlin {
+            return sload_gas(self.config.spec);
+        }
+        let address = self.address;
+        match self.with_host(|h| h.access_storage(&address, &key)) {
+            host::AccessStatus::Cold => COLD_SLOAD_COST,
+            host::AccessStatus::Warm => WARM_STORAGE_READ_COST,
+        }
+    }
+    pub fn sload(&mut self) -> Result<(), String> {
+        let key = self.pop()?;
+        let cost = self.access_storage(key);
+        self.substract_gas(cost)?;
+        let value = self.get_storage(&key);
+        self.push(value);
         Ok(())
     }
     pub fn sstore(&mut self) -> Result<(), String> {
+        // https://eips.ethereum.org/EIPS/eip-2200
+        // https://eips.ethereum.org/EIPS/eip-2929
         // https://eips.ethereum.org/EIPS/eip-3529
-        // https://github.com/ethereum/EIPs/blob/master/EIPS/eip-1283.md
-        // 1. If current value equals new value (this is a no-op), 200 gas is deducted.
+        // 1. If current value equals new value (this is a no-op), 100 gas is deducted.
         // 2. If current value does not equal new value
         //   2.1. If original value equals current value (this storage slot has not been changed by the current execution context)
         //     2.1.1. If original value is 0, 20000 gas is deducted.
-        // 	   2.1.2. Otherwise, 5000 gas is deducted. If new value is 0, add 15000 gas to refund counter.
-        // 	2.2. If original value does not equal current value (this storage slot is dirty), 200 gas is deducted. Apply both of the following clauses.
+        // 	   2.1.2. Otherwise, 2900 gas is deducted. If new value is 0, add 4800 gas to refund counter.
+        // 	2.2. If original value does not equal current value (this storage slot is dirty), 100 gas is deducted. Apply both of the following clauses.
         // 	  2.2.1. If original value is not 0
-        //       2.2.1.1. If current value is 0 (also means that new value is not 0), remove 15000 gas from refund counter. We can prove that refund counter will never go below 0.
-        //       2.2.1.2. If new value is 0 (also means that current value is not 0), add 15000 gas to refund counter.
+        //       2.2.1.1. If current value is 0 (also means that new value is not 0), remove 4800 gas from refund counter.
+        //       2.2.1.2. If new value is 0 (also means that current value is not 0), add 4800 gas to refund counter.
         // 	  2.2.2. If original value equals new value (this storage slot is reset)
-        //       2.2.2.1. If original value is 0, add 19800 gas to refund counter.
-        // 	     2.2.2.2. Otherwise, add 4800 gas to refund counter.
-
-        let empty: Vec<u8> = Vec::new();
+        //       2.2.2.1. If original value is 0, add 19900 gas to refund counter.
+        // 	     2.2.2.2. Otherwise, add 2800 gas to refund counter.
+        // A cold slot costs 2100 more.
+        // The costs and refunds of the other forks are in sstore_cost.
+        if self.is_static {
+            return Err("static mode violation".to_string());
+        }
+        let spec = self.config.spec;
+        if spec >= Spec::Istanbul && self.gas <= SSTORE_SENTRY_GAS {
+            return Err("out of gas".to_string());
+        }
+        // The slot is written first, the host returning the status of the
+        // write, from which the cost follows. When the gas does not cover it
+        // the previous value is written back, so that no host keeps it.
         let key = self.pop()?;
         let value = self.pop()?;
-        let original = match self.storage_committed.get(&key) {
-            Some(v) => v.clone(),
-            None => empty.clone(),
-        };
-        let current = match self.storage.get(&key) {
-            Some(v) => v.clone(),
-            None => {
-                self.gas -= 2100;
-                empty.clone()
-            }
+        let mut cost = 0;
+        if spec >= Spec::Berlin && self.access_storage(key) == COLD_SLOAD_COST {
+            cost = COLD_SLOAD_COST;
+        }
+        let address = self.address;
+        let current = self.get_storage(&key);
+        let status = self.with_host(|h| h.set_storage(&address, &key, value));
+        let (gas, refund) = sstore_cost(spec, status, &current, &value);
+        if let Err(e) = self.substract_gas(cost + gas) {
+            self.with_host(|h| h.set_storage(&address, &key, current));
+            return Err(e);
+        }
+        self.refund += refund;
+        Ok(())
+    }
+    // log appends a log with n topics (LOG0 to LOG4), the base and per topic
+    // costs being charged by the opcode table
+    pub fn log(&mut self, n: usize) -> Result<(), String> {
+        if self.is_static {
+            return Err("static mode violation".to_string());
+        }
+        let offset = self.pop()?;
+        let size = self.pop()?;
+        let mut topics = Vec::with_capacity(n);
+        for _ in 0..n {
+            topics.push(self.pop()?);
+        }
+        let (offset, size) = self.mem_range(offset, size)?;
+        self.substract_gas((GLOGBYTE * size) as u64)?;
+        let data = if size == 0 {
+            Vec::new()
+        } else {
+            self.mem[offset..offset + size].to_vec()
         };
-
-        if current == value {
-            self.gas -= NETSSTORENOOPGAS;
-            return Ok(());
+        let address = self.address;
+        self.with_host(|h| h.emit_log(&address, &topics, &data));
+        Ok(())
+    }
+    // selfdestruct sends the balance of the account to the beneficiary, and
+    // halts. The host keeps the account until the end of the transaction.
+    pub fn selfdestruct(&mut self) -> Result<(), String> {
+        if self.is_static {
+            return Err("static mode violation".to_string());
         }
-        if original == current {
-            if original.is_empty() {
-                self.gas -= NETSSTOREINITGAS;
-                return Ok(());
-            }
-            if value.is_empty() {
-                self.gas += NETSSTORECLEARREFUND;
-            }
-            self.gas -= NETSSTORECLEANGAS;
-            return Ok(());
+        let beneficiary = self.pop_address()?;
+        let spec = self.config.spec;
+        let mut cost = 0;
+        if spec >= Spec::TangerineWhistle {
+            cost += SELFDESTRUCT_GAS;
         }
-        if !original.is_empty() {
-            if current.is_empty() {
-                self.gas -= NETSSTORECLEARREFUND;
-            } else if value.is_empty() {
-                self.gas += NETSSTORECLEARREFUND;
-            }
+        if spec >= Spec::Berlin
+            && self.with_host(|h| h.access_account(&beneficiary)) == host::AccessStatus::Cold
+        {
+            cost += COLD_ACCOUNT_ACCESS_COST;
         }
-        if original == value {
-            if original.is_empty() {
-                self.gas += NETSSTORERESETCLEARREFUND;
-            } else {
-                self.gas += NETSSTORERESETREFUND;
-            }
+        // creating the beneficiary, only when receiving a balance from
+        // Spurious Dragon (EIP-161)
+        let address = self.address;
+        let balance = self.with_host(|h| h.get_balance(&address));
+        let exists = self.with_host(|h| h.account_exists(&beneficiary));
+        let creates = match spec {
+            s if s >= Spec::SpuriousDragon => !exists && !balance.is_zero(),
+            s if s >= Spec::TangerineWhistle => !exists,
+            _ => false,
+        };
+        if creates {
+            cost += GCALLNEWACCOUNT as u64;
+        }
+        self.substract_gas(cost)?;
+        let first = self.with_host(|h| h.selfdestruct(&address, &beneficiary));
+        // no refund from London (EIP-3529)
+        if first && spec < Spec::London {
+            self.refund += GSUICIDEREFUND as i64;
         }
-        self.gas -= NETSSTOREDIRTYGAS;
-        self.storage.insert(key, value.to_vec());
         Ok(())
     }
     pub fn jump(&mut self, code: &[u8]) -> Result<(), String> {
         // TODO that jump destination is valid
-        let new_pc = u256::u256_to_u64(self.pop()?) as usize;
+        let new_pc = u256::u256_to_u64_saturating(self.pop()?) as usize;
         if !valid_dest(code, new_pc) {
             return Err(format!("not valid dest: {:02x}", new_pc));
         }
@@ -458,13 +891,13 @@ impl Stack {
         Ok(())
     }
     pub fn jump_i(&mut self, code: &[u8]) -> Result<(), String> {
-        let new_pc = u256::u256_to_u64(self.pop()?) as usize;
+        let new_pc = u256::u256_to_u64_saturating(self.pop()?) as usize;
         if !valid_dest(code, new_pc) {
             return Err(format!("not valid dest: {:02x}", new_pc));
         }
         if !self.stack.is_empty() {
-            let cond = u256::u256_to_u64(self.pop()?) as usize;
-            if cond != 0 {
+            let cond = self.pop()?;
+            if cond != [0; 32] {
                 self.pc = new_pc;
             }
         }
@@ -479,12 +912,12 @@ impl Stack {
 }
 
 fn valid_dest(code: &[u8], pos: usize) -> bool {
-    if code[pos] == 0x5b {
-        return true;
-    }
-    false
+    code.get(pos) == Some(&0x5b)
 }
 
 fn upper_multiple_of_32(n: usize) -> usize {
+    if n == 0 {
+        return 0;
+    }
     ((n - 1) | 31) + 1
 }
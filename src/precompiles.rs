@@ -0,0 +1,96 @@
+use crate::spec::{Config, Spec};
+use std::collections::HashMap;
+
+pub mod bls12_381;
+pub mod secp256r1;
+
+// A precompile takes the call input and the gas available for the call, and
+// returns the gas used together with the output. An error means that all the
+// gas passed to the call is consumed.
+//
+// The precompiles are reached by a transaction sent to their address, by a
+// CALL without value or a STATICCALL from the executed code, or directly
+// with Stack::call_precompile.
+pub type PrecompileFn = fn(&[u8], u64) -> Result<(u64, Vec<u8>), String>;
+
+pub struct Precompile {
+    pub name: String,
+    pub run: PrecompileFn,
+}
+
+pub fn new_precompile(name: &str, run: PrecompileFn) -> Precompile {
+    Precompile {
+        name: name.to_string(),
+        run,
+    }
+}
+
+pub fn address(n: u64) -> [u8; 20] {
+    let mut a: [u8; 20] = [0; 20];
+    a[12..].copy_from_slice(&n.to_be_bytes());
+    a
+}
+
+pub fn new_precompiles(config: &Config) -> HashMap<[u8; 20], Precompile> {
+    let mut precompiles: HashMap<[u8; 20], Precompile> = HashMap::new();
+
+    if config.spec >= Spec::Prague {
+        new_bls12_381_precompiles(&mut precompiles);
+    }
+
+    // secp256r1 (EIP-7951, RIP-7212)
+    if config.spec >= Spec::Osaka {
+        precompiles.insert(
+            address(0x100),
+            new_precompile("P256VERIFY", secp256r1::p256_verify_osaka),
+        );
+    } else if config.rip7212 {
+        precompiles.insert(
+            address(0x100),
+            new_precompile("P256VERIFY", secp256r1::p256_verify),
+        );
+    }
+
+    precompiles
+}
+
+// bls12-381 (EIP-2537)
+fn new_bls12_381_precompiles(precompiles: &mut HashMap<[u8; 20], Precompile>) {
+    precompiles.insert(
+        address(0x0b),
+        new_precompile("BLS12_G1ADD", bls12_381::g1_add),
+    );
+    precompiles.insert(
+        address(0x0c),
+        new_precompile("BLS12_G1MSM", bls12_381::g1_msm),
+    );
+    precompiles.insert(
+        address(0x0d),
+        new_precompile("BLS12_G2ADD", bls12_381::g2_add),
+    );
+    precompiles.insert(
+        address(0x0e),
+        new_precompile("BLS12_G2MSM", bls12_381::g2_msm),
+    );
+    precompiles.insert(
+        address(0x0f),
+        new_precompile("BLS12_PAIRING_CHECK", bls12_381::pairing_check),
+    );
+    precompiles.insert(
+        address(0x10),
+        new_precompile("BLS12_MAP_FP_TO_G1", bls12_381::map_fp_to_g1),
+    );
+    precompiles.insert(
+        address(0x11),
+        new_precompile("BLS12_MAP_FP2_TO_G2", bls12_381::map_fp2_to_g2),
+    );
+}
+
+// check_gas returns an error when the gas needed by the precompile exceeds the
+// gas available for the call
+pub fn check_gas(needed: u64, gas_limit: u64) -> Result<(), String> {
+    if needed > gas_limit {
+        return Err("out of gas".to_string());
+    }
+    Ok(())
+}
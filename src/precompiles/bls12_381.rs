@@ -0,0 +1,306 @@
+// BLS12-381 precompiles, as specified in https://eips.ethereum.org/EIPS/eip-2537
+use super::check_gas;
+use blst::*;
+
+const G1ADD_GAS: u64 = 375;
+const G2ADD_GAS: u64 = 600;
+const G1MUL_GAS: u64 = 12000;
+const G2MUL_GAS: u64 = 22500;
+const PAIRING_BASE_GAS: u64 = 37700;
+const PAIRING_PER_PAIR_GAS: u64 = 32600;
+const MAP_FP_TO_G1_GAS: u64 = 5500;
+const MAP_FP2_TO_G2_GAS: u64 = 23800;
+const MSM_MULTIPLIER: u64 = 1000;
+
+// encoded lengths
+const FP_LEN: usize = 64;
+const FP_PAD: usize = 16; // top bytes of an encoded field element, must be zero
+const SCALAR_LEN: usize = 32;
+const G1_LEN: usize = 2 * FP_LEN;
+const G2_LEN: usize = 4 * FP_LEN;
+const G1_MSM_PAIR_LEN: usize = G1_LEN + SCALAR_LEN;
+const G2_MSM_PAIR_LEN: usize = G2_LEN + SCALAR_LEN;
+const PAIRING_PAIR_LEN: usize = G1_LEN + G2_LEN;
+
+// field modulus p, big endian
+const MODULUS: [u8; 48] = [
+    0x1a, 0x01, 0x11, 0xea, 0x39, 0x7f, 0xe6, 0x9a, 0x4b, 0x1b, 0xa7, 0xb6, 0x43, 0x4b, 0xac, 0xd7,
+    0x64, 0x77, 0x4b, 0x84, 0xf3, 0x85, 0x12, 0xbf, 0x67, 0x30, 0xd2, 0xa0, 0xf6, 0xb0, 0xf6, 0x24,
+    0x1e, 0xab, 0xff, 0xfe, 0xb1, 0x53, 0xff, 0xff, 0xb9, 0xfe, 0xff, 0xff, 0xff, 0xff, 0xaa, 0xab,
+];
+
+// MSM discounts (per mille) indexed by k-1, for k up to 128. For bigger k the
+// last value is used.
+pub const G1_MSM_DISCOUNTS: [u16; 128] = [
+    1000, 949, 848, 797, 764, 750, 738, 728, 719, 712, 705, 698, 692, 687, 682, 677, 673, 669, 665,
+    661, 658, 654, 651, 648, 645, 642, 640, 637, 635, 632, 630, 627, 625, 623, 621, 619, 617, 615,
+    613, 611, 609, 608, 606, 604, 603, 601, 599, 598, 596, 595, 593, 592, 591, 589, 588, 586, 585,
+    584, 582, 581, 580, 579, 577, 576, 575, 574, 573, 572, 570, 569, 568, 567, 566, 565, 564, 563,
+    562, 561, 560, 559, 558, 557, 556, 555, 554, 553, 552, 551, 550, 549, 548, 547, 547, 546, 545,
+    544, 543, 542, 541, 540, 540, 539, 538, 537, 536, 536, 535, 534, 533, 532, 532, 531, 530, 529,
+    528, 528, 527, 526, 525, 525, 524, 523, 522, 522, 521, 520, 520, 519,
+];
+pub const G2_MSM_DISCOUNTS: [u16; 128] = [
+    1000, 1000, 923, 884, 855, 832, 812, 796, 782, 770, 759, 749, 740, 732, 724, 717, 711, 704,
+    699, 693, 688, 683, 679, 674, 670, 666, 663, 659, 655, 652, 649, 646, 643, 640, 637, 634, 632,
+    629, 627, 624, 622, 620, 618, 615, 613, 611, 609, 607, 606, 604, 602, 600, 598, 597, 595, 593,
+    592, 590, 589, 587, 586, 584, 583, 582, 580, 579, 578, 576, 575, 574, 573, 571, 570, 569, 568,
+    567, 566, 565, 563, 562, 561, 560, 559, 558, 557, 556, 555, 554, 553, 552, 552, 551, 550, 549,
+    548, 547, 546, 545, 545, 544, 543, 542, 541, 541, 540, 539, 538, 537, 537, 536, 535, 535, 534,
+    533, 532, 532, 531, 530, 530, 529, 528, 528, 527, 526, 526, 525, 524, 524,
+];
+
+pub fn msm_gas(k: usize, mul_gas: u64, discounts: &[u16; 128]) -> u64 {
+    if k == 0 {
+        return 0;
+    }
+    let discount = discounts[k.min(discounts.len()) - 1] as u64;
+    (k as u64 * mul_gas * discount) / MSM_MULTIPLIER
+}
+
+pub fn g1_add(input: &[u8], gas_limit: u64) -> Result<(u64, Vec<u8>), String> {
+    check_gas(G1ADD_GAS, gas_limit)?;
+    if input.len() != 2 * G1_LEN {
+        return Err(format!("G1ADD invalid input length {}", input.len()));
+    }
+    // no subgroup check for additions
+    let a = decode_g1(&input[..G1_LEN], false)?;
+    let b = decode_g1(&input[G1_LEN..], false)?;
+
+    let mut p = blst_p1::default();
+    let mut out = blst_p1_affine::default();
+    unsafe {
+        blst_p1_from_affine(&mut p, &a);
+        let t = p;
+        blst_p1_add_or_double_affine(&mut p, &t, &b);
+        blst_p1_to_affine(&mut out, &p);
+    }
+    Ok((G1ADD_GAS, encode_g1(&out)))
+}
+
+pub fn g1_msm(input: &[u8], gas_limit: u64) -> Result<(u64, Vec<u8>), String> {
+    let k = input.len() / G1_MSM_PAIR_LEN;
+    if input.is_empty() || !input.len().is_multiple_of(G1_MSM_PAIR_LEN) {
+        return Err(format!("G1MSM invalid input length {}", input.len()));
+    }
+    let gas = msm_gas(k, G1MUL_GAS, &G1_MSM_DISCOUNTS);
+    check_gas(gas, gas_limit)?;
+
+    let mut acc = blst_p1::default();
+    for i in 0..k {
+        let chunk = &input[i * G1_MSM_PAIR_LEN..(i + 1) * G1_MSM_PAIR_LEN];
+        let a = decode_g1(&chunk[..G1_LEN], true)?;
+        let scalar = decode_scalar(&chunk[G1_LEN..]);
+
+        let mut p = blst_p1::default();
+        let mut m = blst_p1::default();
+        unsafe {
+            blst_p1_from_affine(&mut p, &a);
+            blst_p1_mult(&mut m, &p, scalar.b.as_ptr(), 8 * SCALAR_LEN);
+            let t = acc;
+            blst_p1_add_or_double(&mut acc, &t, &m);
+        }
+    }
+    let mut out = blst_p1_affine::default();
+    unsafe { blst_p1_to_affine(&mut out, &acc) };
+    Ok((gas, encode_g1(&out)))
+}
+
+pub fn g2_add(input: &[u8], gas_limit: u64) -> Result<(u64, Vec<u8>), String> {
+    check_gas(G2ADD_GAS, gas_limit)?;
+    if input.len() != 2 * G2_LEN {
+        return Err(format!("G2ADD invalid input length {}", input.len()));
+    }
+    let a = decode_g2(&input[..G2_LEN], false)?;
+    let b = decode_g2(&input[G2_LEN..], false)?;
+
+    let mut p = blst_p2::default();
+    let mut out = blst_p2_affine::default();
+    unsafe {
+        blst_p2_from_affine(&mut p, &a);
+        let t = p;
+        blst_p2_add_or_double_affine(&mut p, &t, &b);
+        blst_p2_to_affine(&mut out, &p);
+    }
+    Ok((G2ADD_GAS, encode_g2(&out)))
+}
+
+pub fn g2_msm(input: &[u8], gas_limit: u64) -> Result<(u64, Vec<u8>), String> {
+    let k = input.len() / G2_MSM_PAIR_LEN;
+    if input.is_empty() || !input.len().is_multiple_of(G2_MSM_PAIR_LEN) {
+        return Err(format!("G2MSM invalid input length {}", input.len()));
+    }
+    let gas = msm_gas(k, G2MUL_GAS, &G2_MSM_DISCOUNTS);
+    check_gas(gas, gas_limit)?;
+
+    let mut acc = blst_p2::default();
+    for i in 0..k {
+        let chunk = &input[i * G2_MSM_PAIR_LEN..(i + 1) * G2_MSM_PAIR_LEN];
+        let a = decode_g2(&chunk[..G2_LEN], true)?;
+        let scalar = decode_scalar(&chunk[G2_LEN..]);
+
+        let mut p = blst_p2::default();
+        let mut m = blst_p2::default();
+        unsafe {
+            blst_p2_from_affine(&mut p, &a);
+            blst_p2_mult(&mut m, &p, scalar.b.as_ptr(), 8 * SCALAR_LEN);
+            let t = acc;
+            blst_p2_add_or_double(&mut acc, &t, &m);
+        }
+    }
+    let mut out = blst_p2_affine::default();
+    unsafe { blst_p2_to_affine(&mut out, &acc) };
+    Ok((gas, encode_g2(&out)))
+}
+
+pub fn pairing_check(input: &[u8], gas_limit: u64) -> Result<(u64, Vec<u8>), String> {
+    let k = input.len() / PAIRING_PAIR_LEN;
+    if input.is_empty() || !input.len().is_multiple_of(PAIRING_PAIR_LEN) {
+        return Err(format!("PAIRING invalid input length {}", input.len()));
+    }
+    let gas = PAIRING_PER_PAIR_GAS * k as u64 + PAIRING_BASE_GAS;
+    check_gas(gas, gas_limit)?;
+
+    let mut acc = unsafe { *blst_fp12_one() };
+    for i in 0..k {
+        let chunk = &input[i * PAIRING_PAIR_LEN..(i + 1) * PAIRING_PAIR_LEN];
+        let p = decode_g1(&chunk[..G1_LEN], true)?;
+        let q = decode_g2(&chunk[G1_LEN..], true)?;
+
+        // pairs containing the point at infinity do not change the result
+        if unsafe { blst_p1_affine_is_inf(&p) || blst_p2_affine_is_inf(&q) } {
+            continue;
+        }
+        let mut ml = blst_fp12::default();
+        unsafe {
+            blst_miller_loop(&mut ml, &q, &p);
+            let t = acc;
+            blst_fp12_mul(&mut acc, &t, &ml);
+        }
+    }
+    let mut r = blst_fp12::default();
+    unsafe { blst_final_exp(&mut r, &acc) };
+
+    let mut out = vec![0; 32];
+    if unsafe { blst_fp12_is_one(&r) } {
+        out[31] = 1;
+    }
+    Ok((gas, out))
+}
+
+pub fn map_fp_to_g1(input: &[u8], gas_limit: u64) -> Result<(u64, Vec<u8>), String> {
+    check_gas(MAP_FP_TO_G1_GAS, gas_limit)?;
+    if input.len() != FP_LEN {
+        return Err(format!("MAP_FP_TO_G1 invalid input length {}", input.len()));
+    }
+    let u = decode_fp(input)?;
+
+    let mut p = blst_p1::default();
+    let mut out = blst_p1_affine::default();
+    unsafe {
+        blst_map_to_g1(&mut p, &u, std::ptr::null());
+        blst_p1_to_affine(&mut out, &p);
+    }
+    Ok((MAP_FP_TO_G1_GAS, encode_g1(&out)))
+}
+
+pub fn map_fp2_to_g2(input: &[u8], gas_limit: u64) -> Result<(u64, Vec<u8>), String> {
+    check_gas(MAP_FP2_TO_G2_GAS, gas_limit)?;
+    if input.len() != 2 * FP_LEN {
+        return Err(format!(
+            "MAP_FP2_TO_G2 invalid input length {}",
+            input.len()
+        ));
+    }
+    let u = decode_fp2(input)?;
+
+    let mut p = blst_p2::default();
+    let mut out = blst_p2_affine::default();
+    unsafe {
+        blst_map_to_g2(&mut p, &u, std::ptr::null());
+        blst_p2_to_affine(&mut out, &p);
+    }
+    Ok((MAP_FP2_TO_G2_GAS, encode_g2(&out)))
+}
+
+// decode_fp decodes a 64 byte big endian field element, whose top 16 bytes
+// must be zero and whose value must be smaller than the modulus
+fn decode_fp(b: &[u8]) -> Result<blst_fp, String> {
+    if b[..FP_PAD].iter().any(|x| *x != 0) {
+        return Err("invalid field element padding".to_string());
+    }
+    let v = &b[FP_PAD..FP_LEN];
+    if v >= &MODULUS[..] {
+        return Err("field element not in field".to_string());
+    }
+    let mut fp = blst_fp::default();
+    unsafe { blst_fp_from_bendian(&mut fp, v.as_ptr()) };
+    Ok(fp)
+}
+
+fn decode_fp2(b: &[u8]) -> Result<blst_fp2, String> {
+    Ok(blst_fp2 {
+        fp: [decode_fp(&b[..FP_LEN])?, decode_fp(&b[FP_LEN..2 * FP_LEN])?],
+    })
+}
+
+fn encode_fp(fp: &blst_fp, out: &mut [u8]) {
+    unsafe { blst_bendian_from_fp(out[FP_PAD..FP_LEN].as_mut_ptr(), fp) };
+}
+
+// decode_g1 decodes a point from its 128 byte encoding, all zeros being the
+// point at infinity. The point must be on the curve, and when subgroup_check
+// is set, also in the G1 subgroup.
+fn decode_g1(b: &[u8], subgroup_check: bool) -> Result<blst_p1_affine, String> {
+    let p = blst_p1_affine {
+        x: decode_fp(&b[..FP_LEN])?,
+        y: decode_fp(&b[FP_LEN..G1_LEN])?,
+    };
+    if !unsafe { blst_p1_affine_on_curve(&p) } {
+        return Err("G1 point not on curve".to_string());
+    }
+    if subgroup_check && !unsafe { blst_p1_affine_in_g1(&p) } {
+        return Err("G1 point not in subgroup".to_string());
+    }
+    Ok(p)
+}
+
+fn encode_g1(p: &blst_p1_affine) -> Vec<u8> {
+    let mut out = vec![0; G1_LEN];
+    encode_fp(&p.x, &mut out[..FP_LEN]);
+    encode_fp(&p.y, &mut out[FP_LEN..]);
+    out
+}
+
+// decode_g2 decodes a point from its 256 byte encoding (x.c0, x.c1, y.c0,
+// y.c1), with the same rules as decode_g1
+fn decode_g2(b: &[u8], subgroup_check: bool) -> Result<blst_p2_affine, String> {
+    let p = blst_p2_affine {
+        x: decode_fp2(&b[..2 * FP_LEN])?,
+        y: decode_fp2(&b[2 * FP_LEN..G2_LEN])?,
+    };
+    if !unsafe { blst_p2_affine_on_curve(&p) } {
+        return Err("G2 point not on curve".to_string());
+    }
+    if subgroup_check && !unsafe { blst_p2_affine_in_g2(&p) } {
+        return Err("G2 point not in subgroup".to_string());
+    }
+    Ok(p)
+}
+
+fn encode_g2(p: &blst_p2_affine) -> Vec<u8> {
+    let mut out = vec![0; G2_LEN];
+    encode_fp(&p.x.fp[0], &mut out[..FP_LEN]);
+    encode_fp(&p.x.fp[1], &mut out[FP_LEN..2 * FP_LEN]);
+    encode_fp(&p.y.fp[0], &mut out[2 * FP_LEN..3 * FP_LEN]);
+    encode_fp(&p.y.fp[1], &mut out[3 * FP_LEN..]);
+    out
+}
+
+// decode_scalar reads a 32 byte big endian scalar. Scalars are not required to
+// be smaller than the subgroup order.
+fn decode_scalar(b: &[u8]) -> blst_scalar {
+    let mut s = blst_scalar::default();
+    unsafe { blst_scalar_from_bendian(&mut s, b.as_ptr()) };
+    s
+}
@@ -0,0 +1,55 @@
+// P256VERIFY precompile, as specified in https://eips.ethereum.org/EIPS/eip-7951
+// (and https://github.com/ethereum/RIPs/blob/master/RIPS/rip-7212.md for L2s)
+use super::check_gas;
+use p256::ecdsa::signature::hazmat::PrehashVerifier;
+use p256::ecdsa::{Signature, VerifyingKey};
+use std::convert::TryInto;
+
+const RIP7212_GAS: u64 = 3450;
+const P256VERIFY_GAS: u64 = 6900;
+const INPUT_LEN: usize = 160;
+
+// p256_verify is the RIP-7212 variant
+pub fn p256_verify(input: &[u8], gas_limit: u64) -> Result<(u64, Vec<u8>), String> {
+    check_gas(RIP7212_GAS, gas_limit)?;
+    Ok((RIP7212_GAS, verify(input)))
+}
+
+// p256_verify_osaka is the EIP-7951 variant, enabled from Osaka
+pub fn p256_verify_osaka(input: &[u8], gas_limit: u64) -> Result<(u64, Vec<u8>), String> {
+    check_gas(P256VERIFY_GAS, gas_limit)?;
+    Ok((P256VERIFY_GAS, verify(input)))
+}
+
+// verify checks a signature over the input hash (32), r (32), s (32), x (32)
+// and y (32). A valid signature returns 1 as a 32 byte word, anything else
+// (including a malformed input) returns empty data, without failing the call.
+fn verify(input: &[u8]) -> Vec<u8> {
+    if input.len() != INPUT_LEN {
+        return Vec::new();
+    }
+    let hash = &input[..32];
+    let r: [u8; 32] = input[32..64].try_into().unwrap();
+    let s: [u8; 32] = input[64..96].try_into().unwrap();
+
+    // r and s must be in [1, n-1]
+    let signature = match Signature::from_scalars(r, s) {
+        Ok(sig) => sig,
+        Err(_) => return Vec::new(),
+    };
+    // the public key must be a valid point on the curve, and not the point at
+    // infinity
+    let mut sec1 = vec![0x04];
+    sec1.extend_from_slice(&input[96..INPUT_LEN]);
+    let key = match VerifyingKey::from_sec1_bytes(&sec1) {
+        Ok(key) => key,
+        Err(_) => return Vec::new(),
+    };
+
+    if key.verify_prehash(hash, &signature).is_err() {
+        return Vec::new();
+    }
+    let mut out = vec![0; 32];
+    out[31] = 1;
+    out
+}
@@ -0,0 +1,96 @@
+// Merkle proofs of accounts and storage slots, as returned by eth_getProof
+// https://eips.ethereum.org/EIPS/eip-1186
+use super::*;
+use num_bigint::BigUint;
+use state::{State, EMPTY_CODE_HASH};
+use trie::{verify_proof, EMPTY_ROOT};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct AccountProof {
+    pub address: [u8; 20],
+    pub balance: BigUint,
+    pub nonce: u64,
+    pub code_hash: [u8; 32],
+    pub storage_hash: [u8; 32],
+    // nodes of the state trie, from the root to the account
+    pub account_proof: Vec<Vec<u8>>,
+    pub storage_proof: Vec<StorageProof>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct StorageProof {
+    pub key: [u8; 32],
+    pub value: [u8; 32],
+    // nodes of the storage trie, from the storage root to the slot
+    pub proof: Vec<Vec<u8>>,
+}
+
+// prove returns the proof of the account and of the given storage slots. An
+// account that does not exist is proven absent, with empty fields.
+pub fn prove(state: &State, address: &[u8; 20], keys: &[[u8; 32]]) -> AccountProof {
+    let account = state.get(address).cloned().unwrap_or_default();
+    let storage_trie = account.storage_trie();
+    let storage_proof = keys
+        .iter()
+        .map(|key| StorageProof {
+            key: *key,
+            value: state.storage(address, key),
+            proof: storage_trie.prove(key),
+        })
+        .collect();
+    AccountProof {
+        address: *address,
+        balance: account.balance.clone(),
+        nonce: account.nonce,
+        code_hash: account.code_hash(),
+        storage_hash: storage_trie.root_hash(),
+        account_proof: state.trie().prove(address),
+        storage_proof,
+    }
+}
+
+// verify_account_proof checks the account fields and its storage slots
+// against the state root
+pub fn verify_account_proof(state_root: &[u8; 32], proof: &AccountProof) -> Result<(), String> {
+    let value = verify_proof(state_root, &keccak256(&proof.address), &proof.account_proof)?;
+    let (nonce, balance, storage_hash, code_hash) = match value {
+        Some(v) => {
+            let item = rlp::decode(&v)?;
+            let l = item.as_list()?;
+            if l.len() != 4 {
+                return Err("proof: invalid account".to_string());
+            }
+            (
+                l[0].as_u64()?,
+                l[1].as_biguint()?,
+                l[2].as_hash()?,
+                l[3].as_hash()?,
+            )
+        }
+        None => (0, BigUint::default(), EMPTY_ROOT, EMPTY_CODE_HASH),
+    };
+    if nonce != proof.nonce
+        || balance != proof.balance
+        || storage_hash != proof.storage_hash
+        || code_hash != proof.code_hash
+    {
+        return Err("proof: account mismatch".to_string());
+    }
+    for storage_proof in proof.storage_proof.iter() {
+        verify_storage_proof(&proof.storage_hash, storage_proof)?;
+    }
+    Ok(())
+}
+
+// verify_storage_proof checks the slot value against the storage root of the
+// account
+pub fn verify_storage_proof(storage_hash: &[u8; 32], proof: &StorageProof) -> Result<(), String> {
+    let value = match verify_proof(storage_hash, &keccak256(&proof.key), &proof.proof)? {
+        Some(v) => rlp::decode(&v)?.as_biguint()?,
+        None => BigUint::default(),
+    };
+    if value != BigUint::from_bytes_be(&proof.value) {
+        return Err("proof: storage value mismatch".to_string());
+    }
+    Ok(())
+}
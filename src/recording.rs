@@ -0,0 +1,473 @@
+// Recording of an execution, for time travel debugging. The machine state at
+// the start is kept once, and each executed instruction adds a Delta with the
+// values it changed, before and after. The state at any step is rebuilt by
+// applying the deltas to the last checkpoint before it, a snapshot kept every
+// CHECKPOINT_INTERVAL steps with the accounts written since the start alone,
+// and a machine is moved backwards and forwards by
+// undoing and redoing them, without executing again.
+//   let recording = recording::record(&mut vm, &code, &calldata);
+//   let s = recording.snapshot(10)?; // the state before the 11th instruction
+//
+// Without a host, the changes to the world state and the substate are the
+// ones journaled by LocalHost (slots, balances, created accounts, warm
+// accounts and slots, original values, logs). With a host set, only the slots
+// read and written by SLOAD and SSTORE are recorded, and undone and redone
+// through the host: the substate is kept by the host, which has no way to
+// undo it, so that the logs, the warm accounts and slots and the balances
+// moved by SELFDESTRUCT stay as at the end of the recording.
+use super::*;
+use host::JournalEntry;
+use num_bigint::BigUint;
+
+// number of steps between two checkpoints of a recording
+pub const CHECKPOINT_INTERVAL: usize = 1024;
+
+// storage slot, by address and key
+pub type Slot = ([u8; 20], [u8; 32]);
+
+// Snapshot is the machine state before an instruction
+#[derive(Clone, Debug, PartialEq)]
+pub struct Snapshot {
+    pub pc: usize,
+    pub stack: Vec<[u8; 32]>,
+    pub mem: Vec<u8>,
+    // world state, with the storage of the accounts. With a host, the local
+    // world state of the Stack, with the slots written during the recording.
+    pub state: state::State,
+    pub gas: u64,
+    pub refund: i64,
+    // call depth, from 1 for the frame of the transaction
+    pub depth: usize,
+}
+
+impl Snapshot {
+    pub fn of(vm: &Stack) -> Snapshot {
+        Snapshot {
+            pc: vm.pc,
+            stack: vm.stack.clone(),
+            mem: vm.mem.clone(),
+            state: vm.state.clone(),
+            gas: vm.gas,
+            refund: vm.refund,
+            depth: vm.depth + 1,
+        }
+    }
+
+    // frame returns the snapshot without its world state
+    fn frame(&self) -> Snapshot {
+        Snapshot {
+            pc: self.pc,
+            stack: self.stack.clone(),
+            mem: self.mem.clone(),
+            state: state::State::new(),
+            gas: self.gas,
+            refund: self.refund,
+            depth: self.depth,
+        }
+    }
+
+    // apply moves the snapshot forward by the delta
+    pub fn apply(&mut self, d: &Delta) {
+        self.pc = d.pc.1;
+        self.gas = d.gas.1;
+        self.refund = d.refund.1;
+        self.stack.truncate(d.stack_keep);
+        self.stack.extend(d.stack.1.iter().copied());
+        self.mem.resize(d.mem_size.1, 0);
+        write_mem(&mut self.mem, d.mem_offset, &d.mem.1);
+        for ((address, key), _, after) in d.storage.iter() {
+            self.state.set_storage(address, key, *after);
+        }
+        for (address, _, after) in d.balances.iter() {
+            self.state.account(address).balance = after.clone();
+        }
+    }
+}
+
+// Delta holds the changes made by one instruction, as (before, after) pairs
+#[derive(Clone, Debug, PartialEq)]
+pub struct Delta {
+    pub pc: (usize, usize),
+    pub gas: (u64, u64),
+    pub refund: (i64, i64),
+    pub calldata_i: (usize, usize),
+    // the stack items above the first stack_keep ones, which are unchanged
+    pub stack_keep: usize,
+    pub stack: (Vec<[u8; 32]>, Vec<[u8; 32]>),
+    // memory size, and the bytes changed from mem_offset. Memory only grows,
+    // the bytes before being zeros past the old size.
+    pub mem_size: (usize, usize),
+    pub mem_offset: usize,
+    pub mem: (Vec<u8>, Vec<u8>),
+    // written slots, with their value before and after
+    pub storage: Vec<(Slot, [u8; 32], [u8; 32])>,
+    // balances moved by SELFDESTRUCT, before and after
+    pub balances: Vec<([u8; 20], BigUint, BigUint)>,
+    // accounts created by the writes above
+    pub created: Vec<[u8; 20]>,
+    // slots and accounts made warm, and slots whose original value got
+    // stored (EIP-2200) with that value
+    pub accessed: Vec<Slot>,
+    pub accessed_accounts: Vec<[u8; 20]>,
+    // accounts destructed by SELFDESTRUCT
+    pub destructed: Vec<[u8; 20]>,
+    pub committed: Vec<(Slot, [u8; 32])>,
+    pub logs: Vec<block::Log>,
+    // entries added to the journal of the Stack
+    pub journal: Vec<JournalEntry>,
+    // output, or error, when the instruction halted the execution
+    pub result: Option<Result<Vec<u8>, String>>,
+}
+
+impl Delta {
+    // accounts returns the accounts whose state the delta writes
+    fn accounts(&self) -> impl Iterator<Item = &[u8; 20]> {
+        let slots = self.storage.iter().map(|((address, _), _, _)| address);
+        slots.chain(self.balances.iter().map(|(address, _, _)| address))
+    }
+
+    // undo moves the machine back to the state before the instruction
+    pub fn undo(&self, vm: &mut Stack) {
+        vm.pc = self.pc.0;
+        vm.gas = self.gas.0;
+        vm.refund = self.refund.0;
+        vm.calldata_i = self.calldata_i.0;
+        vm.stack.truncate(self.stack_keep);
+        vm.stack.extend(self.stack.0.iter().copied());
+        write_mem(&mut vm.mem, self.mem_offset, &self.mem.0);
+        vm.mem.truncate(self.mem_size.0);
+        for ((address, key), before, _) in self.storage.iter() {
+            set_storage(vm, address, key, *before);
+        }
+        for (address, before, _) in self.balances.iter() {
+            vm.state.account(address).balance = before.clone();
+        }
+        for address in self.created.iter() {
+            vm.state.accounts.remove(address);
+        }
+        for slot in self.accessed.iter() {
+            vm.accessed_storage.remove(slot);
+        }
+        for address in self.accessed_accounts.iter() {
+            vm.accessed_accounts.remove(address);
+        }
+        for address in self.destructed.iter() {
+            vm.destructed.remove(address);
+        }
+        for (slot, _) in self.committed.iter() {
+            vm.storage_committed.remove(slot);
+        }
+        let l = vm.logs.len() - self.logs.len();
+        vm.logs.truncate(l);
+        let l = vm.journal.len() - self.journal.len();
+        vm.journal.truncate(l);
+    }
+
+    // redo moves the machine to the state after the instruction
+    pub fn redo(&self, vm: &mut Stack) {
+        vm.pc = self.pc.1;
+        vm.gas = self.gas.1;
+        vm.refund = self.refund.1;
+        vm.calldata_i = self.calldata_i.1;
+        vm.stack.truncate(self.stack_keep);
+        vm.stack.extend(self.stack.1.iter().copied());
+        vm.mem.resize(self.mem_size.1, 0);
+        write_mem(&mut vm.mem, self.mem_offset, &self.mem.1);
+        for ((address, key), _, after) in self.storage.iter() {
+            set_storage(vm, address, key, *after);
+        }
+        for (address, _, after) in self.balances.iter() {
+            vm.state.account(address).balance = after.clone();
+        }
+        vm.accessed_storage.extend(self.accessed.iter().copied());
+        vm.accessed_accounts
+            .extend(self.accessed_accounts.iter().copied());
+        vm.destructed.extend(self.destructed.iter().copied());
+        for (slot, original) in self.committed.iter() {
+            vm.storage_committed.insert(*slot, *original);
+        }
+        vm.logs.extend(self.logs.iter().cloned());
+        vm.journal.extend(self.journal.iter().cloned());
+    }
+}
+
+fn write_mem(mem: &mut [u8], offset: usize, data: &[u8]) {
+    mem[offset..offset + data.len()].copy_from_slice(data);
+}
+
+// set_storage writes the slot through the host, or to the world state when
+// none is set, without going through LocalHost which would journal it
+fn set_storage(vm: &mut Stack, address: &[u8; 20], key: &[u8; 32], value: [u8; 32]) {
+    match vm.host.as_deref_mut() {
+        Some(h) => {
+            h.set_storage(address, key, value);
+        }
+        None => vm.state.set_storage(address, key, value),
+    }
+}
+
+// written_mem returns the memory range, as offset and size, the instruction
+// writes to, from its operands on the stack
+fn written_mem(opcode: u8, stack: &[[u8; 32]]) -> Option<(usize, usize)> {
+    let arg = |i: usize| {
+        let j = stack.len().checked_sub(i + 1)?;
+        Some(u256::u256_to_u64_saturating(stack[j]) as usize)
+    };
+    match opcode {
+        0x52 => Some((arg(0)?, 32)),
+        0x53 => Some((arg(0)?, 1)),
+        // CALLDATACOPY, CODECOPY, RETURNDATACOPY and MCOPY
+        0x37 | 0x39 | 0x3e | 0x5e => Some((arg(0)?, arg(2)?)),
+        0x3c => Some((arg(1)?, arg(3)?)),
+        // the output of a call to a precompile
+        0xf1 => Some((arg(5)?, arg(6)?)),
+        0xfa => Some((arg(4)?, arg(5)?)),
+        _ => None,
+    }
+}
+
+// step executes the instruction at pc like Stack::step, returning its result
+// and the delta it made
+pub fn step(
+    vm: &mut Stack,
+    code: &[u8],
+    calldata: &[u8],
+) -> (Delta, Result<Option<Vec<u8>>, String>) {
+    // past the end of the code, as after a truncated PUSH, is a STOP
+    let opcode = code.get(vm.pc).copied().unwrap_or(0x00);
+    let pc = vm.pc;
+    let gas = vm.gas;
+    let refund = vm.refund;
+    let calldata_i = vm.calldata_i;
+    // the instruction only changes the items it takes, and the bytes of
+    // memory it writes to
+    let ins = opcodes::table()
+        .get(&opcode)
+        .map_or(vm.stack.len(), |o| o.ins as usize);
+    let stack_base = vm.stack.len().saturating_sub(ins);
+    let stack = vm.stack[stack_base..].to_vec();
+    let mem_size = vm.mem.len();
+    let written = written_mem(opcode, &vm.stack).map(|(offset, size)| {
+        let end = offset.saturating_add(size).min(mem_size);
+        (offset, size, vm.mem[offset.min(end)..end].to_vec())
+    });
+    let logs = vm.logs.len();
+    let journal = vm.journal.len();
+    // with a host, the slot of SLOAD and SSTORE with its value before the
+    // instruction
+    let slot = match (opcode, vm.stack.last(), &vm.host) {
+        (0x54, Some(key), Some(_)) | (0x55, Some(key), Some(_)) => {
+            Some(((vm.address, *key), vm.get_storage(key)))
+        }
+        _ => None,
+    };
+
+    let r = vm.step(code, calldata);
+
+    let stack_keep = stack_base
+        + stack
+            .iter()
+            .zip(vm.stack[stack_base.min(vm.stack.len())..].iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+    let (mem_offset, mem_before, mem_after) = match written {
+        Some((offset, size, old)) => {
+            let byte = |i: usize| old.get(i - offset).copied().unwrap_or(0);
+            let end = offset.saturating_add(size).min(vm.mem.len());
+            let first = (offset..end).find(|i| byte(*i) != vm.mem[*i]);
+            match first {
+                Some(first) => {
+                    let last = (first..end)
+                        .rev()
+                        .find(|i| byte(*i) != vm.mem[*i])
+                        .unwrap_or(first);
+                    let before = (first..=last).map(byte).collect();
+                    (first, before, vm.mem[first..=last].to_vec())
+                }
+                None => (0, Vec::new(), Vec::new()),
+            }
+        }
+        None => (0, Vec::new(), Vec::new()),
+    };
+    let mut delta = Delta {
+        pc: (pc, vm.pc),
+        gas: (gas, vm.gas),
+        refund: (refund, vm.refund),
+        calldata_i: (calldata_i, vm.calldata_i),
+        stack_keep,
+        stack: (
+            stack[stack_keep - stack_base..].to_vec(),
+            vm.stack[stack_keep..].to_vec(),
+        ),
+        mem_size: (mem_size, vm.mem.len()),
+        mem_offset,
+        mem: (mem_before, mem_after),
+        storage: Vec::new(),
+        balances: Vec::new(),
+        created: Vec::new(),
+        accessed: Vec::new(),
+        accessed_accounts: Vec::new(),
+        destructed: Vec::new(),
+        committed: Vec::new(),
+        logs: vm.logs[logs..].to_vec(),
+        journal: vm.journal[journal..].to_vec(),
+        result: match &r {
+            Ok(Some(output)) => Some(Ok(output.clone())),
+            Ok(None) => None,
+            Err(e) => Some(Err(e.clone())),
+        },
+    };
+    if let Some((s, value)) = slot {
+        let new = vm.get_storage(&s.1);
+        if new != value {
+            delta.storage.push((s, value, new));
+        }
+    }
+    // the first journal entry of a slot or balance has its value before
+    for entry in delta.journal.clone() {
+        match entry {
+            JournalEntry::Created(address) => delta.created.push(address),
+            JournalEntry::Storage {
+                address,
+                key,
+                previous,
+                first_write,
+            } => {
+                let s = (address, key);
+                if first_write {
+                    delta.committed.push((s, vm.storage_committed[&s]));
+                }
+                if delta.storage.iter().all(|(w, _, _)| *w != s) {
+                    delta
+                        .storage
+                        .push((s, previous, vm.state.storage(&address, &key)));
+                }
+            }
+            JournalEntry::Balance { address, previous } => {
+                if delta.balances.iter().all(|(a, _, _)| *a != address) {
+                    let after = vm.state.balance(&address);
+                    delta.balances.push((address, previous, after));
+                }
+            }
+            JournalEntry::Log => {}
+            JournalEntry::AccessedAccount(address) => delta.accessed_accounts.push(address),
+            JournalEntry::AccessedStorage(address, key) => delta.accessed.push((address, key)),
+            JournalEntry::Destructed(address) => delta.destructed.push(address),
+        }
+    }
+    (delta, r)
+}
+
+pub struct Recording {
+    // number of the step the recording starts at
+    pub first: usize,
+    // state at the first step
+    pub start: Snapshot,
+    // changes of each step from the first one
+    pub deltas: Vec<Delta>,
+    // states before the steps first + (i + 1) * CHECKPOINT_INTERVAL, their
+    // world state holding the accounts written since the first step alone
+    pub checkpoints: Vec<Snapshot>,
+}
+
+impl Recording {
+    pub fn new(vm: &Stack, first: usize) -> Recording {
+        Recording {
+            first,
+            start: Snapshot::of(vm),
+            deltas: Vec::new(),
+            checkpoints: Vec::new(),
+        }
+    }
+
+    // end is the number of the step after the last recorded one
+    pub fn end(&self) -> usize {
+        self.first + self.deltas.len()
+    }
+
+    // delta returns the changes made by the given step
+    pub fn delta(&self, step: usize) -> Option<&Delta> {
+        step.checked_sub(self.first)
+            .and_then(|i| self.deltas.get(i))
+    }
+
+    // step executes the instruction at pc and records it as the step `end`.
+    // The machine must be in the state of the end of the recording.
+    pub fn step(
+        &mut self,
+        vm: &mut Stack,
+        code: &[u8],
+        calldata: &[u8],
+    ) -> Result<Option<Vec<u8>>, String> {
+        let (delta, r) = step(vm, code, calldata);
+        self.deltas.push(delta);
+        let n = self.deltas.len();
+        if n.is_multiple_of(CHECKPOINT_INTERVAL) {
+            let deltas = &self.deltas[n - CHECKPOINT_INTERVAL..];
+            let mut s = match self.checkpoints.last() {
+                Some(c) => c.clone(),
+                None => self.start.frame(),
+            };
+            // the accounts first written in the interval, from the start
+            for address in deltas.iter().flat_map(|d| d.accounts()) {
+                if !s.state.accounts.contains_key(address) {
+                    if let Some(a) = self.start.state.get(address) {
+                        s.state.accounts.insert(*address, a.clone());
+                    }
+                }
+            }
+            for d in deltas.iter() {
+                s.apply(d);
+            }
+            self.checkpoints.push(s);
+        }
+        r
+    }
+
+    // truncate drops the steps from the given one
+    pub fn truncate(&mut self, step: usize) {
+        let n = step.saturating_sub(self.first);
+        self.deltas.truncate(n);
+        self.checkpoints.truncate(n / CHECKPOINT_INTERVAL);
+    }
+
+    // snapshot rebuilds the state before the given step, from the checkpoint
+    // before it
+    pub fn snapshot(&self, step: usize) -> Result<Snapshot, String> {
+        if step < self.first || step > self.end() {
+            return Err(format!(
+                "step {} not recorded, the recording has steps {} to {}",
+                step,
+                self.first,
+                self.end()
+            ));
+        }
+        let n = step - self.first;
+        let i = n / CHECKPOINT_INTERVAL;
+        let mut s = self.start.clone();
+        if let Some(c) = i.checked_sub(1).map(|i| &self.checkpoints[i]) {
+            let state = std::mem::take(&mut s.state);
+            s = c.clone();
+            s.state = state;
+            s.state
+                .accounts
+                .extend(c.state.accounts.iter().map(|(k, v)| (*k, v.clone())));
+        }
+        for d in self.deltas[i * CHECKPOINT_INTERVAL..n].iter() {
+            s.apply(d);
+        }
+        Ok(s)
+    }
+}
+
+// record executes the code until it halts, recording each step
+pub fn record(vm: &mut Stack, code: &[u8], calldata: &[u8]) -> Recording {
+    let mut recording = Recording::new(vm, 0);
+    while vm.pc < code.len() {
+        match recording.step(vm, code, calldata) {
+            Ok(None) => {}
+            _ => break,
+        }
+    }
+    recording
+}
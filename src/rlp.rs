@@ -0,0 +1,317 @@
+// Recursive Length Prefix encoding
+// https://ethereum.org/en/developers/docs/data-structures-and-encoding/rlp/
+use num_bigint::BigUint;
+
+// maximum nesting of the lists of a decoded item, so that a crafted input
+// can not overflow the stack of the recursive decoder
+pub const MAX_DEPTH: usize = 1024;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Rlp {
+    Bytes(Vec<u8>),
+    List(Vec<Rlp>),
+}
+
+impl Rlp {
+    pub fn from_u64(n: u64) -> Rlp {
+        Rlp::Bytes(trim_leading_zeros(&n.to_be_bytes()).to_vec())
+    }
+    pub fn from_biguint(n: &BigUint) -> Rlp {
+        Rlp::Bytes(trim_leading_zeros(&n.to_bytes_be()).to_vec())
+    }
+    pub fn as_bytes(&self) -> Result<&[u8], String> {
+        match self {
+            Rlp::Bytes(b) => Ok(b),
+            Rlp::List(_) => Err("rlp: expected bytes, found list".to_string()),
+        }
+    }
+    pub fn as_list(&self) -> Result<&[Rlp], String> {
+        match self {
+            Rlp::List(l) => Ok(l),
+            Rlp::Bytes(_) => Err("rlp: expected list, found bytes".to_string()),
+        }
+    }
+    pub fn as_u64(&self) -> Result<u64, String> {
+        let b = self.as_integer_bytes()?;
+        if b.len() > 8 {
+            return Err("rlp: integer overflows u64".to_string());
+        }
+        let mut b8: [u8; 8] = [0; 8];
+        b8[8 - b.len()..].copy_from_slice(b);
+        Ok(u64::from_be_bytes(b8))
+    }
+    pub fn as_biguint(&self) -> Result<BigUint, String> {
+        let b = self.as_integer_bytes()?;
+        if b.len() > 32 {
+            return Err("rlp: integer overflows u256".to_string());
+        }
+        Ok(BigUint::from_bytes_be(b))
+    }
+    // as_integer_bytes returns the big endian bytes of an integer, which in its
+    // canonical form has no leading zeros (zero being the empty string)
+    fn as_integer_bytes(&self) -> Result<&[u8], String> {
+        let b = self.as_bytes()?;
+        if !b.is_empty() && b[0] == 0 {
+            return Err("rlp: integer with leading zeros".to_string());
+        }
+        Ok(b)
+    }
+    pub fn as_address(&self) -> Result<[u8; 20], String> {
+        let b = self.as_bytes()?;
+        if b.len() != 20 {
+            return Err(format!("rlp: invalid address length {}", b.len()));
+        }
+        let mut a: [u8; 20] = [0; 20];
+        a.copy_from_slice(b);
+        Ok(a)
+    }
+    pub fn as_hash(&self) -> Result<[u8; 32], String> {
+        let b = self.as_bytes()?;
+        if b.len() != 32 {
+            return Err(format!("rlp: invalid hash length {}", b.len()));
+        }
+        let mut h: [u8; 32] = [0; 32];
+        h.copy_from_slice(b);
+        Ok(h)
+    }
+}
+
+// Encodable is implemented by the types that have an RLP representation
+pub trait Encodable {
+    fn to_rlp(&self) -> Rlp;
+}
+
+pub trait Decodable: Sized {
+    fn from_rlp(item: &Rlp) -> Result<Self, String>;
+}
+
+// to_bytes returns the RLP encoding of the value
+pub fn to_bytes<T: Encodable + ?Sized>(v: &T) -> Vec<u8> {
+    encode(&v.to_rlp())
+}
+
+// from_bytes decodes a value from its RLP encoding
+pub fn from_bytes<T: Decodable>(data: &[u8]) -> Result<T, String> {
+    T::from_rlp(&decode(data)?)
+}
+
+// encode_list encodes the items as a list. Byte strings are encoded through
+// Vec<u8>, so lists of other types go through this function.
+pub fn encode_list<T: Encodable>(items: &[T]) -> Rlp {
+    Rlp::List(items.iter().map(|i| i.to_rlp()).collect())
+}
+
+pub fn decode_list<T: Decodable>(item: &Rlp) -> Result<Vec<T>, String> {
+    item.as_list()?.iter().map(T::from_rlp).collect()
+}
+
+impl Encodable for Rlp {
+    fn to_rlp(&self) -> Rlp {
+        self.clone()
+    }
+}
+impl Decodable for Rlp {
+    fn from_rlp(item: &Rlp) -> Result<Rlp, String> {
+        Ok(item.clone())
+    }
+}
+
+impl Encodable for u64 {
+    fn to_rlp(&self) -> Rlp {
+        Rlp::from_u64(*self)
+    }
+}
+impl Decodable for u64 {
+    fn from_rlp(item: &Rlp) -> Result<u64, String> {
+        item.as_u64()
+    }
+}
+
+impl Encodable for bool {
+    fn to_rlp(&self) -> Rlp {
+        Rlp::from_u64(*self as u64)
+    }
+}
+impl Decodable for bool {
+    fn from_rlp(item: &Rlp) -> Result<bool, String> {
+        match item.as_u64()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err("rlp: invalid bool".to_string()),
+        }
+    }
+}
+
+impl Encodable for BigUint {
+    fn to_rlp(&self) -> Rlp {
+        Rlp::from_biguint(self)
+    }
+}
+impl Decodable for BigUint {
+    fn from_rlp(item: &Rlp) -> Result<BigUint, String> {
+        item.as_biguint()
+    }
+}
+
+impl Encodable for Vec<u8> {
+    fn to_rlp(&self) -> Rlp {
+        Rlp::Bytes(self.clone())
+    }
+}
+impl Decodable for Vec<u8> {
+    fn from_rlp(item: &Rlp) -> Result<Vec<u8>, String> {
+        Ok(item.as_bytes()?.to_vec())
+    }
+}
+
+impl Encodable for [u8] {
+    fn to_rlp(&self) -> Rlp {
+        Rlp::Bytes(self.to_vec())
+    }
+}
+
+impl Encodable for str {
+    fn to_rlp(&self) -> Rlp {
+        Rlp::Bytes(self.as_bytes().to_vec())
+    }
+}
+
+impl Encodable for [u8; 20] {
+    fn to_rlp(&self) -> Rlp {
+        Rlp::Bytes(self.to_vec())
+    }
+}
+impl Decodable for [u8; 20] {
+    fn from_rlp(item: &Rlp) -> Result<[u8; 20], String> {
+        item.as_address()
+    }
+}
+
+impl Encodable for [u8; 32] {
+    fn to_rlp(&self) -> Rlp {
+        Rlp::Bytes(self.to_vec())
+    }
+}
+impl Decodable for [u8; 32] {
+    fn from_rlp(item: &Rlp) -> Result<[u8; 32], String> {
+        item.as_hash()
+    }
+}
+
+pub fn encode(item: &Rlp) -> Vec<u8> {
+    match item {
+        Rlp::Bytes(b) => {
+            if b.len() == 1 && b[0] < 0x80 {
+                return b.clone();
+            }
+            let mut r = encode_length(b.len(), 0x80);
+            r.extend_from_slice(b);
+            r
+        }
+        Rlp::List(l) => {
+            let mut payload = Vec::new();
+            for i in l.iter() {
+                payload.append(&mut encode(i));
+            }
+            let mut r = encode_length(payload.len(), 0xc0);
+            r.append(&mut payload);
+            r
+        }
+    }
+}
+
+fn encode_length(len: usize, offset: u8) -> Vec<u8> {
+    if len < 56 {
+        return vec![offset + len as u8];
+    }
+    let len_bytes = trim_leading_zeros(&len.to_be_bytes()).to_vec();
+    let mut r = vec![offset + 55 + len_bytes.len() as u8];
+    r.extend_from_slice(&len_bytes);
+    r
+}
+
+// decode decodes a single item, which must span the whole input
+pub fn decode(data: &[u8]) -> Result<Rlp, String> {
+    let (item, consumed) = decode_item(data, 0)?;
+    if consumed != data.len() {
+        return Err("rlp: trailing bytes".to_string());
+    }
+    Ok(item)
+}
+
+// decode_item decodes the first item of the input, inside depth lists,
+// returning it together with the number of bytes it takes
+fn decode_item(data: &[u8], depth: usize) -> Result<(Rlp, usize), String> {
+    if data.is_empty() {
+        return Err("rlp: unexpected end of input".to_string());
+    }
+    let prefix = data[0];
+    if prefix < 0x80 {
+        return Ok((Rlp::Bytes(vec![prefix]), 1));
+    }
+    let (offset, len, is_list) = if prefix < 0xb8 {
+        let len = (prefix - 0x80) as usize;
+        if len == 1 && data.len() > 1 && data[1] < 0x80 {
+            return Err("rlp: non-canonical single byte".to_string());
+        }
+        (1, len, false)
+    } else if prefix < 0xc0 {
+        let n = (prefix - 0xb7) as usize;
+        (1 + n, decode_length(&data[1..], n)?, false)
+    } else if prefix < 0xf8 {
+        (1, (prefix - 0xc0) as usize, true)
+    } else {
+        let n = (prefix - 0xf7) as usize;
+        (1 + n, decode_length(&data[1..], n)?, true)
+    };
+    let end = offset
+        .checked_add(len)
+        .ok_or_else(|| "rlp: length overflow".to_string())?;
+    if end > data.len() {
+        return Err("rlp: unexpected end of input".to_string());
+    }
+    let payload = &data[offset..end];
+
+    if !is_list {
+        return Ok((Rlp::Bytes(payload.to_vec()), end));
+    }
+    if depth >= MAX_DEPTH {
+        return Err("rlp: nesting too deep".to_string());
+    }
+    let mut items = Vec::new();
+    let mut i = 0;
+    while i < payload.len() {
+        let (item, consumed) = decode_item(&payload[i..], depth + 1)?;
+        items.push(item);
+        i += consumed;
+    }
+    Ok((Rlp::List(items), end))
+}
+
+// decode_length reads the n bytes long length of a long string or list. Its
+// canonical form has no leading zeros, and is only used for lengths of 56 or
+// more.
+fn decode_length(data: &[u8], n: usize) -> Result<usize, String> {
+    if data.len() < n {
+        return Err("rlp: unexpected end of input".to_string());
+    }
+    if n > 8 {
+        return Err("rlp: length overflow".to_string());
+    }
+    if data[0] == 0 {
+        return Err("rlp: length with leading zeros".to_string());
+    }
+    let mut len: usize = 0;
+    for b in data[..n].iter() {
+        len = (len << 8) | *b as usize;
+    }
+    if len < 56 {
+        return Err("rlp: non-canonical length".to_string());
+    }
+    Ok(len)
+}
+
+fn trim_leading_zeros(b: &[u8]) -> &[u8] {
+    let i = b.iter().position(|x| *x != 0).unwrap_or(b.len());
+    &b[i..]
+}
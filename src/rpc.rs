@@ -0,0 +1,666 @@
+// JSON-RPC server over a Devnet, with the subset of the eth namespace used by
+// the web3 tooling to deploy and call contracts, so that they can point at it
+// as at a local node. The state is only kept for the latest block, so the
+// state queries at older blocks fail. The HTTP server is single threaded,
+// closing the connection after each request.
+// https://ethereum.org/en/developers/docs/apis/json-rpc/
+use super::*;
+use block::Log;
+use devnet::{Devnet, MinedBlock};
+use envelope::SignedTransaction;
+use json::{
+    hex_biguint, hex_bytes, hex_u64, log_to_json, parse_access_list, parse_address, parse_biguint,
+    parse_bytes, parse_u64, parse_word,
+};
+use num_bigint::BigUint;
+use serde_json::{json, Map, Value};
+use simulate::Overrides;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::time::Duration;
+use tracers::TraceConfig;
+use transaction::{Transaction, DYNAMIC_FEE_TX_TYPE, LEGACY_TX_TYPE};
+
+pub const PARSE_ERROR: i64 = -32700;
+pub const INVALID_REQUEST: i64 = -32600;
+pub const METHOD_NOT_FOUND: i64 = -32601;
+pub const INVALID_PARAMS: i64 = -32602;
+pub const SERVER_ERROR: i64 = -32000;
+
+// limits of the HTTP server, the body one being geth's
+pub const MAX_BODY_SIZE: usize = 5 * 1024 * 1024;
+const MAX_HEADER_SIZE: usize = 64 * 1024;
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+// Error is a JSON-RPC error
+#[derive(Clone, Debug, PartialEq)]
+pub struct Error {
+    pub code: i64,
+    pub message: String,
+}
+
+impl Error {
+    fn new(code: i64, message: impl Into<String>) -> Error {
+        Error {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+// errors of the devnet and of the execution are server errors
+impl From<String> for Error {
+    fn from(message: String) -> Error {
+        Error::new(SERVER_ERROR, message)
+    }
+}
+
+// handle answers a request, or a batch of requests. None is returned for a
+// batch of notifications only, which has no response.
+pub fn handle(devnet: &mut Devnet, request: &Value) -> Option<Value> {
+    match request {
+        Value::Array(batch) if !batch.is_empty() => {
+            let responses: Vec<Value> =
+                batch.iter().filter_map(|r| handle_one(devnet, r)).collect();
+            if responses.is_empty() {
+                return None;
+            }
+            Some(Value::Array(responses))
+        }
+        _ => handle_one(devnet, request),
+    }
+}
+
+// handle_str answers a request given as text, as received by the server
+pub fn handle_str(devnet: &mut Devnet, request: &str) -> Option<Value> {
+    match serde_json::from_str::<Value>(request) {
+        Ok(request) => handle(devnet, &request),
+        Err(e) => Some(response(
+            &Value::Null,
+            Err(Error::new(PARSE_ERROR, e.to_string())),
+        )),
+    }
+}
+
+fn handle_one(devnet: &mut Devnet, request: &Value) -> Option<Value> {
+    let id = request.get("id").cloned();
+    let method = match request.get("method").and_then(|m| m.as_str()) {
+        Some(method) => method,
+        None => {
+            return Some(response(
+                &Value::Null,
+                Err(Error::new(INVALID_REQUEST, "invalid request")),
+            ))
+        }
+    };
+    let params = match request.get("params") {
+        Some(Value::Array(params)) => params.clone(),
+        None | Some(Value::Null) => Vec::new(),
+        Some(_) => {
+            let e = Error::new(INVALID_PARAMS, "params must be an array");
+            return id.map(|id| response(&id, Err(e)));
+        }
+    };
+    // a bug hit by the request fails it alone, not the node
+    let r = catch_panic(|| Ok(call(devnet, method, &params))).unwrap_or_else(|e| Err(e.into()));
+    // requests without id are notifications
+    id.map(|id| response(&id, r))
+}
+
+fn response(id: &Value, r: Result<Value, Error>) -> Value {
+    match r {
+        Ok(result) => json!({"jsonrpc": "2.0", "id": id, "result": result}),
+        Err(e) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": {"code": e.code, "message": e.message},
+        }),
+    }
+}
+
+// call runs the method
+pub fn call(devnet: &mut Devnet, method: &str, params: &[Value]) -> Result<Value, Error> {
+    let param = |i: usize| params.get(i).unwrap_or(&Value::Null);
+    match method {
+        "web3_clientVersion" => Ok(Value::from(format!(
+            "evm-rs/v{}",
+            env!("CARGO_PKG_VERSION")
+        ))),
+        "net_version" => Ok(Value::from(devnet.chain_id.to_string())),
+        "eth_chainId" => Ok(Value::from(hex_u64(devnet.chain_id))),
+        "eth_blockNumber" => Ok(Value::from(hex_u64(devnet.block_number()))),
+        "eth_gasPrice" => {
+            let base_fee = devnet.pending_header().base_fee.unwrap_or_default();
+            Ok(Value::from(hex_biguint(&(base_fee + 1u64))))
+        }
+        "eth_maxPriorityFeePerGas" => Ok(Value::from(hex_u64(1))),
+        "eth_getBalance" => {
+            let address = invalid_params(parse_address(param(0)))?;
+            latest_state(devnet, param(1))?;
+            Ok(Value::from(hex_biguint(&devnet.state.balance(&address))))
+        }
+        "eth_getTransactionCount" => {
+            let address = invalid_params(parse_address(param(0)))?;
+            latest_state(devnet, param(1))?;
+            Ok(Value::from(hex_u64(devnet.state.nonce(&address))))
+        }
+        "eth_getCode" => {
+            let address = invalid_params(parse_address(param(0)))?;
+            latest_state(devnet, param(1))?;
+            Ok(Value::from(hex_bytes(&devnet.state.code(&address))))
+        }
+        "eth_getStorageAt" => {
+            let address = invalid_params(parse_address(param(0)))?;
+            let key = invalid_params(parse_word(param(1)))?;
+            latest_state(devnet, param(2))?;
+            Ok(Value::from(hex_bytes(
+                &devnet.state.storage(&address, &key),
+            )))
+        }
+        "eth_call" => {
+            let tx = invalid_params(parse_call(devnet, param(0)))?;
+            latest_state(devnet, param(1))?;
+            let overrides = invalid_params(Overrides::from_json(param(2), param(3)))?;
+            let r = devnet.call(&tx, &overrides)?;
+            match r.error {
+                Some(e) => Err(Error::new(SERVER_ERROR, e)),
+                None => Ok(Value::from(hex_bytes(&r.output))),
+            }
+        }
+        "eth_estimateGas" => {
+            let tx = invalid_params(parse_call(devnet, param(0)))?;
+            latest_state(devnet, param(1))?;
+            let overrides = invalid_params(Overrides::from_json(param(2), &Value::Null))?;
+            Ok(Value::from(hex_u64(devnet.estimate_gas(&tx, &overrides)?)))
+        }
+        "eth_sendRawTransaction" => {
+            let raw = invalid_params(parse_bytes(param(0)))?;
+            let stx = invalid_params(envelope::decode(&raw))?;
+            let hash = devnet.send_transaction(stx)?;
+            Ok(Value::from(hex_bytes(&hash)))
+        }
+        "eth_getTransactionByHash" => {
+            let hash = invalid_params(parse_word(param(0)))?;
+            if let Some((b, i)) = devnet.transaction(&hash) {
+                return Ok(transaction_to_json(b, i));
+            }
+            Ok(devnet
+                .pending
+                .iter()
+                .find(|stx| stx.hash() == hash)
+                .map_or(Value::Null, pending_transaction_to_json))
+        }
+        "eth_getTransactionReceipt" => {
+            let hash = invalid_params(parse_word(param(0)))?;
+            Ok(devnet
+                .transaction(&hash)
+                .map_or(Value::Null, |(b, i)| receipt_to_json(b, i)))
+        }
+        "eth_getBlockByNumber" => {
+            let number = block_number(devnet, param(0))?;
+            let full = param(1).as_bool().unwrap_or(false);
+            Ok(devnet
+                .block_by_number(number)
+                .map_or(Value::Null, |b| block_to_json(b, full)))
+        }
+        "eth_getBlockByHash" => {
+            let hash = invalid_params(parse_word(param(0)))?;
+            let full = param(1).as_bool().unwrap_or(false);
+            Ok(devnet
+                .block_by_hash(&hash)
+                .map_or(Value::Null, |b| block_to_json(b, full)))
+        }
+        "eth_getLogs" => get_logs(devnet, param(0)),
+        "debug_traceTransaction" => {
+            let hash = invalid_params(parse_word(param(0)))?;
+            let trace_config = invalid_params(TraceConfig::from_json(param(1)))?;
+            Ok(devnet.trace_transaction(&hash, &trace_config)?)
+        }
+        "debug_traceCall" => {
+            let tx = invalid_params(parse_call(devnet, param(0)))?;
+            latest_state(devnet, param(1))?;
+            let trace_config = invalid_params(TraceConfig::from_json(param(2)))?;
+            // the overrides are given with the tracing options
+            let overrides = invalid_params(Overrides::from_json(
+                param(2).get("stateOverrides").unwrap_or(&Value::Null),
+                param(2).get("blockOverrides").unwrap_or(&Value::Null),
+            ))?;
+            Ok(devnet.trace_call(&tx, &overrides, &trace_config)?)
+        }
+        "evm_mine" => {
+            devnet.mine();
+            Ok(Value::from("0x0"))
+        }
+        _ => Err(Error::new(
+            METHOD_NOT_FOUND,
+            format!("the method {} does not exist/is not available", method),
+        )),
+    }
+}
+
+fn invalid_params<T>(r: Result<T, String>) -> Result<T, Error> {
+    r.map_err(|e| Error::new(INVALID_PARAMS, e))
+}
+
+// block_number resolves a block tag or number, the latest block by default
+fn block_number(devnet: &Devnet, tag: &Value) -> Result<u64, Error> {
+    match tag.as_str() {
+        None if tag.is_null() => Ok(devnet.block_number()),
+        Some("latest") | Some("pending") | Some("safe") | Some("finalized") => {
+            Ok(devnet.block_number())
+        }
+        Some("earliest") => Ok(devnet.blocks[0].number()),
+        _ => invalid_params(parse_u64(tag)),
+    }
+}
+
+// latest_state checks that the block of a state query is the latest one, the
+// only one whose state is kept
+fn latest_state(devnet: &Devnet, tag: &Value) -> Result<(), Error> {
+    // the pending state is the latest one, transactions being mined at once
+    if tag.as_str() == Some("pending") {
+        return Ok(());
+    }
+    if let Some(hash) = tag.get("blockHash") {
+        let hash = invalid_params(parse_word(hash))?;
+        if hash != devnet.latest().hash() {
+            return Err(Error::new(SERVER_ERROR, "historical state not available"));
+        }
+        return Ok(());
+    }
+    let tag = tag.get("blockNumber").unwrap_or(tag);
+    if block_number(devnet, tag)? != devnet.block_number() {
+        return Err(Error::new(SERVER_ERROR, "historical state not available"));
+    }
+    Ok(())
+}
+
+// parse_call parses the transaction of eth_call and eth_estimateGas, whose
+// fields are all optional, the gas defaulting to the block gas limit
+fn parse_call(devnet: &Devnet, v: &Value) -> Result<Transaction, String> {
+    let opt = |field: &str| v.get(field).filter(|f| !f.is_null());
+    let mut tx = Transaction {
+        chain_id: Some(devnet.chain_id),
+        gas_limit: devnet.pending_header().gas_limit,
+        ..Transaction::default()
+    };
+    if let Some(from) = opt("from") {
+        tx.from = parse_address(from)?;
+    }
+    if let Some(to) = opt("to") {
+        tx.to = Some(parse_address(to)?);
+    }
+    if let Some(gas) = opt("gas") {
+        tx.gas_limit = parse_u64(gas)?;
+    }
+    if let Some(value) = opt("value") {
+        tx.value = parse_biguint(value)?;
+    }
+    if let Some(data) = opt("input").or(opt("data")) {
+        tx.data = parse_bytes(data)?;
+    }
+    match (opt("maxFeePerGas"), opt("gasPrice")) {
+        (Some(max_fee), _) => {
+            tx.tx_type = DYNAMIC_FEE_TX_TYPE;
+            tx.gas_price = parse_biguint(max_fee)?;
+            tx.max_priority_fee_per_gas = Some(match opt("maxPriorityFeePerGas") {
+                Some(tip) => parse_biguint(tip)?,
+                None => BigUint::default(),
+            });
+        }
+        (None, Some(gas_price)) => tx.gas_price = parse_biguint(gas_price)?,
+        (None, None) => {}
+    }
+    if let Some(access_list) = opt("accessList") {
+        tx.access_list = parse_access_list(access_list)?;
+    }
+    Ok(tx)
+}
+
+fn transaction_fields(stx: &SignedTransaction) -> Map<String, Value> {
+    let tx = &stx.tx;
+    let mut t = Map::new();
+    t.insert("hash".to_string(), Value::from(hex_bytes(&stx.hash())));
+    t.insert("type".to_string(), Value::from(hex_u64(tx.tx_type as u64)));
+    t.insert("from".to_string(), Value::from(hex_bytes(&tx.from)));
+    t.insert(
+        "to".to_string(),
+        tx.to.map_or(Value::Null, |to| Value::from(hex_bytes(&to))),
+    );
+    t.insert("nonce".to_string(), Value::from(hex_u64(tx.nonce)));
+    t.insert("gas".to_string(), Value::from(hex_u64(tx.gas_limit)));
+    t.insert("value".to_string(), Value::from(hex_biguint(&tx.value)));
+    t.insert("input".to_string(), Value::from(hex_bytes(&tx.data)));
+    if let Some(chain_id) = tx.chain_id {
+        t.insert("chainId".to_string(), Value::from(hex_u64(chain_id)));
+    }
+    if let Some(tip) = &tx.max_priority_fee_per_gas {
+        t.insert(
+            "maxFeePerGas".to_string(),
+            Value::from(hex_biguint(&tx.gas_price)),
+        );
+        t.insert(
+            "maxPriorityFeePerGas".to_string(),
+            Value::from(hex_biguint(tip)),
+        );
+    } else {
+        t.insert(
+            "gasPrice".to_string(),
+            Value::from(hex_biguint(&tx.gas_price)),
+        );
+    }
+    if tx.tx_type != LEGACY_TX_TYPE {
+        let access_list: Vec<Value> = tx
+            .access_list
+            .iter()
+            .map(|(address, keys)| {
+                let keys: Vec<String> = keys.iter().map(|k| hex_bytes(k)).collect();
+                json!({"address": hex_bytes(address), "storageKeys": keys})
+            })
+            .collect();
+        t.insert("accessList".to_string(), Value::Array(access_list));
+        t.insert("yParity".to_string(), Value::from(hex_u64(stx.v)));
+    }
+    t.insert("v".to_string(), Value::from(hex_u64(stx.v)));
+    t.insert("r".to_string(), Value::from(hex_biguint(&stx.r)));
+    t.insert("s".to_string(), Value::from(hex_biguint(&stx.s)));
+    t
+}
+
+fn pending_transaction_to_json(stx: &SignedTransaction) -> Value {
+    let mut t = transaction_fields(stx);
+    t.insert("blockHash".to_string(), Value::Null);
+    t.insert("blockNumber".to_string(), Value::Null);
+    t.insert("transactionIndex".to_string(), Value::Null);
+    Value::Object(t)
+}
+
+pub fn transaction_to_json(b: &MinedBlock, i: usize) -> Value {
+    let stx = &b.block.transactions[i];
+    let mut t = transaction_fields(stx);
+    t.insert("blockHash".to_string(), Value::from(hex_bytes(&b.hash())));
+    t.insert("blockNumber".to_string(), Value::from(hex_u64(b.number())));
+    t.insert(
+        "transactionIndex".to_string(),
+        Value::from(hex_u64(i as u64)),
+    );
+    // the price paid, as geth does for the mined transactions
+    if let Some(base_fee) = &b.block.header.base_fee {
+        t.insert(
+            "gasPrice".to_string(),
+            Value::from(hex_biguint(&stx.tx.effective_gas_price(base_fee))),
+        );
+    }
+    Value::Object(t)
+}
+
+// logs_to_json returns the logs of the i-th transaction of the block, with
+// their position
+fn logs_to_json(b: &MinedBlock, i: usize) -> Vec<Value> {
+    let receipts = &b.result.receipts;
+    let first = receipts[..i].iter().map(|r| r.logs.len()).sum::<usize>();
+    let tx_hash = hex_bytes(&b.block.transactions[i].hash());
+    receipts[i]
+        .logs
+        .iter()
+        .enumerate()
+        .map(|(j, log)| {
+            let mut l = log_to_json(log);
+            l["blockNumber"] = Value::from(hex_u64(b.number()));
+            l["blockHash"] = Value::from(hex_bytes(&b.hash()));
+            l["transactionHash"] = Value::from(tx_hash.clone());
+            l["transactionIndex"] = Value::from(hex_u64(i as u64));
+            l["logIndex"] = Value::from(hex_u64((first + j) as u64));
+            l["removed"] = Value::from(false);
+            l
+        })
+        .collect()
+}
+
+pub fn receipt_to_json(b: &MinedBlock, i: usize) -> Value {
+    let stx = &b.block.transactions[i];
+    let receipt = &b.result.receipts[i];
+    let r = &b.result.results[i];
+    let base_fee = b.block.header.base_fee.clone().unwrap_or_default();
+    let mut j = json!({
+        "transactionHash": hex_bytes(&stx.hash()),
+        "transactionIndex": hex_u64(i as u64),
+        "blockHash": hex_bytes(&b.hash()),
+        "blockNumber": hex_u64(b.number()),
+        "from": hex_bytes(&stx.tx.from),
+        "to": stx.tx.to.map(|to| hex_bytes(&to)),
+        "cumulativeGasUsed": hex_u64(receipt.cumulative_gas_used),
+        "gasUsed": hex_u64(r.gas_used),
+        "effectiveGasPrice": hex_biguint(&stx.tx.effective_gas_price(&base_fee)),
+        "contractAddress": r.contract_address.map(|a| hex_bytes(&a)),
+        "logs": logs_to_json(b, i),
+        "logsBloom": hex_bytes(&receipt.logs_bloom.0),
+        "type": hex_u64(receipt.tx_type as u64),
+    });
+    match receipt.post_state {
+        Some(root) => j["root"] = Value::from(hex_bytes(&root)),
+        None => j["status"] = Value::from(hex_u64(receipt.success as u64)),
+    }
+    j
+}
+
+pub fn block_to_json(b: &MinedBlock, full: bool) -> Value {
+    let h = &b.block.header;
+    let transactions: Vec<Value> = (0..b.block.transactions.len())
+        .map(|i| match full {
+            true => transaction_to_json(b, i),
+            false => Value::from(hex_bytes(&b.block.transactions[i].hash())),
+        })
+        .collect();
+    let mut j = json!({
+        "number": hex_u64(h.number),
+        "hash": hex_bytes(&b.hash()),
+        "parentHash": hex_bytes(&h.parent_hash),
+        "sha3Uncles": hex_bytes(&h.ommers_hash),
+        "miner": hex_bytes(&h.coinbase),
+        "stateRoot": hex_bytes(&h.state_root),
+        "transactionsRoot": hex_bytes(&h.transactions_root),
+        "receiptsRoot": hex_bytes(&h.receipts_root),
+        "logsBloom": hex_bytes(&h.logs_bloom.0),
+        "difficulty": hex_biguint(&h.difficulty),
+        "gasLimit": hex_u64(h.gas_limit),
+        "gasUsed": hex_u64(h.gas_used),
+        "timestamp": hex_u64(h.timestamp),
+        "extraData": hex_bytes(&h.extra_data),
+        "mixHash": hex_bytes(&h.mix_hash),
+        "nonce": hex_bytes(&h.nonce),
+        "size": hex_u64(rlp::to_bytes(&b.block).len() as u64),
+        "transactions": transactions,
+        "uncles": [],
+    });
+    if let Some(base_fee) = &h.base_fee {
+        j["baseFeePerGas"] = Value::from(hex_biguint(base_fee));
+    }
+    if let Some(root) = h.withdrawals_root {
+        j["withdrawalsRoot"] = Value::from(hex_bytes(&root));
+        j["withdrawals"] = json!([]);
+    }
+    if let Some(blob_gas_used) = h.blob_gas_used {
+        j["blobGasUsed"] = Value::from(hex_u64(blob_gas_used));
+    }
+    if let Some(excess_blob_gas) = h.excess_blob_gas {
+        j["excessBlobGas"] = Value::from(hex_u64(excess_blob_gas));
+    }
+    if let Some(root) = h.parent_beacon_block_root {
+        j["parentBeaconBlockRoot"] = Value::from(hex_bytes(&root));
+    }
+    if let Some(hash) = h.requests_hash {
+        j["requestsHash"] = Value::from(hex_bytes(&hash));
+    }
+    j
+}
+
+// LogFilter is the filter of eth_getLogs. A None topic matches any topic, the
+// topics of a position being alternatives.
+struct LogFilter {
+    addresses: Vec<[u8; 20]>,
+    topics: Vec<Option<Vec<[u8; 32]>>>,
+}
+
+impl LogFilter {
+    fn parse(v: &Value) -> Result<LogFilter, String> {
+        let addresses = match v.get("address") {
+            None | Some(Value::Null) => Vec::new(),
+            Some(Value::Array(a)) => a.iter().map(parse_address).collect::<Result<_, _>>()?,
+            Some(a) => vec![parse_address(a)?],
+        };
+        let mut topics = Vec::new();
+        for t in v
+            .get("topics")
+            .and_then(|t| t.as_array())
+            .into_iter()
+            .flatten()
+        {
+            topics.push(match t {
+                Value::Null => None,
+                Value::Array(a) => Some(a.iter().map(parse_word).collect::<Result<_, _>>()?),
+                t => Some(vec![parse_word(t)?]),
+            });
+        }
+        Ok(LogFilter { addresses, topics })
+    }
+
+    fn matches(&self, log: &Log) -> bool {
+        if !self.addresses.is_empty() && !self.addresses.contains(&log.address) {
+            return false;
+        }
+        self.topics.iter().enumerate().all(|(i, t)| match t {
+            None => true,
+            Some(alternatives) => log.topics.get(i).is_some_and(|t| alternatives.contains(t)),
+        })
+    }
+}
+
+fn get_logs(devnet: &Devnet, v: &Value) -> Result<Value, Error> {
+    let filter = invalid_params(LogFilter::parse(v))?;
+    let (from, to) = match v.get("blockHash") {
+        Some(hash) => {
+            let hash = invalid_params(parse_word(hash))?;
+            let b = devnet
+                .block_by_hash(&hash)
+                .ok_or_else(|| Error::new(SERVER_ERROR, "unknown block"))?;
+            (b.number(), b.number())
+        }
+        None => (
+            block_number(devnet, v.get("fromBlock").unwrap_or(&Value::Null))?,
+            block_number(devnet, v.get("toBlock").unwrap_or(&Value::Null))?,
+        ),
+    };
+    let mut logs = Vec::new();
+    for number in from..=to.min(devnet.block_number()) {
+        let b = match devnet.block_by_number(number) {
+            Some(b) => b,
+            None => continue,
+        };
+        for i in 0..b.block.transactions.len() {
+            for (log, l) in b.result.receipts[i].logs.iter().zip(logs_to_json(b, i)) {
+                if filter.matches(log) {
+                    logs.push(l);
+                }
+            }
+        }
+    }
+    Ok(Value::Array(logs))
+}
+
+// Server is the HTTP server of the JSON-RPC API
+pub struct Server {
+    listener: TcpListener,
+}
+
+impl Server {
+    // bind listens on the port of localhost, 0 choosing a free one
+    pub fn bind(port: u16) -> Result<Server, String> {
+        let listener = TcpListener::bind(("127.0.0.1", port))
+            .map_err(|e| format!("127.0.0.1:{}: {}", port, e))?;
+        Ok(Server { listener })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr, String> {
+        self.listener.local_addr().map_err(|e| e.to_string())
+    }
+
+    // run serves the requests until the process ends
+    pub fn run(&self, devnet: &mut Devnet) {
+        loop {
+            if let Err(e) = self.serve_connection(devnet) {
+                eprintln!("rpc: {}", e);
+            }
+        }
+    }
+
+    // serve_connection accepts a connection and answers its request
+    pub fn serve_connection(&self, devnet: &mut Devnet) -> Result<(), String> {
+        let (stream, _) = self.listener.accept().map_err(|e| e.to_string())?;
+        // a client that stops sending does not hold the server
+        stream
+            .set_read_timeout(Some(READ_TIMEOUT))
+            .map_err(|e| e.to_string())?;
+        stream
+            .set_write_timeout(Some(READ_TIMEOUT))
+            .map_err(|e| e.to_string())?;
+        let limit = (MAX_HEADER_SIZE + MAX_BODY_SIZE) as u64;
+        let mut reader = BufReader::new((&stream).take(limit));
+        let mut request_line = String::new();
+        reader
+            .read_line(&mut request_line)
+            .map_err(|e| e.to_string())?;
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).map_err(|e| e.to_string())?;
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value
+                        .trim()
+                        .parse()
+                        .map_err(|_| format!("invalid content length {}", value))?;
+                }
+            }
+        }
+        // preflight requests of the browsers
+        if request_line.starts_with("OPTIONS") {
+            return write_response(&stream, "204 No Content", "");
+        }
+        if !request_line.starts_with("POST") {
+            return write_response(&stream, "405 Method Not Allowed", "");
+        }
+        if content_length > MAX_BODY_SIZE {
+            return write_response(&stream, "413 Payload Too Large", "");
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).map_err(|e| e.to_string())?;
+        let body = String::from_utf8_lossy(&body);
+        let response = match handle_str(devnet, &body) {
+            Some(response) => response.to_string(),
+            None => String::new(),
+        };
+        write_response(&stream, "200 OK", &response)
+    }
+}
+
+fn write_response(mut stream: &TcpStream, status: &str, body: &str) -> Result<(), String> {
+    let response = format!(
+        "HTTP/1.1 {}\r\n\
+         Content-Type: application/json\r\n\
+         Content-Length: {}\r\n\
+         Access-Control-Allow-Origin: *\r\n\
+         Access-Control-Allow-Headers: *\r\n\
+         Connection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream
+        .write_all(response.as_bytes())
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,68 @@
+// secp256k1 signatures, as used by transactions and EIP-7702 authorizations
+use super::keccak256;
+use k256::ecdsa::{RecoveryId, Signature, SigningKey, VerifyingKey};
+use num_bigint::BigUint;
+
+// secp256k1 curve order n
+const SECP256K1N: &str =
+    "115792089237316195423570985008687907852837564279074904382605163141518161494337";
+
+pub fn secp256k1n() -> BigUint {
+    SECP256K1N.parse::<BigUint>().unwrap()
+}
+
+// recover_address returns the address that signed the hash. As required since
+// Homestead (EIP-2), s must be in the lower half of the curve order.
+pub fn recover_address(
+    hash: &[u8; 32],
+    y_parity: u8,
+    r: &BigUint,
+    s: &BigUint,
+) -> Result<[u8; 20], String> {
+    let n = secp256k1n();
+    if *s > n.clone() >> 1 {
+        return Err("invalid signature: s value too high".to_string());
+    }
+    if *r >= n || *s >= n {
+        return Err("invalid signature: r or s out of range".to_string());
+    }
+    let recid = RecoveryId::from_byte(y_parity)
+        .ok_or_else(|| format!("invalid signature: y parity {}", y_parity))?;
+    let signature = Signature::from_scalars(to_bytes32(r), to_bytes32(s))
+        .map_err(|_| "invalid signature".to_string())?;
+    let key = VerifyingKey::recover_from_prehash(hash, &signature, recid)
+        .map_err(|_| "invalid signature: recovery failed".to_string())?;
+    Ok(public_key_to_address(&key))
+}
+
+// sign signs the hash with the secret key, returning (y_parity, r, s)
+pub fn sign(hash: &[u8; 32], secret_key: &[u8; 32]) -> Result<(u8, BigUint, BigUint), String> {
+    let key = SigningKey::from_slice(secret_key).map_err(|_| "invalid secret key".to_string())?;
+    let (signature, recid) = key
+        .sign_prehash_recoverable(hash)
+        .map_err(|e| e.to_string())?;
+    Ok((
+        recid.to_byte(),
+        BigUint::from_bytes_be(&signature.r().to_bytes()),
+        BigUint::from_bytes_be(&signature.s().to_bytes()),
+    ))
+}
+
+pub fn secret_key_to_address(secret_key: &[u8; 32]) -> Result<[u8; 20], String> {
+    let key = SigningKey::from_slice(secret_key).map_err(|_| "invalid secret key".to_string())?;
+    Ok(public_key_to_address(key.verifying_key()))
+}
+
+fn public_key_to_address(key: &VerifyingKey) -> [u8; 20] {
+    let point = key.to_encoded_point(false);
+    let mut address: [u8; 20] = [0; 20];
+    address.copy_from_slice(&keccak256(&point.as_bytes()[1..])[12..]);
+    address
+}
+
+fn to_bytes32(n: &BigUint) -> [u8; 32] {
+    let b = n.to_bytes_be();
+    let mut r: [u8; 32] = [0; 32];
+    r[32 - b.len()..].copy_from_slice(&b);
+    r
+}
@@ -0,0 +1,178 @@
+// Call simulation, as eth_call does: the transaction is executed on a copy of
+// the state, without checking its nonce, and with temporary overrides of
+// accounts (code, balance, nonce, storage) and of the block environment, to
+// see the effect of a patched contract or of another block.
+// https://geth.ethereum.org/docs/interacting-with-geth/rpc/ns-eth#eth-call
+use super::*;
+use json::{parse_biguint, parse_bytes, parse_u64, parse_word};
+use num_bigint::BigUint;
+use num_traits::identities::Zero;
+use serde_json::Value;
+use spec::Config;
+use state::State;
+use std::collections::{BTreeMap, HashMap};
+use transaction::{BlockEnv, ExecutionResult, Transaction};
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct AccountOverride {
+    pub balance: Option<BigUint>,
+    pub nonce: Option<u64>,
+    pub code: Option<Vec<u8>>,
+    // replaces the whole storage of the account
+    pub state: Option<HashMap<[u8; 32], [u8; 32]>>,
+    // replaces the given slots, keeping the others
+    pub state_diff: HashMap<[u8; 32], [u8; 32]>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct BlockOverrides {
+    pub number: Option<u64>,
+    pub timestamp: Option<u64>,
+    pub base_fee: Option<BigUint>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Overrides {
+    pub accounts: BTreeMap<[u8; 20], AccountOverride>,
+    pub block: BlockOverrides,
+}
+
+fn parse_storage(v: &Value) -> Result<HashMap<[u8; 32], [u8; 32]>, String> {
+    let slots = v
+        .as_object()
+        .ok_or_else(|| format!("invalid storage {}", v))?;
+    let mut storage = HashMap::new();
+    for (key, value) in slots.iter() {
+        storage.insert(parse_word(&Value::from(key.as_str()))?, parse_word(value)?);
+    }
+    Ok(storage)
+}
+
+impl AccountOverride {
+    pub fn from_json(v: &Value) -> Result<AccountOverride, String> {
+        let field = |name: &str| v.get(name).filter(|f| !f.is_null());
+        let mut a = AccountOverride::default();
+        if let Some(f) = field("balance") {
+            a.balance = Some(parse_biguint(f)?);
+        }
+        if let Some(f) = field("nonce") {
+            a.nonce = Some(parse_u64(f)?);
+        }
+        if let Some(f) = field("code") {
+            a.code = Some(parse_bytes(f)?);
+        }
+        match (field("state"), field("stateDiff")) {
+            (Some(_), Some(_)) => return Err("both state and stateDiff overridden".to_string()),
+            (Some(f), None) => a.state = Some(parse_storage(f)?),
+            (None, Some(f)) => a.state_diff = parse_storage(f)?,
+            (None, None) => {}
+        }
+        Ok(a)
+    }
+
+    pub fn apply(&self, state: &mut State, address: &[u8; 20]) {
+        let account = state.account(address);
+        if let Some(balance) = &self.balance {
+            account.balance = balance.clone();
+        }
+        if let Some(nonce) = self.nonce {
+            account.nonce = nonce;
+        }
+        if let Some(code) = &self.code {
+            account.code = code.clone();
+        }
+        if self.state.is_some() {
+            account.storage.clear();
+        }
+        let slots = self.state.iter().flatten().chain(self.state_diff.iter());
+        for (key, value) in slots {
+            state.set_storage(address, key, *value);
+        }
+    }
+}
+
+impl BlockOverrides {
+    pub fn from_json(v: &Value) -> Result<BlockOverrides, String> {
+        let field = |name: &str| v.get(name).filter(|f| !f.is_null());
+        let mut b = BlockOverrides::default();
+        if let Some(f) = field("number") {
+            b.number = Some(parse_u64(f)?);
+        }
+        if let Some(f) = field("time") {
+            b.timestamp = Some(parse_u64(f)?);
+        }
+        if let Some(f) = field("baseFeePerGas") {
+            b.base_fee = Some(parse_biguint(f)?);
+        }
+        Ok(b)
+    }
+
+    pub fn apply(&self, block: &mut BlockEnv) {
+        if let Some(number) = self.number {
+            block.number = number;
+        }
+        if let Some(timestamp) = self.timestamp {
+            block.timestamp = timestamp;
+        }
+        if let Some(base_fee) = &self.base_fee {
+            block.base_fee = base_fee.clone();
+        }
+    }
+}
+
+impl Overrides {
+    // from_json parses the state override set and the block overrides of
+    // eth_call, either being null when missing
+    pub fn from_json(accounts: &Value, block: &Value) -> Result<Overrides, String> {
+        let mut overrides = Overrides::default();
+        if let Some(accounts) = accounts.as_object() {
+            for (address, account) in accounts.iter() {
+                let address = json::parse_address(&Value::from(address.as_str()))?;
+                overrides
+                    .accounts
+                    .insert(address, AccountOverride::from_json(account)?);
+            }
+        } else if !accounts.is_null() {
+            return Err(format!("invalid state override {}", accounts));
+        }
+        if !block.is_null() {
+            overrides.block = BlockOverrides::from_json(block)?;
+        }
+        Ok(overrides)
+    }
+}
+
+// prepare returns the state, the block environment and the transaction of
+// the simulated call. As in geth, the nonce is the one of the sender, and
+// without a gas price the base fee is zero.
+pub fn prepare(
+    state: &State,
+    block: &BlockEnv,
+    tx: &Transaction,
+    overrides: &Overrides,
+) -> (State, BlockEnv, Transaction) {
+    let mut state = state.clone();
+    for (address, account) in overrides.accounts.iter() {
+        account.apply(&mut state, address);
+    }
+    let mut block = block.clone();
+    overrides.block.apply(&mut block);
+    let mut tx = tx.clone();
+    tx.nonce = state.nonce(&tx.from);
+    if tx.gas_price.is_zero() {
+        block.base_fee = BigUint::zero();
+    }
+    (state, block, tx)
+}
+
+// call executes the transaction with the overrides, leaving the state as is
+pub fn call(
+    config: &Config,
+    state: &State,
+    block: &BlockEnv,
+    tx: &Transaction,
+    overrides: &Overrides,
+) -> Result<ExecutionResult, String> {
+    let (mut state, block, tx) = prepare(state, block, tx, overrides);
+    transaction::transact(config, &mut state, &block, &tx)
+}
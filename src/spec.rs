@@ -0,0 +1,75 @@
+use serde::{Deserialize, Serialize};
+
+// Hard forks, in activation order, so that a feature can be gated with
+// `config.spec >= Spec::Prague`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Spec {
+    Frontier,
+    Homestead,
+    TangerineWhistle,
+    SpuriousDragon,
+    Byzantium,
+    Constantinople,
+    Petersburg,
+    Istanbul,
+    Berlin,
+    London,
+    Paris,
+    Shanghai,
+    Cancun,
+    Prague,
+    Osaka,
+}
+
+impl Spec {
+    // from_name parses the fork names used by geth and the ethereum/tests
+    // fixtures
+    pub fn from_name(name: &str) -> Result<Spec, String> {
+        match name {
+            "Frontier" => Ok(Spec::Frontier),
+            "Homestead" => Ok(Spec::Homestead),
+            "EIP150" | "TangerineWhistle" => Ok(Spec::TangerineWhistle),
+            "EIP158" | "SpuriousDragon" => Ok(Spec::SpuriousDragon),
+            "Byzantium" => Ok(Spec::Byzantium),
+            "Constantinople" => Ok(Spec::Constantinople),
+            "ConstantinopleFix" | "Petersburg" => Ok(Spec::Petersburg),
+            "Istanbul" | "MuirGlacier" => Ok(Spec::Istanbul),
+            "Berlin" => Ok(Spec::Berlin),
+            // the glacier forks only delay the difficulty bomb
+            "London" | "ArrowGlacier" | "GrayGlacier" => Ok(Spec::London),
+            "Merge" | "Paris" => Ok(Spec::Paris),
+            "Shanghai" => Ok(Spec::Shanghai),
+            "Cancun" => Ok(Spec::Cancun),
+            "Prague" => Ok(Spec::Prague),
+            "Osaka" => Ok(Spec::Osaka),
+            _ => Err(format!("unknown fork {}", name)),
+        }
+    }
+}
+
+// Config selects the rules the interpreter runs with
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    pub spec: Spec,
+    // enables the P256VERIFY precompile at 0x100 with the RIP-7212 gas cost,
+    // as deployed on L2s before EIP-7951
+    pub rip7212: bool,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            spec: Spec::Prague,
+            rip7212: false,
+        }
+    }
+}
+
+impl Config {
+    pub fn new(spec: Spec) -> Config {
+        Config {
+            spec,
+            rip7212: false,
+        }
+    }
+}
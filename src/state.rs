@@ -0,0 +1,160 @@
+use super::{json, keccak256, rlp, trie};
+use num_bigint::BigUint;
+use num_traits::identities::Zero;
+use rlp::{Encodable, Rlp};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use trie::Trie;
+
+// keccak256 of the empty code
+pub const EMPTY_CODE_HASH: [u8; 32] = [
+    0xc5, 0xd2, 0x46, 0x01, 0x86, 0xf7, 0x23, 0x3c, 0x92, 0x7e, 0x7d, 0xb2, 0xdc, 0xc7, 0x03, 0xc0,
+    0xe5, 0x00, 0xb6, 0x53, 0xca, 0x82, 0x27, 0x3b, 0x7b, 0xfa, 0xd8, 0x04, 0x5d, 0x85, 0xa4, 0x70,
+];
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Account {
+    pub nonce: u64,
+    pub balance: BigUint,
+    pub code: Vec<u8>,
+    // slots with a zero value are not stored
+    #[serde(with = "json::entries")]
+    pub storage: HashMap<[u8; 32], [u8; 32]>,
+}
+
+impl Account {
+    // is_empty follows the EIP-161 definition: no code, zero nonce and zero
+    // balance
+    pub fn is_empty(&self) -> bool {
+        self.code.is_empty() && self.nonce == 0 && self.balance.is_zero()
+    }
+    pub fn code_hash(&self) -> [u8; 32] {
+        if self.code.is_empty() {
+            return EMPTY_CODE_HASH;
+        }
+        keccak256(&self.code)
+    }
+    // storage_trie returns the secure trie of the storage, which maps each
+    // slot to the RLP encoding of its value without leading zeros
+    pub fn storage_trie(&self) -> Trie {
+        let mut trie = Trie::new_secure();
+        for (key, value) in self.storage.iter() {
+            trie.insert(key, &rlp::to_bytes(&BigUint::from_bytes_be(value)));
+        }
+        trie
+    }
+    pub fn storage_root(&self) -> [u8; 32] {
+        self.storage_trie().root_hash()
+    }
+}
+
+// accounts are encoded in the state trie as [nonce, balance, storage_root,
+// code_hash]
+impl Encodable for Account {
+    fn to_rlp(&self) -> Rlp {
+        Rlp::List(vec![
+            self.nonce.to_rlp(),
+            self.balance.to_rlp(),
+            self.storage_root().to_rlp(),
+            self.code_hash().to_rlp(),
+        ])
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct State {
+    #[serde(with = "json::entries")]
+    pub accounts: HashMap<[u8; 20], Account>,
+}
+
+impl State {
+    pub fn new() -> State {
+        State {
+            accounts: HashMap::new(),
+        }
+    }
+    pub fn get(&self, address: &[u8; 20]) -> Option<&Account> {
+        self.accounts.get(address)
+    }
+    // account returns a mutable reference to the account, creating it if it
+    // does not exist
+    pub fn account(&mut self, address: &[u8; 20]) -> &mut Account {
+        self.accounts.entry(*address).or_default()
+    }
+    pub fn balance(&self, address: &[u8; 20]) -> BigUint {
+        match self.accounts.get(address) {
+            Some(a) => a.balance.clone(),
+            None => BigUint::zero(),
+        }
+    }
+    pub fn nonce(&self, address: &[u8; 20]) -> u64 {
+        match self.accounts.get(address) {
+            Some(a) => a.nonce,
+            None => 0,
+        }
+    }
+    pub fn code(&self, address: &[u8; 20]) -> Vec<u8> {
+        match self.accounts.get(address) {
+            Some(a) => a.code.clone(),
+            None => Vec::new(),
+        }
+    }
+    pub fn storage(&self, address: &[u8; 20], key: &[u8; 32]) -> [u8; 32] {
+        match self.accounts.get(address) {
+            Some(a) => a.storage.get(key).copied().unwrap_or([0; 32]),
+            None => [0; 32],
+        }
+    }
+    pub fn set_storage(&mut self, address: &[u8; 20], key: &[u8; 32], value: [u8; 32]) {
+        let account = self.account(address);
+        if value == [0; 32] {
+            account.storage.remove(key);
+        } else {
+            account.storage.insert(*key, value);
+        }
+    }
+    pub fn add_balance(&mut self, address: &[u8; 20], value: &BigUint) {
+        let account = self.account(address);
+        account.balance += value;
+    }
+    pub fn sub_balance(&mut self, address: &[u8; 20], value: &BigUint) -> Result<(), String> {
+        let account = self.account(address);
+        if account.balance < *value {
+            return Err("insufficient balance".to_string());
+        }
+        account.balance -= value;
+        Ok(())
+    }
+    pub fn transfer(
+        &mut self,
+        from: &[u8; 20],
+        to: &[u8; 20],
+        value: &BigUint,
+    ) -> Result<(), String> {
+        self.sub_balance(from, value)?;
+        self.add_balance(to, value);
+        Ok(())
+    }
+
+    // trie returns the secure trie of the accounts, keyed by address
+    pub fn trie(&self) -> Trie {
+        let mut trie = Trie::new_secure();
+        for (address, account) in self.accounts.iter() {
+            trie.insert(address, &rlp::to_bytes(account));
+        }
+        trie
+    }
+    pub fn root(&self) -> [u8; 32] {
+        self.trie().root_hash()
+    }
+    // remove_empty deletes the given accounts if they are empty, as done at
+    // the end of a transaction for the touched accounts since Spurious Dragon
+    // (EIP-161)
+    pub fn remove_empty(&mut self, addresses: &[[u8; 20]]) {
+        for address in addresses.iter() {
+            if self.accounts.get(address).is_some_and(|a| a.is_empty()) {
+                self.accounts.remove(address);
+            }
+        }
+    }
+}
@@ -0,0 +1,211 @@
+// Runner for the GeneralStateTests fixtures of ethereum/tests and
+// execution-spec-tests: each test has a pre-state, a matrix of transactions
+// (data, gas limit and value variants) and, for each fork, the expected
+// post-state root of each of the transactions.
+// https://ethereum-tests.readthedocs.io/en/latest/state-transition-tutorial.html
+use super::*;
+use json::{
+    parse_access_list, parse_address, parse_alloc, parse_authorization, parse_biguint, parse_bytes,
+    parse_u64, parse_word,
+};
+use serde_json::Value;
+use spec::{Config, Spec};
+use std::collections::BTreeMap;
+use std::path::Path;
+use transaction::{
+    BlockEnv, Transaction, ACCESS_LIST_TX_TYPE, BLOB_TX_TYPE, DYNAMIC_FEE_TX_TYPE, LEGACY_TX_TYPE,
+    SET_CODE_TX_TYPE,
+};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestResult {
+    pub name: String,
+    pub fork: String,
+    // position of the case in the fork's post entries
+    pub index: usize,
+    pub pass: bool,
+    pub error: Option<String>,
+}
+
+// run_file runs all the tests of a fixture file
+pub fn run_file(path: &Path) -> Result<Vec<TestResult>, String> {
+    let data = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let tests: Value =
+        serde_json::from_str(&data).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let tests = tests
+        .as_object()
+        .ok_or_else(|| format!("{}: expected an object of tests", path.display()))?;
+    let mut results = Vec::new();
+    for (name, test) in tests.iter() {
+        results.append(&mut run_test(name, test)?);
+    }
+    Ok(results)
+}
+
+// run_test runs every post-state case of the test, for each of its forks
+pub fn run_test(name: &str, test: &Value) -> Result<Vec<TestResult>, String> {
+    let pre = parse_alloc(&test["pre"]).map_err(|e| format!("{}: pre: {}", name, e))?;
+    let post = test["post"]
+        .as_object()
+        .ok_or_else(|| format!("{}: missing post", name))?;
+    let mut results = Vec::new();
+    for (fork, cases) in post.iter() {
+        let cases = cases
+            .as_array()
+            .ok_or_else(|| format!("{}: {}: post must be an array", name, fork))?;
+        for (index, case) in cases.iter().enumerate() {
+            // a case hitting a bug fails alone
+            let error = catch_panic(|| run_case(&pre, test, fork, case)).err();
+            results.push(TestResult {
+                name: name.to_string(),
+                fork: fork.clone(),
+                index,
+                pass: error.is_none(),
+                error,
+            });
+        }
+    }
+    Ok(results)
+}
+
+// summary counts the passed and failed cases of each fork
+pub fn summary(results: &[TestResult]) -> BTreeMap<String, (usize, usize)> {
+    let mut forks: BTreeMap<String, (usize, usize)> = BTreeMap::new();
+    for r in results.iter() {
+        let entry = forks.entry(r.fork.clone()).or_default();
+        if r.pass {
+            entry.0 += 1;
+        } else {
+            entry.1 += 1;
+        }
+    }
+    forks
+}
+
+fn run_case(pre: &state::State, test: &Value, fork: &str, case: &Value) -> Result<(), String> {
+    let spec = Spec::from_name(fork)?;
+    let config = Config::new(spec);
+    let block = parse_env(spec, &test["env"])?;
+    let tx = parse_transaction(&test["transaction"], &case["indexes"])?;
+    let expect_exception = case.get("expectException").and_then(|e| e.as_str());
+
+    let mut state = pre.clone();
+    let logs = match (
+        transaction::transact(&config, &mut state, &block, &tx),
+        expect_exception,
+    ) {
+        (Ok(_), Some(e)) => return Err(format!("expected exception {}", e)),
+        (Err(e), None) => return Err(format!("unexpected exception: {}", e)),
+        (Ok(r), None) => r.logs,
+        (Err(_), Some(_)) => Vec::new(),
+    };
+
+    let root = state.root();
+    let expected = parse_word(&case["hash"])?;
+    if root != expected {
+        return Err(format!(
+            "post state root mismatch: got 0x{}, want 0x{}",
+            hex::encode(root),
+            hex::encode(expected)
+        ));
+    }
+    if let Some(expected) = case.get("logs") {
+        let logs_hash = keccak256(&rlp::encode(&rlp::encode_list(&logs)));
+        let expected = parse_word(expected)?;
+        if logs_hash != expected {
+            return Err(format!(
+                "logs hash mismatch: got 0x{}, want 0x{}",
+                hex::encode(logs_hash),
+                hex::encode(expected)
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn parse_env(spec: Spec, env: &Value) -> Result<BlockEnv, String> {
+    let mut block = BlockEnv {
+        coinbase: parse_address(&env["currentCoinbase"])?,
+        number: parse_u64(&env["currentNumber"])?,
+        timestamp: parse_u64(&env["currentTimestamp"])?,
+        gas_limit: parse_u64(&env["currentGasLimit"])?,
+        ..BlockEnv::default()
+    };
+    if let Some(base_fee) = env.get("currentBaseFee") {
+        block.base_fee = parse_biguint(base_fee)?;
+    }
+    match (env.get("currentRandom"), env.get("currentDifficulty")) {
+        (Some(random), _) if spec >= Spec::Paris => block.prev_randao = parse_word(random)?,
+        (_, Some(difficulty)) => {
+            block.prev_randao = u256::biguint_to_u256(&parse_biguint(difficulty)?)
+        }
+        _ => {}
+    }
+    if let Some(excess_blob_gas) = env.get("currentExcessBlobGas") {
+        block.blob_base_fee = transaction::blob_base_fee(spec, parse_u64(excess_blob_gas)?);
+    }
+    Ok(block)
+}
+
+// parse_transaction builds the transaction of the matrix selected by the
+// data, gas and value indexes
+fn parse_transaction(t: &Value, indexes: &Value) -> Result<Transaction, String> {
+    let d = parse_u64(&indexes["data"])? as usize;
+    let g = parse_u64(&indexes["gas"])? as usize;
+    let v = parse_u64(&indexes["value"])? as usize;
+    let pick = |field: &str, i: usize| -> Result<&Value, String> {
+        t[field]
+            .get(i)
+            .ok_or_else(|| format!("transaction: missing {}[{}]", field, i))
+    };
+
+    let mut tx = Transaction {
+        nonce: parse_u64(&t["nonce"])?,
+        data: parse_bytes(pick("data", d)?)?,
+        gas_limit: parse_u64(pick("gasLimit", g)?)?,
+        value: parse_biguint(pick("value", v)?)?,
+        ..Transaction::default()
+    };
+    tx.from = match t.get("sender") {
+        Some(sender) => parse_address(sender)?,
+        None => {
+            let key = parse_word(&t["secretKey"])?;
+            signature::secret_key_to_address(&key)?
+        }
+    };
+    tx.to = match t["to"].as_str() {
+        Some("") | None => None,
+        Some(_) => Some(parse_address(&t["to"])?),
+    };
+
+    if let Some(access_list) = t.get("accessLists").and_then(|l| l.get(d)) {
+        if !access_list.is_null() {
+            tx.tx_type = ACCESS_LIST_TX_TYPE;
+            tx.access_list = parse_access_list(access_list)?;
+        }
+    }
+    match t.get("maxFeePerGas") {
+        Some(max_fee) => {
+            tx.tx_type = DYNAMIC_FEE_TX_TYPE;
+            tx.gas_price = parse_biguint(max_fee)?;
+            tx.max_priority_fee_per_gas = Some(parse_biguint(&t["maxPriorityFeePerGas"])?);
+        }
+        None => tx.gas_price = parse_biguint(&t["gasPrice"])?,
+    }
+    if let Some(hashes) = t.get("blobVersionedHashes").and_then(|h| h.as_array()) {
+        tx.tx_type = BLOB_TX_TYPE;
+        tx.blob_versioned_hashes = hashes.iter().map(parse_word).collect::<Result<_, _>>()?;
+        tx.max_fee_per_blob_gas = Some(parse_biguint(&t["maxFeePerBlobGas"])?);
+    }
+    if let Some(authorizations) = t.get("authorizationList").and_then(|a| a.as_array()) {
+        tx.tx_type = SET_CODE_TX_TYPE;
+        tx.authorization_list = authorizations
+            .iter()
+            .map(parse_authorization)
+            .collect::<Result<_, _>>()?;
+    }
+    if tx.tx_type != LEGACY_TX_TYPE {
+        tx.chain_id = Some(1);
+    }
+    Ok(tx)
+}
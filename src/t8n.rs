@@ -0,0 +1,413 @@
+// State transition tool with the interface of geth's `evm t8n`, used by
+// execution-spec-tests to fill and consume fixtures: the pre-state (alloc),
+// the block environment (env) and the transactions (txs) are applied, giving
+// the post-state and the result (roots, receipts and rejected transactions).
+// https://github.com/ethereum/go-ethereum/tree/master/cmd/evm#state-transition-tool-t8n
+use super::*;
+use block::{
+    apply_withdrawals, blob_gas_limits, calc_base_fee, calc_excess_blob_gas, logs_bloom,
+    requests_hash, system_calls, transactions_root, withdrawals_root, BlockResult, Header,
+    Withdrawal,
+};
+use envelope::SignedTransaction;
+use json::{
+    hex_biguint, hex_bytes, hex_u64, log_to_json, parse_access_list, parse_address, parse_alloc,
+    parse_authorization, parse_biguint, parse_bytes, parse_u64, parse_word,
+};
+use num_bigint::BigUint;
+use rlp::Decodable;
+use serde_json::{json, Value};
+use spec::{Config, Spec};
+use state::State;
+use std::collections::HashMap;
+use transaction::{Transaction, LEGACY_TX_TYPE};
+
+pub struct Output {
+    pub alloc: State,
+    pub result: Value,
+    // rlp list of the included transactions
+    pub body: Vec<u8>,
+}
+
+// Env is the block environment of env.json
+struct Env {
+    header: Header,
+    // (number - ommer number, coinbase) of the ommers
+    ommers: Vec<(u64, [u8; 20])>,
+    withdrawals: Option<Vec<Withdrawal>>,
+    // blockHashes, number => hash
+    block_hashes: HashMap<u64, [u8; 32]>,
+}
+
+// transition applies the transactions on top of the alloc. The fork may be a
+// transition network such as ShanghaiToCancunAtTime15k. txs is either a list
+// of transactions in the JSON format of geth, signed (v, r, s) or to be
+// signed (secretKey), or the hex encoded rlp list of signed transactions.
+// A reward of None disables the mining rewards.
+pub fn transition(
+    fork: &str,
+    chain_id: u64,
+    reward: Option<BigUint>,
+    alloc: &Value,
+    env: &Value,
+    txs: &Value,
+) -> Result<Output, String> {
+    let number = parse_u64(&env["currentNumber"])?;
+    let timestamp = parse_u64(&env["currentTimestamp"])?;
+    let spec = blocktest::spec_at(fork, number, timestamp)?;
+    let config = Config::new(spec);
+    let mut state = parse_alloc(alloc).map_err(|e| format!("alloc: {}", e))?;
+    let env = parse_env(spec, env).map_err(|e| format!("env: {}", e))?;
+    let txs = match txs {
+        Value::String(_) => decode_txs(&parse_bytes(txs)?)?,
+        Value::Null => Vec::new(),
+        _ => parse_txs(txs, chain_id)?,
+    };
+    let header = &env.header;
+
+    system_calls(spec, &mut state, header);
+    let mut block_env = header.block_env(spec, chain_id);
+    block_env.block_hashes = env.block_hashes.clone();
+    let (_, max_blob_gas) = blob_gas_limits(spec);
+    let mut result = BlockResult::default();
+    let mut included: Vec<SignedTransaction> = Vec::new();
+    let mut receipts = Vec::new();
+    let mut rejected = Vec::new();
+    for (i, stx) in txs.into_iter().enumerate() {
+        let stx = match stx {
+            Ok(stx) => stx,
+            Err(e) => {
+                rejected.push(json!({"index": i, "error": e}));
+                continue;
+            }
+        };
+        let tx = &stx.tx;
+        if tx.gas_limit > header.gas_limit - result.gas_used {
+            rejected.push(json!({"index": i, "error": "gas limit reached"}));
+            continue;
+        }
+        if result.blob_gas_used + tx.blob_gas() > max_blob_gas {
+            rejected.push(json!({"index": i, "error": "blob gas limit reached"}));
+            continue;
+        }
+        let r = match transaction::transact(&config, &mut state, &block_env, tx) {
+            Ok(r) => r,
+            Err(e) => {
+                rejected.push(json!({"index": i, "error": e}));
+                continue;
+            }
+        };
+        let log_index = result.receipts.iter().map(|r| r.logs.len()).sum::<usize>();
+        result.push(spec, &state, tx, r);
+        receipts.push(receipt_to_json(&result, &stx, header, log_index));
+        included.push(stx);
+    }
+
+    if let Some(withdrawals) = &env.withdrawals {
+        apply_withdrawals(&mut state, withdrawals);
+    }
+    if let Some(reward) = reward {
+        apply_rewards(spec, &mut state, header, &env.ommers, &reward);
+    }
+
+    let logs: Vec<block::Log> = result
+        .receipts
+        .iter()
+        .flat_map(|r| r.logs.iter().cloned())
+        .collect();
+    let mut r = json!({
+        "stateRoot": hex_bytes(&state.root()),
+        "txRoot": hex_bytes(&transactions_root(&included)),
+        "receiptsRoot": hex_bytes(&result.receipts_root()),
+        "logsHash": hex_bytes(&keccak256(&rlp::encode(&rlp::encode_list(&logs)))),
+        "logsBloom": hex_bytes(&logs_bloom(&logs).0),
+        "receipts": receipts,
+        "currentDifficulty": if spec >= Spec::Paris {
+            Value::Null
+        } else {
+            Value::from(hex_biguint(&header.difficulty))
+        },
+        "gasUsed": hex_u64(result.gas_used),
+    });
+    if !rejected.is_empty() {
+        r["rejected"] = Value::Array(rejected);
+    }
+    if let Some(base_fee) = &header.base_fee {
+        r["currentBaseFee"] = Value::from(hex_biguint(base_fee));
+    }
+    if let Some(withdrawals) = &env.withdrawals {
+        r["withdrawalsRoot"] = Value::from(hex_bytes(&withdrawals_root(withdrawals)));
+    }
+    if spec >= Spec::Cancun {
+        r["currentExcessBlobGas"] = Value::from(hex_u64(header.excess_blob_gas.unwrap_or(0)));
+        r["blobGasUsed"] = Value::from(hex_u64(result.blob_gas_used));
+    }
+    // EIP-7685 requests are not collected
+    if spec >= Spec::Prague {
+        r["requestsHash"] = Value::from(hex_bytes(&requests_hash(&[])));
+        r["requests"] = Value::Array(Vec::new());
+    }
+
+    Ok(Output {
+        alloc: state,
+        result: r,
+        body: rlp::encode(&rlp::encode_list(&included)),
+    })
+}
+
+fn parse_env(spec: Spec, env: &Value) -> Result<Env, String> {
+    let mut header = Header {
+        coinbase: parse_address(&env["currentCoinbase"])?,
+        gas_limit: parse_u64(&env["currentGasLimit"])?,
+        number: parse_u64(&env["currentNumber"])?,
+        timestamp: parse_u64(&env["currentTimestamp"])?,
+        ..Header::default()
+    };
+    match env.get("currentDifficulty").filter(|d| !d.is_null()) {
+        Some(d) => header.difficulty = parse_biguint(d)?,
+        None if spec < Spec::Paris => {
+            return Err("currentDifficulty required before Paris".to_string())
+        }
+        None => {}
+    }
+    match env.get("currentRandom").filter(|r| !r.is_null()) {
+        Some(r) => header.mix_hash = parse_word(r)?,
+        None if spec >= Spec::Paris => return Err("currentRandom required since Paris".to_string()),
+        None => {}
+    }
+    let mut block_hashes = HashMap::new();
+    for (number, hash) in env["blockHashes"].as_object().into_iter().flatten() {
+        block_hashes.insert(parse_u64(&Value::from(number.as_str()))?, parse_word(hash)?);
+    }
+    // the parent hash is needed by the EIP-2935 system call
+    if header.number > 0 {
+        if let Some(hash) = block_hashes.get(&(header.number - 1)) {
+            header.parent_hash = *hash;
+        }
+    }
+
+    if spec >= Spec::London {
+        header.base_fee = Some(match env.get("currentBaseFee") {
+            Some(base_fee) => parse_biguint(base_fee)?,
+            None => {
+                let parent = Header {
+                    base_fee: Some(parse_biguint(&env["parentBaseFee"]).map_err(|_| {
+                        "currentBaseFee or the parent fields required since London".to_string()
+                    })?),
+                    gas_used: parse_u64(&env["parentGasUsed"])?,
+                    gas_limit: parse_u64(&env["parentGasLimit"])?,
+                    ..Header::default()
+                };
+                calc_base_fee(&parent)
+            }
+        });
+    }
+    let mut withdrawals = None;
+    if spec >= Spec::Shanghai {
+        let mut w = Vec::new();
+        for v in env["withdrawals"].as_array().into_iter().flatten() {
+            w.push(Withdrawal {
+                index: parse_u64(&v["index"])?,
+                validator_index: parse_u64(&v["validatorIndex"])?,
+                address: parse_address(&v["address"])?,
+                amount: parse_u64(&v["amount"])?,
+            });
+        }
+        withdrawals = Some(w);
+    }
+    if spec >= Spec::Cancun {
+        header.excess_blob_gas = Some(match env.get("currentExcessBlobGas") {
+            Some(excess) => parse_u64(excess)?,
+            None => {
+                let parent = Header {
+                    excess_blob_gas: Some(parse_u64(&env["parentExcessBlobGas"]).unwrap_or(0)),
+                    blob_gas_used: Some(parse_u64(&env["parentBlobGasUsed"]).unwrap_or(0)),
+                    ..Header::default()
+                };
+                calc_excess_blob_gas(spec, &parent)
+            }
+        });
+        header.parent_beacon_block_root = match env.get("parentBeaconBlockRoot") {
+            Some(root) => Some(parse_word(root)?),
+            None => Some([0; 32]),
+        };
+    }
+
+    let mut ommers = Vec::new();
+    for o in env["ommers"].as_array().into_iter().flatten() {
+        ommers.push((parse_u64(&o["delta"])?, parse_address(&o["address"])?));
+    }
+    Ok(Env {
+        header,
+        ommers,
+        withdrawals,
+        block_hashes,
+    })
+}
+
+// parse_txs parses the JSON transactions. A transaction whose sender can't be
+// recovered is rejected, not an input error.
+fn parse_txs(txs: &Value, chain_id: u64) -> Result<Vec<Result<SignedTransaction, String>>, String> {
+    let txs = txs
+        .as_array()
+        .ok_or_else(|| "txs: expected an array".to_string())?;
+    let mut r = Vec::new();
+    for (i, t) in txs.iter().enumerate() {
+        r.push(parse_tx(t, chain_id).map_err(|e| format!("txs[{}]: {}", i, e))?);
+    }
+    Ok(r)
+}
+
+fn parse_tx(t: &Value, chain_id: u64) -> Result<Result<SignedTransaction, String>, String> {
+    let opt = |field: &str| t.get(field).filter(|v| !v.is_null());
+    let mut tx = Transaction {
+        tx_type: match opt("type") {
+            Some(ty) => parse_u64(ty)? as u8,
+            None => LEGACY_TX_TYPE,
+        },
+        nonce: parse_u64(&t["nonce"])?,
+        gas_limit: parse_u64(&t["gas"])?,
+        value: parse_biguint(&t["value"])?,
+        data: parse_bytes(opt("input").or(opt("data")).unwrap_or(&Value::from("0x")))?,
+        ..Transaction::default()
+    };
+    tx.to = match opt("to") {
+        Some(to) if to != "" => Some(parse_address(to)?),
+        _ => None,
+    };
+    match opt("maxFeePerGas") {
+        Some(max_fee) => {
+            tx.gas_price = parse_biguint(max_fee)?;
+            tx.max_priority_fee_per_gas = Some(parse_biguint(&t["maxPriorityFeePerGas"])?);
+        }
+        None => tx.gas_price = parse_biguint(&t["gasPrice"])?,
+    }
+    if let Some(access_list) = opt("accessList") {
+        tx.access_list = parse_access_list(access_list)?;
+    }
+    if let Some(fee) = opt("maxFeePerBlobGas") {
+        tx.max_fee_per_blob_gas = Some(parse_biguint(fee)?);
+    }
+    if let Some(hashes) = opt("blobVersionedHashes").and_then(|h| h.as_array()) {
+        tx.blob_versioned_hashes = hashes.iter().map(parse_word).collect::<Result<_, _>>()?;
+    }
+    if let Some(authorizations) = opt("authorizationList").and_then(|a| a.as_array()) {
+        tx.authorization_list = authorizations
+            .iter()
+            .map(parse_authorization)
+            .collect::<Result<_, _>>()?;
+    }
+
+    if let Some(key) = opt("secretKey") {
+        tx.chain_id = match opt("chainId") {
+            Some(id) => Some(parse_u64(id)?),
+            // legacy transactions are EIP-155 protected unless stated otherwise
+            None if tx.tx_type != LEGACY_TX_TYPE
+                || opt("protected").and_then(|p| p.as_bool()) != Some(false) =>
+            {
+                Some(chain_id)
+            }
+            None => None,
+        };
+        return Ok(SignedTransaction::sign(&tx, &parse_word(key)?));
+    }
+
+    let v = parse_u64(&t["v"])?;
+    tx.chain_id = if tx.tx_type != LEGACY_TX_TYPE {
+        Some(match opt("chainId") {
+            Some(id) => parse_u64(id)?,
+            None => chain_id,
+        })
+    } else if v >= 35 {
+        Some((v - 35) / 2)
+    } else {
+        None
+    };
+    let mut stx = SignedTransaction {
+        tx,
+        v,
+        r: parse_biguint(&t["r"])?,
+        s: parse_biguint(&t["s"])?,
+    };
+    Ok(stx.sender().map(|from| {
+        stx.tx.from = from;
+        stx
+    }))
+}
+
+// decode_txs decodes an rlp list of signed transactions, rejecting the ones
+// that can't be decoded
+fn decode_txs(data: &[u8]) -> Result<Vec<Result<SignedTransaction, String>>, String> {
+    let item = rlp::decode(data).map_err(|e| format!("txs: {}", e))?;
+    Ok(item
+        .as_list()
+        .map_err(|e| format!("txs: {}", e))?
+        .iter()
+        .map(SignedTransaction::from_rlp)
+        .collect())
+}
+
+fn receipt_to_json(
+    result: &BlockResult,
+    stx: &SignedTransaction,
+    header: &Header,
+    log_index: usize,
+) -> Value {
+    let index = result.receipts.len() - 1;
+    let receipt = &result.receipts[index];
+    let r = &result.results[index];
+    let hash = hex_bytes(&stx.hash());
+    let logs: Vec<Value> = receipt
+        .logs
+        .iter()
+        .enumerate()
+        .map(|(i, log)| {
+            let mut l = log_to_json(log);
+            l["blockNumber"] = Value::from(hex_u64(header.number));
+            l["transactionHash"] = Value::from(hash.clone());
+            l["transactionIndex"] = Value::from(hex_u64(index as u64));
+            l["blockHash"] = Value::from(hex_bytes(&[0; 32]));
+            l["logIndex"] = Value::from(hex_u64((log_index + i) as u64));
+            l["removed"] = Value::from(false);
+            l
+        })
+        .collect();
+    let base_fee = header.base_fee.clone().unwrap_or_default();
+    json!({
+        "type": hex_u64(receipt.tx_type as u64),
+        "root": hex_bytes(&receipt.post_state.map(|r| r.to_vec()).unwrap_or_default()),
+        "status": hex_u64(receipt.success as u64),
+        "cumulativeGasUsed": hex_u64(receipt.cumulative_gas_used),
+        "logsBloom": hex_bytes(&receipt.logs_bloom.0),
+        "logs": logs,
+        "transactionHash": hash,
+        "contractAddress": hex_bytes(&r.contract_address.unwrap_or([0; 20])),
+        "gasUsed": hex_u64(r.gas_used),
+        "effectiveGasPrice": hex_biguint(&stx.tx.effective_gas_price(&base_fee)),
+        "blockHash": hex_bytes(&[0; 32]),
+        "transactionIndex": hex_u64(index as u64),
+    })
+}
+
+// apply_rewards pays the given block reward to the coinbase, and the ommers
+// rewards, as geth's t8n does with --state.reward
+fn apply_rewards(
+    spec: Spec,
+    state: &mut State,
+    header: &Header,
+    ommers: &[(u64, [u8; 20])],
+    reward: &BigUint,
+) {
+    let mut miner_reward = reward.clone();
+    let mut touched = vec![header.coinbase];
+    for (delta, address) in ommers.iter() {
+        let r = reward * 8u64.saturating_sub(*delta) / 8u64;
+        state.add_balance(address, &r);
+        miner_reward += reward / 32u64;
+        touched.push(*address);
+    }
+    state.add_balance(&header.coinbase, &miner_reward);
+    if spec >= Spec::SpuriousDragon {
+        state.remove_empty(&touched);
+    }
+}
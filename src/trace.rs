@@ -0,0 +1,70 @@
+// EIP-3155 execution traces: one JSON line per executed instruction, followed
+// by a summary line
+// https://eips.ethereum.org/EIPS/eip-3155
+use super::*;
+use serde_json::{json, Value};
+
+pub type Tracer = Box<dyn FnMut(&Step)>;
+
+// Step is the state of the machine before the instruction at pc, with the gas
+// the instruction cost
+#[derive(Clone, Debug, PartialEq)]
+pub struct Step {
+    pub pc: usize,
+    pub op: u8,
+    pub gas: u64,
+    pub gas_cost: u64,
+    pub mem_size: usize,
+    pub stack: Vec<[u8; 32]>,
+    pub depth: usize,
+    pub refund: i64,
+    pub op_name: String,
+    pub error: Option<String>,
+    // memory before the instruction, when Stack::trace_memory is set, not
+    // part of the EIP-3155 line
+    pub memory: Vec<u8>,
+    // slot read by SLOAD or written by SSTORE, with its value
+    pub storage: Option<([u8; 32], [u8; 32])>,
+}
+
+impl Step {
+    pub fn to_json(&self) -> Value {
+        let stack: Vec<Value> = self
+            .stack
+            .iter()
+            .map(|w| Value::from(hex_word(w)))
+            .collect();
+        let mut v = json!({
+            "pc": self.pc,
+            "op": self.op,
+            "gas": json::hex_u64(self.gas),
+            "gasCost": json::hex_u64(self.gas_cost),
+            "memSize": self.mem_size,
+            "stack": stack,
+            "depth": self.depth,
+            "refund": self.refund,
+            "opName": self.op_name,
+        });
+        if let Some(e) = &self.error {
+            v["error"] = Value::from(e.clone());
+        }
+        v
+    }
+}
+
+// summary returns the line ending the trace
+pub fn summary(output: &[u8], gas_used: u64, error: Option<&str>) -> Value {
+    let mut v = json!({
+        "output": hex::encode(output),
+        "gasUsed": json::hex_u64(gas_used),
+    });
+    if let Some(e) = error {
+        v["error"] = Value::from(e);
+    }
+    v
+}
+
+// hex_word formats a stack item as a quantity, without leading zeros
+pub fn hex_word(w: &[u8; 32]) -> String {
+    json::hex_biguint(&num_bigint::BigUint::from_bytes_be(w))
+}
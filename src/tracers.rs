@@ -0,0 +1,336 @@
+// geth's built-in tracers, as returned by debug_traceTransaction and
+// debug_traceCall: the struct logger (the default, one entry per executed
+// instruction), callTracer, prestateTracer and 4byteTracer. The output has
+// the shape of geth's, so that the tools reading it work unchanged. The
+// transactions run on the local host, which makes no nested calls, so that
+// callTracer gives the top call alone, as geth does with onlyTopCall.
+// https://geth.ethereum.org/docs/developers/evm-tracing/built-in-tracers
+use super::*;
+use json::{hex_biguint, hex_bytes, hex_u64};
+use serde_json::{json, Map, Value};
+use spec::Config;
+use state::State;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::rc::Rc;
+use trace::Step;
+use transaction::{BlockEnv, ExecutionResult, Transaction};
+
+// TraceConfig is the tracing options of the debug methods
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TraceConfig {
+    // tracer name, the struct logger when None
+    pub tracer: Option<String>,
+    // options of the tracer (withLog, onlyTopCall, diffMode)
+    pub tracer_config: Value,
+    // options of the struct logger
+    pub disable_stack: bool,
+    pub disable_storage: bool,
+    pub enable_memory: bool,
+}
+
+impl TraceConfig {
+    pub fn from_json(v: &Value) -> Result<TraceConfig, String> {
+        if v.is_null() {
+            return Ok(TraceConfig::default());
+        }
+        let flag = |name: &str| match v.get(name) {
+            None | Some(Value::Null) => Ok(false),
+            Some(Value::Bool(b)) => Ok(*b),
+            Some(f) => Err(format!("invalid {}: {}", name, f)),
+        };
+        let tracer = match v.get("tracer") {
+            None | Some(Value::Null) => None,
+            Some(Value::String(name)) => Some(name.clone()),
+            Some(t) => return Err(format!("invalid tracer {}", t)),
+        };
+        Ok(TraceConfig {
+            tracer,
+            tracer_config: v.get("tracerConfig").cloned().unwrap_or(Value::Null),
+            disable_stack: flag("disableStack")?,
+            disable_storage: flag("disableStorage")?,
+            enable_memory: flag("enableMemory")?,
+        })
+    }
+
+    fn tracer_flag(&self, name: &str) -> bool {
+        self.tracer_config
+            .get(name)
+            .and_then(|f| f.as_bool())
+            .unwrap_or(false)
+    }
+}
+
+// trace applies the transaction to the state, returning the output of the
+// tracer. An Err means that the transaction is invalid, or the tracer unknown.
+pub fn trace(
+    config: &Config,
+    state: &mut State,
+    block: &BlockEnv,
+    tx: &Transaction,
+    trace_config: &TraceConfig,
+) -> Result<Value, String> {
+    let name = trace_config.tracer.as_deref().unwrap_or("structLogger");
+    let steps: Rc<RefCell<Vec<Step>>> = Rc::default();
+    let tracer: Option<trace::Tracer> = match name {
+        "structLogger" | "prestateTracer" => {
+            let s = steps.clone();
+            Some(Box::new(move |step| s.borrow_mut().push(step.clone())))
+        }
+        "callTracer" | "4byteTracer" => None,
+        _ => return Err(format!("tracer {} not found", name)),
+    };
+    // only the struct logger shows the memory
+    let trace_memory = name == "structLogger" && trace_config.enable_memory;
+    let pre = state.clone();
+    let r = transaction::transact_with_tracer(config, state, block, tx, tracer, trace_memory)?;
+    let steps = steps.borrow();
+    Ok(match name {
+        "structLogger" => struct_logs(trace_config, &r, &steps),
+        "callTracer" => call_frame(state, tx, &r, trace_config.tracer_flag("withLog")),
+        "prestateTracer" => {
+            let touched = touched(block, tx, &pre, state, &steps);
+            match trace_config.tracer_flag("diffMode") {
+                true => prestate_diff(&pre, state, &touched),
+                false => prestate(&pre, &touched),
+            }
+        }
+        _ => four_byte(config, tx),
+    })
+}
+
+// word_hex formats a memory or storage word as geth's struct logger does,
+// without 0x prefix
+fn word_hex(w: &[u8]) -> String {
+    hex::encode(w)
+}
+
+fn struct_logs(trace_config: &TraceConfig, r: &ExecutionResult, steps: &[Step]) -> Value {
+    // storage of the contract as seen by the trace so far
+    let mut storage = BTreeMap::new();
+    let logs: Vec<Value> = steps
+        .iter()
+        .map(|step| {
+            let mut l = json!({
+                "pc": step.pc,
+                "op": step.op_name,
+                "gas": step.gas,
+                "gasCost": step.gas_cost,
+                "depth": step.depth,
+            });
+            if !trace_config.disable_stack {
+                let stack: Vec<String> = step.stack.iter().map(trace::hex_word).collect();
+                l["stack"] = json!(stack);
+            }
+            if trace_config.enable_memory {
+                let memory: Vec<String> = step.memory.chunks(32).map(word_hex).collect();
+                l["memory"] = json!(memory);
+            }
+            if let (Some((key, value)), false) = (step.storage, trace_config.disable_storage) {
+                storage.insert(word_hex(&key), Value::from(word_hex(&value)));
+                l["storage"] = Value::Object(storage.clone().into_iter().collect());
+            }
+            if step.refund != 0 {
+                l["refund"] = Value::from(step.refund);
+            }
+            if let Some(e) = &step.error {
+                l["error"] = Value::from(e.clone());
+            }
+            l
+        })
+        .collect();
+    json!({
+        "gas": r.gas_used,
+        "failed": !r.success,
+        "returnValue": word_hex(&r.output),
+        "structLogs": logs,
+    })
+}
+
+// receiver returns the called account, or the created one
+fn receiver(tx: &Transaction) -> [u8; 20] {
+    match tx.to {
+        Some(to) => to,
+        None => transaction::create_address(&tx.from, tx.nonce),
+    }
+}
+
+// call_frame is the top call frame of callTracer, without subcalls whether
+// onlyTopCall is set or not
+fn call_frame(post: &State, tx: &Transaction, r: &ExecutionResult, with_log: bool) -> Value {
+    let to = receiver(tx);
+    let mut f = json!({
+        "type": if tx.is_create() { "CREATE" } else { "CALL" },
+        "from": hex_bytes(&tx.from),
+        "to": hex_bytes(&to),
+        "value": hex_biguint(&tx.value),
+        "gas": hex_u64(tx.gas_limit),
+        "gasUsed": hex_u64(r.gas_used),
+        "input": hex_bytes(&tx.data),
+    });
+    match &r.error {
+        Some(e) => f["error"] = Value::from(e.clone()),
+        // the output of a creation is the deployed code
+        None if tx.is_create() => f["output"] = Value::from(hex_bytes(&post.code(&to))),
+        None => f["output"] = Value::from(hex_bytes(&r.output)),
+    }
+    if with_log && !r.logs.is_empty() {
+        let logs: Vec<Value> = r
+            .logs
+            .iter()
+            .map(|log| {
+                let mut l = json::log_to_json(log);
+                // number of subcalls made before the log, none
+                l["position"] = Value::from(hex_u64(0));
+                l
+            })
+            .collect();
+        f["logs"] = Value::Array(logs);
+    }
+    f
+}
+
+// four_byte counts the calls by selector and calldata size
+fn four_byte(config: &Config, tx: &Transaction) -> Value {
+    let mut counts = Map::new();
+    let precompile = tx
+        .to
+        .is_some_and(|to| precompiles::new_precompiles(config).contains_key(&to));
+    if !tx.is_create() && !precompile && tx.data.len() >= 4 {
+        let id = format!("{}-{}", hex_bytes(&tx.data[..4]), tx.data.len() - 4);
+        counts.insert(id, Value::from(1));
+    }
+    Value::Object(counts)
+}
+
+// touched returns the accounts and the slots read or written by the
+// transaction
+fn touched(
+    block: &BlockEnv,
+    tx: &Transaction,
+    pre: &State,
+    post: &State,
+    steps: &[Step],
+) -> BTreeMap<[u8; 20], BTreeSet<[u8; 32]>> {
+    let mut touched: BTreeMap<[u8; 20], BTreeSet<[u8; 32]>> = BTreeMap::new();
+    for address in [tx.from, receiver(tx), block.coinbase].iter() {
+        touched.entry(*address).or_default();
+    }
+    let to = receiver(tx);
+    for (key, _) in steps.iter().filter_map(|s| s.storage) {
+        touched.entry(to).or_default().insert(key);
+    }
+    // the changes made outside of the interpreter, as the EIP-7702
+    // delegations
+    for (address, account) in post.accounts.iter() {
+        if pre.get(address) != Some(account) {
+            touched.entry(*address).or_default();
+        }
+    }
+    for (address, keys) in touched.iter_mut() {
+        let storage = |s: &State| {
+            s.get(address)
+                .map(|a| a.storage.clone())
+                .unwrap_or_default()
+        };
+        let (before, after) = (storage(pre), storage(post));
+        keys.extend(before.keys().filter(|k| before.get(*k) != after.get(*k)));
+        keys.extend(after.keys().filter(|k| before.get(*k) != after.get(*k)));
+    }
+    touched
+}
+
+// account_json returns the account as the prestate tracer does, without the
+// empty fields
+fn account_json(
+    account: &state::Account,
+    fields: (bool, bool, bool),
+    slots: &[([u8; 32], [u8; 32])],
+) -> Value {
+    let (balance, nonce, code) = fields;
+    let mut a = Map::new();
+    if balance {
+        a.insert(
+            "balance".to_string(),
+            Value::from(hex_biguint(&account.balance)),
+        );
+    }
+    if nonce && account.nonce != 0 {
+        a.insert("nonce".to_string(), Value::from(account.nonce));
+    }
+    if code && !account.code.is_empty() {
+        a.insert("code".to_string(), Value::from(hex_bytes(&account.code)));
+    }
+    if !slots.is_empty() {
+        let storage: Map<String, Value> = slots
+            .iter()
+            .map(|(k, v)| (hex_bytes(k), Value::from(hex_bytes(v))))
+            .collect();
+        a.insert("storage".to_string(), Value::Object(storage));
+    }
+    Value::Object(a)
+}
+
+// prestate returns the touched accounts before the transaction, with the
+// touched slots
+fn prestate(pre: &State, touched: &BTreeMap<[u8; 20], BTreeSet<[u8; 32]>>) -> Value {
+    let mut accounts = Map::new();
+    for (address, keys) in touched.iter() {
+        let account = pre.get(address).cloned().unwrap_or_default();
+        let slots: Vec<_> = keys.iter().map(|k| (*k, pre.storage(address, k))).collect();
+        accounts.insert(
+            hex_bytes(address),
+            account_json(&account, (true, true, true), &slots),
+        );
+    }
+    Value::Object(accounts)
+}
+
+// prestate_diff returns the accounts changed by the transaction, before it
+// with the slots that changed, and after it with only the changed fields. The
+// created accounts are not in pre, and the deleted ones not in post.
+fn prestate_diff(
+    pre: &State,
+    post: &State,
+    touched: &BTreeMap<[u8; 20], BTreeSet<[u8; 32]>>,
+) -> Value {
+    let (mut pre_accounts, mut post_accounts) = (Map::new(), Map::new());
+    for (address, keys) in touched.iter() {
+        let (before, after) = (pre.get(address), post.get(address));
+        if before == after {
+            continue;
+        }
+        let changed: Vec<[u8; 32]> = keys
+            .iter()
+            .filter(|k| pre.storage(address, k) != post.storage(address, k))
+            .copied()
+            .collect();
+        // the zero slots are omitted from both
+        let slots = |s: &State| -> Vec<([u8; 32], [u8; 32])> {
+            changed
+                .iter()
+                .map(|k| (*k, s.storage(address, k)))
+                .filter(|(_, v)| *v != [0; 32])
+                .collect()
+        };
+        if let Some(before) = before {
+            pre_accounts.insert(
+                hex_bytes(address),
+                account_json(before, (true, true, true), &slots(pre)),
+            );
+        }
+        if let Some(after) = after {
+            let before = before.cloned().unwrap_or_default();
+            let fields = (
+                after.balance != before.balance,
+                after.nonce != before.nonce,
+                after.code != before.code,
+            );
+            post_accounts.insert(
+                hex_bytes(address),
+                account_json(after, fields, &slots(post)),
+            );
+        }
+    }
+    json!({"pre": pre_accounts, "post": post_accounts})
+}
@@ -0,0 +1,620 @@
+use super::*;
+use num_bigint::BigUint;
+use num_traits::identities::Zero;
+use opcodes::{
+    GCONTRACTBYTE, GINITCODEWORD, GTXACCESSLISTADDRESS, GTXACCESSLISTSTORAGEKEY, GTXCOST,
+    GTXCREATE, GTXDATANONZERO, GTXDATANONZERO_EIP2028, GTXDATAZERO, MAX_CODE_SIZE,
+    MAX_INITCODE_SIZE, PER_AUTH_BASE_COST, PER_EMPTY_ACCOUNT_COST, STANDARD_TOKEN_COST,
+    TOTAL_COST_FLOOR_PER_TOKEN,
+};
+use rlp::{Decodable, Encodable, Rlp};
+use spec::{Config, Spec};
+use state::State;
+use std::collections::HashMap;
+
+pub const LEGACY_TX_TYPE: u8 = 0x00;
+pub const ACCESS_LIST_TX_TYPE: u8 = 0x01; // EIP-2930
+pub const DYNAMIC_FEE_TX_TYPE: u8 = 0x02; // EIP-1559
+pub const BLOB_TX_TYPE: u8 = 0x03; // EIP-4844
+pub const SET_CODE_TX_TYPE: u8 = 0x04; // EIP-7702
+
+// (address, storage keys) pairs, EIP-2930
+pub type AccessList = Vec<([u8; 20], Vec<[u8; 32]>)>;
+
+pub const GAS_PER_BLOB: u64 = 131072;
+const MIN_BASE_FEE_PER_BLOB_GAS: u64 = 1;
+const BLOB_BASE_FEE_UPDATE_FRACTION_CANCUN: u64 = 3338477;
+const BLOB_BASE_FEE_UPDATE_FRACTION_PRAGUE: u64 = 5007716;
+const VERSIONED_HASH_VERSION_KZG: u8 = 0x01;
+// EIP-7702 delegation designator prefix
+pub const DELEGATION_PREFIX: [u8; 3] = [0xef, 0x01, 0x00];
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Transaction {
+    pub tx_type: u8,
+    // None for legacy transactions not protected by EIP-155
+    pub chain_id: Option<u64>,
+    pub from: [u8; 20],
+    // None for contract creation
+    pub to: Option<[u8; 20]>,
+    pub nonce: u64,
+    pub gas_limit: u64,
+    // gas price for legacy transactions, max fee per gas for EIP-1559 ones
+    pub gas_price: BigUint,
+    pub max_priority_fee_per_gas: Option<BigUint>,
+    pub value: BigUint,
+    pub data: Vec<u8>,
+    pub access_list: AccessList,
+    // EIP-4844
+    pub max_fee_per_blob_gas: Option<BigUint>,
+    pub blob_versioned_hashes: Vec<[u8; 32]>,
+    // EIP-7702
+    pub authorization_list: Vec<Authorization>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Authorization {
+    pub chain_id: BigUint,
+    pub address: [u8; 20],
+    pub nonce: u64,
+    pub y_parity: u8,
+    pub r: BigUint,
+    pub s: BigUint,
+}
+
+impl Authorization {
+    // signing_hash is keccak256(0x05 || rlp([chain_id, address, nonce]))
+    pub fn signing_hash(&self) -> [u8; 32] {
+        let mut b = vec![0x05];
+        b.append(&mut rlp::encode(&Rlp::List(vec![
+            self.chain_id.to_rlp(),
+            self.address.to_rlp(),
+            self.nonce.to_rlp(),
+        ])));
+        keccak256(&b)
+    }
+    pub fn authority(&self) -> Result<[u8; 20], String> {
+        signature::recover_address(&self.signing_hash(), self.y_parity, &self.r, &self.s)
+    }
+}
+
+// [chain_id, address, nonce, y_parity, r, s]
+impl Encodable for Authorization {
+    fn to_rlp(&self) -> Rlp {
+        Rlp::List(vec![
+            self.chain_id.to_rlp(),
+            self.address.to_rlp(),
+            self.nonce.to_rlp(),
+            (self.y_parity as u64).to_rlp(),
+            self.r.to_rlp(),
+            self.s.to_rlp(),
+        ])
+    }
+}
+impl Decodable for Authorization {
+    fn from_rlp(item: &Rlp) -> Result<Authorization, String> {
+        let l = item.as_list()?;
+        if l.len() != 6 {
+            return Err("invalid authorization entry".to_string());
+        }
+        let y_parity = l[3].as_u64()?;
+        if y_parity > 255 {
+            return Err("invalid authorization y parity".to_string());
+        }
+        Ok(Authorization {
+            chain_id: l[0].as_biguint()?,
+            address: l[1].as_address()?,
+            nonce: l[2].as_u64()?,
+            y_parity: y_parity as u8,
+            r: l[4].as_biguint()?,
+            s: l[5].as_biguint()?,
+        })
+    }
+}
+
+// access list entries are encoded as [address, [storage_keys...]]
+impl Encodable for ([u8; 20], Vec<[u8; 32]>) {
+    fn to_rlp(&self) -> Rlp {
+        Rlp::List(vec![self.0.to_rlp(), rlp::encode_list(&self.1)])
+    }
+}
+impl Decodable for ([u8; 20], Vec<[u8; 32]>) {
+    fn from_rlp(item: &Rlp) -> Result<([u8; 20], Vec<[u8; 32]>), String> {
+        let l = item.as_list()?;
+        if l.len() != 2 {
+            return Err("invalid access list entry".to_string());
+        }
+        Ok((l[0].as_address()?, rlp::decode_list(&l[1])?))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BlockEnv {
+    pub number: u64,
+    pub coinbase: [u8; 20],
+    pub timestamp: u64,
+    pub gas_limit: u64,
+    pub base_fee: BigUint,
+    pub blob_base_fee: BigUint,
+    pub chain_id: u64,
+    // mix hash of the header from Paris (EIP-4399), the difficulty before,
+    // for PREVRANDAO
+    pub prev_randao: [u8; 32],
+    // hashes of the previous blocks by number, for BLOCKHASH
+    #[serde(with = "json::entries")]
+    pub block_hashes: HashMap<u64, [u8; 32]>,
+}
+
+impl Default for BlockEnv {
+    fn default() -> BlockEnv {
+        BlockEnv {
+            number: 0,
+            coinbase: [0; 20],
+            timestamp: 0,
+            gas_limit: 30000000,
+            base_fee: BigUint::zero(),
+            blob_base_fee: BigUint::from(1u64),
+            chain_id: 1,
+            prev_randao: [0; 32],
+            block_hashes: HashMap::new(),
+        }
+    }
+}
+
+// ExecutionResult is the receipt-like outcome of an included transaction. A
+// transaction that fails during execution is still included (its gas is paid),
+// with success set to false.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ExecutionResult {
+    pub success: bool,
+    pub gas_used: u64,
+    pub output: Vec<u8>,
+    pub contract_address: Option<[u8; 20]>,
+    pub logs: Vec<block::Log>,
+    pub error: Option<String>,
+}
+
+impl Transaction {
+    pub fn is_create(&self) -> bool {
+        self.to.is_none()
+    }
+
+    // effective_gas_price returns the price paid per unit of gas, given the
+    // block base fee
+    pub fn effective_gas_price(&self, base_fee: &BigUint) -> BigUint {
+        match &self.max_priority_fee_per_gas {
+            Some(tip) => {
+                let price = base_fee + tip;
+                if price < self.gas_price {
+                    price
+                } else {
+                    self.gas_price.clone()
+                }
+            }
+            None => self.gas_price.clone(),
+        }
+    }
+
+    pub fn intrinsic_gas(&self, spec: Spec) -> u64 {
+        let mut gas = GTXCOST;
+        if self.is_create() && spec >= Spec::Homestead {
+            gas += GTXCREATE;
+        }
+        let nonzero_cost = if spec >= Spec::Istanbul {
+            GTXDATANONZERO_EIP2028
+        } else {
+            GTXDATANONZERO
+        };
+        for b in self.data.iter() {
+            if *b == 0 {
+                gas += GTXDATAZERO;
+            } else {
+                gas += nonzero_cost;
+            }
+        }
+        for (_, keys) in self.access_list.iter() {
+            gas += GTXACCESSLISTADDRESS + keys.len() * GTXACCESSLISTSTORAGEKEY;
+        }
+        if self.is_create() && spec >= Spec::Shanghai {
+            gas += GINITCODEWORD * self.data.len().div_ceil(32);
+        }
+        gas += self.authorization_list.len() * PER_EMPTY_ACCOUNT_COST;
+        gas as u64
+    }
+
+    // floor_data_gas is the minimum gas used by the transaction from Prague,
+    // from its calldata tokens, a zero byte being one token and a non zero
+    // byte four (EIP-7623)
+    pub fn floor_data_gas(&self) -> u64 {
+        let zeros = self.data.iter().filter(|b| **b == 0).count();
+        let tokens = zeros + (self.data.len() - zeros) * STANDARD_TOKEN_COST;
+        (GTXCOST + tokens * TOTAL_COST_FLOOR_PER_TOKEN) as u64
+    }
+
+    pub fn blob_gas(&self) -> u64 {
+        GAS_PER_BLOB * self.blob_versioned_hashes.len() as u64
+    }
+}
+
+// transact validates the transaction against the state, buys its gas, executes
+// it, refunds the unused gas and pays the coinbase. An Err means that the
+// transaction is invalid and the state has not been modified.
+pub fn transact(
+    config: &Config,
+    state: &mut State,
+    block: &BlockEnv,
+    tx: &Transaction,
+) -> Result<ExecutionResult, String> {
+    transact_with_tracer(config, state, block, tx, None, false)
+}
+
+// transact_with_tracer is transact, calling the tracer after each instruction
+// executed, with a copy of the memory in the steps when trace_memory is set
+pub fn transact_with_tracer(
+    config: &Config,
+    state: &mut State,
+    block: &BlockEnv,
+    tx: &Transaction,
+    tracer: Option<trace::Tracer>,
+    trace_memory: bool,
+) -> Result<ExecutionResult, String> {
+    let spec = config.spec;
+    let base_fee = if spec >= Spec::London {
+        block.base_fee.clone()
+    } else {
+        BigUint::zero()
+    };
+
+    // validation
+    let nonce = state.nonce(&tx.from);
+    if tx.nonce < nonce {
+        return Err(format!("nonce too low: tx {} state {}", tx.nonce, nonce));
+    }
+    if tx.nonce > nonce {
+        return Err(format!("nonce too high: tx {} state {}", tx.nonce, nonce));
+    }
+    if tx.gas_limit > block.gas_limit {
+        return Err("gas limit reached".to_string());
+    }
+    let intrinsic_gas = tx.intrinsic_gas(spec);
+    if tx.gas_limit < intrinsic_gas {
+        return Err(format!(
+            "intrinsic gas too low: have {}, want {}",
+            tx.gas_limit, intrinsic_gas
+        ));
+    }
+    let floor_data_gas = match spec >= Spec::Prague {
+        true => tx.floor_data_gas(),
+        false => 0,
+    };
+    if tx.gas_limit < floor_data_gas {
+        return Err(format!(
+            "insufficient gas for floor data gas cost: have {}, want {}",
+            tx.gas_limit, floor_data_gas
+        ));
+    }
+    if spec >= Spec::London && tx.gas_price < base_fee {
+        return Err("max fee per gas less than block base fee".to_string());
+    }
+    if let Some(tip) = &tx.max_priority_fee_per_gas {
+        if *tip > tx.gas_price {
+            return Err("max priority fee per gas higher than max fee per gas".to_string());
+        }
+    }
+    if tx.is_create() && spec >= Spec::Shanghai && tx.data.len() > MAX_INITCODE_SIZE {
+        return Err("max initcode size exceeded".to_string());
+    }
+    validate_typed(config, block, tx)?;
+    let mut max_cost = BigUint::from(tx.gas_limit) * &tx.gas_price + &tx.value;
+    if let Some(max_fee_per_blob_gas) = &tx.max_fee_per_blob_gas {
+        max_cost += BigUint::from(tx.blob_gas()) * max_fee_per_blob_gas;
+    }
+    if state.balance(&tx.from) < max_cost {
+        return Err("insufficient funds for gas * price + value".to_string());
+    }
+
+    // buy gas, the blob gas is burnt
+    let gas_price = tx.effective_gas_price(&base_fee);
+    let mut cost = BigUint::from(tx.gas_limit) * &gas_price;
+    cost += BigUint::from(tx.blob_gas()) * &block.blob_base_fee;
+    state.sub_balance(&tx.from, &cost)?;
+    state.account(&tx.from).nonce += 1;
+
+    // EIP-7702 delegations are applied before the execution, and are kept even
+    // if the execution fails
+    let mut refund = apply_authorizations(state, block, tx);
+
+    // execute, reverting the state changes (but not the gas purchase) on
+    // failure
+    let snapshot = state.clone();
+    let gas = tx.gas_limit - intrinsic_gas;
+    let contract_address = if tx.is_create() {
+        Some(create_address(&tx.from, tx.nonce))
+    } else {
+        None
+    };
+    let (gas_left, output, logs, error) = match execute(
+        config,
+        state,
+        block,
+        tx,
+        contract_address,
+        gas,
+        tracer.map(|t| (t, trace_memory)),
+    ) {
+        Ok((gas_left, output, exec_refund, logs)) => {
+            refund += exec_refund.max(0) as u64;
+            (gas_left, output, logs, None)
+        }
+        Err(e) => {
+            *state = snapshot;
+            (0, Vec::new(), Vec::new(), Some(e))
+        }
+    };
+
+    // refund the unused gas and pay the coinbase. The refund counter is capped
+    // to a fifth of the gas used from London (EIP-3529), to a half before.
+    let max_refund_quotient = if spec >= Spec::London { 5 } else { 2 };
+    let gas_left = gas_left + refund.min((tx.gas_limit - gas_left) / max_refund_quotient);
+    // the calldata floor from Prague (EIP-7623), after the refund
+    let gas_used = (tx.gas_limit - gas_left).max(floor_data_gas);
+    let gas_left = tx.gas_limit - gas_used;
+    state.add_balance(&tx.from, &(BigUint::from(gas_left) * &gas_price));
+    state.add_balance(
+        &block.coinbase,
+        &(BigUint::from(gas_used) * (gas_price - base_fee)),
+    );
+    if spec >= Spec::SpuriousDragon {
+        let mut touched = vec![tx.from, block.coinbase];
+        touched.extend(tx.to);
+        state.remove_empty(&touched);
+    }
+
+    Ok(ExecutionResult {
+        success: error.is_none(),
+        gas_used,
+        output,
+        contract_address: if error.is_none() {
+            contract_address
+        } else {
+            None
+        },
+        logs,
+        error,
+    })
+}
+
+// execute runs the call or the contract creation, returning the gas left, the
+// output, the refund counter and the logs. The tracer comes with whether its
+// steps hold the memory.
+fn execute(
+    config: &Config,
+    state: &mut State,
+    block: &BlockEnv,
+    tx: &Transaction,
+    contract_address: Option<[u8; 20]>,
+    gas: u64,
+    tracer: Option<(trace::Tracer, bool)>,
+) -> Result<(u64, Vec<u8>, i64, Vec<block::Log>), String> {
+    let to = match contract_address {
+        Some(address) => {
+            let account = state.account(&address);
+            if account.nonce != 0 || !account.code.is_empty() {
+                return Err("contract address collision".to_string());
+            }
+            if config.spec >= Spec::SpuriousDragon {
+                account.nonce = 1;
+            }
+            address
+        }
+        None => tx.to.unwrap(),
+    };
+    state.transfer(&tx.from, &to, &tx.value)?;
+
+    let mut s = Stack::with_config(config.clone());
+    s.gas = gas;
+    if contract_address.is_none() && s.precompiles().contains_key(&to) {
+        let output = s.call_precompile(&to, &tx.data)?;
+        return Ok((s.gas, output, 0, Vec::new()));
+    }
+
+    let (code, calldata) = match contract_address {
+        Some(_) => (tx.data.clone(), Vec::new()),
+        None => (resolve_code(state, &to), tx.data.clone()),
+    };
+    if code.is_empty() {
+        return Ok((gas, Vec::new(), 0, Vec::new()));
+    }
+    // the sender, the recipient, the precompiles and the access list are warm
+    // (EIP-2929)
+    if config.spec >= Spec::Berlin {
+        s.accessed_accounts.insert(tx.from);
+        s.accessed_accounts.insert(to);
+        s.accessed_accounts.extend(s.precompiles().keys());
+        // and the coinbase from Shanghai (EIP-3651)
+        if config.spec >= Spec::Shanghai {
+            s.accessed_accounts.insert(block.coinbase);
+        }
+        for (address, keys) in tx.access_list.iter() {
+            s.accessed_accounts.insert(*address);
+            for key in keys.iter() {
+                s.accessed_storage.insert((*address, *key));
+            }
+        }
+    }
+    // the stack executes against the world state
+    s.address = to;
+    s.caller = tx.from;
+    s.origin = tx.from;
+    let base_fee = match config.spec >= Spec::London {
+        true => block.base_fee.clone(),
+        false => BigUint::zero(),
+    };
+    s.gas_price = tx.effective_gas_price(&base_fee);
+    s.blob_hashes = tx.blob_versioned_hashes.clone();
+    s.value = u256::biguint_to_u256(&tx.value);
+    s.block = block.clone();
+    if let Some((tracer, trace_memory)) = tracer {
+        s.tracer = Some(tracer);
+        s.trace_memory = trace_memory;
+    }
+    s.state = std::mem::take(state);
+    let result = s.execute(&code, &calldata, false);
+    *state = std::mem::take(&mut s.state);
+    let mut output = result?;
+    let mut gas_left = s.gas;
+
+    // code deposit
+    if contract_address.is_some() {
+        if output.len() > MAX_CODE_SIZE && config.spec >= Spec::SpuriousDragon {
+            return Err("max code size exceeded".to_string());
+        }
+        if !output.is_empty() && output[0] == 0xef && config.spec >= Spec::London {
+            return Err("invalid code: must not begin with 0xef".to_string());
+        }
+        let deposit = (output.len() * GCONTRACTBYTE) as u64;
+        if deposit > gas_left {
+            return Err("out of gas".to_string());
+        }
+        gas_left -= deposit;
+        state.account(&to).code = std::mem::take(&mut output);
+    }
+    // the destructed accounts are deleted, from Cancun only the ones created
+    // in the transaction (EIP-6780)
+    for address in s.destructed.iter() {
+        if config.spec < Spec::Cancun || Some(*address) == contract_address {
+            state.accounts.remove(address);
+        }
+    }
+    Ok((gas_left, output, s.refund, s.logs))
+}
+
+// validate_typed checks the fields specific to blob and set code transactions
+fn validate_typed(config: &Config, block: &BlockEnv, tx: &Transaction) -> Result<(), String> {
+    if tx.tx_type == BLOB_TX_TYPE {
+        if config.spec < Spec::Cancun {
+            return Err("blob transactions not supported".to_string());
+        }
+        if tx.is_create() {
+            return Err("blob transaction can not create contracts".to_string());
+        }
+        if tx.blob_versioned_hashes.is_empty() {
+            return Err("blob transaction without blobs".to_string());
+        }
+        for h in tx.blob_versioned_hashes.iter() {
+            if h[0] != VERSIONED_HASH_VERSION_KZG {
+                return Err("invalid blob versioned hash version".to_string());
+            }
+        }
+        match &tx.max_fee_per_blob_gas {
+            Some(fee) if *fee >= block.blob_base_fee => {}
+            _ => return Err("max fee per blob gas less than block blob gas fee".to_string()),
+        }
+    }
+    if tx.tx_type == SET_CODE_TX_TYPE {
+        if config.spec < Spec::Prague {
+            return Err("set code transactions not supported".to_string());
+        }
+        if tx.is_create() {
+            return Err("set code transaction can not create contracts".to_string());
+        }
+        if tx.authorization_list.is_empty() {
+            return Err("set code transaction with empty authorization list".to_string());
+        }
+    }
+    Ok(())
+}
+
+// apply_authorizations sets the EIP-7702 delegations of the valid
+// authorizations, skipping the invalid ones, and returns the gas refund for
+// the authorities that already existed
+fn apply_authorizations(state: &mut State, block: &BlockEnv, tx: &Transaction) -> u64 {
+    let mut refund = 0;
+    for auth in tx.authorization_list.iter() {
+        if !auth.chain_id.is_zero() && auth.chain_id != BigUint::from(block.chain_id) {
+            continue;
+        }
+        if auth.nonce == u64::MAX {
+            continue;
+        }
+        let authority = match auth.authority() {
+            Ok(a) => a,
+            Err(_) => continue,
+        };
+        let exists = state.get(&authority).is_some_and(|a| !a.is_empty());
+        let account = state.account(&authority);
+        if !account.code.is_empty() && delegation(&account.code).is_none() {
+            continue;
+        }
+        if account.nonce != auth.nonce {
+            continue;
+        }
+        if exists {
+            refund += (PER_EMPTY_ACCOUNT_COST - PER_AUTH_BASE_COST) as u64;
+        }
+        account.code = if auth.address == [0; 20] {
+            Vec::new()
+        } else {
+            [&DELEGATION_PREFIX[..], &auth.address[..]].concat()
+        };
+        account.nonce += 1;
+    }
+    refund
+}
+
+// blob_base_fee returns the blob gas price for the excess blob gas of the
+// block (EIP-4844, with the EIP-7691 update fraction since Prague)
+pub fn blob_base_fee(spec: Spec, excess_blob_gas: u64) -> BigUint {
+    let fraction = if spec >= Spec::Prague {
+        BLOB_BASE_FEE_UPDATE_FRACTION_PRAGUE
+    } else {
+        BLOB_BASE_FEE_UPDATE_FRACTION_CANCUN
+    };
+    fake_exponential(
+        &BigUint::from(MIN_BASE_FEE_PER_BLOB_GAS),
+        &BigUint::from(excess_blob_gas),
+        &BigUint::from(fraction),
+    )
+}
+
+// fake_exponential approximates factor * e ** (numerator / denominator)
+fn fake_exponential(factor: &BigUint, numerator: &BigUint, denominator: &BigUint) -> BigUint {
+    let mut i = 1u64;
+    let mut output = BigUint::zero();
+    let mut numerator_accum = factor * denominator;
+    while !numerator_accum.is_zero() {
+        output += &numerator_accum;
+        numerator_accum = (numerator_accum * numerator) / (denominator * i);
+        i += 1;
+    }
+    output / denominator
+}
+
+// delegation returns the address an EIP-7702 delegated account points to
+pub fn delegation(code: &[u8]) -> Option<[u8; 20]> {
+    if code.len() != 23 || code[..3] != DELEGATION_PREFIX {
+        return None;
+    }
+    let mut address: [u8; 20] = [0; 20];
+    address.copy_from_slice(&code[3..]);
+    Some(address)
+}
+
+// resolve_code returns the code executed when calling the address, following
+// its EIP-7702 delegation if any
+fn resolve_code(state: &State, address: &[u8; 20]) -> Vec<u8> {
+    let code = state.code(address);
+    match delegation(&code) {
+        Some(target) => state.code(&target),
+        None => code,
+    }
+}
+
+// create_address returns the address of a contract created by sender with the
+// given nonce: keccak256(rlp([sender, nonce]))[12..]
+pub fn create_address(sender: &[u8; 20], nonce: u64) -> [u8; 20] {
+    let rlp = rlp::encode(&Rlp::List(vec![sender.to_rlp(), nonce.to_rlp()]));
+    let mut address: [u8; 20] = [0; 20];
+    address.copy_from_slice(&keccak256(&rlp)[12..]);
+    address
+}
@@ -0,0 +1,372 @@
+// Merkle Patricia Trie
+// https://ethereum.org/en/developers/docs/data-structures-and-encoding/patricia-merkle-trie/
+use super::{keccak256, rlp};
+use rlp::Rlp;
+
+// keccak256(rlp(""))
+pub const EMPTY_ROOT: [u8; 32] = [
+    0x56, 0xe8, 0x1f, 0x17, 0x1b, 0xcc, 0x55, 0xa6, 0xff, 0x83, 0x45, 0xe6, 0x92, 0xc0, 0xf8, 0x6e,
+    0x5b, 0x48, 0xe0, 0x1b, 0x99, 0x6c, 0xad, 0xc0, 0x01, 0x62, 0x2f, 0xb5, 0xe3, 0x63, 0xb4, 0x21,
+];
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum Node {
+    #[default]
+    Empty,
+    // remaining key nibbles, value
+    Leaf(Vec<u8>, Vec<u8>),
+    // shared key nibbles, child (a branch)
+    Extension(Vec<u8>, Box<Node>),
+    Branch(Box<[Node; 16]>, Option<Vec<u8>>),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Trie {
+    pub root: Node,
+    // a secure trie uses keccak256(key) as the path, as done for the state
+    // and storage tries
+    pub secure: bool,
+}
+
+impl Trie {
+    pub fn new() -> Trie {
+        Trie {
+            root: Node::Empty,
+            secure: false,
+        }
+    }
+    pub fn new_secure() -> Trie {
+        Trie {
+            root: Node::Empty,
+            secure: true,
+        }
+    }
+    // insert sets the value of the key. Inserting an empty value deletes the
+    // key.
+    pub fn insert(&mut self, key: &[u8], value: &[u8]) {
+        if value.is_empty() {
+            self.delete(key);
+            return;
+        }
+        let path = self.path(key);
+        let root = std::mem::take(&mut self.root);
+        self.root = insert(root, &path, value.to_vec());
+    }
+    pub fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        get(&self.root, &self.path(key))
+    }
+    pub fn delete(&mut self, key: &[u8]) {
+        let path = self.path(key);
+        let root = std::mem::take(&mut self.root);
+        self.root = delete(root, &path);
+    }
+    pub fn root_hash(&self) -> [u8; 32] {
+        keccak256(&rlp::encode(&node_rlp(&self.root)))
+    }
+    // prove returns the proof of the key: the encoding of the nodes on its
+    // path that are referenced by hash, starting with the root. It proves the
+    // absence of the key too.
+    pub fn prove(&self, key: &[u8]) -> Vec<Vec<u8>> {
+        let path = self.path(key);
+        let mut proof = vec![rlp::encode(&node_rlp(&self.root))];
+        let mut node = &self.root;
+        let mut pos = 0;
+        loop {
+            let next = match node {
+                Node::Branch(children, _) if pos < path.len() => {
+                    pos += 1;
+                    &children[path[pos - 1] as usize]
+                }
+                Node::Extension(k, child) if path[pos..].starts_with(k) => {
+                    pos += k.len();
+                    child
+                }
+                _ => break,
+            };
+            let encoded = rlp::encode(&node_rlp(next));
+            if encoded.len() >= 32 {
+                proof.push(encoded);
+            }
+            node = next;
+        }
+        proof
+    }
+    // path returns the nibbles of the (hashed, in a secure trie) key
+    pub fn path(&self, key: &[u8]) -> Vec<u8> {
+        if self.secure {
+            return to_nibbles(&keccak256(key));
+        }
+        to_nibbles(key)
+    }
+}
+
+// ordered_root returns the root of the trie of the values keyed by their
+// RLP encoded index, as used for the transactions, receipts and withdrawals of
+// a block
+pub fn ordered_root(values: &[Vec<u8>]) -> [u8; 32] {
+    let mut trie = Trie::new();
+    for (i, v) in values.iter().enumerate() {
+        trie.insert(&rlp::to_bytes(&(i as u64)), v);
+    }
+    trie.root_hash()
+}
+
+fn insert(node: Node, path: &[u8], value: Vec<u8>) -> Node {
+    match node {
+        Node::Empty => Node::Leaf(path.to_vec(), value),
+        Node::Leaf(k, v) => {
+            if k == path {
+                return Node::Leaf(k, value);
+            }
+            let p = common_prefix(&k, path);
+            let branch = Node::Branch(Box::default(), None);
+            let branch = insert(branch, &k[p..], v);
+            let branch = insert(branch, &path[p..], value);
+            extend(path[..p].to_vec(), branch)
+        }
+        Node::Extension(k, child) => {
+            let p = common_prefix(&k, path);
+            if p == k.len() {
+                return Node::Extension(k, Box::new(insert(*child, &path[p..], value)));
+            }
+            // split the extension at the first differing nibble
+            let mut children: [Node; 16] = Default::default();
+            children[k[p] as usize] = extend(k[p + 1..].to_vec(), *child);
+            let branch = insert(Node::Branch(Box::new(children), None), &path[p..], value);
+            extend(k[..p].to_vec(), branch)
+        }
+        Node::Branch(mut children, v) => {
+            if path.is_empty() {
+                return Node::Branch(children, Some(value));
+            }
+            let i = path[0] as usize;
+            let child = std::mem::take(&mut children[i]);
+            children[i] = insert(child, &path[1..], value);
+            Node::Branch(children, v)
+        }
+    }
+}
+
+fn get(node: &Node, path: &[u8]) -> Option<Vec<u8>> {
+    match node {
+        Node::Empty => None,
+        Node::Leaf(k, v) => {
+            if k == path {
+                return Some(v.clone());
+            }
+            None
+        }
+        Node::Extension(k, child) => {
+            if !path.starts_with(k) {
+                return None;
+            }
+            get(child, &path[k.len()..])
+        }
+        Node::Branch(children, v) => {
+            if path.is_empty() {
+                return v.clone();
+            }
+            get(&children[path[0] as usize], &path[1..])
+        }
+    }
+}
+
+fn delete(node: Node, path: &[u8]) -> Node {
+    match node {
+        Node::Empty => Node::Empty,
+        Node::Leaf(k, v) => {
+            if k == path {
+                return Node::Empty;
+            }
+            Node::Leaf(k, v)
+        }
+        Node::Extension(k, child) => {
+            if !path.starts_with(&k) {
+                return Node::Extension(k, child);
+            }
+            let child = delete(*child, &path[k.len()..]);
+            extend(k, child)
+        }
+        Node::Branch(mut children, mut v) => {
+            if path.is_empty() {
+                v = None;
+            } else {
+                let i = path[0] as usize;
+                let child = std::mem::take(&mut children[i]);
+                children[i] = delete(child, &path[1..]);
+            }
+            // a branch left with a single child (and no value), or with only a
+            // value, is collapsed
+            let used: Vec<usize> = (0..16).filter(|i| children[*i] != Node::Empty).collect();
+            if used.is_empty() {
+                return match v {
+                    Some(v) => Node::Leaf(Vec::new(), v),
+                    None => Node::Empty,
+                };
+            }
+            if used.len() == 1 && v.is_none() {
+                let i = used[0];
+                let child = std::mem::take(&mut children[i]);
+                return extend(vec![i as u8], child);
+            }
+            Node::Branch(children, v)
+        }
+    }
+}
+
+// extend prepends the nibbles to the node's path
+fn extend(prefix: Vec<u8>, node: Node) -> Node {
+    if prefix.is_empty() {
+        return node;
+    }
+    match node {
+        Node::Empty => Node::Empty,
+        Node::Leaf(k, v) => Node::Leaf([prefix, k].concat(), v),
+        Node::Extension(k, child) => Node::Extension([prefix, k].concat(), child),
+        Node::Branch(_, _) => Node::Extension(prefix, Box::new(node)),
+    }
+}
+
+fn common_prefix(a: &[u8], b: &[u8]) -> usize {
+    a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count()
+}
+
+pub fn to_nibbles(key: &[u8]) -> Vec<u8> {
+    let mut nibbles = Vec::with_capacity(key.len() * 2);
+    for b in key.iter() {
+        nibbles.push(b >> 4);
+        nibbles.push(b & 0x0f);
+    }
+    nibbles
+}
+
+// hex_prefix encodes the nibbles of a leaf or extension path, with a flag for
+// the node type and the parity of the path length
+pub fn hex_prefix(nibbles: &[u8], leaf: bool) -> Vec<u8> {
+    let flag = if leaf { 2 } else { 0 };
+    let mut r = Vec::with_capacity(nibbles.len() / 2 + 1);
+    let rest = if nibbles.len() % 2 == 1 {
+        r.push(((flag + 1) << 4) | nibbles[0]);
+        &nibbles[1..]
+    } else {
+        r.push(flag << 4);
+        nibbles
+    };
+    for pair in rest.chunks(2) {
+        r.push((pair[0] << 4) | pair[1]);
+    }
+    r
+}
+
+// decode_hex_prefix returns the nibbles of a hex prefix encoded path, and
+// whether it belongs to a leaf
+pub fn decode_hex_prefix(b: &[u8]) -> Result<(Vec<u8>, bool), String> {
+    if b.is_empty() {
+        return Err("trie: empty path".to_string());
+    }
+    let flag = b[0] >> 4;
+    if flag > 3 {
+        return Err("trie: invalid path flag".to_string());
+    }
+    let mut nibbles = Vec::with_capacity(b.len() * 2);
+    if flag & 1 == 1 {
+        nibbles.push(b[0] & 0x0f);
+    } else if b[0] & 0x0f != 0 {
+        return Err("trie: invalid path padding".to_string());
+    }
+    nibbles.append(&mut to_nibbles(&b[1..]));
+    Ok((nibbles, flag & 2 == 2))
+}
+
+// verify_proof checks the proof against the root hash and returns the value of
+// the key, or None if the proof shows that the key is absent. For a secure trie
+// the key is the hash of the original key.
+pub fn verify_proof(
+    root: &[u8; 32],
+    key: &[u8],
+    proof: &[Vec<u8>],
+) -> Result<Option<Vec<u8>>, String> {
+    let path = to_nibbles(key);
+    let mut pos = 0;
+    let mut next = 0;
+    // reference to the node to visit: a hash, an embedded node, or empty
+    let mut reference = Rlp::Bytes(root.to_vec());
+    loop {
+        let node = match reference {
+            Rlp::Bytes(ref b) if b.is_empty() => return Ok(None),
+            Rlp::Bytes(ref h) if h.len() == 32 => {
+                let encoded = proof
+                    .get(next)
+                    .ok_or_else(|| "proof: missing node".to_string())?;
+                if keccak256(encoded)[..] != h[..] {
+                    return Err("proof: node hash mismatch".to_string());
+                }
+                next += 1;
+                rlp::decode(encoded)?
+            }
+            Rlp::Bytes(_) => return Err("proof: invalid node reference".to_string()),
+            Rlp::List(_) => reference,
+        };
+        let items = match node {
+            Rlp::List(items) => items,
+            Rlp::Bytes(ref b) if b.is_empty() => return Ok(None),
+            Rlp::Bytes(_) => return Err("proof: invalid node".to_string()),
+        };
+        match items.len() {
+            17 => {
+                if pos == path.len() {
+                    let value = items[16].as_bytes()?;
+                    if value.is_empty() {
+                        return Ok(None);
+                    }
+                    return Ok(Some(value.to_vec()));
+                }
+                reference = items[path[pos] as usize].clone();
+                pos += 1;
+            }
+            2 => {
+                let (k, leaf) = decode_hex_prefix(items[0].as_bytes()?)?;
+                if leaf {
+                    if path[pos..] == k[..] {
+                        return Ok(Some(items[1].as_bytes()?.to_vec()));
+                    }
+                    return Ok(None);
+                }
+                if !path[pos..].starts_with(&k) {
+                    return Ok(None);
+                }
+                pos += k.len();
+                reference = items[1].clone();
+            }
+            _ => return Err("proof: invalid node".to_string()),
+        }
+    }
+}
+
+// node_rlp returns the RLP structure of the node
+pub fn node_rlp(node: &Node) -> Rlp {
+    match node {
+        Node::Empty => Rlp::Bytes(Vec::new()),
+        Node::Leaf(k, v) => Rlp::List(vec![Rlp::Bytes(hex_prefix(k, true)), Rlp::Bytes(v.clone())]),
+        Node::Extension(k, child) => {
+            Rlp::List(vec![Rlp::Bytes(hex_prefix(k, false)), node_ref(child)])
+        }
+        Node::Branch(children, v) => {
+            let mut items: Vec<Rlp> = children.iter().map(node_ref).collect();
+            items.push(Rlp::Bytes(v.clone().unwrap_or_default()));
+            Rlp::List(items)
+        }
+    }
+}
+
+// node_ref returns how a node is referenced from its parent: nodes whose
+// encoding is shorter than 32 bytes are embedded, the others are referenced by
+// their hash
+pub fn node_ref(node: &Node) -> Rlp {
+    let item = node_rlp(node);
+    let encoded = rlp::encode(&item);
+    if encoded.len() < 32 {
+        return item;
+    }
+    Rlp::Bytes(keccak256(&encoded).to_vec())
+}
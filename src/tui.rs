@@ -0,0 +1,246 @@
+// Terminal UI of the debugger, for `evm tui`: the disassembly with the next
+// instruction highlighted, the stack, the memory, the storage changed by the
+// execution, and the gas and depth, stepped with the keyboard.
+use super::*;
+use breakpoint::Breakpoint;
+use debugger::Debugger;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph};
+use ratatui::Frame;
+
+const KEYS: &str =
+    " n/→ step  p/← back  c continue  b breakpoint  r reset  PgUp/PgDn memory  q quit";
+
+// Tui holds the debugger and the view state
+pub struct Tui {
+    pub debugger: Debugger,
+    // first memory line shown
+    pub memory_offset: usize,
+    // error of the last action, shown in the status bar
+    pub message: Option<String>,
+}
+
+impl Tui {
+    pub fn new(debugger: Debugger) -> Tui {
+        Tui {
+            debugger,
+            memory_offset: 0,
+            message: None,
+        }
+    }
+
+    // run draws the UI and handles the keys until quit
+    pub fn run(&mut self) -> Result<(), String> {
+        let mut terminal = ratatui::try_init().map_err(|e| e.to_string())?;
+        let r = self.event_loop(&mut terminal);
+        ratatui::restore();
+        r
+    }
+
+    fn event_loop(&mut self, terminal: &mut ratatui::DefaultTerminal) -> Result<(), String> {
+        loop {
+            terminal.draw(|f| self.draw(f)).map_err(|e| e.to_string())?;
+            if let Event::Key(key) = event::read().map_err(|e| e.to_string())? {
+                if key.kind == KeyEventKind::Press && !self.key(key.code) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    // key handles a key press, returning false to quit
+    pub fn key(&mut self, code: KeyCode) -> bool {
+        let d = &mut self.debugger;
+        let r = match code {
+            KeyCode::Char('q') | KeyCode::Esc => return false,
+            KeyCode::Char('n') | KeyCode::Char('s') | KeyCode::Right | KeyCode::Down => d.step(),
+            KeyCode::Char('p') | KeyCode::Left | KeyCode::Up => d.step_back(),
+            KeyCode::Char('c') => d.continue_().map(|_| ()),
+            KeyCode::Char('b') => {
+                let b = Breakpoint::Pc(d.vm.pc);
+                match d.breakpoints.iter().position(|x| *x == b) {
+                    Some(i) => {
+                        d.breakpoints.remove(i);
+                    }
+                    None => d.breakpoints.push(b),
+                }
+                Ok(())
+            }
+            KeyCode::Char('r') => {
+                d.reset();
+                Ok(())
+            }
+            KeyCode::PageDown => {
+                self.memory_offset += 8;
+                Ok(())
+            }
+            KeyCode::PageUp => {
+                self.memory_offset = self.memory_offset.saturating_sub(8);
+                Ok(())
+            }
+            _ => Ok(()),
+        };
+        self.message = r.err();
+        true
+    }
+
+    pub fn draw(&self, f: &mut Frame) {
+        let [main, status, keys] = Layout::vertical([
+            Constraint::Min(0),
+            Constraint::Length(1),
+            Constraint::Length(1),
+        ])
+        .areas(f.area());
+        let [code, right] =
+            Layout::horizontal([Constraint::Percentage(35), Constraint::Percentage(65)])
+                .areas(main);
+        let [stack, memory, storage] = Layout::vertical([
+            Constraint::Percentage(40),
+            Constraint::Percentage(35),
+            Constraint::Percentage(25),
+        ])
+        .areas(right);
+
+        self.draw_code(f, code);
+        self.draw_stack(f, stack);
+        self.draw_memory(f, memory);
+        self.draw_storage(f, storage);
+        f.render_widget(
+            Paragraph::new(self.status()).style(Style::default().add_modifier(Modifier::REVERSED)),
+            status,
+        );
+        f.render_widget(Paragraph::new(KEYS), keys);
+    }
+
+    // status shows the step, pc, gas and depth, or the result once halted
+    pub fn status(&self) -> String {
+        let d = &self.debugger;
+        let mut s = format!(
+            " step {}  pc {}  gas {}  used {}  depth 1",
+            d.steps,
+            d.vm.pc,
+            d.vm.gas,
+            d.gas_used()
+        );
+        match &d.result {
+            Some(Ok(output)) => s.push_str(&format!("  halted, output 0x{}", hex::encode(output))),
+            Some(Err(e)) => s.push_str(&format!("  halted, error: {}", e)),
+            None => {}
+        }
+        if let Some(m) = &self.message {
+            s.push_str(&format!("  ({})", m));
+        }
+        s
+    }
+
+    fn draw_code(&self, f: &mut Frame, area: Rect) {
+        let d = &self.debugger;
+        let instructions = asm::disassemble(&d.code);
+        let current = instructions.iter().position(|i| i.pc == d.vm.pc);
+        // keep the next instruction in the middle of the view
+        let height = area.height.saturating_sub(2) as usize;
+        let first = current.unwrap_or(0).saturating_sub(height / 2);
+        let lines: Vec<Line> = instructions
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(height)
+            .map(|(n, i)| {
+                let bp = d.breakpoints.iter().any(|b| match b {
+                    Breakpoint::Pc(pc) => *pc == i.pc,
+                    Breakpoint::Opcode(op) => *op == i.opcode,
+                    _ => false,
+                });
+                let text = format!("{}{:5}  {}", if bp { "●" } else { " " }, i.pc, i);
+                if Some(n) == current && !d.halted() {
+                    Line::styled(text, Style::default().fg(Color::Black).bg(Color::Yellow))
+                } else {
+                    Line::raw(text)
+                }
+            })
+            .collect();
+        f.render_widget(Paragraph::new(lines).block(panel("Disassembly")), area);
+    }
+
+    fn draw_stack(&self, f: &mut Frame, area: Rect) {
+        let stack = &self.debugger.vm.stack;
+        let lines: Vec<Line> = stack
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(i, w)| Line::raw(format!("{:3}  0x{}", i, hex::encode(w))))
+            .collect();
+        let title = format!("Stack ({})", stack.len());
+        f.render_widget(Paragraph::new(lines).block(panel(&title)), area);
+    }
+
+    fn draw_memory(&self, f: &mut Frame, area: Rect) {
+        let mem = &self.debugger.vm.mem;
+        let lines: Vec<Line> = mem
+            .chunks(16)
+            .enumerate()
+            .skip(self.memory_offset)
+            .map(|(i, chunk)| {
+                let bytes: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+                let ascii: String = chunk
+                    .iter()
+                    .map(|b| {
+                        if b.is_ascii_graphic() {
+                            *b as char
+                        } else {
+                            '.'
+                        }
+                    })
+                    .collect();
+                Line::raw(format!("{:04x}  {}  {}", i * 16, bytes.join(" "), ascii))
+            })
+            .collect();
+        let title = format!("Memory ({} bytes)", mem.len());
+        f.render_widget(Paragraph::new(lines).block(panel(&title)), area);
+    }
+
+    // draw_storage lists the slots of the executing account, the ones changed
+    // by the execution with their initial value
+    fn draw_storage(&self, f: &mut Frame, area: Rect) {
+        let d = &self.debugger;
+        let address = d.vm.address;
+        let mut keys: Vec<[u8; 32]> = Vec::new();
+        for state in [&d.initial_state, &d.vm.state] {
+            if let Some(account) = state.get(&address) {
+                keys.extend(account.storage.keys());
+            }
+        }
+        keys.sort();
+        keys.dedup();
+        let lines: Vec<Line> = keys
+            .iter()
+            .map(|key| {
+                let old = d.initial_state.storage(&address, key);
+                let new = d.vm.state.storage(&address, key);
+                let mut spans = vec![Span::raw(format!("{}: ", trace::hex_word(key)))];
+                if old == new {
+                    spans.push(Span::raw(trace::hex_word(&new)));
+                } else {
+                    spans.push(Span::styled(
+                        trace::hex_word(&old),
+                        Style::default().fg(Color::Red),
+                    ));
+                    spans.push(Span::raw(" → "));
+                    spans.push(Span::styled(
+                        trace::hex_word(&new),
+                        Style::default().fg(Color::Green),
+                    ));
+                }
+                Line::from(spans)
+            })
+            .collect();
+        f.render_widget(Paragraph::new(lines).block(panel("Storage")), area);
+    }
+}
+
+fn panel(title: &str) -> Block<'_> {
+    Block::default().borders(Borders::ALL).title(title)
+}
@@ -5,12 +5,32 @@ pub fn u256_to_u64(a: [u8; 32]) -> u64 {
     b8.copy_from_slice(&a[32 - 8..32]);
     u64::from_be_bytes(b8)
 }
+// u256_to_u64_saturating returns u64::MAX for the numbers that do not fit
+pub fn u256_to_u64_saturating(a: [u8; 32]) -> u64 {
+    if a[..24] != [0; 24] {
+        return u64::MAX;
+    }
+    u256_to_u64(a)
+}
 pub fn usize_to_u256(i: usize) -> [u8; 32] {
     let i_bytes = i.to_be_bytes();
     let mut r: [u8; 32] = [0; 32];
     r[32 - i_bytes.len()..].copy_from_slice(&i_bytes);
     r
 }
+pub fn u64_to_u256(i: u64) -> [u8; 32] {
+    let mut r: [u8; 32] = [0; 32];
+    r[24..].copy_from_slice(&i.to_be_bytes());
+    r
+}
+// biguint_to_u256 keeps the low 256 bits of the number
+pub fn biguint_to_u256(b: &BigUint) -> [u8; 32] {
+    let bi = b.to_bytes_be();
+    let bi = &bi[bi.len().saturating_sub(32)..];
+    let mut r: [u8; 32] = [0; 32];
+    r[32 - bi.len()..].copy_from_slice(bi);
+    r
+}
 pub fn str_to_u256(s: &str) -> [u8; 32] {
     let bi = s.parse::<BigUint>().unwrap().to_bytes_be();
     let mut r: [u8; 32] = [0; 32];
@@ -0,0 +1,288 @@
+use evm::block::*;
+use evm::blocktest::{run_test, spec_at};
+use evm::envelope::SignedTransaction;
+use evm::json::{alloc_to_json, hex_bytes};
+use evm::rlp;
+use evm::signature::secret_key_to_address;
+use evm::spec::{Config, Spec};
+use evm::state::State;
+use evm::transaction::{Transaction, DYNAMIC_FEE_TX_TYPE};
+use evm::trie::EMPTY_ROOT;
+use num_bigint::BigUint;
+use serde_json::{json, Value};
+
+const CONTRACT: [u8; 20] = [0x10; 20];
+
+#[test]
+fn header_hash() {
+    // mainnet genesis block
+    let mut extra_data = [0; 32];
+    hex::decode_to_slice(
+        "11bbe8db4e347b4e8c937c1c8370e4b5ed33adb3db69cbdb7a38e1e50b1b82fa",
+        &mut extra_data,
+    )
+    .unwrap();
+    let mut state_root = [0; 32];
+    hex::decode_to_slice(
+        "d7f8974fb5ac78d9ac099b9ad5018bedc2ce0a72dad1827a1709da30580f0544",
+        &mut state_root,
+    )
+    .unwrap();
+    let header = Header {
+        state_root,
+        difficulty: BigUint::from(0x400000000u64),
+        gas_limit: 5000,
+        extra_data: extra_data.to_vec(),
+        nonce: [0, 0, 0, 0, 0, 0, 0, 0x42],
+        ..Header::default()
+    };
+    assert_eq!(
+        hex::encode(header.hash()),
+        "d4e56740f876aef8c010b86a40d5f56745a118d0906a34e69aec8c0db1cb8fa3"
+    );
+    let decoded: Header = rlp::from_bytes(&rlp::to_bytes(&header)).unwrap();
+    assert_eq!(decoded, header);
+}
+
+#[test]
+fn base_fee_and_blob_gas() {
+    let parent = Header {
+        gas_limit: 30000000,
+        gas_used: 15000000,
+        base_fee: Some(BigUint::from(1000000000u64)),
+        excess_blob_gas: Some(0),
+        blob_gas_used: Some(786432),
+        ..Header::default()
+    };
+    assert_eq!(calc_base_fee(&parent), BigUint::from(1000000000u64));
+    let full = Header {
+        gas_used: 30000000,
+        ..parent.clone()
+    };
+    assert_eq!(calc_base_fee(&full), BigUint::from(1125000000u64));
+    let empty = Header {
+        gas_used: 0,
+        ..parent.clone()
+    };
+    assert_eq!(calc_base_fee(&empty), BigUint::from(875000000u64));
+    assert_eq!(calc_excess_blob_gas(Spec::Cancun, &parent), 393216);
+    assert_eq!(calc_excess_blob_gas(Spec::Prague, &parent), 0);
+
+    assert_eq!(
+        hex::encode(requests_hash(&[])),
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+}
+
+#[test]
+fn transition_networks() {
+    assert_eq!(spec_at("Cancun", 1, 1).unwrap(), Spec::Cancun);
+    assert_eq!(spec_at("BerlinToLondonAt5", 4, 0).unwrap(), Spec::Berlin);
+    assert_eq!(spec_at("BerlinToLondonAt5", 5, 0).unwrap(), Spec::London);
+    assert_eq!(
+        spec_at("ShanghaiToCancunAtTime15k", 100, 14999).unwrap(),
+        Spec::Shanghai
+    );
+    assert_eq!(
+        spec_at("ShanghaiToCancunAtTime15k", 1, 15000).unwrap(),
+        Spec::Cancun
+    );
+    assert!(spec_at("HomesteadToDaoAt5", 5, 0).is_err());
+}
+
+// build_test returns a Cancun blockchain test with a block calling a contract
+// that logs and stores, and an invalid block
+fn build_test() -> Value {
+    let secret_key = [0x45; 32];
+    let sender = secret_key_to_address(&secret_key).unwrap();
+    let mut pre = State::new();
+    pre.add_balance(&sender, &BigUint::from(10u64).pow(18));
+    // mstore 0xaa at 0; log1(0, 32, topic 1); sstore 1 at 0
+    pre.account(&CONTRACT).code = hex::decode("60aa600052600160206000a1600160005500").unwrap();
+    pre.account(&BEACON_ROOTS_ADDRESS).code = vec![0x00];
+
+    let genesis = Block {
+        header: Header {
+            state_root: pre.root(),
+            base_fee: Some(BigUint::from(7u64)),
+            withdrawals_root: Some(EMPTY_ROOT),
+            blob_gas_used: Some(0),
+            excess_blob_gas: Some(0),
+            parent_beacon_block_root: Some([0; 32]),
+            ..Header::default()
+        },
+        withdrawals: Some(Vec::new()),
+        ..Block::default()
+    };
+
+    let tx = Transaction {
+        tx_type: DYNAMIC_FEE_TX_TYPE,
+        chain_id: Some(1),
+        to: Some(CONTRACT),
+        gas_limit: 100000,
+        gas_price: BigUint::from(1000000000u64),
+        max_priority_fee_per_gas: Some(BigUint::from(2u64)),
+        value: BigUint::from(1u64),
+        ..Transaction::default()
+    };
+    let mut block = Block {
+        header: Header {
+            parent_hash: genesis.hash(),
+            coinbase: [0xcc; 20],
+            number: 1,
+            timestamp: 12,
+            base_fee: Some(calc_base_fee(&genesis.header)),
+            withdrawals_root: Some(EMPTY_ROOT),
+            blob_gas_used: Some(0),
+            excess_blob_gas: Some(calc_excess_blob_gas(Spec::Cancun, &genesis.header)),
+            parent_beacon_block_root: Some([0xbb; 32]),
+            ..Header::default()
+        },
+        transactions: vec![SignedTransaction::sign(&tx, &secret_key).unwrap()],
+        withdrawals: Some(vec![Withdrawal {
+            index: 0,
+            validator_index: 5,
+            address: [0xdd; 20],
+            amount: 3,
+        }]),
+        ..Block::default()
+    };
+
+    // fill the header from the execution
+    let mut state = pre.clone();
+    let result = execute_block(&Config::new(Spec::Cancun), &mut state, &block, 1).unwrap();
+    let header = &mut block.header;
+    header.gas_used = result.gas_used;
+    header.logs_bloom = result.logs_bloom;
+    header.receipts_root = result.receipts_root();
+    header.transactions_root = transactions_root(&block.transactions);
+    header.withdrawals_root = Some(withdrawals_root(block.withdrawals.as_ref().unwrap()));
+    header.state_root = state.root();
+    validate_block(&block, &result, &state).unwrap();
+
+    assert!(result.results[0].success);
+    assert_eq!(result.receipts[0].logs.len(), 1);
+    assert!(result.logs_bloom.contains(&CONTRACT));
+    assert!(!result.logs_bloom.contains(&[0xee; 20]));
+    assert_eq!(state.balance(&[0xdd; 20]), BigUint::from(3000000000u64));
+    assert_eq!(
+        state.storage(&BEACON_ROOTS_ADDRESS, &{
+            let mut k = [0; 32];
+            k[31] = 12;
+            k
+        })[31],
+        12
+    );
+
+    let mut invalid = Block {
+        header: Header {
+            parent_hash: block.hash(),
+            number: 2,
+            timestamp: 24,
+            base_fee: Some(calc_base_fee(&block.header)),
+            ..block.header.clone()
+        },
+        transactions: Vec::new(),
+        withdrawals: Some(Vec::new()),
+        ..Block::default()
+    };
+    invalid.header.gas_used = 0;
+    invalid.header.transactions_root = EMPTY_ROOT;
+    invalid.header.withdrawals_root = Some(EMPTY_ROOT);
+    invalid.header.receipts_root = EMPTY_ROOT;
+    invalid.header.logs_bloom = Bloom::default();
+    // state root left as the parent one: the beacon root write changes it
+
+    json!({
+        "network": "Cancun",
+        "pre": alloc_to_json(&pre),
+        "genesisRLP": hex_bytes(&rlp::to_bytes(&genesis)),
+        "blocks": [
+            {"rlp": hex_bytes(&rlp::to_bytes(&block))},
+            {"rlp": hex_bytes(&rlp::to_bytes(&invalid)), "expectException": "BlockException.INVALID_STATE_ROOT"},
+        ],
+        "lastblockhash": hex_bytes(&block.hash()),
+        "postState": alloc_to_json(&state),
+        "sealEngine": "NoProof",
+    })
+}
+
+#[test]
+fn blocktest_chain() {
+    let test = build_test();
+    let r = run_test("chain", &test);
+    assert!(r.pass, "{:?}", r.error);
+    assert_eq!(r.fork, "Cancun");
+
+    // the invalid block is rejected
+    let mut t = test.clone();
+    t["blocks"][1]
+        .as_object_mut()
+        .unwrap()
+        .remove("expectException");
+    let r = run_test("chain", &t);
+    assert!(r.error.unwrap().starts_with("block 1: invalid state root"));
+
+    // post-state mismatch
+    let mut t = test.clone();
+    let sender = hex_bytes(&secret_key_to_address(&[0x45; 32]).unwrap());
+    t["postState"][&sender]["balance"] = Value::from("0x01");
+    let r = run_test("chain", &t);
+    assert!(r.error.unwrap().starts_with("post state root mismatch"));
+
+    // a block on an unknown parent
+    let mut t = test.clone();
+    t["blocks"] = json!([test["blocks"][1]]);
+    t["blocks"][0]
+        .as_object_mut()
+        .unwrap()
+        .remove("expectException");
+    let r = run_test("chain", &t);
+    assert_eq!(r.error, Some("block 0: unknown parent".to_string()));
+}
+
+#[test]
+fn zero_withdrawals_and_requests() {
+    // a zero amount does not create the account
+    let mut state = State::new();
+    let withdrawal = |address, amount| Withdrawal {
+        index: 0,
+        validator_index: 0,
+        address,
+        amount,
+    };
+    apply_withdrawals(
+        &mut state,
+        &[withdrawal([1; 20], 0), withdrawal([2; 20], 1)],
+    );
+    assert!(state.get(&[1; 20]).is_none());
+    assert_eq!(state.balance(&[2; 20]), BigUint::from(1000000000u64));
+
+    // the blocks with EIP-7685 requests are not supported
+    let mut block = Block {
+        header: Header {
+            number: 1,
+            base_fee: Some(BigUint::from(7u64)),
+            withdrawals_root: Some(EMPTY_ROOT),
+            blob_gas_used: Some(0),
+            excess_blob_gas: Some(0),
+            parent_beacon_block_root: Some([0; 32]),
+            requests_hash: Some(requests_hash(&[])),
+            ..Header::default()
+        },
+        withdrawals: Some(Vec::new()),
+        ..Block::default()
+    };
+    let mut state = State::new();
+    let result = execute_block(&Config::new(Spec::Prague), &mut state, &block, 1).unwrap();
+    let header = &mut block.header;
+    header.transactions_root = EMPTY_ROOT;
+    header.receipts_root = result.receipts_root();
+    header.state_root = state.root();
+    validate_block(&block, &result, &state).unwrap();
+    block.header.requests_hash = Some(requests_hash(&[vec![0, 1]]));
+    assert_eq!(
+        validate_block(&block, &result, &state),
+        Err("unsupported block: EIP-7685 requests are not collected".to_string())
+    );
+}
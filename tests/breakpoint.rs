@@ -0,0 +1,122 @@
+use evm::asm::assemble;
+use evm::breakpoint::{Breakpoint, Exit};
+use evm::debugger::Debugger;
+use evm::u256;
+use evm::Stack;
+
+// stores the counter at its slot and at 32 * counter in memory, from 3 to 1
+const CODE: &str = "PUSH1 3
+    loop: JUMPDEST
+    DUP1 DUP1 SSTORE
+    DUP1 DUP1 PUSH1 0x20 MUL MSTORE
+    PUSH1 1 SWAP1 SUB DUP1 PUSH @loop JUMPI
+    PUSH1 0 SLOAD PUSH1 1 SSTORE STOP";
+
+#[test]
+fn run_breakpoints() {
+    let code = assemble(CODE).unwrap();
+    let two = u256::u64_to_u256(2);
+    let breakpoints = vec![
+        Breakpoint::StorageWrite(two),
+        Breakpoint::Memory(64, 96),
+        Breakpoint::StorageRead([0; 32]),
+    ];
+    let mut vm = Stack::new();
+
+    // before the SSTORE of the slot 2
+    assert_eq!(vm.run(&code, &[], &breakpoints), Ok(Exit::Paused(0)));
+    assert_eq!(code[vm.pc], 0x55);
+    assert_eq!(vm.stack.last(), Some(&two));
+    assert_eq!(vm.get_storage(&two), [0; 32]);
+    // pausing again without executing it
+    assert_eq!(vm.run(&code, &[], &breakpoints), Ok(Exit::Paused(0)));
+
+    // after the MSTORE at 64
+    assert_eq!(vm.resume(&code, &[], &breakpoints), Ok(Exit::Paused(1)));
+    assert_eq!(vm.get_storage(&two), two);
+    assert_eq!(code[vm.pc - 1], 0x52);
+    assert_eq!(vm.mem[95], 2);
+
+    assert_eq!(vm.resume(&code, &[], &breakpoints), Ok(Exit::Paused(2)));
+    assert_eq!(code[vm.pc], 0x54);
+    assert_eq!(
+        vm.resume(&code, &[], &breakpoints),
+        Ok(Exit::Halted(vec![]))
+    );
+
+    // each JUMPI of the loop, and the opcode at pc 0 before it executes
+    let breakpoints = vec![Breakpoint::Pc(0), Breakpoint::Opcode(0x57)];
+    let mut vm = Stack::new();
+    assert_eq!(vm.run(&code, &[], &breakpoints), Ok(Exit::Paused(0)));
+    let mut jumps = 0;
+    while vm.resume(&code, &[], &breakpoints) == Ok(Exit::Paused(1)) {
+        jumps += 1;
+    }
+    assert_eq!(jumps, 3);
+
+    // after the instruction making the gas drop below the value
+    let breakpoints = vec![Breakpoint::Gas(9_999_977_000)];
+    let mut vm = Stack::new();
+    assert_eq!(vm.run(&code, &[], &breakpoints), Ok(Exit::Paused(0)));
+    assert_eq!(code[vm.pc - 1], 0x55);
+    assert!(vm.gas < 9_999_977_000);
+    assert_eq!(
+        vm.resume(&code, &[], &breakpoints),
+        Ok(Exit::Halted(vec![]))
+    );
+}
+
+#[test]
+fn debugger_watchpoints() {
+    let code = assemble(CODE).unwrap();
+    let mut d = Debugger::new(&code, &[]);
+    d.command("break sstore 1").unwrap();
+    d.command("break mem 0x40 0x60").unwrap();
+    d.command("break gas 100").unwrap();
+    assert_eq!(
+        d.command("breakpoints").unwrap(),
+        "0: sstore 0x1\n1: memory 0x40..0x60\n2: gas < 100"
+    );
+    assert!(d.command("c").unwrap().starts_with("breakpoint 1 hit\n"));
+    assert_eq!(d.vm.mem[95], 2);
+    // the slot 1 written in the loop, then at the end
+    assert!(d.command("c").unwrap().starts_with("breakpoint 0 hit\n"));
+    assert_eq!(d.vm.pc, 5);
+    assert!(d.command("c").unwrap().starts_with("breakpoint 0 hit\n"));
+    assert_eq!(d.vm.pc, code.len() - 2);
+    assert!(d.command("c").unwrap().starts_with("halted"));
+    assert!(d.command("break mem 0").is_err());
+}
+
+#[test]
+fn memory_watch_unbounded() {
+    let code = assemble(CODE).unwrap();
+    let mut d = Debugger::new(&code, &[]);
+    // the range is not allocated past the memory size
+    d.command("break mem 0 18446744073709551615").unwrap();
+    d.command("step").unwrap();
+    assert!(d
+        .command("c")
+        .unwrap()
+        .starts_with("breakpoint 0 hit\npc=12"));
+    assert_eq!(d.vm.mem[127], 3);
+}
+
+#[test]
+fn run_commits_and_reverts() {
+    let code = assemble(CODE).unwrap();
+    let mut vm = Stack::new();
+    assert_eq!(vm.run(&code, &[], &[]), Ok(Exit::Halted(vec![])));
+    assert!(vm.journal.is_empty());
+
+    // SSTORE then an invalid jump, the write being undone
+    let code = assemble("PUSH1 1 PUSH1 1 SSTORE PUSH1 0 JUMP").unwrap();
+    let mut vm = Stack::new();
+    let one = u256::u64_to_u256(1);
+    let breakpoints = vec![Breakpoint::Opcode(0x56)];
+    assert_eq!(vm.run(&code, &[], &breakpoints), Ok(Exit::Paused(0)));
+    assert_eq!(vm.get_storage(&one), one);
+    assert!(vm.resume(&code, &[], &breakpoints).is_err());
+    assert_eq!(vm.get_storage(&one), [0; 32]);
+    assert!(vm.journal.is_empty());
+}
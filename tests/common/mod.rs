@@ -0,0 +1,21 @@
+// helpers shared by the integration tests, each test using a part of them
+#![allow(dead_code)]
+use std::path::PathBuf;
+
+pub fn address(n: u8) -> [u8; 20] {
+    let mut a = [0; 20];
+    a[19] = n;
+    a
+}
+
+pub fn word(n: u8) -> [u8; 32] {
+    let mut w = [0; 32];
+    w[31] = n;
+    w
+}
+
+// temp_path returns a path in the temporary directory unique to the test
+// process, so that concurrent runs do not share it
+pub fn temp_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("{}-{}", std::process::id(), name))
+}
@@ -0,0 +1,289 @@
+mod common;
+
+use common::{temp_path, word};
+use evm::asm::assemble;
+use evm::db::{commit, fork, AccountInfo, CacheDb, Database, DbHost, FileDb, MemoryDb};
+use evm::json;
+use evm::state::{State, EMPTY_CODE_HASH};
+use evm::Stack;
+use num_bigint::BigUint;
+use std::cell::RefCell;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::rc::Rc;
+
+// commit_state writes a contract account with code and a storage slot
+fn commit_state(db: &mut dyn Database) -> [u8; 32] {
+    let mut state = State::new();
+    let account = state.account(&[1; 20]);
+    account.nonce = 1;
+    account.balance = BigUint::from(100u64);
+    account.code = vec![0x60, 0x00];
+    account.storage.insert(word(1), word(2));
+    commit(db, &State::new(), &state).unwrap();
+    db.set_block_hash(7, word(7)).unwrap();
+    evm::keccak256(&[0x60, 0x00])
+}
+
+fn check_state(db: &dyn Database, code_hash: [u8; 32]) {
+    let info = db.account(&[1; 20]).unwrap().unwrap();
+    assert_eq!(
+        info,
+        AccountInfo {
+            nonce: 1,
+            balance: BigUint::from(100u64),
+            code_hash,
+        }
+    );
+    assert_eq!(db.code_by_hash(&code_hash).unwrap(), vec![0x60, 0x00]);
+    assert_eq!(db.storage(&[1; 20], &word(1)).unwrap(), word(2));
+    assert_eq!(db.storage(&[1; 20], &word(2)).unwrap(), [0; 32]);
+    assert_eq!(db.block_hash(7).unwrap(), word(7));
+    assert_eq!(db.account(&[2; 20]).unwrap(), None);
+}
+
+#[test]
+fn memory_db() {
+    let mut db = MemoryDb::new();
+    let code_hash = commit_state(&mut db);
+    check_state(&db, code_hash);
+    assert_eq!(db.set_code(&[]).unwrap(), EMPTY_CODE_HASH);
+
+    db.set_storage(&[1; 20], &word(1), [0; 32]).unwrap();
+    assert!(db.storage.is_empty());
+    db.set_account(&[1; 20], None).unwrap();
+    assert_eq!(db.account(&[1; 20]).unwrap(), None);
+}
+
+#[test]
+fn file_db() {
+    let path = temp_path("evm-file-db.log");
+    let _ = std::fs::remove_file(&path);
+    let mut db = FileDb::open(&path).unwrap();
+    let code_hash = commit_state(&mut db);
+    check_state(&db, code_hash);
+    db.set_storage(&[1; 20], &word(3), word(4)).unwrap();
+    db.set_storage(&[1; 20], &word(3), word(5)).unwrap();
+    drop(db);
+
+    // the state survives reopening the log, with the last write of each slot
+    let mut db = FileDb::open(&path).unwrap();
+    check_state(&db, code_hash);
+    assert_eq!(db.storage(&[1; 20], &word(3)).unwrap(), word(5));
+    db.set_account(&[1; 20], None).unwrap();
+    drop(db);
+    let db = FileDb::open(&path).unwrap();
+    assert_eq!(db.account(&[1; 20]).unwrap(), None);
+    // the storage of a deleted account is deleted with it
+    assert_eq!(db.storage(&[1; 20], &word(1)).unwrap(), [0; 32]);
+    assert_eq!(db.storage(&[1; 20], &word(3)).unwrap(), [0; 32]);
+    drop(db);
+
+    // a record torn at the end of the log is dropped
+    let size = std::fs::metadata(&path).unwrap().len();
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(&[0, 0, 0, 40, 0xc0]).unwrap();
+    drop(file);
+    let mut db = FileDb::open(&path).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), size);
+    db.set_block_hash(8, word(8)).unwrap();
+    drop(db);
+    let db = FileDb::open(&path).unwrap();
+    assert_eq!(db.block_hash(8).unwrap(), word(8));
+    drop(db);
+
+    // a length over the bound is not trusted
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(&[0xff, 0xff, 0xff, 0xff, 0xc0]).unwrap();
+    drop(file);
+    assert!(FileDb::open(&path)
+        .err()
+        .unwrap()
+        .ends_with("record too large (4294967295 bytes)"));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn commit_deletions() {
+    let mut db = MemoryDb::new();
+    commit_state(&mut db);
+    let mut pre = State::new();
+    let account = pre.account(&[1; 20]);
+    account.nonce = 1;
+    account.balance = BigUint::from(100u64);
+    account.code = vec![0x60, 0x00];
+    account.storage.insert(word(1), word(2));
+    pre.account(&[2; 20]).nonce = 1;
+    db.set_account(
+        &[2; 20],
+        Some(AccountInfo {
+            nonce: 1,
+            ..AccountInfo::default()
+        }),
+    )
+    .unwrap();
+
+    // the slot is cleared, and the account 0x02 deleted
+    let mut post = pre.clone();
+    post.account(&[1; 20]).storage.clear();
+    post.accounts.remove(&[2; 20]);
+    commit(&mut db, &pre, &post).unwrap();
+    assert_eq!(db.storage(&[1; 20], &word(1)).unwrap(), [0; 32]);
+    assert!(db.storage.is_empty());
+    assert_eq!(db.account(&[2; 20]).unwrap(), None);
+    assert!(db.account(&[1; 20]).unwrap().is_some());
+
+    // deleting an account deletes its storage
+    db.set_storage(&[1; 20], &word(1), word(2)).unwrap();
+    db.set_account(&[1; 20], None).unwrap();
+    assert_eq!(db.storage(&[1; 20], &word(1)).unwrap(), [0; 32]);
+}
+
+#[test]
+fn execute_with_db_host() {
+    let path = temp_path("evm-db-host.log");
+    let _ = std::fs::remove_file(&path);
+    let code = assemble("PUSH1 1 SLOAD PUSH1 1 ADD PUSH1 1 SSTORE STOP").unwrap();
+    // each run increments the slot, read from the previous run
+    for i in 1..=3 {
+        let mut vm = Stack::new();
+        vm.address = [1; 20];
+        vm.host = Some(Box::new(DbHost::new(FileDb::open(&path).unwrap())));
+        vm.execute(&code, &[], false).unwrap();
+        assert_eq!(vm.get_storage(&word(1)), word(i));
+        assert!(vm.state.get(&[1; 20]).is_none());
+    }
+    let db = FileDb::open(&path).unwrap();
+    assert_eq!(db.storage(&[1; 20], &word(1)).unwrap(), word(3));
+    assert!(db.account(&[1; 20]).unwrap().is_some());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn cache_db() {
+    let mut base = MemoryDb::new();
+    let code_hash = commit_state(&mut base);
+    let mut db = CacheDb::new(base);
+    db.set_storage(&[1; 20], &word(1), [0; 32]).unwrap();
+    db.set_storage(&[1; 20], &word(3), word(3)).unwrap();
+    db.set_account(&[2; 20], Some(AccountInfo::default()))
+        .unwrap();
+    assert_eq!(db.storage(&[1; 20], &word(1)).unwrap(), [0; 32]);
+    assert_eq!(db.storage(&[1; 20], &word(3)).unwrap(), word(3));
+    assert!(db.account(&[2; 20]).unwrap().is_some());
+    // the base is not written
+    check_state(&db.base, code_hash);
+
+    db.discard();
+    check_state(&db, code_hash);
+
+    db.set_storage(&[1; 20], &word(3), word(3)).unwrap();
+    db.set_account(&[1; 20], None).unwrap();
+    // the storage of the base is shadowed by the deletion
+    assert_eq!(db.storage(&[1; 20], &word(1)).unwrap(), [0; 32]);
+    db.set_storage(&[1; 20], &word(3), word(3)).unwrap();
+    db.flush().unwrap();
+    assert!(db.storage.is_empty());
+    assert_eq!(db.base.storage(&[1; 20], &word(3)).unwrap(), word(3));
+    assert_eq!(db.base.storage(&[1; 20], &word(1)).unwrap(), [0; 32]);
+    assert_eq!(db.base.account(&[1; 20]).unwrap(), None);
+}
+
+#[test]
+fn db_host_revert() {
+    let db = Rc::new(RefCell::new(MemoryDb::new()));
+    // writes the slot and the log, then fails
+    let code = assemble("PUSH1 2 PUSH1 1 SSTORE PUSH1 0 PUSH1 0 LOG0 PUSH1 1 SLOAD DUP3").unwrap();
+    let mut vm = Stack::new();
+    vm.address = [1; 20];
+    vm.host = Some(Box::new(DbHost::new(db.clone())));
+    assert_eq!(
+        vm.execute(&code, &[], false),
+        Err("stack underflow".to_string())
+    );
+    assert_eq!(db.storage(&[1; 20], &word(1)).unwrap(), [0; 32]);
+    assert_eq!(db.account(&[1; 20]).unwrap(), None);
+
+    // a successful execution is kept
+    let code = assemble("PUSH1 2 PUSH1 1 SSTORE").unwrap();
+    vm.execute(&code, &[], false).unwrap();
+    let code = assemble("PUSH1 3 PUSH1 1 SSTORE POP").unwrap();
+    assert!(vm.execute(&code, &[], false).is_err());
+    assert_eq!(db.storage(&[1; 20], &word(1)).unwrap(), word(2));
+}
+
+#[test]
+fn fork_dump() {
+    let (mut contract, mut account) = ([0; 20], [0; 20]);
+    contract[19] = 1;
+    account[19] = 2;
+    let code = vec![0x60, 0x01, 0x60, 0x00, 0x54, 0x01, 0x60, 0x00, 0x55];
+    for dump in ["geth.json", "geth.jsonl", "anvil.json"].iter() {
+        let path = Path::new("tests/fixtures/dumps").join(dump);
+        let db = Rc::new(RefCell::new(fork(&path).unwrap()));
+        let info = db.account(&contract).unwrap().unwrap();
+        assert_eq!(info.nonce, 1, "{}", dump);
+        assert_eq!(info.balance, BigUint::from(1000u64), "{}", dump);
+        assert_eq!(db.code_by_hash(&info.code_hash).unwrap(), code, "{}", dump);
+        let info = db.account(&account).unwrap().unwrap();
+        assert_eq!(info.balance, BigUint::from(7u64), "{}", dump);
+        assert_eq!(info.code_hash, EMPTY_CODE_HASH, "{}", dump);
+
+        // the contract increments the slot 0 of the dump
+        for i in 6..=7 {
+            let mut vm = Stack::new();
+            vm.address = contract;
+            vm.host = Some(Box::new(DbHost::new(db.clone())));
+            vm.execute(&code, &[], false).unwrap();
+            assert_eq!(
+                db.storage(&contract, &[0; 32]).unwrap(),
+                word(i),
+                "{}",
+                dump
+            );
+        }
+        assert_eq!(
+            db.borrow().base.storage(&contract, &[0; 32]).unwrap(),
+            word(5)
+        );
+        db.borrow_mut().discard();
+        assert_eq!(
+            db.storage(&contract, &[0; 32]).unwrap(),
+            word(5),
+            "{}",
+            dump
+        );
+    }
+}
+
+#[test]
+fn parse_dump_invalid() {
+    let key = "0x1468288056310c82aa4c01a7e12a10f8111a0560e72b700555479031b86c357d";
+    // without the preimage of the key of an account, as an object or a line
+    let dumps = [
+        format!(
+            r#"{{"root": "0x00", "accounts": {{"pre({})": {{"balance": "1", "key": "{}"}}}}}}"#,
+            key, key
+        ),
+        format!(
+            "{{\"root\": \"0x00\"}}\n{{\"balance\": \"1\", \"key\": \"{}\"}}\n",
+            key
+        ),
+    ];
+    for dump in dumps.iter() {
+        let e = json::parse_dump(dump).unwrap_err();
+        assert!(e.starts_with("dump: 1 accounts without address"), "{}", e);
+        assert!(e.contains(key), "{}", e);
+    }
+
+    // dumped without the code
+    let dump = r#"{"accounts": {"0x0000000000000000000000000000000000000001": {
+        "balance": "1",
+        "codeHash": "0xbcfbb14639506d4e1be8b81663bcc972303aeca04134c0f7861b183bc6077af4"
+    }}}"#;
+    assert_eq!(
+        json::parse_dump(dump).unwrap_err(),
+        "dump: code of 0x0000000000000000000000000000000000000001 does not match its codeHash"
+    );
+}
@@ -0,0 +1,117 @@
+use evm::asm::{assemble, disassemble};
+use evm::breakpoint::Breakpoint;
+use evm::debugger::Debugger;
+
+#[test]
+fn asm_roundtrip() {
+    let code = assemble(
+        "PUSH1 0x05 ; counter
+         loop: JUMPDEST
+         push1 1 SWAP1 SUB DUP1 PUSH @loop JUMPI // until zero
+         PUSH 0 PUSH 300 PUSH32 0x01 STOP",
+    )
+    .unwrap();
+    assert_eq!(
+        hex::encode(&code),
+        "60055b60019003806100025760006101 2c7f0000000000000000000000000000000000000000000000000000000000000001 00"
+            .replace(' ', "")
+    );
+    let text: Vec<String> = disassemble(&code).iter().map(|i| i.to_string()).collect();
+    assert_eq!(text[0], "PUSH1 0x05");
+    assert_eq!(text[6], "PUSH2 0x0002");
+    assert_eq!(text[9], "PUSH2 0x012c");
+    assert_eq!(disassemble(&code)[1].pc, 2);
+    assert_eq!(assemble(&text.join(" ")).unwrap(), code);
+
+    // truncated push and unknown opcode
+    let d = disassemble(&[0xfe, 0x61, 0x01]);
+    assert_eq!(d[0].name, "INVALID(0xfe)");
+    assert_eq!(d[1].immediate, vec![0x01]);
+
+    assert_eq!(
+        assemble("PUSH1 0x0100"),
+        Err("asm: 0x0100 does not fit in PUSH1".to_string())
+    );
+    assert_eq!(
+        assemble("PUSH @end"),
+        Err("asm: unknown label end".to_string())
+    );
+    assert_eq!(
+        assemble("ADD FOO"),
+        Err("asm: unknown instruction FOO".to_string())
+    );
+}
+
+#[test]
+fn debugger_commands() {
+    let mut d = Debugger::new(&[], &[]);
+    d.command("asm PUSH1 5 loop: JUMPDEST PUSH1 1 SWAP1 SUB DUP1 PUSH @loop JUMPI")
+        .unwrap();
+    assert_eq!(
+        d.command("step 2").unwrap(),
+        "pc=3 gas=9999999996 next: PUSH1 0x01"
+    );
+
+    d.command("break op SUB").unwrap();
+    assert_eq!(d.breakpoints, vec![Breakpoint::Opcode(0x03)]);
+    assert_eq!(
+        d.command("c").unwrap(),
+        "breakpoint 0 hit\npc=6 gas=9999999990 next: SUB"
+    );
+    assert_eq!(d.vm.stack.len(), 2);
+
+    // edit the counter so that the loop ends
+    d.command("set 0 1").unwrap();
+    d.command("delete 0").unwrap();
+    d.command("break pc 2").unwrap();
+    assert_eq!(d.command("c").unwrap(), "halted: output 0x, gas used 29");
+    assert!(d.command("step").is_err());
+
+    // restart, keeping the breakpoints
+    d.command("reset").unwrap();
+    assert!(d
+        .command("c")
+        .unwrap()
+        .starts_with("breakpoint 0 hit\npc=2"));
+
+    d.command("push 0x2a").unwrap();
+    assert_eq!(d.vm.pop().unwrap()[31], 0x2a);
+    d.command("mstore 40 0xffff").unwrap();
+    assert_eq!(d.vm.mem.len(), 64);
+    assert_eq!(d.vm.mem[41], 0xff);
+    for offset in ["0xffffffffffffffff", "0x1000000"].iter() {
+        assert_eq!(
+            d.command(&format!("mstore {} 0xff", offset)).err(),
+            Some("mstore: memory past 16777216 bytes".to_string())
+        );
+    }
+    assert_eq!(d.vm.mem.len(), 64);
+    d.command("sstore 1 7").unwrap();
+    assert_eq!(
+        d.command("storage").unwrap(),
+        format!("storage (1):\n0x{:064x}: 0x{:064x}", 1, 7)
+    );
+    assert_eq!(
+        d.command("foo").err(),
+        Some("unknown command foo, see help".to_string())
+    );
+}
+
+#[test]
+fn debugger_truncated_push() {
+    let mut d = Debugger::new(&[], &[]);
+    d.command("load 0x61").unwrap();
+    // the PUSH2 moves pc past the end of the code
+    let info = d.command("step").unwrap();
+    assert!(info.ends_with("next: end of code"), "{}", info);
+    assert!(d.command("step").unwrap().starts_with("halted"));
+}
+
+#[test]
+fn debugger_set_gas() {
+    let mut d = Debugger::new(&[], &[]);
+    d.command("asm PUSH1 1 PUSH1 2 ADD").unwrap();
+    d.command("gas 100").unwrap();
+    // the gas used is counted from the gas set
+    assert_eq!(d.command("c").unwrap(), "halted: output 0x, gas used 9");
+}
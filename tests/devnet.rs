@@ -0,0 +1,133 @@
+mod common;
+
+use common::address;
+use evm::asm::assemble;
+use evm::block::calc_base_fee;
+use evm::devnet::Devnet;
+use evm::envelope::SignedTransaction;
+use evm::genesis::Genesis;
+use evm::signature;
+use evm::spec::Spec;
+use evm::state::State;
+use evm::transaction::{Transaction, DYNAMIC_FEE_TX_TYPE};
+use num_bigint::BigUint;
+
+const SECRET_KEY: [u8; 32] = [1; 32];
+const CHAIN_ID: u64 = 1337;
+
+// devnet with a funded sender and a contract at 0x..01 storing the number
+// and the hash of the previous block, and logging the caller
+fn devnet(gas_limit: u64) -> Devnet {
+    let mut alloc = State::new();
+    let sender = signature::secret_key_to_address(&SECRET_KEY).unwrap();
+    alloc.account(&sender).balance = BigUint::from(10u64).pow(20);
+    alloc.account(&address(1)).code = assemble(
+        "NUMBER DUP1 PUSH1 0 SSTORE
+         PUSH1 1 SWAP1 SUB BLOCKHASH PUSH1 1 SSTORE
+         CALLER PUSH1 0 PUSH1 0 LOG1 STOP",
+    )
+    .unwrap();
+    let mut genesis = Genesis::new(alloc);
+    genesis.config = serde_json::json!({ "chainId": CHAIN_ID });
+    genesis.gas_limit = gas_limit;
+    Devnet::new(&genesis, Spec::Prague)
+}
+
+fn tx(nonce: u64, to: [u8; 20]) -> SignedTransaction {
+    let tx = Transaction {
+        tx_type: DYNAMIC_FEE_TX_TYPE,
+        chain_id: Some(CHAIN_ID),
+        nonce,
+        to: Some(to),
+        gas_limit: 100000,
+        gas_price: BigUint::from(10u64).pow(10),
+        max_priority_fee_per_gas: Some(BigUint::from(1u64)),
+        value: BigUint::from(1u64),
+        ..Transaction::default()
+    };
+    SignedTransaction::sign(&tx, &SECRET_KEY).unwrap()
+}
+
+#[test]
+fn auto_mine() {
+    let mut d = devnet(30000000);
+    assert_eq!(d.block_number(), 0);
+    let hash = d.send_transaction(tx(0, address(2))).unwrap();
+    assert_eq!(d.block_number(), 1);
+    let (b, i) = d.transaction(&hash).unwrap();
+    assert_eq!((b.number(), i), (1, 0));
+    let receipt = &b.result.receipts[0];
+    assert!(receipt.success);
+    assert_eq!(receipt.cumulative_gas_used, 21000);
+    assert_eq!(b.block.header.gas_used, 21000);
+    assert_eq!(b.block.header.state_root, d.state.root());
+    assert_eq!(d.state.balance(&address(2)), BigUint::from(1u64));
+    assert!(d.block_by_hash(&b.hash()).is_some());
+
+    // the same transaction, and a used nonce, are rejected
+    assert!(d.send_transaction(tx(0, address(2))).is_err());
+    // a transaction for another chain is rejected
+    let mut other = tx(1, address(2)).tx;
+    other.chain_id = Some(1);
+    let other = SignedTransaction::sign(&other, &SECRET_KEY).unwrap();
+    assert!(d.send_transaction(other).is_err());
+    assert_eq!(d.block_number(), 1);
+}
+
+#[test]
+fn mine_blocks() {
+    let mut d = devnet(30000000);
+    d.auto_mine = false;
+    // sent out of order, mined by nonce
+    d.send_transaction(tx(1, address(1))).unwrap();
+    d.send_transaction(tx(0, address(2))).unwrap();
+    assert_eq!(d.pending.len(), 2);
+    let b = d.mine().clone();
+    assert!(d.pending.is_empty());
+    assert_eq!(b.number(), 1);
+    assert_eq!(b.block.transactions.len(), 2);
+    let receipts = &b.result.receipts;
+    assert!(receipts[1].success);
+    assert!(receipts[1].cumulative_gas_used > receipts[0].cumulative_gas_used);
+    assert_eq!(b.block.header.gas_used, receipts[1].cumulative_gas_used);
+    // the log of the contract is in the receipt and the block bloom
+    assert_eq!(receipts[1].logs.len(), 1);
+    assert!(b.block.header.logs_bloom.contains(&address(1)));
+    assert!(!receipts[0].logs_bloom.contains(&address(1)));
+
+    // BLOCKHASH of the previous block
+    let genesis = d.block_by_number(0).unwrap().clone();
+    let mut one = [0; 32];
+    one[31] = 1;
+    assert_eq!(d.state.storage(&address(1), &[0; 32]), one);
+    assert_eq!(d.state.storage(&address(1), &one), genesis.hash());
+    d.send_transaction(tx(2, address(1))).unwrap();
+    d.mine();
+    assert_eq!(d.state.storage(&address(1), &one), b.hash());
+
+    // empty blocks, with the base fee of EIP-1559
+    let parent = d.latest().block.header.clone();
+    let b = d.mine();
+    assert_eq!(b.block.header.parent_hash, parent.hash());
+    assert_eq!(b.block.header.base_fee, Some(calc_base_fee(&parent)));
+    assert!(b.block.header.base_fee < parent.base_fee);
+    assert_eq!(b.block.header.timestamp, parent.timestamp + 1);
+    assert_eq!(d.block_number(), 3);
+}
+
+#[test]
+fn block_gas_limit() {
+    // the gas limit of the transactions is checked against the gas left in
+    // the block: a second one of 100000 does not fit after a transfer
+    let mut d = devnet(120000);
+    d.auto_mine = false;
+    for nonce in 0..3 {
+        d.send_transaction(tx(nonce, address(2))).unwrap();
+    }
+    assert_eq!(d.mine().block.transactions.len(), 1);
+    assert_eq!(d.pending.len(), 2);
+    assert_eq!(d.mine().block.transactions.len(), 1);
+    assert_eq!(d.mine().block.transactions.len(), 1);
+    assert!(d.pending.is_empty());
+    assert_eq!(d.state.nonce(&d.blocks[1].block.transactions[0].tx.from), 3);
+}
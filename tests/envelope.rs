@@ -0,0 +1,185 @@
+use evm::envelope::*;
+use evm::transaction::*;
+use evm::*;
+use num_bigint::BigUint;
+
+fn addr(s: &str) -> [u8; 20] {
+    let mut a: [u8; 20] = [0; 20];
+    a.copy_from_slice(&hex::decode(s).unwrap());
+    a
+}
+
+const SECRET_KEY: [u8; 32] = [0x46; 32];
+
+#[test]
+fn decode_legacy_eip155() {
+    // example from https://eips.ethereum.org/EIPS/eip-155
+    let raw = hex::decode("f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83").unwrap();
+    let stx = decode(&raw).unwrap();
+
+    assert_eq!(stx.tx.tx_type, LEGACY_TX_TYPE);
+    assert_eq!(stx.tx.chain_id, Some(1));
+    assert_eq!(stx.tx.nonce, 9);
+    assert_eq!(stx.tx.gas_price, BigUint::from(20000000000u64));
+    assert_eq!(stx.tx.gas_limit, 21000);
+    assert_eq!(
+        stx.tx.to,
+        Some(addr("3535353535353535353535353535353535353535"))
+    );
+    assert_eq!(stx.tx.value, BigUint::from(1000000000000000000u64));
+    assert_eq!(
+        stx.signing_hash().to_vec(),
+        hex::decode("daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53").unwrap()
+    );
+    assert_eq!(
+        stx.tx.from,
+        addr("9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f")
+    );
+    assert_eq!(
+        signature::secret_key_to_address(&SECRET_KEY).unwrap(),
+        stx.tx.from
+    );
+    assert_eq!(stx.encode(), raw);
+    assert_eq!(stx.hash(), keccak256(&raw));
+
+    // signing is deterministic (RFC 6979)
+    let signed = SignedTransaction::sign(&stx.tx, &SECRET_KEY).unwrap();
+    assert_eq!(signed.encode(), raw);
+}
+
+fn typed_tx(tx_type: u8) -> Transaction {
+    let mut tx = Transaction {
+        tx_type,
+        chain_id: Some(1337),
+        nonce: 3,
+        gas_limit: 100000,
+        gas_price: BigUint::from(30000000000u64),
+        to: Some(addr("00000000000000000000000000000000000000aa")),
+        value: BigUint::from(12345u64),
+        data: hex::decode("a5f3c23b").unwrap(),
+        access_list: vec![([0xbb; 20], vec![[1; 32], [2; 32]])],
+        ..Transaction::default()
+    };
+    if tx_type >= DYNAMIC_FEE_TX_TYPE {
+        tx.max_priority_fee_per_gas = Some(BigUint::from(1000000000u64));
+    }
+    if tx_type == BLOB_TX_TYPE {
+        tx.max_fee_per_blob_gas = Some(BigUint::from(7u64));
+        let mut h = [3; 32];
+        h[0] = 1;
+        tx.blob_versioned_hashes = vec![h];
+    }
+    if tx_type == SET_CODE_TX_TYPE {
+        tx.authorization_list = vec![Authorization {
+            chain_id: BigUint::from(0u64),
+            address: [0xcc; 20],
+            nonce: 1,
+            y_parity: 1,
+            r: BigUint::from(5u64),
+            s: BigUint::from(6u64),
+        }];
+    }
+    tx
+}
+
+#[test]
+fn typed_transactions_roundtrip() {
+    let sender = signature::secret_key_to_address(&SECRET_KEY).unwrap();
+    for tx_type in [
+        LEGACY_TX_TYPE,
+        ACCESS_LIST_TX_TYPE,
+        DYNAMIC_FEE_TX_TYPE,
+        BLOB_TX_TYPE,
+        SET_CODE_TX_TYPE,
+    ] {
+        let mut tx = typed_tx(tx_type);
+        if tx_type == LEGACY_TX_TYPE {
+            tx.access_list = vec![];
+        }
+        let stx = SignedTransaction::sign(&tx, &SECRET_KEY).unwrap();
+        let raw = stx.encode();
+        if tx_type != LEGACY_TX_TYPE {
+            assert_eq!(raw[0], tx_type);
+        }
+
+        let decoded = decode(&raw).unwrap();
+        assert_eq!(decoded, stx);
+        assert_eq!(decoded.tx.from, sender);
+        assert_eq!(decoded.hash(), keccak256(&raw));
+    }
+}
+
+#[test]
+fn decode_blob_network_form() {
+    let stx = SignedTransaction::sign(&typed_tx(BLOB_TX_TYPE), &SECRET_KEY).unwrap();
+    let raw = stx.encode();
+    let body = rlp::decode(&raw[1..]).unwrap();
+    let wrapper = rlp::Rlp::List(vec![
+        body,
+        rlp::Rlp::List(vec![rlp::Rlp::Bytes(vec![0; 131072])]),
+        rlp::Rlp::List(vec![rlp::Rlp::Bytes(vec![0; 48])]),
+        rlp::Rlp::List(vec![rlp::Rlp::Bytes(vec![0; 48])]),
+    ]);
+    let mut network = vec![BLOB_TX_TYPE];
+    network.append(&mut rlp::encode(&wrapper));
+
+    let decoded = decode(&network).unwrap();
+    assert_eq!(decoded, stx);
+    assert_eq!(decoded.hash(), keccak256(&raw));
+}
+
+#[test]
+fn decode_errors() {
+    assert_eq!(decode(&[]), Err("empty transaction".to_string()));
+    assert_eq!(
+        decode(&[0x05, 0xc0]),
+        Err("unsupported transaction type 5".to_string())
+    );
+    assert_eq!(
+        decode(&[0x02, 0xc1, 0x01]),
+        Err("type 2 transaction: expected 12 fields, got 1".to_string())
+    );
+
+    // blob transactions can not create contracts
+    let mut tx = typed_tx(BLOB_TX_TYPE);
+    tx.to = None;
+    let raw = SignedTransaction::sign(&tx, &SECRET_KEY).unwrap().encode();
+    assert_eq!(
+        decode(&raw),
+        Err("type 3 transaction: missing to".to_string())
+    );
+
+    // a tampered signature recovers another sender, or fails
+    let stx = SignedTransaction::sign(&typed_tx(DYNAMIC_FEE_TX_TYPE), &SECRET_KEY).unwrap();
+    let mut tampered = stx.clone();
+    tampered.tx.nonce += 1;
+    match decode(&tampered.encode()) {
+        Ok(d) => assert_ne!(d.tx.from, stx.tx.from),
+        Err(e) => assert!(e.starts_with("invalid signature")),
+    }
+}
+
+#[test]
+fn legacy_chain_id_overflow() {
+    // v = 35 + 2 * chain_id does not fit in 64 bits
+    let tx = Transaction {
+        chain_id: Some(u64::MAX / 2),
+        gas_limit: 21000,
+        ..Transaction::default()
+    };
+    assert_eq!(
+        SignedTransaction::sign(&tx, &SECRET_KEY).err(),
+        Some(format!(
+            "chain id {} too large for a legacy transaction",
+            u64::MAX / 2
+        ))
+    );
+    let tx = Transaction {
+        chain_id: Some(u64::MAX / 2 - 18),
+        ..tx
+    };
+    let mut stx = SignedTransaction::sign(&tx, &SECRET_KEY).unwrap();
+    assert_eq!(stx.sender(), Ok(stx.tx.from));
+    stx.tx.chain_id = Some(u64::MAX);
+    assert!(stx.sender().unwrap_err().starts_with("invalid signature"));
+}
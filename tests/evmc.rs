@@ -0,0 +1,292 @@
+use evm::asm::assemble;
+use evm::evmc::*;
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+
+// client side of the EVMC host interface, keeping the storage
+#[derive(Default)]
+struct Client {
+    storage: HashMap<[u8; 32], [u8; 32]>,
+    logs: usize,
+    // depth and recipient of the calls made
+    calls: Vec<(i32, [u8; 20])>,
+}
+
+fn client<'a>(context: *mut HostContext) -> &'a mut Client {
+    unsafe { &mut *(context as *mut Client) }
+}
+
+extern "C" fn account_exists(_c: *mut HostContext, _a: *const Address) -> bool {
+    true
+}
+extern "C" fn get_storage(c: *mut HostContext, _a: *const Address, key: *const Bytes32) -> Bytes32 {
+    let key = unsafe { (*key).bytes };
+    Bytes32 {
+        bytes: client(c).storage.get(&key).copied().unwrap_or([0; 32]),
+    }
+}
+extern "C" fn set_storage(
+    c: *mut HostContext,
+    _a: *const Address,
+    key: *const Bytes32,
+    value: *const Bytes32,
+) -> StorageStatusC {
+    let (key, value) = unsafe { ((*key).bytes, (*value).bytes) };
+    match client(c).storage.insert(key, value) {
+        None => StorageStatusC::Added,
+        Some(_) => StorageStatusC::Assigned,
+    }
+}
+extern "C" fn get_balance(_c: *mut HostContext, _a: *const Address) -> Bytes32 {
+    let mut b = Bytes32::default();
+    b.bytes[31] = 5;
+    b
+}
+extern "C" fn get_code_size(_c: *mut HostContext, _a: *const Address) -> usize {
+    0
+}
+extern "C" fn get_code_hash(_c: *mut HostContext, _a: *const Address) -> Bytes32 {
+    Bytes32::default()
+}
+extern "C" fn copy_code(
+    _c: *mut HostContext,
+    _a: *const Address,
+    _offset: usize,
+    _buffer: *mut u8,
+    _size: usize,
+) -> usize {
+    0
+}
+extern "C" fn selfdestruct(_c: *mut HostContext, _a: *const Address, _b: *const Address) -> bool {
+    true
+}
+// call returns 0xca, using 100 gas
+extern "C" fn call(c: *mut HostContext, msg: *const EvmcMessage) -> EvmcResult {
+    static OUTPUT: [u8; 1] = [0xca];
+    let msg = unsafe { &*msg };
+    client(c).calls.push((msg.depth, msg.recipient.bytes));
+    EvmcResult {
+        status_code: StatusCode::Success,
+        gas_left: msg.gas - 100,
+        gas_refund: 0,
+        output_data: OUTPUT.as_ptr(),
+        output_size: 1,
+        release: None,
+        create_address: Address::default(),
+        padding: [0; 4],
+    }
+}
+extern "C" fn get_tx_context(_c: *mut HostContext) -> EvmcTxContext {
+    EvmcTxContext {
+        tx_gas_price: Bytes32::default(),
+        tx_origin: Address { bytes: [7; 20] },
+        block_coinbase: Address::default(),
+        block_number: 42,
+        block_timestamp: 0,
+        block_gas_limit: 0,
+        block_prev_randao: Bytes32::default(),
+        chain_id: Bytes32::default(),
+        block_base_fee: Bytes32::default(),
+        blob_base_fee: Bytes32::default(),
+        blob_hashes: std::ptr::null(),
+        blob_hashes_count: 0,
+        initcodes: std::ptr::null(),
+        initcodes_count: 0,
+    }
+}
+extern "C" fn get_block_hash(_c: *mut HostContext, _number: i64) -> Bytes32 {
+    Bytes32::default()
+}
+extern "C" fn emit_log(
+    c: *mut HostContext,
+    _a: *const Address,
+    _data: *const u8,
+    _data_size: usize,
+    _topics: *const Bytes32,
+    _topics_count: usize,
+) {
+    client(c).logs += 1;
+}
+extern "C" fn access_account(_c: *mut HostContext, _a: *const Address) -> AccessStatusC {
+    AccessStatusC::Warm
+}
+extern "C" fn access_storage(
+    _c: *mut HostContext,
+    _a: *const Address,
+    _key: *const Bytes32,
+) -> AccessStatusC {
+    AccessStatusC::Cold
+}
+extern "C" fn get_transient_storage(
+    _c: *mut HostContext,
+    _a: *const Address,
+    _key: *const Bytes32,
+) -> Bytes32 {
+    Bytes32::default()
+}
+extern "C" fn set_transient_storage(
+    _c: *mut HostContext,
+    _a: *const Address,
+    _key: *const Bytes32,
+    _value: *const Bytes32,
+) {
+}
+
+const HOST: HostInterface = HostInterface {
+    account_exists,
+    get_storage,
+    set_storage,
+    get_balance,
+    get_code_size,
+    get_code_hash,
+    copy_code,
+    selfdestruct,
+    call,
+    get_tx_context,
+    get_block_hash,
+    emit_log,
+    access_account,
+    access_storage,
+    get_transient_storage,
+    set_transient_storage,
+};
+
+fn message(gas: i64) -> EvmcMessage {
+    EvmcMessage {
+        kind: CallKindC::Call,
+        flags: 0,
+        depth: 0,
+        gas,
+        recipient: Address { bytes: [1; 20] },
+        sender: Address { bytes: [2; 20] },
+        input_data: std::ptr::null(),
+        input_size: 0,
+        value: Bytes32::default(),
+        create2_salt: Bytes32::default(),
+        code_address: Address { bytes: [1; 20] },
+        code: std::ptr::null(),
+        code_size: 0,
+    }
+}
+
+#[test]
+fn evmc_vm() {
+    let vm = evmc_create_evm();
+    let v = unsafe { &*vm };
+    assert_eq!(v.abi_version, ABI_VERSION);
+    assert_eq!(
+        unsafe { CStr::from_ptr(v.name) }.to_str().unwrap(),
+        "evm-rs"
+    );
+    assert_eq!((v.get_capabilities)(vm), CAPABILITY_EVM1);
+
+    let set_option = v.set_option.unwrap();
+    let (name, value) = (CString::new("rip7212").unwrap(), CString::new("1").unwrap());
+    assert_eq!(
+        set_option(vm, name.as_ptr(), value.as_ptr()),
+        SetOptionResult::Success
+    );
+    let value = CString::new("yes").unwrap();
+    assert_eq!(
+        set_option(vm, name.as_ptr(), value.as_ptr()),
+        SetOptionResult::InvalidValue
+    );
+    let name = CString::new("unknown").unwrap();
+    assert_eq!(
+        set_option(vm, name.as_ptr(), value.as_ptr()),
+        SetOptionResult::InvalidName
+    );
+    (v.destroy)(vm);
+}
+
+#[test]
+fn evmc_execute() {
+    let code = assemble(
+        "NUMBER PUSH1 1 SSTORE
+         PUSH1 1 SLOAD SELFBALANCE ADD
+         PUSH1 0 MSTORE PUSH1 32 PUSH1 0 LOG0
+         PUSH1 32 PUSH1 0 RETURN",
+    )
+    .unwrap();
+    let vm = evmc_create_evm();
+    let v = unsafe { &*vm };
+    let mut c = Client::default();
+    let context = &mut c as *mut Client as *mut HostContext;
+    // Prague
+    let msg = message(100000);
+    let r = (v.execute)(vm, &HOST, context, 13, &msg, code.as_ptr(), code.len());
+    assert_eq!(r.status_code, StatusCode::Success);
+    let output = unsafe { std::slice::from_raw_parts(r.output_data, r.output_size) };
+    assert_eq!(output[31], 42 + 5);
+    assert!(r.gas_left > 0 && r.gas_left < 100000);
+    (r.release.unwrap())(&r);
+
+    let mut one = [0; 32];
+    one[31] = 1;
+    assert_eq!(c.storage.get(&one).unwrap()[31], 42);
+    assert_eq!(c.logs, 1);
+
+    // out of gas, and unknown revision
+    let msg = message(100);
+    let r = (v.execute)(vm, &HOST, context, 13, &msg, code.as_ptr(), code.len());
+    assert_eq!(r.status_code, StatusCode::OutOfGas);
+    assert_eq!(r.gas_left, 0);
+    let r = (v.execute)(vm, &HOST, context, 99, &msg, code.as_ptr(), code.len());
+    assert_eq!(r.status_code, StatusCode::Rejected);
+    (v.destroy)(vm);
+}
+
+#[test]
+fn evmc_malformed_and_static() {
+    let vm = evmc_create_evm();
+    let v = unsafe { &*vm };
+    let mut c = Client::default();
+    let context = &mut c as *mut Client as *mut HostContext;
+    let msg = message(100000);
+    let run = |msg: &EvmcMessage, code: &str| {
+        let code = hex::decode(code).unwrap();
+        (v.execute)(vm, &HOST, context, 13, msg, code.as_ptr(), code.len()).status_code
+    };
+    assert_eq!(run(&msg, "80"), StatusCode::StackUnderflow);
+    assert_eq!(run(&msg, "90"), StatusCode::StackUnderflow);
+    assert_eq!(run(&msg, "60"), StatusCode::Success);
+    assert_eq!(run(&msg, "600a56"), StatusCode::BadJumpDestination);
+    assert_eq!(run(&msg, "6000677fffffffffffffff52"), StatusCode::OutOfGas);
+    assert_eq!(run(&msg, "f0"), StatusCode::Failure);
+
+    // state changes in a static call
+    let mut msg = message(100000);
+    msg.flags = STATIC;
+    assert_eq!(run(&msg, "6001600155"), StatusCode::StaticModeViolation);
+    assert_eq!(run(&msg, "60006000a0"), StatusCode::StaticModeViolation);
+    assert_eq!(run(&msg, "600154"), StatusCode::Success);
+    assert!(c.storage.is_empty());
+    assert_eq!(c.logs, 0);
+    (v.destroy)(vm);
+}
+
+#[test]
+fn evmc_call() {
+    // CALL of 0x42, returning its status and output
+    let code = assemble(
+        "PUSH1 1 PUSH1 63 PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 0x42 PUSH2 0x1000 CALL
+         PUSH1 0 MSTORE PUSH1 64 PUSH1 0 RETURN",
+    )
+    .unwrap();
+    let vm = evmc_create_evm();
+    let v = unsafe { &*vm };
+    let mut c = Client::default();
+    let context = &mut c as *mut Client as *mut HostContext;
+    let mut msg = message(100000);
+    msg.depth = 2;
+    let r = (v.execute)(vm, &HOST, context, 13, &msg, code.as_ptr(), code.len());
+    assert_eq!(r.status_code, StatusCode::Success);
+    let output = unsafe { std::slice::from_raw_parts(r.output_data, r.output_size) };
+    assert_eq!(output[31], 1);
+    assert_eq!(output[63], 0xca);
+    (r.release.unwrap())(&r);
+    let mut recipient = [0; 20];
+    recipient[19] = 0x42;
+    assert_eq!(c.calls, vec![(3, recipient)]);
+    (v.destroy)(vm);
+}
@@ -1,3 +1,4 @@
+#![allow(clippy::useless_format)]
 use evm::*;
 
 #[test]
@@ -192,6 +193,69 @@ fn execute_exceptions() {
     assert_eq!(out, Err(format!("out of gas")));
 }
 
+#[test]
+fn execute_malformed() {
+    let calldata = vec![];
+    let run = |code: &str| {
+        let mut s = Stack::new();
+        let out = s.execute(&hex::decode(code).unwrap(), &calldata, false);
+        (out, s)
+    };
+
+    // truncated push data is padded with zeros
+    let (out, mut s) = run("61ab");
+    assert_eq!(out, Ok(vec![]));
+    assert_eq!(s.pop().unwrap(), u256::str_to_u256("43776")); // 0xab00
+
+    assert_eq!(run("80").0, Err(format!("stack underflow")));
+    assert_eq!(run("600190").0, Err(format!("stack underflow")));
+    assert_eq!(run("600a56").0, Err(format!("not valid dest: 0a")));
+    assert_eq!(run("f0").0, Err(format!("unimplemented f0")));
+
+    // return past the end of the memory expands it
+    let (out, s) = run("6001600af3");
+    assert_eq!(out, Ok(vec![0]));
+    assert_eq!(s.mem.len(), 32);
+
+    // division by zero is zero
+    let (out, mut s) = run("6000600104");
+    assert_eq!(out, Ok(vec![]));
+    assert_eq!(s.pop().unwrap(), [0; 32]);
+}
+
+#[test]
+fn execute_memory_overflow() {
+    let calldata = vec![];
+    for code in [
+        // mstore at 0x7fffffffffffffff, at 2^96-1 and at 2^256-1
+        "6000677fffffffffffffff52",
+        "60006bffffffffffffffffffffffff52",
+        "600060001952",
+        // return and log of 2^64 bytes
+        "680100000000000000006000f3",
+        "680100000000000000006000a0",
+    ]
+    .iter()
+    {
+        let mut s = Stack::new();
+        let out = s.execute(&hex::decode(code).unwrap(), &calldata, false);
+        assert_eq!(out, Err(format!("out of gas")), "{}", code);
+    }
+
+    // a zero sized range is free, whatever its offset
+    let mut s = Stack::new();
+    let out = s.execute(&hex::decode("6000600019a0").unwrap(), &calldata, false);
+    assert_eq!(out, Ok(vec![]));
+    assert!(s.mem.is_empty());
+}
+
+#[test]
+fn catch_panic_error() {
+    assert_eq!(catch_panic(|| Ok(1)), Ok(1));
+    let r: Result<(), String> = catch_panic(|| panic!("boom {}", 1));
+    assert_eq!(r, Err(format!("panic: boom 1")));
+}
+
 #[test]
 fn execute_opcodes_8() {
     let code = hex::decode("611000805151").unwrap();
@@ -215,11 +279,11 @@ fn execute_opcodes_9() {
     let mut s = Stack::new();
     s.execute(&code, &calldata, false).unwrap();
 
-    // assert_eq!(s.gas, 9999977788); // TODO WIP geth reported gas
-    assert_eq!(s.gas, 9999955788);
+    assert_eq!(s.gas, 9999977788); // geth reported gas
     assert_eq!(s.pc, 10);
     assert_eq!(s.stack.len(), 0);
-    assert_eq!(s.storage.len(), 0);
+    assert_eq!(s.state.get(&s.address).unwrap().storage.len(), 1);
+    assert_eq!(s.get_storage(&[0; 32])[31], 1);
 }
 
 #[test]
@@ -236,5 +300,121 @@ fn execute_opcodes_10() {
     assert_eq!(s.gas, 9999977752);
     assert_eq!(s.pc, 25);
     assert_eq!(s.stack.len(), 1);
-    assert_eq!(s.storage.len(), 0);
+    assert_eq!(s.state.get(&s.address).unwrap().storage.len(), 1);
+    assert_eq!(s.get_storage(&[0; 32])[31], 9);
+}
+
+#[test]
+fn execute_context() {
+    // address, caller, callvalue, number, chainid, selfbalance
+    let code = hex::decode("3033344346474a").unwrap();
+
+    let mut s = Stack::new();
+    s.address = [1; 20];
+    s.caller = [2; 20];
+    s.value = u256::u64_to_u256(7);
+    s.block.number = 100;
+    s.state.account(&[1; 20]).balance = 42u64.into();
+    s.execute(&code, &[], false).unwrap();
+
+    assert_eq!(s.pop().unwrap(), u256::u64_to_u256(1)); // blob base fee
+    assert_eq!(s.pop().unwrap(), u256::u64_to_u256(42));
+    assert_eq!(s.pop().unwrap(), u256::u64_to_u256(1));
+    assert_eq!(s.pop().unwrap(), u256::u64_to_u256(100));
+    assert_eq!(s.pop().unwrap(), u256::u64_to_u256(7));
+    assert_eq!(s.pop().unwrap()[12..], [2; 20]);
+    assert_eq!(s.pop().unwrap()[12..], [1; 20]);
+    assert_eq!(s.gas, 9999999983);
+}
+
+#[test]
+fn execute_trace() {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    let code = hex::decode("6005600c0160005200").unwrap();
+    let steps: Rc<RefCell<Vec<trace::Step>>> = Rc::default();
+    let mut s = Stack::new();
+    let t = steps.clone();
+    s.tracer = Some(Box::new(move |step| t.borrow_mut().push(step.clone())));
+    s.execute(&code, &[], false).unwrap();
+
+    let steps = steps.borrow();
+    assert_eq!(steps.len(), 6);
+    assert_eq!(steps[2].op_name, "ADD");
+    assert_eq!(steps[2].stack.len(), 2);
+    assert_eq!(steps[2].gas_cost, 3);
+    // memory expansion
+    assert_eq!(steps[4].gas_cost, 6);
+    assert_eq!(steps[5].mem_size, 32);
+    // the memory is copied only when asked
+    assert!(steps[5].memory.is_empty());
+    assert_eq!(
+        steps[2].to_json().to_string(),
+        r#"{"depth":1,"gas":"0x2540be3fa","gasCost":"0x3","memSize":0,"op":1,"opName":"ADD","pc":4,"refund":0,"stack":["0x5","0xc"]}"#
+    );
+
+    let steps: Rc<RefCell<Vec<trace::Step>>> = Rc::default();
+    let mut s = Stack::new();
+    let t = steps.clone();
+    s.tracer = Some(Box::new(move |step| t.borrow_mut().push(step.clone())));
+    s.trace_memory = true;
+    s.execute(&code, &[], false).unwrap();
+    assert_eq!(steps.borrow()[5].memory[31], 0x11);
+}
+
+#[test]
+fn storage_gas_by_spec() {
+    use spec::{Config, Spec};
+
+    // sets a new slot, clears it, and reads it, with the gas used and the
+    // refund counter
+    let code = hex::decode("6001600055600060005560005400").unwrap();
+    let cases = [
+        (Spec::Frontier, 6 + 20000 + 6 + 5000 + 3 + 50, 15000),
+        (Spec::Petersburg, 6 + 20000 + 6 + 5000 + 3 + 200, 15000),
+        // EIP-1283
+        (Spec::Constantinople, 6 + 20000 + 6 + 200 + 3 + 200, 19800),
+        // EIP-2200
+        (Spec::Istanbul, 6 + 20000 + 6 + 800 + 3 + 800, 19200),
+        // EIP-2929
+        (Spec::Berlin, 6 + 22100 + 6 + 100 + 3 + 100, 19900),
+        (Spec::London, 6 + 22100 + 6 + 100 + 3 + 100, 19900),
+    ];
+    for (spec, gas, refund) in cases.iter() {
+        let mut s = Stack::with_config(Config::new(*spec));
+        s.execute(&code, &[], false).unwrap();
+        assert_eq!(
+            (10000000000 - s.gas, s.refund),
+            (*gas, *refund),
+            "{:?}",
+            spec
+        );
+    }
+
+    // clears a slot set before the transaction
+    let code = hex::decode("6000600055").unwrap();
+    let cases = [
+        (Spec::Frontier, 5000, 15000),
+        (Spec::Istanbul, 5000, 15000),
+        (Spec::Berlin, 2100 + 2900, 15000),
+        (Spec::London, 2100 + 2900, 4800),
+    ];
+    for (spec, gas, refund) in cases.iter() {
+        let mut s = Stack::with_config(Config::new(*spec));
+        s.state.set_storage(&s.address.clone(), &[0; 32], [1; 32]);
+        s.execute(&code, &[], false).unwrap();
+        assert_eq!(
+            (10000000000 - s.gas - 6, s.refund),
+            (*gas, *refund),
+            "{:?}",
+            spec
+        );
+    }
+
+    // the SSTORE sentry from Istanbul only
+    let code = hex::decode("6001600055").unwrap();
+    let mut s = Stack::with_config(Config::new(Spec::Istanbul));
+    s.gas = 6 + 2300;
+    assert_eq!(s.execute(&code, &[], false), Err("out of gas".to_string()));
 }
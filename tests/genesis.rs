@@ -0,0 +1,63 @@
+mod common;
+
+use common::address;
+use evm::genesis::Genesis;
+use evm::spec::Spec;
+use evm::Stack;
+use num_bigint::BigUint;
+use std::path::Path;
+
+const FIXTURE: &str = "tests/fixtures/genesis/genesis.json";
+
+#[test]
+fn load_genesis() {
+    let genesis = Genesis::load(Path::new(FIXTURE)).unwrap();
+    assert_eq!(genesis.chain_id(), 1337);
+    assert_eq!(genesis.timestamp, 0x6553f100);
+    assert_eq!(genesis.gas_limit, 30000000);
+    assert_eq!(genesis.base_fee, Some(BigUint::from(1000000000u64)));
+
+    let contract = genesis.alloc.get(&address(1)).unwrap();
+    assert_eq!(contract.nonce, 1);
+    assert_eq!(contract.balance, BigUint::from(10u64).pow(18));
+    assert_eq!(contract.storage[&[0; 32]][31], 5);
+    assert_eq!(genesis.alloc.balance(&address(2)), BigUint::from(255u64));
+
+    let header = genesis.header(Spec::Prague);
+    assert_eq!(header.state_root, genesis.alloc.root());
+    assert_eq!(header.timestamp, genesis.timestamp);
+    assert_eq!(header.blob_gas_used, Some(0));
+    assert!(header.requests_hash.is_some());
+    assert!(genesis.header(Spec::Berlin).base_fee.is_none());
+
+    // the contract increments the slot 0
+    let mut vm = Stack::new();
+    vm.address = address(1);
+    vm.state = genesis.alloc.clone();
+    vm.block = header.block_env(Spec::Prague, genesis.chain_id());
+    vm.execute(&contract.code, &[], false).unwrap();
+    assert_eq!(vm.state.storage(&address(1), &[0; 32])[31], 6);
+}
+
+#[test]
+fn export_genesis() {
+    let mut genesis = Genesis::load(Path::new(FIXTURE)).unwrap();
+    genesis.alloc.set_storage(&address(2), &[1; 32], [2; 32]);
+    let path = std::env::temp_dir().join("evm-genesis.json");
+    genesis.save(&path).unwrap();
+    let exported = Genesis::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(exported, genesis);
+    assert_eq!(exported.to_json()["config"]["chainId"], 1337);
+    assert_eq!(exported.to_json()["gasLimit"], "0x1c9c380");
+    assert_eq!(
+        exported.header(Spec::Cancun).hash(),
+        genesis.header(Spec::Cancun).hash()
+    );
+
+    // the missing fields take the defaults of geth
+    let genesis = Genesis::from_json(&serde_json::json!({ "alloc": {} })).unwrap();
+    assert_eq!(genesis.gas_limit, 4712388);
+    assert_eq!(genesis.difficulty, BigUint::from(131072u64));
+    assert_eq!(genesis.chain_id(), 1);
+}
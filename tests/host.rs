@@ -0,0 +1,299 @@
+use evm::asm::assemble;
+use evm::block::Log;
+use evm::host::{AccessStatus, CallKind, CallResult, Host, Message, StorageStatus, TxContext};
+use evm::transaction::BlockEnv;
+use evm::Stack;
+use num_bigint::BigUint;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+
+// host keeping the storage and the logs, shared with the test
+#[derive(Default)]
+struct TestHost {
+    storage: HashMap<[u8; 32], [u8; 32]>,
+    warm: HashSet<[u8; 32]>,
+    logs: Vec<Log>,
+    // code of every account
+    code: Vec<u8>,
+    accessed: Vec<[u8; 20]>,
+    destructed: Vec<([u8; 20], [u8; 20])>,
+    calls: Vec<Message>,
+}
+
+struct SharedHost(Rc<RefCell<TestHost>>);
+
+impl Host for SharedHost {
+    fn account_exists(&self, _address: &[u8; 20]) -> bool {
+        true
+    }
+    fn get_storage(&self, _address: &[u8; 20], key: &[u8; 32]) -> [u8; 32] {
+        self.0.borrow().storage.get(key).copied().unwrap_or([0; 32])
+    }
+    fn set_storage(
+        &mut self,
+        address: &[u8; 20],
+        key: &[u8; 32],
+        value: [u8; 32],
+    ) -> StorageStatus {
+        let current = self.get_storage(address, key);
+        self.0.borrow_mut().storage.insert(*key, value);
+        StorageStatus::new(&[0; 32], &current, &value)
+    }
+    fn get_balance(&self, _address: &[u8; 20]) -> BigUint {
+        BigUint::from(1000u64)
+    }
+    fn get_code_size(&self, _address: &[u8; 20]) -> usize {
+        self.0.borrow().code.len()
+    }
+    fn get_code_hash(&self, _address: &[u8; 20]) -> [u8; 32] {
+        evm::keccak256(&self.0.borrow().code)
+    }
+    fn copy_code(&self, _address: &[u8; 20], offset: usize, buffer: &mut [u8]) -> usize {
+        let code = &self.0.borrow().code;
+        let n = buffer.len().min(code.len().saturating_sub(offset));
+        buffer[..n].copy_from_slice(&code[offset..offset + n]);
+        n
+    }
+    fn selfdestruct(&mut self, address: &[u8; 20], beneficiary: &[u8; 20]) -> bool {
+        let mut h = self.0.borrow_mut();
+        h.destructed.push((*address, *beneficiary));
+        h.destructed.len() == 1
+    }
+    // call returns the input reversed, using 100 gas
+    fn call(&mut self, msg: &Message) -> CallResult {
+        self.0.borrow_mut().calls.push(msg.clone());
+        CallResult {
+            output: msg.input.iter().rev().copied().collect(),
+            gas_left: msg.gas - 100,
+            ..CallResult::default()
+        }
+    }
+    fn get_tx_context(&self) -> TxContext {
+        TxContext {
+            origin: [7; 20],
+            gas_price: BigUint::from(9u64),
+            blob_hashes: vec![[0xb1; 32]],
+            block: BlockEnv {
+                number: 42,
+                prev_randao: [0x5a; 32],
+                ..BlockEnv::default()
+            },
+        }
+    }
+    fn get_block_hash(&self, _number: u64) -> [u8; 32] {
+        [0; 32]
+    }
+    fn emit_log(&mut self, address: &[u8; 20], topics: &[[u8; 32]], data: &[u8]) {
+        self.0.borrow_mut().logs.push(Log {
+            address: *address,
+            topics: topics.to_vec(),
+            data: data.to_vec(),
+        });
+    }
+    fn access_account(&mut self, address: &[u8; 20]) -> AccessStatus {
+        let mut h = self.0.borrow_mut();
+        if h.accessed.contains(address) {
+            return AccessStatus::Warm;
+        }
+        h.accessed.push(*address);
+        AccessStatus::Cold
+    }
+    fn access_storage(&mut self, _address: &[u8; 20], key: &[u8; 32]) -> AccessStatus {
+        match self.0.borrow_mut().warm.insert(*key) {
+            true => AccessStatus::Cold,
+            false => AccessStatus::Warm,
+        }
+    }
+}
+
+#[test]
+fn execute_with_host() {
+    let code = assemble(
+        "NUMBER PUSH1 1 SSTORE
+         PUSH1 1 SLOAD ORIGIN SELFBALANCE
+         PUSH1 0 MSTORE PUSH1 0xaa PUSH1 32 PUSH1 0 LOG1
+         STOP",
+    )
+    .unwrap();
+    let test_host = Rc::new(RefCell::new(TestHost::default()));
+    let mut vm = Stack::new();
+    vm.host = Some(Box::new(SharedHost(test_host.clone())));
+    vm.execute(&code, &[], false).unwrap();
+
+    let mut one = [0; 32];
+    one[31] = 1;
+    let mut number = [0; 32];
+    number[31] = 42;
+    let h = test_host.borrow();
+    assert_eq!(h.storage.get(&one), Some(&number));
+    assert_eq!(vm.get_storage(&one), number);
+    // the world state of the stack is not used
+    assert!(vm.state.get(&[0; 20]).is_none());
+    assert!(vm.logs.is_empty());
+
+    assert_eq!(h.logs.len(), 1);
+    assert_eq!(h.logs[0].topics[0][31], 0xaa);
+    assert_eq!(&h.logs[0].data[30..], &1000u16.to_be_bytes());
+    assert_eq!(vm.stack.len(), 2);
+    assert_eq!(&vm.stack[1][12..], &[7; 20]);
+    assert_eq!(vm.stack[0], number);
+    // cold SSTORE of a new value, and warm SLOAD
+    assert_eq!(
+        10000000000 - vm.gas,
+        2 + 3 + 22100 + 3 + 100 + 2 + 5 + 3 + 6 + 3 + 3 + 3 + 750 + 256
+    );
+}
+
+#[test]
+fn account_opcodes_with_host() {
+    let code = assemble(
+        "PUSH1 5 BALANCE PUSH1 5 EXTCODESIZE PUSH1 6 EXTCODEHASH
+         PUSH1 4 PUSH1 1 PUSH1 0 PUSH1 6 EXTCODECOPY
+         PUSH1 9 SELFDESTRUCT",
+    )
+    .unwrap();
+    let test_host = Rc::new(RefCell::new(TestHost {
+        code: vec![0xaa, 0xbb, 0xcc],
+        ..TestHost::default()
+    }));
+    let mut vm = Stack::new();
+    vm.address = [1; 20];
+    vm.host = Some(Box::new(SharedHost(test_host.clone())));
+    assert_eq!(vm.execute(&code, &[], false), Ok(vec![]));
+
+    let h = test_host.borrow();
+    assert_eq!(vm.stack.len(), 3);
+    assert_eq!(&vm.stack[0][30..], &1000u16.to_be_bytes());
+    assert_eq!(vm.stack[1][31], 3);
+    assert_eq!(vm.stack[2], evm::keccak256(&[0xaa, 0xbb, 0xcc]));
+    assert_eq!(&vm.mem[..4], &[0xbb, 0xcc, 0, 0]);
+    let mut beneficiary = [0; 20];
+    beneficiary[19] = 9;
+    assert_eq!(h.destructed, vec![([1; 20], beneficiary)]);
+    // the account of 0x05 is cold once, then warm
+    assert_eq!(h.accessed.len(), 3);
+    assert_eq!(
+        10000000000 - vm.gas,
+        3 + 2600 + 3 + 100 + 3 + 2600 + 4 * 3 + 100 + 3 + 3 + 3 + 5000 + 2600
+    );
+
+    // not in a static call
+    let mut vm = Stack::new();
+    vm.is_static = true;
+    vm.host = Some(Box::new(SharedHost(test_host.clone())));
+    let code = assemble("PUSH1 9 SELFDESTRUCT").unwrap();
+    assert_eq!(
+        vm.execute(&code, &[], false),
+        Err("static mode violation".to_string())
+    );
+}
+
+#[test]
+fn sstore_out_of_gas_with_host() {
+    let test_host = Rc::new(RefCell::new(TestHost::default()));
+    let mut vm = Stack::new();
+    vm.host = Some(Box::new(SharedHost(test_host.clone())));
+    // enough for the sentry, not for the write
+    vm.gas = 2400 + 6;
+    let code = assemble("PUSH1 1 PUSH1 1 SSTORE").unwrap();
+    assert_eq!(vm.execute(&code, &[], false), Err("out of gas".to_string()));
+    let mut one = [0; 32];
+    one[31] = 1;
+    assert_eq!(test_host.borrow().storage.get(&one), Some(&[0; 32]));
+}
+
+#[test]
+fn storage_status() {
+    let w = |n: u8| {
+        let mut w = [0; 32];
+        w[31] = n;
+        w
+    };
+    let cases = [
+        ((0, 0, 0), StorageStatus::Assigned),
+        ((1, 2, 3), StorageStatus::Assigned),
+        ((0, 1, 2), StorageStatus::Assigned),
+        ((0, 0, 1), StorageStatus::Added),
+        ((1, 1, 0), StorageStatus::Deleted),
+        ((1, 1, 2), StorageStatus::Modified),
+        ((1, 0, 2), StorageStatus::DeletedAdded),
+        ((1, 2, 0), StorageStatus::ModifiedDeleted),
+        ((1, 0, 1), StorageStatus::DeletedRestored),
+        ((0, 1, 0), StorageStatus::AddedDeleted),
+        ((1, 2, 1), StorageStatus::ModifiedRestored),
+    ];
+    for ((original, current, new), status) in cases.iter() {
+        assert_eq!(
+            StorageStatus::new(&w(*original), &w(*current), &w(*new)),
+            *status,
+            "{} {} {}",
+            original,
+            current,
+            new
+        );
+    }
+}
+
+#[test]
+fn call_with_host() {
+    // CALL of 0x42 with a value of 5, then STATICCALL of 0x43
+    let code = assemble(
+        "PUSH2 0x0102 PUSH1 0 MSTORE
+         PUSH1 2 PUSH1 32 PUSH1 2 PUSH1 30 PUSH1 5 PUSH1 0x42 PUSH2 0x1000 CALL
+         PUSH1 2 PUSH1 64 PUSH1 2 PUSH1 30 PUSH1 0x43 PUSH2 0x1000 STATICCALL",
+    )
+    .unwrap();
+    let test_host = Rc::new(RefCell::new(TestHost::default()));
+    let mut vm = Stack::new();
+    vm.address = [1; 20];
+    vm.depth = 3;
+    vm.host = Some(Box::new(SharedHost(test_host.clone())));
+    vm.execute(&code, &[], false).unwrap();
+
+    let h = test_host.borrow();
+    assert_eq!(h.calls.len(), 2);
+    let msg = &h.calls[0];
+    assert_eq!(msg.kind, CallKind::Call);
+    assert!(!msg.is_static);
+    assert_eq!(msg.depth, 4);
+    assert_eq!(msg.recipient[19], 0x42);
+    assert_eq!(msg.sender, [1; 20]);
+    assert_eq!(msg.value[31], 5);
+    assert_eq!(msg.input, vec![1, 2]);
+    // the stipend is added to the gas passed
+    assert_eq!(msg.gas, 0x1000 + 2300);
+    let msg = &h.calls[1];
+    assert!(msg.is_static);
+    assert_eq!(msg.recipient[19], 0x43);
+    assert_eq!(msg.value, [0; 32]);
+    assert_eq!(msg.gas, 0x1000);
+    let mut one = [0; 32];
+    one[31] = 1;
+    assert_eq!(vm.stack, vec![one, one]);
+    assert_eq!(&vm.mem[32..34], &[2, 1]);
+    assert_eq!(&vm.mem[64..66], &[2, 1]);
+    assert_eq!(vm.return_data, vec![2, 1]);
+}
+
+#[test]
+fn tx_context_with_host() {
+    let code = assemble("GASPRICE PREVRANDAO PUSH1 0 BLOBHASH PUSH1 1 BLOBHASH").unwrap();
+    let test_host = Rc::new(RefCell::new(TestHost::default()));
+    let mut vm = Stack::new();
+    vm.host = Some(Box::new(SharedHost(test_host.clone())));
+    vm.execute(&code, &[], false).unwrap();
+    let mut gas_price = [0; 32];
+    gas_price[31] = 9;
+    assert_eq!(vm.stack, vec![gas_price, [0x5a; 32], [0xb1; 32], [0; 32]]);
+    assert_eq!(10000000000 - vm.gas, 2 + 2 + 3 + 3 + 3 + 3);
+
+    // BLOBHASH from Cancun
+    let mut vm = Stack::with_config(evm::spec::Config::new(evm::spec::Spec::Shanghai));
+    vm.host = Some(Box::new(SharedHost(test_host)));
+    let code = assemble("PUSH1 0 BLOBHASH").unwrap();
+    assert_eq!(
+        vm.execute(&code, &[], false),
+        Err("invalid opcode 49".to_string())
+    );
+}
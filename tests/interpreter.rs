@@ -0,0 +1,86 @@
+mod common;
+
+use common::temp_path;
+use evm::asm::assemble;
+use evm::interpreter::Interpreter;
+use evm::Stack;
+use num_bigint::BigUint;
+
+fn new_interpreter() -> Interpreter {
+    let code = assemble(
+        "PUSH1 3
+         loop: JUMPDEST
+         DUP1 DUP1 SSTORE
+         DUP1 DUP1 PUSH1 0x20 MUL MSTORE
+         PUSH1 1 SWAP1 SUB DUP1 PUSH @loop JUMPI
+         PUSH1 0x20 PUSH1 0x60 LOG0
+         PUSH1 0x20 PUSH1 0x40 RETURN",
+    )
+    .unwrap();
+    let mut vm = Stack::new();
+    vm.address = [1; 20];
+    vm.state
+        .add_balance(&[1; 20], &BigUint::from(10u64).pow(30));
+    Interpreter::new(vm, &code, &[])
+}
+
+#[test]
+fn interpreter_resume() {
+    let mut i = new_interpreter();
+    let output = i.run().unwrap();
+    assert_eq!(output[31], 2);
+    assert!(i.step().is_err());
+
+    // stopped in the middle of the loop, saved and resumed
+    let mut j = new_interpreter();
+    assert_eq!(j.run_until(|j| j.steps == 20), Ok(false));
+    assert_eq!(j.vm.mem.len(), 128);
+    let path = temp_path("evm-interpreter-resume.json");
+    j.save(&path).unwrap();
+    let mut k = Interpreter::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!((k.steps, k.vm.pc, &k.vm.stack), (20, j.vm.pc, &j.vm.stack));
+    assert_eq!(k.vm.state, j.vm.state);
+    assert_eq!(k.vm.storage_committed, j.vm.storage_committed);
+
+    assert_eq!(k.run().unwrap(), output);
+    assert_eq!(k.steps, i.steps);
+    assert_eq!((k.vm.gas, k.vm.refund), (i.vm.gas, i.vm.refund));
+    assert_eq!(k.vm.state, i.vm.state);
+    assert_eq!(k.vm.accessed_storage, i.vm.accessed_storage);
+    assert_eq!(k.vm.logs, i.vm.logs);
+
+    assert!(Interpreter::from_json("{}").is_err());
+}
+
+#[test]
+fn interpreter_resume_revert() {
+    // fails after warming and writing a slot, and emitting a log
+    let code = assemble(
+        "PUSH1 1 PUSH1 1 SSTORE
+         PUSH1 0 PUSH1 0 LOG0
+         PUSH1 1 SLOAD
+         JUMP",
+    )
+    .unwrap();
+    let mut vm = Stack::new();
+    vm.address = [1; 20];
+    vm.state.add_balance(&[1; 20], &BigUint::from(5u64));
+    let state = vm.state.clone();
+    let mut i = Interpreter::new(vm, &code, &[]);
+    assert_eq!(i.run_until(|i| i.steps == 8), Ok(false));
+    assert_eq!(i.vm.logs.len(), 1);
+    assert_eq!(i.vm.journal.len(), 3);
+
+    // the writes made before the checkpoint are undone by the resumed one
+    let path = temp_path("evm-interpreter-revert.json");
+    i.save(&path).unwrap();
+    let mut j = Interpreter::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert!(j.run().is_err());
+    assert_eq!(j.vm.state, state);
+    assert!(j.vm.logs.is_empty());
+    assert!(j.vm.storage_committed.is_empty());
+    assert!(j.vm.accessed_storage.is_empty());
+    assert!(j.vm.journal.is_empty());
+}
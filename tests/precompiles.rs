@@ -0,0 +1,281 @@
+use evm::precompiles::bls12_381;
+use evm::*;
+
+const G1_X: &str = "17f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb";
+const G1_Y: &str = "08b3f481e3aaa0f1a09e30ed741d8ae4fcf5e095d5d00af600db18cb2c04b3edd03cc744a2888ae40caa232946c5e7e1";
+// -y mod p
+const G1_NEG_Y: &str = "114d1d6855d545a8aa7d76c8cf2e21f267816aef1db507c96655b9d5caac42364e6f38ba0ecb751bad54dcd6b939c2ca";
+const G2_X0: &str = "024aa2b2f08f0a91260805272dc51051c6e47ad4fa403b02b4510b647ae3d1770bac0326a805bbefd48056c8c121bdb8";
+const G2_X1: &str = "13e02b6052719f607dacd3a088274f65596bd0d09920b61ab5da61bbdc7f5049334cf11213945d57e5ac7d055d042b7e";
+const G2_Y0: &str = "0ce5d527727d6e118cc9cdc6da2e351aadfd9baa8cbdd3a76d429a695160d12c923ac9cc3baca289e193548608b82801";
+const G2_Y1: &str = "0606c4a02ea734cc32acd2b02bc28b99cb3e287e85a763af267492ab572e99ab3f370d275cec1da1aaa9075ff05f79be";
+
+fn fp(s: &str) -> Vec<u8> {
+    let mut r = vec![0; 16];
+    r.append(&mut hex::decode(s).unwrap());
+    r
+}
+fn g1() -> Vec<u8> {
+    [fp(G1_X), fp(G1_Y)].concat()
+}
+fn g1_neg() -> Vec<u8> {
+    [fp(G1_X), fp(G1_NEG_Y)].concat()
+}
+fn g2() -> Vec<u8> {
+    [fp(G2_X0), fp(G2_X1), fp(G2_Y0), fp(G2_Y1)].concat()
+}
+fn scalar(n: usize) -> Vec<u8> {
+    u256::usize_to_u256(n).to_vec()
+}
+
+#[test]
+fn bls12_g1_add() {
+    let mut s = Stack::new();
+    let addr = precompiles::address(0x0b);
+
+    // G + 0 = G
+    let input = [g1(), vec![0; 128]].concat();
+    let out = s.call_precompile(&addr, &input).unwrap();
+    assert_eq!(out, g1());
+    assert_eq!(s.gas, 10000000000 - 375);
+
+    // G + (-G) = 0
+    let input = [g1(), g1_neg()].concat();
+    let out = s.call_precompile(&addr, &input).unwrap();
+    assert_eq!(out, vec![0; 128]);
+
+    // G + G = 2*G
+    let input = [g1(), g1()].concat();
+    let doubled = s.call_precompile(&addr, &input).unwrap();
+    let input = [g1(), scalar(2)].concat();
+    let out = s
+        .call_precompile(&precompiles::address(0x0c), &input)
+        .unwrap();
+    assert_eq!(out, doubled);
+}
+
+#[test]
+fn bls12_g1_add_staticcall() {
+    // copies G and 0 to memory, and G1ADDs them with STATICCALL, returning
+    // the result and pushing the status and the size of the return data
+    let prefix = asm::assemble(
+        "PUSH2 0x100 PUSH1 31 PUSH1 0 CODECOPY
+         PUSH1 0x80 PUSH2 0x100 PUSH2 0x100 PUSH1 0 PUSH1 0x0b PUSH2 0xffff STATICCALL
+         RETURNDATASIZE
+         PUSH1 0x80 PUSH2 0x100 RETURN",
+    )
+    .unwrap();
+    assert_eq!(prefix.len(), 31);
+    let code = [prefix, g1(), vec![0; 128]].concat();
+    let mut s = Stack::new();
+    let out = s.execute(&code, &[], false).unwrap();
+    assert_eq!(out, g1());
+    assert_eq!(s.stack.len(), 2);
+    assert_eq!(s.stack[0], u256::usize_to_u256(1));
+    assert_eq!(s.stack[1], u256::usize_to_u256(128));
+
+    // a failing precompile consumes the gas passed to it, and pushes 0
+    let code = asm::assemble("PUSH1 0 PUSH1 0 PUSH1 1 PUSH1 0 PUSH1 0x0b PUSH2 0x1000 STATICCALL")
+        .unwrap();
+    let mut s = Stack::new();
+    s.execute(&code, &[], false).unwrap();
+    assert_eq!(s.stack, vec![[0; 32]]);
+    assert_eq!(s.gas, 10000000000 - 6 * 3 - 3 - 2600 - 0x1000);
+
+    // calls to accounts that are not precompiles go to the host, and fail
+    // with the local one
+    let code =
+        asm::assemble("PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 0 PUSH1 0x42 PUSH1 0 STATICCALL").unwrap();
+    let mut s = Stack::new();
+    assert_eq!(s.execute(&code, &[], false), Ok(vec![]));
+    assert_eq!(s.stack, vec![[0; 32]]);
+}
+
+#[test]
+fn bls12_g2_add_and_msm() {
+    let mut s = Stack::new();
+
+    let input = [g2(), g2()].concat();
+    let doubled = s
+        .call_precompile(&precompiles::address(0x0d), &input)
+        .unwrap();
+    assert_eq!(s.gas, 10000000000 - 600);
+
+    // 3*G + 0*G
+    let input = [g2(), scalar(3), g2(), scalar(0)].concat();
+    let tripled = s
+        .call_precompile(&precompiles::address(0x0e), &input)
+        .unwrap();
+    assert_eq!(s.gas, 10000000000 - 600 - 2 * 22500);
+    assert_ne!(tripled, doubled);
+
+    let input = [g2(), scalar(2), vec![0; 256], scalar(5)].concat();
+    let out = s
+        .call_precompile(&precompiles::address(0x0e), &input)
+        .unwrap();
+    assert_eq!(out, doubled);
+}
+
+#[test]
+fn bls12_msm_gas() {
+    assert_eq!(
+        bls12_381::msm_gas(1, 12000, &bls12_381::G1_MSM_DISCOUNTS),
+        12000
+    );
+    assert_eq!(
+        bls12_381::msm_gas(2, 12000, &bls12_381::G1_MSM_DISCOUNTS),
+        22776
+    );
+    assert_eq!(
+        bls12_381::msm_gas(128, 12000, &bls12_381::G1_MSM_DISCOUNTS),
+        797184
+    );
+    // the discount is capped after 128 pairs
+    assert_eq!(
+        bls12_381::msm_gas(200, 12000, &bls12_381::G1_MSM_DISCOUNTS),
+        1245600
+    );
+    assert_eq!(
+        bls12_381::msm_gas(2, 22500, &bls12_381::G2_MSM_DISCOUNTS),
+        45000
+    );
+    assert_eq!(
+        bls12_381::msm_gas(200, 22500, &bls12_381::G2_MSM_DISCOUNTS),
+        2358000
+    );
+}
+
+#[test]
+fn bls12_pairing_check() {
+    let mut s = Stack::new();
+    let addr = precompiles::address(0x0f);
+
+    // e(G1, G2) != 1
+    let out = s.call_precompile(&addr, &[g1(), g2()].concat()).unwrap();
+    assert_eq!(out, scalar(0));
+    assert_eq!(s.gas, 10000000000 - 70300);
+
+    // e(G1, G2) * e(-G1, G2) == 1
+    let input = [g1(), g2(), g1_neg(), g2()].concat();
+    let out = s.call_precompile(&addr, &input).unwrap();
+    assert_eq!(out, scalar(1));
+
+    // pairs with the point at infinity are skipped
+    let input = [vec![0; 128], g2()].concat();
+    let out = s.call_precompile(&addr, &input).unwrap();
+    assert_eq!(out, scalar(1));
+}
+
+#[test]
+fn bls12_map_to_curve() {
+    let mut s = Stack::new();
+
+    let out = s
+        .call_precompile(&precompiles::address(0x10), &fp(G1_X))
+        .unwrap();
+    assert_eq!(out.len(), 128);
+    // the output is in the subgroup, so it is accepted by the MSM
+    let input = [out, scalar(1)].concat();
+    s.call_precompile(&precompiles::address(0x0c), &input)
+        .unwrap();
+
+    let out = s
+        .call_precompile(
+            &precompiles::address(0x11),
+            &[fp(G2_X0), fp(G2_X1)].concat(),
+        )
+        .unwrap();
+    assert_eq!(out.len(), 256);
+    let input = [out, scalar(1)].concat();
+    s.call_precompile(&precompiles::address(0x0e), &input)
+        .unwrap();
+}
+
+#[test]
+fn bls12_exceptions() {
+    let mut s = Stack::new();
+    let addr = precompiles::address(0x0b);
+
+    let out = s.call_precompile(&addr, &g1());
+    assert_eq!(out, Err("G1ADD invalid input length 128".to_string()));
+    assert_eq!(s.gas, 0);
+
+    s.gas = 10000;
+    let mut p = g1();
+    p[0] = 1;
+    let out = s.call_precompile(&addr, &[p, g1()].concat());
+    assert_eq!(out, Err("invalid field element padding".to_string()));
+
+    s.gas = 10000;
+    let mut p = g1();
+    p[127] ^= 1;
+    let out = s.call_precompile(&addr, &[p, g1()].concat());
+    assert_eq!(out, Err("G1 point not on curve".to_string()));
+
+    s.gas = 100;
+    let out = s.call_precompile(&addr, &[g1(), g1()].concat());
+    assert_eq!(out, Err("out of gas".to_string()));
+}
+
+fn p256_input() -> Vec<u8> {
+    use p256::ecdsa::signature::hazmat::PrehashSigner;
+    use p256::ecdsa::{Signature, SigningKey};
+
+    let key = SigningKey::from_slice(&[7; 32]).unwrap();
+    let hash = [0xab; 32];
+    let sig: Signature = key.sign_prehash(&hash).unwrap();
+    let point = key.verifying_key().to_encoded_point(false);
+    [
+        hash.to_vec(),
+        sig.r().to_bytes().to_vec(),
+        sig.s().to_bytes().to_vec(),
+        point.x().unwrap().to_vec(),
+        point.y().unwrap().to_vec(),
+    ]
+    .concat()
+}
+
+#[test]
+fn p256_verify() {
+    let addr = precompiles::address(0x100);
+    let input = p256_input();
+
+    // not available before Osaka unless RIP-7212 is enabled
+    let mut s = Stack::new();
+    assert!(s.call_precompile(&addr, &input).is_err());
+
+    let mut config = spec::Config::new(spec::Spec::Cancun);
+    config.rip7212 = true;
+    let mut s = Stack::with_config(config);
+    let out = s.call_precompile(&addr, &input).unwrap();
+    assert_eq!(out, scalar(1));
+    assert_eq!(s.gas, 10000000000 - 3450);
+
+    let mut s = Stack::with_config(spec::Config::new(spec::Spec::Osaka));
+    let out = s.call_precompile(&addr, &input).unwrap();
+    assert_eq!(out, scalar(1));
+    assert_eq!(s.gas, 10000000000 - 6900);
+
+    // an invalid signature returns empty data, consuming the gas
+    let mut bad = input.clone();
+    bad[0] ^= 1;
+    let out = s.call_precompile(&addr, &bad).unwrap();
+    assert_eq!(out, Vec::<u8>::new());
+    assert_eq!(s.gas, 10000000000 - 2 * 6900);
+
+    // r = 0
+    let mut bad = input.clone();
+    bad[32..64].copy_from_slice(&[0; 32]);
+    assert_eq!(s.call_precompile(&addr, &bad).unwrap(), Vec::<u8>::new());
+
+    // public key not on the curve
+    let mut bad = input.clone();
+    bad[159] ^= 1;
+    assert_eq!(s.call_precompile(&addr, &bad).unwrap(), Vec::<u8>::new());
+
+    // wrong input length
+    assert_eq!(
+        s.call_precompile(&addr, &input[..159]).unwrap(),
+        Vec::<u8>::new()
+    );
+}
@@ -0,0 +1,129 @@
+use evm::proof::{prove, verify_account_proof, verify_storage_proof};
+use evm::state::State;
+use evm::trie::{verify_proof, Trie};
+use num_bigint::BigUint;
+
+fn slot(n: u8) -> [u8; 32] {
+    let mut k: [u8; 32] = [0; 32];
+    k[31] = n;
+    k
+}
+
+fn test_state() -> State {
+    let mut state = State::new();
+    for i in 1..=40u8 {
+        let address = [i; 20];
+        let account = state.account(&address);
+        account.nonce = i as u64;
+        account.balance = BigUint::from(i as u64 * 1000);
+        if i % 4 == 0 {
+            account.code = vec![0x60, i, 0x00];
+            for j in 0..i {
+                account.storage.insert(slot(j), slot(j + 1));
+            }
+        }
+    }
+    state
+}
+
+#[test]
+fn trie_proof() {
+    let mut trie = Trie::new();
+    trie.insert(b"doe", b"reindeer");
+    trie.insert(b"dog", b"puppy");
+    trie.insert(b"dogglesworth", b"cat");
+    let root = trie.root_hash();
+
+    for (k, v) in [
+        ("doe", "reindeer"),
+        ("dog", "puppy"),
+        ("dogglesworth", "cat"),
+    ] {
+        let proof = trie.prove(k.as_bytes());
+        let value = verify_proof(&root, k.as_bytes(), &proof).unwrap();
+        assert_eq!(value, Some(v.as_bytes().to_vec()));
+    }
+    for k in ["do", "dogs", "cat", ""] {
+        let proof = trie.prove(k.as_bytes());
+        assert_eq!(verify_proof(&root, k.as_bytes(), &proof).unwrap(), None);
+    }
+
+    // a trie whose root encoding is shorter than 32 bytes
+    let mut trie = Trie::new();
+    trie.insert(b"a", b"b");
+    let proof = trie.prove(b"a");
+    assert_eq!(proof.len(), 1);
+    let value = verify_proof(&trie.root_hash(), b"a", &proof).unwrap();
+    assert_eq!(value, Some(b"b".to_vec()));
+
+    // empty trie
+    let trie = Trie::new();
+    let proof = trie.prove(b"a");
+    assert_eq!(verify_proof(&trie.root_hash(), b"a", &proof).unwrap(), None);
+}
+
+#[test]
+fn account_proof() {
+    let state = test_state();
+    let root = state.root();
+
+    for i in 1..=40u8 {
+        let keys: Vec<[u8; 32]> = (0..6).map(slot).collect();
+        let proof = prove(&state, &[i; 20], &keys);
+        assert_eq!(proof.nonce, i as u64);
+        verify_account_proof(&root, &proof).unwrap();
+        for p in proof.storage_proof.iter() {
+            verify_storage_proof(&proof.storage_hash, p).unwrap();
+        }
+        if i % 4 == 0 {
+            assert_eq!(proof.storage_proof[1].value, slot(2));
+        }
+    }
+
+    // absent account and slots
+    let proof = prove(&state, &[0xaa; 20], &[slot(1)]);
+    assert_eq!(proof.balance, BigUint::default());
+    assert_eq!(proof.storage_proof[0].value, [0; 32]);
+    verify_account_proof(&root, &proof).unwrap();
+}
+
+#[test]
+fn account_proof_invalid() {
+    let state = test_state();
+    let root = state.root();
+    let proof = prove(&state, &[8; 20], &[slot(3)]);
+
+    let mut p = proof.clone();
+    p.balance += 1u64;
+    assert_eq!(
+        verify_account_proof(&root, &p),
+        Err("proof: account mismatch".to_string())
+    );
+
+    let mut p = proof.clone();
+    p.storage_proof[0].value = slot(9);
+    assert_eq!(
+        verify_account_proof(&root, &p),
+        Err("proof: storage value mismatch".to_string())
+    );
+
+    let mut p = proof.clone();
+    let last = p.account_proof.len() - 1;
+    p.account_proof[last][5] ^= 1;
+    assert_eq!(
+        verify_account_proof(&root, &p),
+        Err("proof: node hash mismatch".to_string())
+    );
+
+    let mut p = proof.clone();
+    p.account_proof.pop();
+    assert_eq!(
+        verify_account_proof(&root, &p),
+        Err("proof: missing node".to_string())
+    );
+
+    // a proof of another state
+    let mut other = state.clone();
+    other.add_balance(&[1; 20], &BigUint::from(1u64));
+    assert!(verify_account_proof(&other.root(), &proof).is_err());
+}
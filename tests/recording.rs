@@ -0,0 +1,193 @@
+use evm::asm::assemble;
+use evm::db::{Database, DbHost, MemoryDb};
+use evm::debugger::Debugger;
+use evm::recording::{self, Snapshot, CHECKPOINT_INTERVAL};
+use evm::Stack;
+use num_bigint::BigUint;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+const CODE: &str = "PUSH1 3
+    loop: JUMPDEST
+    DUP1 DUP1 SSTORE
+    DUP1 DUP1 PUSH1 0x20 MUL MSTORE
+    PUSH1 1 SWAP1 SUB DUP1 PUSH @loop JUMPI
+    PUSH1 0 SLOAD PUSH1 1 SSTORE STOP";
+
+#[test]
+fn recording_snapshots() {
+    let code = assemble(CODE).unwrap();
+    let mut vm = Stack::new();
+    let r = recording::record(&mut vm, &code, &[]);
+    assert_eq!(r.end(), 51);
+    assert_eq!(r.deltas.last().unwrap().result, Some(Ok(Vec::new())));
+
+    // each snapshot is the state of a machine executing up to that step
+    let mut live = Stack::new();
+    for step in 0..=r.end() {
+        let s = r.snapshot(step).unwrap();
+        assert_eq!(s, Snapshot::of(&live), "step {}", step);
+        if step < r.end() {
+            live.step(&code, &[]).unwrap();
+        }
+    }
+    let end = r.snapshot(51).unwrap();
+    assert_eq!(end.mem.len(), 128);
+    assert_eq!(end.state.get(&[0; 20]).unwrap().storage.len(), 2);
+    assert!(r.snapshot(52).is_err());
+
+    // the deltas hold the changes only
+    let sstore = &r.deltas[4];
+    assert_eq!(sstore.stack_keep, 1);
+    assert!(sstore.stack.1.is_empty());
+    assert_eq!(sstore.storage.len(), 1);
+    let mstore = &r.deltas[9];
+    assert_eq!((mstore.mem_offset, mstore.mem.1.clone()), (127, vec![3]));
+
+    // undoing every delta goes back to the start
+    for d in r.deltas.iter().rev() {
+        d.undo(&mut vm);
+    }
+    assert_eq!(Snapshot::of(&vm), r.start);
+    assert!(vm.state.get(&[0; 20]).is_none());
+    assert!(vm.accessed_storage.is_empty());
+    assert!(vm.storage_committed.is_empty());
+    assert!(vm.journal.is_empty());
+}
+
+#[test]
+fn recording_checkpoints() {
+    // the loop of CODE, 15 steps each, run 300 times
+    let code = assemble(&CODE.replacen("PUSH1 3", "PUSH2 300", 1)).unwrap();
+    let mut vm = Stack::new();
+    vm.state.add_balance(&[0xee; 20], &BigUint::from(1u64));
+    let start = vm.state.clone();
+    let r = recording::record(&mut vm, &code, &[]);
+    assert_eq!(r.end(), 4506);
+    assert_eq!(r.checkpoints.len(), r.end() / CHECKPOINT_INTERVAL);
+    // the checkpoints hold the written account alone
+    for c in r.checkpoints.iter() {
+        assert_eq!(c.state.accounts.len(), 1);
+        assert!(c.state.get(&[0; 20]).is_some());
+    }
+
+    let mut live = Stack::new();
+    live.state = start;
+    for step in 0..=r.end() {
+        if step % 1000 == 0 || step % CHECKPOINT_INTERVAL < 2 || step == r.end() {
+            assert_eq!(
+                r.snapshot(step).unwrap(),
+                Snapshot::of(&live),
+                "step {}",
+                step
+            );
+        }
+        if step < r.end() {
+            live.step(&code, &[]).unwrap();
+        }
+    }
+    // the deltas hold the items and bytes changed, whatever the size of the
+    // stack and the memory
+    let mstore = r.deltas.iter().rev().find(|d| !d.mem.1.is_empty()).unwrap();
+    assert_eq!(mstore.stack.0.len(), 2);
+    assert_eq!(mstore.mem_offset, 63);
+    assert_eq!(mstore.mem, (vec![0], vec![1]));
+}
+
+#[test]
+fn recording_accounts() {
+    let code = assemble("PUSH1 9 BALANCE PUSH1 9 SELFDESTRUCT").unwrap();
+    let mut vm = Stack::new();
+    vm.address = [1; 20];
+    vm.state.add_balance(&[1; 20], &BigUint::from(5u64));
+    let start = vm.state.clone();
+    let r = recording::record(&mut vm, &code, &[]);
+    let mut beneficiary = [0; 20];
+    beneficiary[19] = 9;
+    assert_eq!(vm.state.balance(&beneficiary), BigUint::from(5u64));
+    let end = vm.state.clone();
+
+    // the balances, the created account and the warm account are undone
+    for d in r.deltas.iter().rev() {
+        d.undo(&mut vm);
+    }
+    assert_eq!(vm.state, start);
+    assert!(vm.accessed_accounts.is_empty());
+    assert!(vm.journal.is_empty());
+    for d in r.deltas.iter() {
+        d.redo(&mut vm);
+    }
+    assert_eq!(vm.state, end);
+    assert!(vm.accessed_accounts.contains(&beneficiary));
+    assert_eq!(r.snapshot(r.end()).unwrap(), Snapshot::of(&vm));
+}
+
+#[test]
+fn recording_with_host() {
+    let code = assemble(CODE).unwrap();
+    let db = Rc::new(RefCell::new(MemoryDb::new()));
+    let mut vm = Stack::new();
+    vm.host = Some(Box::new(DbHost::new(db.clone())));
+    let r = recording::record(&mut vm, &code, &[]);
+    let mut slot = [0; 32];
+    slot[31] = 3;
+    assert_eq!(db.storage(&[0; 20], &slot).unwrap(), slot);
+    assert!(vm.state.get(&[0; 20]).is_none());
+
+    // the slots are undone and redone in the database of the host
+    for d in r.deltas.iter().rev() {
+        d.undo(&mut vm);
+    }
+    assert_eq!(db.storage(&[0; 20], &slot).unwrap(), [0; 32]);
+    for d in r.deltas[..5].iter() {
+        d.redo(&mut vm);
+    }
+    assert_eq!(db.storage(&[0; 20], &slot).unwrap(), slot);
+    assert_eq!(r.snapshot(5).unwrap().state.storage(&[0; 20], &slot), slot);
+}
+
+#[test]
+fn debugger_time_travel() {
+    let code = assemble(CODE).unwrap();
+    let mut d = Debugger::new(&code, &[]);
+    d.command("c").unwrap();
+    assert!(d.halted());
+    let gas = d.vm.gas;
+    let mem = d.vm.mem.clone();
+
+    d.goto(5).unwrap();
+    let mut three = [0; 32];
+    three[31] = 3;
+    assert_eq!(d.vm.stack, vec![three]);
+    assert_eq!(d.vm.get_storage(&three), three);
+    assert!(d.vm.mem.is_empty());
+    assert_eq!(d.recording.end(), 51);
+
+    // forward again from the recording, to the same end
+    d.command("goto 51").unwrap();
+    assert!(d.halted());
+    assert_eq!((d.vm.gas, &d.vm.mem), (gas, &mem));
+    assert!(d.goto(55).is_err());
+
+    // an edit starts a new recording
+    d.command("back 2").unwrap();
+    d.command("push 0").unwrap();
+    assert_eq!(d.recording.first, 49);
+    assert_eq!(
+        d.command("back"),
+        Err("at the start of the recording, the state was edited".to_string())
+    );
+}
+
+#[test]
+fn recording_past_the_end() {
+    // the PUSH2 moves pc past the end of the code, where the step is a STOP
+    let code = hex::decode("61").unwrap();
+    let mut vm = Stack::new();
+    let mut r = recording::Recording::new(&vm, 0);
+    assert_eq!(r.step(&mut vm, &code, &[]), Ok(None));
+    assert_eq!(vm.pc, 3);
+    assert_eq!(r.step(&mut vm, &code, &[]), Ok(Some(vec![])));
+    assert_eq!(r.deltas[1].result, Some(Ok(vec![])));
+    assert_eq!(r.deltas[1].pc, (3, 3));
+}
@@ -0,0 +1,129 @@
+use evm::rlp;
+use evm::rlp::*;
+use num_bigint::BigUint;
+
+fn h(s: &str) -> Vec<u8> {
+    hex::decode(s).unwrap()
+}
+
+#[test]
+fn rlp_encode() {
+    // examples from the ethereum.org RLP documentation
+    assert_eq!(to_bytes("dog"), h("83646f67"));
+    assert_eq!(
+        rlp::encode(&encode_list(&[b"cat".to_vec(), b"dog".to_vec()])),
+        h("c88363617483646f67")
+    );
+    assert_eq!(to_bytes(""), h("80"));
+    assert_eq!(rlp::encode(&Rlp::List(vec![])), h("c0"));
+    assert_eq!(to_bytes(&0u64), h("80"));
+    assert_eq!(to_bytes(&[0u8][..]), h("00"));
+    assert_eq!(to_bytes(&15u64), h("0f"));
+    assert_eq!(to_bytes(&1024u64), h("820400"));
+    assert_eq!(to_bytes(&BigUint::from(1024u64)), h("820400"));
+    assert_eq!(to_bytes(&true), h("01"));
+
+    // the set theoretical representation of three
+    let zero = Rlp::List(vec![]);
+    let one = Rlp::List(vec![zero.clone()]);
+    let two = Rlp::List(vec![zero.clone(), one.clone()]);
+    let three = Rlp::List(vec![zero, one, two]);
+    assert_eq!(rlp::encode(&three), h("c7c0c1c0c3c0c1c0"));
+
+    let lorem = "Lorem ipsum dolor sit amet, consectetur adipisicing elit";
+    let mut expected = h("b838");
+    expected.extend_from_slice(lorem.as_bytes());
+    assert_eq!(to_bytes(lorem), expected);
+
+    // long list
+    let l: Vec<u64> = (0..60).collect();
+    let encoded = rlp::encode(&encode_list(&l));
+    assert_eq!(encoded[..2], h("f83c")[..]);
+    assert_eq!(
+        decode_list::<u64>(&rlp::decode(&encoded).unwrap()).unwrap(),
+        l
+    );
+}
+
+#[test]
+fn rlp_decode() {
+    assert_eq!(from_bytes::<Vec<u8>>(&h("83646f67")).unwrap(), b"dog");
+    assert_eq!(from_bytes::<u64>(&h("820400")).unwrap(), 1024);
+    assert_eq!(from_bytes::<u64>(&h("80")).unwrap(), 0);
+    assert!(from_bytes::<bool>(&h("01")).unwrap());
+    assert_eq!(
+        from_bytes::<BigUint>(&h(
+            "a0ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff"
+        ))
+        .unwrap(),
+        BigUint::from_bytes_be(&[0xff; 32])
+    );
+    assert_eq!(
+        rlp::decode(&h("c7c0c1c0c3c0c1c0")).unwrap(),
+        Rlp::List(vec![
+            Rlp::List(vec![]),
+            Rlp::List(vec![Rlp::List(vec![])]),
+            Rlp::List(vec![Rlp::List(vec![]), Rlp::List(vec![Rlp::List(vec![])])]),
+        ])
+    );
+    let address: [u8; 20] = from_bytes(&h("94cd234a471b72ba2f1ccf0a70fcaba648a5eecd8d")).unwrap();
+    assert_eq!(
+        address.to_vec(),
+        h("cd234a471b72ba2f1ccf0a70fcaba648a5eecd8d")
+    );
+}
+
+#[test]
+fn rlp_decode_non_canonical() {
+    let err = |s: &str| rlp::decode(&h(s)).unwrap_err();
+    assert_eq!(err(""), "rlp: unexpected end of input");
+    assert_eq!(err("8100"), "rlp: non-canonical single byte");
+    assert_eq!(err("817f"), "rlp: non-canonical single byte");
+    assert_eq!(err("b80100"), "rlp: non-canonical length");
+    assert_eq!(err("f80100"), "rlp: non-canonical length");
+    assert_eq!(err("b900380000"), "rlp: length with leading zeros");
+    assert_eq!(err("83646f"), "rlp: unexpected end of input");
+    assert_eq!(err("c3646f"), "rlp: unexpected end of input");
+    assert_eq!(err("c2836f67"), "rlp: unexpected end of input");
+    assert_eq!(err("83646f6700"), "rlp: trailing bytes");
+    assert_eq!(err("bfffffffffffffffff"), "rlp: length overflow");
+
+    // lists nested up to the maximum depth
+    let nested = |depth: usize| {
+        let mut item = Rlp::List(vec![]);
+        for _ in 1..depth {
+            item = Rlp::List(vec![item]);
+        }
+        rlp::encode(&item)
+    };
+    assert!(rlp::decode(&nested(MAX_DEPTH)).is_ok());
+    assert_eq!(
+        rlp::decode(&nested(MAX_DEPTH + 1)).unwrap_err(),
+        "rlp: nesting too deep"
+    );
+
+    assert_eq!(
+        from_bytes::<u64>(&h("820004")).unwrap_err(),
+        "rlp: integer with leading zeros"
+    );
+    assert_eq!(
+        from_bytes::<u64>(&h("00")).unwrap_err(),
+        "rlp: integer with leading zeros"
+    );
+    assert_eq!(
+        from_bytes::<u64>(&h("89010000000000000000")).unwrap_err(),
+        "rlp: integer overflows u64"
+    );
+    assert_eq!(
+        from_bytes::<bool>(&h("02")).unwrap_err(),
+        "rlp: invalid bool"
+    );
+    assert_eq!(
+        from_bytes::<u64>(&h("c0")).unwrap_err(),
+        "rlp: expected bytes, found list"
+    );
+    assert_eq!(
+        from_bytes::<[u8; 20]>(&h("83646f67")).unwrap_err(),
+        "rlp: invalid address length 3"
+    );
+}
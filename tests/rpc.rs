@@ -0,0 +1,267 @@
+use evm::asm::assemble;
+use evm::devnet::Devnet;
+use evm::envelope::SignedTransaction;
+use evm::genesis::Genesis;
+use evm::json::{hex_bytes, hex_u64};
+use evm::rpc::{self, Server};
+use evm::signature;
+use evm::spec::Spec;
+use evm::state::State;
+use evm::transaction::{Transaction, DYNAMIC_FEE_TX_TYPE};
+use num_bigint::BigUint;
+use serde_json::{json, Value};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+
+const SECRET_KEY: [u8; 32] = [1; 32];
+const CHAIN_ID: u64 = 1337;
+const COUNTER: &str = "0x0000000000000000000000000000000000000001";
+
+// devnet with a funded sender and a counter at 0x..01, which increments and
+// returns its slot 0, logging it with the caller as topic
+fn devnet() -> Devnet {
+    let mut alloc = State::new();
+    alloc.account(&sender()).balance = BigUint::from(10u64).pow(20);
+    let mut counter = [0; 20];
+    counter[19] = 1;
+    alloc.account(&counter).code = assemble(
+        "PUSH1 0 SLOAD PUSH1 1 ADD DUP1 PUSH1 0 SSTORE
+         PUSH1 0 MSTORE CALLER PUSH1 32 PUSH1 0 LOG1
+         PUSH1 32 PUSH1 0 RETURN",
+    )
+    .unwrap();
+    let mut genesis = Genesis::new(alloc);
+    genesis.config = json!({ "chainId": CHAIN_ID });
+    genesis.gas_limit = 30000000;
+    Devnet::new(&genesis, Spec::Prague)
+}
+
+fn sender() -> [u8; 20] {
+    signature::secret_key_to_address(&SECRET_KEY).unwrap()
+}
+
+fn raw_tx(nonce: u64) -> String {
+    let tx = Transaction {
+        tx_type: DYNAMIC_FEE_TX_TYPE,
+        chain_id: Some(CHAIN_ID),
+        nonce,
+        to: Some(evm::json::parse_address(&Value::from(COUNTER)).unwrap()),
+        gas_limit: 100000,
+        gas_price: BigUint::from(10u64).pow(10),
+        max_priority_fee_per_gas: Some(BigUint::from(1u64)),
+        ..Transaction::default()
+    };
+    hex_bytes(&SignedTransaction::sign(&tx, &SECRET_KEY).unwrap().encode())
+}
+
+// call returns the result of the method, panicking on errors
+fn call(d: &mut Devnet, method: &str, params: Value) -> Value {
+    let request = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+    let response = rpc::handle(d, &request).unwrap();
+    assert_eq!(response["id"], 1);
+    match response.get("error") {
+        Some(e) => panic!("{}: {}", method, e),
+        None => response["result"].clone(),
+    }
+}
+
+fn error(d: &mut Devnet, method: &str, params: Value) -> i64 {
+    let request = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+    rpc::handle(d, &request).unwrap()["error"]["code"]
+        .as_i64()
+        .unwrap()
+}
+
+fn word(n: u64) -> String {
+    format!("0x{:064x}", n)
+}
+
+#[test]
+fn rpc_methods() {
+    let mut d = devnet();
+    let from = hex_bytes(&sender());
+    assert_eq!(call(&mut d, "eth_chainId", json!([])), hex_u64(CHAIN_ID));
+    assert_eq!(call(&mut d, "eth_blockNumber", json!([])), "0x0");
+    assert_eq!(
+        call(&mut d, "eth_getBalance", json!([from, "latest"])),
+        "0x56bc75e2d63100000"
+    );
+    assert!(call(&mut d, "eth_getCode", json!([COUNTER, "latest"]))
+        .as_str()
+        .unwrap()
+        .starts_with("0x6000"));
+
+    // eth_call and eth_estimateGas don't change the state
+    let c = json!({"from": from, "to": COUNTER});
+    assert_eq!(call(&mut d, "eth_call", json!([c, "latest"])), word(1));
+    assert_eq!(call(&mut d, "eth_call", json!([c])), word(1));
+    let gas = call(&mut d, "eth_estimateGas", json!([c]));
+    let gas = evm::json::parse_u64(&gas).unwrap();
+    assert!(gas > 21000 + 22100 && gas < 50000);
+    let low = json!({"from": from, "to": COUNTER, "gas": hex_u64(gas - 1)});
+    assert_eq!(error(&mut d, "eth_call", json!([low])), rpc::SERVER_ERROR);
+
+    let hash = call(&mut d, "eth_sendRawTransaction", json!([raw_tx(0)]));
+    assert_eq!(call(&mut d, "eth_blockNumber", json!([])), "0x1");
+    let receipt = call(&mut d, "eth_getTransactionReceipt", json!([hash]));
+    assert_eq!(receipt["status"], "0x1");
+    assert_eq!(receipt["blockNumber"], "0x1");
+    assert_eq!(receipt["gasUsed"], hex_u64(gas));
+    assert_eq!(receipt["logs"][0]["data"], word(1));
+    assert_eq!(receipt["logs"][0]["transactionHash"], hash);
+    let block = call(&mut d, "eth_getBlockByNumber", json!(["latest", false]));
+    assert_eq!(receipt["blockHash"], block["hash"]);
+    assert_eq!(block["transactions"][0], hash);
+    let tx = call(&mut d, "eth_getTransactionByHash", json!([hash]));
+    assert_eq!(tx["from"], from);
+    assert_eq!(
+        call(
+            &mut d,
+            "eth_getStorageAt",
+            json!([COUNTER, "0x0", "latest"])
+        ),
+        word(1)
+    );
+    assert_eq!(
+        call(&mut d, "eth_getTransactionCount", json!([from])),
+        "0x1"
+    );
+    assert_eq!(
+        call(&mut d, "eth_getTransactionReceipt", json!([word(0)])),
+        Value::Null
+    );
+
+    // the state of the previous blocks is not kept
+    assert_eq!(
+        error(&mut d, "eth_getBalance", json!([from, "0x0"])),
+        rpc::SERVER_ERROR
+    );
+    // nonce already used
+    assert_eq!(
+        error(&mut d, "eth_sendRawTransaction", json!([raw_tx(0)])),
+        rpc::SERVER_ERROR
+    );
+}
+
+#[test]
+fn get_logs() {
+    let mut d = devnet();
+    for nonce in 0..3 {
+        call(&mut d, "eth_sendRawTransaction", json!([raw_tx(nonce)]));
+    }
+    let caller = format!("0x{:0>64}", &hex_bytes(&sender())[2..]);
+    let logs = call(&mut d, "eth_getLogs", json!([{"fromBlock": "0x0"}]));
+    assert_eq!(logs.as_array().unwrap().len(), 3);
+    assert_eq!(logs[2]["data"], word(3));
+    assert_eq!(logs[2]["blockNumber"], "0x3");
+    assert_eq!(logs[2]["topics"][0], caller);
+
+    let logs = call(
+        &mut d,
+        "eth_getLogs",
+        json!([{"fromBlock": "0x2", "toBlock": "0x2", "address": [COUNTER]}]),
+    );
+    assert_eq!(logs.as_array().unwrap().len(), 1);
+    assert_eq!(logs[0]["data"], word(2));
+    // latest by default
+    let logs = call(
+        &mut d,
+        "eth_getLogs",
+        json!([{"topics": [[caller, word(0)]]}]),
+    );
+    assert_eq!(logs.as_array().unwrap().len(), 1);
+    let logs = call(
+        &mut d,
+        "eth_getLogs",
+        json!([{"fromBlock": "earliest", "topics": [null, caller]}]),
+    );
+    assert!(logs.as_array().unwrap().is_empty());
+    let block = call(&mut d, "eth_getBlockByNumber", json!(["0x1", false]));
+    let logs = call(&mut d, "eth_getLogs", json!([{"blockHash": block["hash"]}]));
+    assert_eq!(logs[0]["data"], word(1));
+}
+
+#[test]
+fn rpc_errors() {
+    let mut d = devnet();
+    assert_eq!(error(&mut d, "eth_foo", json!([])), rpc::METHOD_NOT_FOUND);
+    assert_eq!(
+        error(&mut d, "eth_getBalance", json!(["0x01"])),
+        rpc::INVALID_PARAMS
+    );
+    let r = rpc::handle_str(&mut d, "{").unwrap();
+    assert_eq!(r["error"]["code"], rpc::PARSE_ERROR);
+    let r = rpc::handle(&mut d, &json!({"id": 1})).unwrap();
+    assert_eq!(r["error"]["code"], rpc::INVALID_REQUEST);
+
+    // batch, without the response of the notification
+    let batch = json!([
+        {"jsonrpc": "2.0", "id": 1, "method": "eth_blockNumber"},
+        {"jsonrpc": "2.0", "method": "eth_blockNumber"},
+        {"jsonrpc": "2.0", "id": 2, "method": "eth_chainId"},
+    ]);
+    let r = rpc::handle(&mut d, &batch).unwrap();
+    assert_eq!(r.as_array().unwrap().len(), 2);
+    assert_eq!(r[1]["result"], hex_u64(CHAIN_ID));
+
+    // malformed code fails the call, the node still answering
+    let from = hex_bytes(&sender());
+    let r = rpc::handle(
+        &mut d,
+        &json!({"jsonrpc": "2.0", "id": 1, "method": "eth_call", "params": [{"from": from, "data": "0x80"}]}),
+    )
+    .unwrap();
+    assert_eq!(r["error"]["message"], "stack underflow", "{}", r);
+    assert_eq!(call(&mut d, "eth_chainId", json!([])), hex_u64(CHAIN_ID));
+}
+
+#[test]
+fn http_server() {
+    let server = Server::bind(0).unwrap();
+    let addr = server.local_addr().unwrap();
+    let client = std::thread::spawn(move || {
+        let body = r#"{"jsonrpc":"2.0","id":7,"method":"eth_chainId","params":[]}"#;
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "POST / HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n{}",
+            addr,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    });
+    let mut d = devnet();
+    server.serve_connection(&mut d).unwrap();
+    let response = client.join().unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+    let body = response.split("\r\n\r\n").nth(1).unwrap();
+    let body: Value = serde_json::from_str(body).unwrap();
+    assert_eq!(body["id"], 7);
+    assert_eq!(body["result"], hex_u64(CHAIN_ID));
+}
+
+#[test]
+fn http_server_body_limit() {
+    let server = Server::bind(0).unwrap();
+    let addr = server.local_addr().unwrap();
+    let client = std::thread::spawn(move || {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            rpc::MAX_BODY_SIZE + 1
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    });
+    let mut d = devnet();
+    server.serve_connection(&mut d).unwrap();
+    let response = client.join().unwrap();
+    assert!(response.starts_with("HTTP/1.1 413 Payload Too Large\r\n"));
+}
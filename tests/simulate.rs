@@ -0,0 +1,166 @@
+mod common;
+
+use common::{address, word};
+use evm::asm::assemble;
+use evm::devnet::Devnet;
+use evm::genesis::Genesis;
+use evm::json::hex_bytes;
+use evm::rpc;
+use evm::simulate::{self, AccountOverride, Overrides};
+use evm::spec::{Config, Spec};
+use evm::state::State;
+use evm::transaction::{BlockEnv, Transaction};
+use num_bigint::BigUint;
+use serde_json::{json, Value};
+
+// contract at 0x..01 returning slot 0 + slot 1 + NUMBER + TIMESTAMP +
+// BASEFEE + its balance
+fn state() -> State {
+    let mut state = State::new();
+    state.account(&address(1)).code = assemble(
+        "PUSH1 0 SLOAD PUSH1 1 SLOAD ADD NUMBER ADD TIMESTAMP ADD BASEFEE ADD
+         SELFBALANCE ADD PUSH1 0 MSTORE PUSH1 32 PUSH1 0 RETURN",
+    )
+    .unwrap();
+    state.set_storage(&address(1), &word(0), word(1));
+    state.set_storage(&address(1), &word(1), word(2));
+    state.account(&address(2)).balance = BigUint::from(10u64).pow(18);
+    state
+}
+
+fn tx() -> Transaction {
+    Transaction {
+        from: address(2),
+        to: Some(address(1)),
+        gas_limit: 100000,
+        ..Transaction::default()
+    }
+}
+
+fn block() -> BlockEnv {
+    BlockEnv {
+        number: 10,
+        timestamp: 20,
+        base_fee: BigUint::from(7u64),
+        ..BlockEnv::default()
+    }
+}
+
+fn output(state: &State, block: &BlockEnv, tx: &Transaction, overrides: &Overrides) -> u8 {
+    let config = Config::new(Spec::Prague);
+    let r = simulate::call(&config, state, block, tx, overrides).unwrap();
+    assert!(r.success, "{:?}", r.error);
+    r.output[31]
+}
+
+#[test]
+fn simulate_call() {
+    let state = state();
+    let root = state.root();
+    // without gas price the base fee is zero, and the nonce is not checked
+    let mut tx = tx();
+    tx.nonce = 5;
+    assert_eq!(
+        output(&state, &block(), &tx, &Overrides::default()),
+        1 + 2 + 10 + 20
+    );
+
+    let mut overrides = Overrides::default();
+    overrides.accounts.insert(
+        address(1),
+        AccountOverride {
+            balance: Some(BigUint::from(4u64)),
+            state_diff: vec![(word(1), word(3))].into_iter().collect(),
+            ..AccountOverride::default()
+        },
+    );
+    overrides.block.number = Some(100);
+    overrides.block.timestamp = Some(50);
+    assert_eq!(
+        output(&state, &block(), &tx, &overrides),
+        1 + 3 + 100 + 50 + 4
+    );
+
+    // the whole storage replaced, and the base fee with a gas price
+    let a = overrides.accounts.get_mut(&address(1)).unwrap();
+    a.state = Some(vec![(word(1), word(6))].into_iter().collect());
+    a.state_diff.clear();
+    overrides.block.base_fee = Some(BigUint::from(9u64));
+    tx.gas_price = BigUint::from(9u64);
+    assert_eq!(
+        output(&state, &block(), &tx, &overrides),
+        6 + 100 + 50 + 9 + 4
+    );
+
+    // patched code
+    let mut overrides = Overrides::default();
+    overrides.accounts.insert(
+        address(1),
+        AccountOverride {
+            code: Some(assemble("PUSH1 42 PUSH1 0 MSTORE PUSH1 32 PUSH1 0 RETURN").unwrap()),
+            ..AccountOverride::default()
+        },
+    );
+    assert_eq!(output(&state, &block(), &tx, &overrides), 42);
+    assert_eq!(state.root(), root);
+}
+
+#[test]
+fn overrides_json() {
+    let o = Overrides::from_json(
+        &json!({
+            "0x0000000000000000000000000000000000000001": {
+                "balance": "0x10",
+                "nonce": "0x2",
+                "code": "0x00",
+                "stateDiff": {"0x01": "0x05"},
+            }
+        }),
+        &json!({"number": "0x20", "time": "0x30", "baseFeePerGas": "0x40"}),
+    )
+    .unwrap();
+    let a = &o.accounts[&address(1)];
+    assert_eq!(a.balance, Some(BigUint::from(16u64)));
+    assert_eq!(a.nonce, Some(2));
+    assert_eq!(a.code, Some(vec![0]));
+    assert_eq!(a.state_diff[&word(1)], word(5));
+    assert_eq!(a.state, None);
+    assert_eq!(o.block.number, Some(32));
+    assert_eq!(o.block.timestamp, Some(48));
+    assert_eq!(o.block.base_fee, Some(BigUint::from(64u64)));
+
+    let both =
+        json!({"0x0000000000000000000000000000000000000001": {"state": {}, "stateDiff": {}}});
+    assert!(Overrides::from_json(&both, &Value::Null).is_err());
+    assert_eq!(
+        Overrides::from_json(&Value::Null, &Value::Null).unwrap(),
+        Overrides::default()
+    );
+}
+
+#[test]
+fn eth_call_overrides() {
+    let mut genesis = Genesis::new(state());
+    genesis.gas_limit = 30000000;
+    let mut d = Devnet::new(&genesis, Spec::Prague);
+    let c = json!({"from": hex_bytes(&address(2)), "to": hex_bytes(&address(1))});
+    let mut call = |params: Value| {
+        let request = json!({"jsonrpc": "2.0", "id": 1, "method": "eth_call", "params": params});
+        rpc::handle(&mut d, &request).unwrap()
+    };
+    // block 1 with a timestamp of 1
+    let r = call(json!([c, "latest"]));
+    assert_eq!(r["result"], hex_bytes(&word(1 + 2 + 1 + 1)));
+    let r = call(json!([
+        c,
+        "latest",
+        {"0x0000000000000000000000000000000000000001": {"state": {"0x0": "0x3"}}},
+        {"number": "0x10"},
+    ]));
+    assert_eq!(r["result"], hex_bytes(&word(3 + 16 + 1)));
+    let r = call(json!([c, "latest", {"0x01": {}}]));
+    assert_eq!(r["error"]["code"], rpc::INVALID_PARAMS);
+    // the state is unchanged
+    let r = call(json!([c, "latest"]));
+    assert_eq!(r["result"], hex_bytes(&word(1 + 2 + 1 + 1)));
+}
@@ -0,0 +1,80 @@
+use evm::json::parse_alloc;
+use evm::statetest::{run_file, run_test, summary};
+use serde_json::Value;
+use std::path::Path;
+
+const FIXTURE: &str = "tests/fixtures/statetests/transferAndStore.json";
+
+fn load() -> Value {
+    serde_json::from_str(&std::fs::read_to_string(FIXTURE).unwrap()).unwrap()
+}
+
+#[test]
+fn statetest_fixture() {
+    let results = run_file(Path::new(FIXTURE)).unwrap();
+    assert_eq!(results.len(), 4);
+    for r in results.iter() {
+        assert!(r.pass, "{} {}[{}]: {:?}", r.name, r.fork, r.index, r.error);
+    }
+    let forks = summary(&results);
+    assert_eq!(forks["Cancun"], (3, 0));
+    assert_eq!(forks["Shanghai"], (1, 0));
+
+    // an invalid transaction leaves the pre-state untouched
+    let test = &load()["transferAndStore"];
+    let pre = parse_alloc(&test["pre"]).unwrap();
+    assert_eq!(
+        test["post"]["Cancun"][2]["hash"].as_str().unwrap(),
+        format!("0x{}", hex::encode(pre.root()))
+    );
+}
+
+#[test]
+fn statetest_failures() {
+    let mut test = load()["transferAndStore"].clone();
+    test["post"]["Cancun"][0]["hash"] = Value::from(format!("0x{}", "11".repeat(32)));
+    test["post"]["Cancun"][1]["logs"] = Value::from(format!("0x{}", "22".repeat(32)));
+    test["post"]["Cancun"][2]
+        .as_object_mut()
+        .unwrap()
+        .remove("expectException");
+    test["post"]["Shanghai"][0]["expectException"] =
+        Value::from("TransactionException.INSUFFICIENT_ACCOUNT_FUNDS");
+
+    let results = run_test("transferAndStore", &test).unwrap();
+    assert!(results.iter().all(|r| !r.pass));
+    assert!(results[0]
+        .error
+        .clone()
+        .unwrap()
+        .starts_with("post state root mismatch"));
+    assert!(results[1]
+        .error
+        .clone()
+        .unwrap()
+        .starts_with("logs hash mismatch"));
+    assert_eq!(
+        results[2].error,
+        Some("unexpected exception: intrinsic gas too low: have 20480, want 21000".to_string())
+    );
+    assert_eq!(
+        results[3].error,
+        Some("expected exception TransactionException.INSUFFICIENT_ACCOUNT_FUNDS".to_string())
+    );
+    assert_eq!(summary(&results)["Cancun"], (0, 3));
+}
+
+#[test]
+fn statetest_malformed_code() {
+    // the contract code is a lone DUP1, every case fails without aborting
+    // the run
+    let mut test = load()["transferAndStore"].clone();
+    test["pre"]["0x1000000000000000000000000000000000000000"]["code"] = Value::from("0x80");
+    let results = run_test("transferAndStore", &test).unwrap();
+    assert_eq!(results.len(), 4);
+    assert!(results[0]
+        .error
+        .clone()
+        .unwrap()
+        .starts_with("post state root mismatch"));
+}
@@ -0,0 +1,149 @@
+use evm::block::{execute_block, validate_block, Block, Bloom, Header, Withdrawal};
+use evm::envelope::SignedTransaction;
+use evm::json::{hex_bytes, parse_biguint, parse_bytes, parse_u64, parse_word};
+use evm::rlp;
+use evm::signature::secret_key_to_address;
+use evm::spec::{Config, Spec};
+use evm::t8n::transition;
+use evm::transaction::Transaction;
+use num_bigint::BigUint;
+use serde_json::{json, Value};
+
+const KEY: &str = "0x4545454545454545454545454545454545454545454545454545454545454545";
+const CONTRACT: &str = "0x1010101010101010101010101010101010101010";
+
+fn inputs() -> (Value, Value, Value) {
+    let sender = hex_bytes(&secret_key_to_address(&[0x45; 32]).unwrap());
+    let alloc = json!({
+        sender: {"balance": "0xde0b6b3a7640000"},
+        // mstore 0xaa at 0; log1(0, 32, topic 1); sstore 1 at 0
+        CONTRACT: {"balance": "0x0", "code": "0x60aa600052600160206000a1600160005500"},
+    });
+    let env = json!({
+        "currentCoinbase": "0xcccccccccccccccccccccccccccccccccccccccc",
+        "currentGasLimit": "0x1c9c380",
+        "currentNumber": "0x1",
+        "currentTimestamp": "0xc",
+        "currentRandom": "0x0",
+        "parentBaseFee": "0x8",
+        "parentGasUsed": "0x0",
+        "parentGasLimit": "0x1c9c380",
+        "withdrawals": [
+            {"index": "0x0", "validatorIndex": "0x5", "address": "0xdddddddddddddddddddddddddddddddddddddddd", "amount": "0x3"},
+        ],
+    });
+
+    // a legacy transaction given with its signature
+    let tx = Transaction {
+        chain_id: Some(1),
+        nonce: 2,
+        to: Some([0xee; 20]),
+        gas_limit: 21000,
+        gas_price: BigUint::from(10u64),
+        value: BigUint::from(5u64),
+        ..Transaction::default()
+    };
+    let stx = SignedTransaction::sign(&tx, &[0x45; 32]).unwrap();
+    let txs = json!([
+        {"type": "0x2", "chainId": "0x1", "nonce": "0x0", "maxFeePerGas": "0x3b9aca00",
+         "maxPriorityFeePerGas": "0x2", "gas": "0x186a0", "to": CONTRACT, "value": "0x1",
+         "input": "0x", "secretKey": KEY},
+        // nonce too high
+        {"nonce": "0x5", "gasPrice": "0xa", "gas": "0x5208", "to": CONTRACT, "value": "0x0",
+         "input": "0x", "secretKey": KEY},
+        {"nonce": "0x1", "gasPrice": "0xa", "gas": "0xd2f0", "to": null, "value": "0x0",
+         "input": "0x", "secretKey": KEY, "protected": false},
+        {"nonce": "0x2", "gasPrice": "0xa", "gas": "0x5208",
+         "to": "0xeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeeee", "value": "0x5", "input": "0x",
+         "v": format!("0x{:x}", stx.v), "r": format!("0x{:x}", stx.r), "s": format!("0x{:x}", stx.s)},
+    ]);
+    (alloc, env, txs)
+}
+
+#[test]
+fn t8n_transition() {
+    let (alloc, env, txs) = inputs();
+    let out = transition("Cancun", 1, Some(BigUint::from(0u64)), &alloc, &env, &txs).unwrap();
+    let r = &out.result;
+
+    assert_eq!(r["rejected"].as_array().unwrap().len(), 1);
+    assert_eq!(r["rejected"][0]["index"], 1);
+    let receipts = r["receipts"].as_array().unwrap();
+    assert_eq!(receipts.len(), 3);
+    assert_eq!(receipts[0]["status"], "0x1");
+    assert_eq!(receipts[0]["logs"][0]["address"], CONTRACT);
+    assert_eq!(receipts[0]["logs"][0]["logIndex"], "0x0");
+    // the contract creation, with empty code
+    assert_eq!(receipts[1]["transactionIndex"], "0x1");
+    assert_ne!(receipts[1]["contractAddress"], hex_bytes(&[0; 20]));
+    assert_eq!(r["currentBaseFee"], "0x7");
+    assert_eq!(r["currentExcessBlobGas"], "0x0");
+    assert_eq!(r["currentDifficulty"], Value::Null);
+    assert_eq!(out.alloc.balance(&[0xee; 20]), BigUint::from(5u64));
+    assert_eq!(out.alloc.balance(&[0xdd; 20]), BigUint::from(3000000000u64));
+    // the coinbase is paid the priority fees
+    assert!(out.alloc.get(&[0xcc; 20]).is_some());
+
+    // the block built from the result is valid
+    let transactions: Vec<SignedTransaction> =
+        rlp::decode_list(&rlp::decode(&out.body).unwrap()).unwrap();
+    assert_eq!(transactions.len(), 3);
+    let block = Block {
+        header: Header {
+            coinbase: [0xcc; 20],
+            number: 1,
+            timestamp: 12,
+            state_root: parse_word(&r["stateRoot"]).unwrap(),
+            transactions_root: parse_word(&r["txRoot"]).unwrap(),
+            receipts_root: parse_word(&r["receiptsRoot"]).unwrap(),
+            logs_bloom: {
+                let mut b = Bloom::default();
+                b.0.copy_from_slice(&parse_bytes(&r["logsBloom"]).unwrap());
+                b
+            },
+            gas_used: parse_u64(&r["gasUsed"]).unwrap(),
+            base_fee: Some(parse_biguint(&r["currentBaseFee"]).unwrap()),
+            withdrawals_root: Some(parse_word(&r["withdrawalsRoot"]).unwrap()),
+            blob_gas_used: Some(0),
+            excess_blob_gas: Some(0),
+            parent_beacon_block_root: Some([0; 32]),
+            ..Header::default()
+        },
+        transactions,
+        withdrawals: Some(vec![Withdrawal {
+            index: 0,
+            validator_index: 5,
+            address: [0xdd; 20],
+            amount: 3,
+        }]),
+        ..Block::default()
+    };
+    let mut state = evm::json::parse_alloc(&alloc).unwrap();
+    let result = execute_block(&Config::new(Spec::Cancun), &mut state, &block, 1).unwrap();
+    validate_block(&block, &result, &state).unwrap();
+
+    // the same transactions given as rlp
+    let body = Value::from(hex_bytes(&out.body));
+    let out2 = transition("Cancun", 1, None, &alloc, &env, &body).unwrap();
+    assert_eq!(out2.result["stateRoot"], r["stateRoot"]);
+    assert_eq!(out2.result["receiptsRoot"], r["receiptsRoot"]);
+    assert!(out2.result.get("rejected").is_none());
+}
+
+#[test]
+fn t8n_errors() {
+    let (alloc, env, txs) = inputs();
+    let mut e = env.clone();
+    e.as_object_mut().unwrap().remove("currentRandom");
+    assert_eq!(
+        transition("Cancun", 1, None, &alloc, &e, &txs).err(),
+        Some("env: currentRandom required since Paris".to_string())
+    );
+    assert!(transition("Bedrock", 1, None, &alloc, &env, &txs).is_err());
+
+    // a bad signature rejects the transaction
+    let mut t = txs.clone();
+    t[3]["r"] = Value::from("0x1");
+    let out = transition("Cancun", 1, None, &alloc, &env, &t).unwrap();
+    assert_eq!(out.result["rejected"][1]["index"], 3);
+}
@@ -0,0 +1,237 @@
+mod common;
+
+use common::{address, word};
+use evm::asm::assemble;
+use evm::devnet::Devnet;
+use evm::envelope::SignedTransaction;
+use evm::genesis::Genesis;
+use evm::json::hex_bytes;
+use evm::rpc;
+use evm::signature;
+use evm::spec::{Config, Spec};
+use evm::state::State;
+use evm::tracers::{trace, TraceConfig};
+use evm::transaction::{BlockEnv, Transaction};
+use num_bigint::BigUint;
+use serde_json::{json, Value};
+
+const SECRET_KEY: [u8; 32] = [1; 32];
+
+fn sender() -> [u8; 20] {
+    signature::secret_key_to_address(&SECRET_KEY).unwrap()
+}
+
+// state with a funded sender and a counter at 0x..01, which increments and
+// returns its slot 0, logging it
+fn state() -> State {
+    let mut state = State::new();
+    state.account(&sender()).balance = BigUint::from(10u64).pow(18);
+    state.account(&address(1)).code = assemble(
+        "PUSH1 0 SLOAD PUSH1 1 ADD DUP1 PUSH1 0 SSTORE
+         PUSH1 0 MSTORE PUSH1 32 PUSH1 0 LOG0
+         PUSH1 32 PUSH1 0 RETURN",
+    )
+    .unwrap();
+    state.set_storage(&address(1), &[0; 32], word(4));
+    state
+}
+
+fn tx() -> Transaction {
+    Transaction {
+        from: sender(),
+        to: Some(address(1)),
+        gas_limit: 100000,
+        gas_price: BigUint::from(10u64),
+        value: BigUint::from(3u64),
+        data: vec![0xa9, 0x05, 0x9c, 0xbb, 0, 0],
+        ..Transaction::default()
+    }
+}
+
+fn block() -> BlockEnv {
+    BlockEnv {
+        coinbase: address(9),
+        gas_limit: 30000000,
+        base_fee: BigUint::from(7u64),
+        ..BlockEnv::default()
+    }
+}
+
+fn run(tracer: Value) -> Value {
+    let trace_config = TraceConfig::from_json(&tracer).unwrap();
+    let config = Config::new(Spec::Prague);
+    trace(&config, &mut state(), &block(), &tx(), &trace_config).unwrap()
+}
+
+#[test]
+fn struct_logger() {
+    let t = run(json!({"enableMemory": true}));
+    assert_eq!(t["failed"], false);
+    assert_eq!(
+        t["returnValue"],
+        "0000000000000000000000000000000000000000000000000000000000000005"
+    );
+    let logs = t["structLogs"].as_array().unwrap();
+    assert_eq!(logs.len(), 15);
+    assert_eq!(logs[0]["op"], "PUSH1");
+    assert_eq!(logs[0]["gas"], 100000 - 21000 - 6 * 16 + 2 * 12);
+    assert_eq!(logs[0]["depth"], 1);
+    // cold SLOAD
+    let slot = "0000000000000000000000000000000000000000000000000000000000000000";
+    assert_eq!(logs[1]["op"], "SLOAD");
+    assert_eq!(logs[1]["gasCost"], 2100);
+    assert_eq!(logs[1]["stack"], json!(["0x0"]));
+    assert_eq!(
+        logs[1]["storage"][slot],
+        "0000000000000000000000000000000000000000000000000000000000000004"
+    );
+    assert_eq!(logs[6]["op"], "SSTORE");
+    assert_eq!(
+        logs[6]["storage"][slot],
+        "0000000000000000000000000000000000000000000000000000000000000005"
+    );
+    assert!(logs[2].get("storage").is_none());
+    assert_eq!(logs[9]["memory"].as_array().unwrap().len(), 1);
+    assert!(logs[7]
+        .get("memory")
+        .unwrap()
+        .as_array()
+        .unwrap()
+        .is_empty());
+
+    let t = run(json!({"disableStack": true, "disableStorage": true}));
+    let logs = t["structLogs"].as_array().unwrap();
+    assert!(logs[1].get("stack").is_none());
+    assert!(logs[1].get("storage").is_none());
+    assert!(logs[1].get("memory").is_none());
+}
+
+#[test]
+fn call_tracer() {
+    let t = run(json!({"tracer": "callTracer", "tracerConfig": {"withLog": true}}));
+    assert_eq!(t["type"], "CALL");
+    assert_eq!(t["from"], hex_bytes(&sender()));
+    assert_eq!(t["to"], hex_bytes(&address(1)));
+    assert_eq!(t["value"], "0x3");
+    assert_eq!(t["gas"], "0x186a0");
+    assert_eq!(t["input"], "0xa9059cbb0000");
+    assert_eq!(t["output"], hex_bytes(&word(5)));
+    assert_eq!(t["logs"][0]["data"], hex_bytes(&word(5)));
+    assert_eq!(t["logs"][0]["position"], "0x0");
+    assert!(t.get("error").is_none());
+    assert!(t.get("calls").is_none());
+    // the same single frame with onlyTopCall, without the logs
+    let top = run(json!({"tracer": "callTracer", "tracerConfig": {"onlyTopCall": true}}));
+    assert_eq!(top["output"], t["output"]);
+    assert!(top.get("logs").is_none());
+
+    // out of gas
+    let mut tx = tx();
+    tx.gas_limit = 25000;
+    let config = Config::new(Spec::Prague);
+    let trace_config = TraceConfig::from_json(&json!({"tracer": "callTracer"})).unwrap();
+    let t = trace(&config, &mut state(), &block(), &tx, &trace_config).unwrap();
+    assert_eq!(t["error"], "out of gas");
+    assert_eq!(t["gasUsed"], "0x61a8");
+    assert!(t.get("output").is_none());
+}
+
+#[test]
+fn prestate_tracer() {
+    let t = run(json!({"tracer": "prestateTracer"}));
+    let from = hex_bytes(&sender());
+    assert_eq!(t[&from]["balance"], "0xde0b6b3a7640000");
+    assert!(t[&from].get("nonce").is_none());
+    let counter = &t[hex_bytes(&address(1))];
+    assert_eq!(counter["balance"], "0x0");
+    assert!(counter["code"].as_str().unwrap().starts_with("0x6000"));
+    assert_eq!(counter["storage"][hex_bytes(&word(0))], hex_bytes(&word(4)));
+    assert_eq!(t[hex_bytes(&address(9))], json!({"balance": "0x0"}));
+
+    let t = run(json!({"tracer": "prestateTracer", "tracerConfig": {"diffMode": true}}));
+    let (pre, post) = (&t["pre"], &t["post"]);
+    // the coinbase did not exist
+    assert!(pre.get(hex_bytes(&address(9))).is_none());
+    assert!(post[hex_bytes(&address(9))]["balance"].is_string());
+    assert_eq!(post[&from]["nonce"], 1);
+    assert!(post[&from].get("code").is_none());
+    let counter = hex_bytes(&address(1));
+    assert_eq!(
+        pre[&counter]["storage"][hex_bytes(&word(0))],
+        hex_bytes(&word(4))
+    );
+    assert_eq!(
+        post[&counter]["storage"][hex_bytes(&word(0))],
+        hex_bytes(&word(5))
+    );
+    assert_eq!(post[&counter]["balance"], "0x3");
+    assert!(post[&counter].get("code").is_none());
+}
+
+#[test]
+fn four_byte_tracer() {
+    let t = run(json!({"tracer": "4byteTracer"}));
+    assert_eq!(t, json!({"0xa9059cbb-2": 1}));
+
+    let config = Config::new(Spec::Prague);
+    let trace_config = TraceConfig::from_json(&json!({"tracer": "unknown"})).unwrap();
+    assert!(trace(&config, &mut state(), &block(), &tx(), &trace_config).is_err());
+}
+
+#[test]
+fn debug_trace_rpc() {
+    let mut alloc = state();
+    alloc.accounts.remove(&address(9));
+    let mut genesis = Genesis::new(alloc);
+    genesis.config = json!({"chainId": 1});
+    genesis.gas_limit = 30000000;
+    let mut d = Devnet::new(&genesis, Spec::Prague);
+    let mut hashes = Vec::new();
+    for nonce in 0..2 {
+        let mut tx = tx();
+        tx.chain_id = Some(1);
+        tx.nonce = nonce;
+        tx.gas_price = BigUint::from(10u64).pow(10);
+        let stx = SignedTransaction::sign(&tx, &SECRET_KEY).unwrap();
+        hashes.push(d.send_transaction(stx).unwrap());
+    }
+    let mut call = |method: &str, params: Value| {
+        let request = json!({"jsonrpc": "2.0", "id": 1, "method": method, "params": params});
+        rpc::handle(&mut d, &request).unwrap()
+    };
+
+    // the second transaction is replayed on top of the first one
+    let r = call(
+        "debug_traceTransaction",
+        json!([hex_bytes(&hashes[1]), {"tracer": "callTracer"}]),
+    );
+    assert_eq!(r["result"]["output"], hex_bytes(&word(6)));
+    let r = call("debug_traceTransaction", json!([hex_bytes(&hashes[0])]));
+    assert_eq!(
+        r["result"]["returnValue"].as_str().unwrap(),
+        hex::encode(word(5))
+    );
+    let r = call("debug_traceTransaction", json!([hex_bytes(&[0; 32])]));
+    assert_eq!(r["error"]["code"], rpc::SERVER_ERROR);
+
+    // on top of the latest state, without changing it
+    let c = json!({"from": hex_bytes(&sender()), "to": hex_bytes(&address(1))});
+    let r = call(
+        "debug_traceCall",
+        json!([c, "latest", {"tracer": "prestateTracer"}]),
+    );
+    let counter = &r["result"][hex_bytes(&address(1))];
+    assert_eq!(counter["storage"][hex_bytes(&word(0))], hex_bytes(&word(6)));
+    let r = call("debug_traceCall", json!([c, "latest"]));
+    assert_eq!(
+        r["result"]["returnValue"].as_str().unwrap(),
+        hex::encode(word(7))
+    );
+    let r = call(
+        "debug_traceCall",
+        json!([c, "latest", {"tracer": "callTracer"}]),
+    );
+    assert_eq!(r["result"]["output"], hex_bytes(&word(7)));
+    let r = call("eth_getStorageAt", json!([hex_bytes(&address(1)), "0x0"]));
+    assert_eq!(r["result"], hex_bytes(&word(6)));
+}
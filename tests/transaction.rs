@@ -0,0 +1,458 @@
+use evm::spec::{Config, Spec};
+use evm::state::State;
+use evm::transaction::*;
+use evm::*;
+use num_bigint::BigUint;
+
+fn addr(s: &str) -> [u8; 20] {
+    let mut a: [u8; 20] = [0; 20];
+    a.copy_from_slice(&hex::decode(s).unwrap());
+    a
+}
+
+fn setup() -> (State, BlockEnv, [u8; 20]) {
+    let sender = addr("6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0");
+    let mut state = State::new();
+    state.account(&sender).balance = BigUint::from(1000000000000000000u64);
+    let block = BlockEnv {
+        coinbase: addr("00000000000000000000000000000000000000cb"),
+        base_fee: BigUint::from(7u64),
+        ..BlockEnv::default()
+    };
+    (state, block, sender)
+}
+
+#[test]
+fn create_address_derivation() {
+    let sender = addr("6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0");
+    assert_eq!(
+        create_address(&sender, 0),
+        addr("cd234a471b72ba2f1ccf0a70fcaba648a5eecd8d")
+    );
+    assert_eq!(
+        create_address(&sender, 1),
+        addr("343c43a37d37dff08ae8c4a11544c718abb4fcf8")
+    );
+}
+
+#[test]
+fn intrinsic_gas() {
+    let mut tx = Transaction {
+        data: hex::decode("000102").unwrap(),
+        ..Transaction::default()
+    };
+    tx.to = Some([1; 20]);
+    assert_eq!(tx.intrinsic_gas(Spec::Prague), 21000 + 4 + 2 * 16);
+    assert_eq!(tx.intrinsic_gas(Spec::Byzantium), 21000 + 4 + 2 * 68);
+
+    tx.access_list = vec![([2; 20], vec![[0; 32], [1; 32]])];
+    assert_eq!(tx.intrinsic_gas(Spec::Prague), 21000 + 36 + 2400 + 2 * 1900);
+
+    tx.to = None;
+    tx.access_list = vec![];
+    tx.data = vec![1; 33];
+    assert_eq!(
+        tx.intrinsic_gas(Spec::Prague),
+        21000 + 32000 + 33 * 16 + 2 * 2
+    );
+    assert_eq!(tx.intrinsic_gas(Spec::London), 21000 + 32000 + 33 * 16);
+}
+
+#[test]
+fn transact_value_transfer() {
+    let (mut state, block, sender) = setup();
+    let to = addr("00000000000000000000000000000000000000aa");
+    let tx = Transaction {
+        from: sender,
+        to: Some(to),
+        gas_limit: 30000,
+        gas_price: BigUint::from(10u64),
+        value: BigUint::from(1000u64),
+        ..Transaction::default()
+    };
+
+    let r = transact(&Config::default(), &mut state, &block, &tx).unwrap();
+    assert!(r.success);
+    assert_eq!(r.gas_used, 21000);
+    assert_eq!(state.balance(&to), BigUint::from(1000u64));
+    assert_eq!(
+        state.balance(&sender),
+        BigUint::from(1000000000000000000u64 - 1000 - 21000 * 10)
+    );
+    // the base fee is burnt, the coinbase gets the tip
+    assert_eq!(state.balance(&block.coinbase), BigUint::from(21000u64 * 3));
+    assert_eq!(state.nonce(&sender), 1);
+
+    // EIP-1559 fees: the effective gas price is base fee + tip
+    let tx = Transaction {
+        nonce: 1,
+        gas_price: BigUint::from(100u64),
+        max_priority_fee_per_gas: Some(BigUint::from(2u64)),
+        ..tx
+    };
+    let r = transact(&Config::default(), &mut state, &block, &tx).unwrap();
+    assert!(r.success);
+    assert_eq!(
+        state.balance(&sender),
+        BigUint::from(1000000000000000000u64 - 2000 - 21000 * 10 - 21000 * 9)
+    );
+    assert_eq!(
+        state.balance(&block.coinbase),
+        BigUint::from(21000u64 * 3 + 21000 * 2)
+    );
+}
+
+#[test]
+fn transact_invalid() {
+    let (mut state, block, sender) = setup();
+    let tx = Transaction {
+        from: sender,
+        to: Some([1; 20]),
+        nonce: 1,
+        gas_limit: 21000,
+        gas_price: BigUint::from(10u64),
+        ..Transaction::default()
+    };
+    let config = Config::default();
+    let before = state.clone();
+
+    let r = transact(&config, &mut state, &block, &tx);
+    assert_eq!(r, Err("nonce too high: tx 1 state 0".to_string()));
+
+    let tx = Transaction { nonce: 0, ..tx };
+    let r = transact(
+        &config,
+        &mut state,
+        &block,
+        &Transaction {
+            gas_limit: 20999,
+            ..tx.clone()
+        },
+    );
+    assert_eq!(
+        r,
+        Err("intrinsic gas too low: have 20999, want 21000".to_string())
+    );
+
+    let r = transact(
+        &config,
+        &mut state,
+        &block,
+        &Transaction {
+            gas_price: BigUint::from(6u64),
+            ..tx.clone()
+        },
+    );
+    assert_eq!(
+        r,
+        Err("max fee per gas less than block base fee".to_string())
+    );
+
+    let r = transact(
+        &config,
+        &mut state,
+        &block,
+        &Transaction {
+            value: BigUint::from(1000000000000000000u64),
+            ..tx.clone()
+        },
+    );
+    assert_eq!(
+        r,
+        Err("insufficient funds for gas * price + value".to_string())
+    );
+    assert_eq!(state, before);
+}
+
+#[test]
+fn transact_call() {
+    let (mut state, block, sender) = setup();
+    let contract = addr("00000000000000000000000000000000000000cc");
+    state.account(&contract).code = hex::decode("6005600c01").unwrap(); // 5+12
+
+    let tx = Transaction {
+        from: sender,
+        to: Some(contract),
+        gas_limit: 100000,
+        gas_price: BigUint::from(10u64),
+        ..Transaction::default()
+    };
+    let r = transact(&Config::default(), &mut state, &block, &tx).unwrap();
+    assert!(r.success);
+    assert_eq!(r.gas_used, 21009);
+    assert_eq!(
+        state.balance(&sender),
+        BigUint::from(1000000000000000000u64 - 21009 * 10)
+    );
+
+    // a failing execution consumes all the gas and reverts the value transfer
+    state.account(&contract).code = hex::decode("56").unwrap();
+    let tx = Transaction {
+        nonce: 1,
+        value: BigUint::from(5u64),
+        ..tx
+    };
+    let r = transact(&Config::default(), &mut state, &block, &tx).unwrap();
+    assert!(!r.success);
+    assert_eq!(r.error, Some("pop err".to_string()));
+    assert_eq!(r.gas_used, 100000);
+    assert_eq!(state.balance(&contract), BigUint::from(0u64));
+    assert_eq!(state.nonce(&sender), 2);
+}
+
+#[test]
+fn transact_refund_cap() {
+    // clears a slot: 21000 + 6 + 2100 + 2900, with 15000 refunded before
+    // London, capped to the half of it, and 4800 from London
+    for (spec, gas_used) in [(Spec::Berlin, 26006 - 13003), (Spec::London, 26006 - 4800)].iter() {
+        let (mut state, block, sender) = setup();
+        let contract = addr("00000000000000000000000000000000000000cc");
+        state.account(&contract).code = hex::decode("6000600055").unwrap();
+        state.set_storage(&contract, &[0; 32], [1; 32]);
+        let tx = Transaction {
+            from: sender,
+            to: Some(contract),
+            gas_limit: 100000,
+            gas_price: BigUint::from(10u64),
+            ..Transaction::default()
+        };
+        let r = transact(&Config::new(*spec), &mut state, &block, &tx).unwrap();
+        assert!(r.success);
+        assert_eq!(r.gas_used, *gas_used, "{:?}", spec);
+    }
+}
+
+#[test]
+fn transact_create() {
+    let (mut state, block, sender) = setup();
+    let tx = Transaction {
+        from: sender,
+        to: None,
+        gas_limit: 100000,
+        gas_price: BigUint::from(10u64),
+        // deploys 6005600401
+        data: hex::decode("600580600b6000396000f36005600401").unwrap(),
+        ..Transaction::default()
+    };
+    let intrinsic = tx.intrinsic_gas(Spec::Prague);
+    assert_eq!(intrinsic, 21000 + 32000 + 2 * 4 + 14 * 16 + 2);
+
+    let r = transact(&Config::new(Spec::Prague), &mut state, &block, &tx).unwrap();
+    assert!(r.success);
+    let contract = create_address(&sender, 0);
+    assert_eq!(r.contract_address, Some(contract));
+    // execution + code deposit
+    assert_eq!(r.gas_used, intrinsic + 24 + 5 * 200);
+    assert_eq!(state.code(&contract), hex::decode("6005600401").unwrap());
+    assert_eq!(state.nonce(&contract), 1);
+    assert_eq!(state.nonce(&sender), 1);
+}
+
+#[test]
+fn transact_precompile() {
+    let (mut state, block, sender) = setup();
+    let tx = Transaction {
+        from: sender,
+        to: Some(precompiles::address(0x0b)),
+        gas_limit: 100000,
+        gas_price: BigUint::from(10u64),
+        data: vec![0; 256],
+        ..Transaction::default()
+    };
+    let r = transact(&Config::default(), &mut state, &block, &tx).unwrap();
+    assert!(r.success);
+    assert_eq!(r.output, vec![0; 128]);
+    // the calldata floor is above intrinsic and execution gas (EIP-7623)
+    assert_eq!(r.gas_used, 21000 + 256 * 10);
+    assert!(r.gas_used > 21000 + 256 * 4 + 375);
+}
+
+#[test]
+fn transact_floor_data_gas() {
+    let (mut state, block, sender) = setup();
+    let tx = Transaction {
+        from: sender,
+        to: Some([0xaa; 20]),
+        gas_limit: 21000 + 4 * 16,
+        gas_price: BigUint::from(10u64),
+        data: vec![1; 4],
+        ..Transaction::default()
+    };
+    assert_eq!(tx.floor_data_gas(), 21000 + 4 * 4 * 10);
+    // enough for the intrinsic gas but not the floor
+    let err = transact(&Config::new(Spec::Prague), &mut state.clone(), &block, &tx).unwrap_err();
+    assert!(err.starts_with("insufficient gas for floor data gas cost"));
+    let r = transact(&Config::new(Spec::Cancun), &mut state.clone(), &block, &tx).unwrap();
+    assert_eq!(r.gas_used, 21000 + 4 * 16);
+    let tx = Transaction {
+        gas_limit: 30000,
+        ..tx
+    };
+    let r = transact(&Config::new(Spec::Prague), &mut state, &block, &tx).unwrap();
+    assert_eq!(r.gas_used, 21000 + 4 * 4 * 10);
+}
+
+#[test]
+fn transact_warm_coinbase() {
+    let (mut state, block, sender) = setup();
+    // BALANCE(COINBASE), the coinbase is warm from Shanghai (EIP-3651)
+    let code = hex::decode("413100").unwrap();
+    state.account(&[0xbb; 20]).code = code;
+    let tx = Transaction {
+        from: sender,
+        to: Some([0xbb; 20]),
+        gas_limit: 100000,
+        gas_price: BigUint::from(10u64),
+        ..Transaction::default()
+    };
+    let r = transact(&Config::new(Spec::Paris), &mut state.clone(), &block, &tx).unwrap();
+    assert_eq!(r.gas_used, 21000 + 2 + 2600);
+    let r = transact(&Config::new(Spec::Shanghai), &mut state, &block, &tx).unwrap();
+    assert_eq!(r.gas_used, 21000 + 2 + 100);
+}
+
+#[test]
+fn transact_set_code() {
+    let (mut state, block, sender) = setup();
+    let authority_key = [0x11; 32];
+    let authority = signature::secret_key_to_address(&authority_key).unwrap();
+    let target = addr("00000000000000000000000000000000000000cc");
+    state.account(&target).code = hex::decode("6005600c01").unwrap();
+
+    let mut auth = Authorization {
+        chain_id: BigUint::from(1u64),
+        address: target,
+        nonce: 0,
+        ..Authorization::default()
+    };
+    let (y_parity, r, s) = signature::sign(&auth.signing_hash(), &authority_key).unwrap();
+    auth.y_parity = y_parity;
+    auth.r = r;
+    auth.s = s;
+    assert_eq!(auth.authority().unwrap(), authority);
+
+    let tx = Transaction {
+        tx_type: SET_CODE_TX_TYPE,
+        from: sender,
+        to: Some(authority),
+        gas_limit: 100000,
+        gas_price: BigUint::from(10u64),
+        max_priority_fee_per_gas: Some(BigUint::from(1u64)),
+        authorization_list: vec![auth],
+        ..Transaction::default()
+    };
+    let r = transact(&Config::default(), &mut state, &block, &tx).unwrap();
+    assert!(r.success);
+    // the call runs the delegated code
+    assert_eq!(r.gas_used, 21000 + 25000 + 9);
+    assert_eq!(
+        state.code(&authority),
+        [&DELEGATION_PREFIX[..], &target[..]].concat()
+    );
+    assert_eq!(delegation(&state.code(&authority)), Some(target));
+    assert_eq!(state.nonce(&authority), 1);
+
+    // not available before Prague
+    let tx = Transaction { nonce: 1, ..tx };
+    let r = transact(&Config::new(Spec::Cancun), &mut state, &block, &tx);
+    assert_eq!(r, Err("set code transactions not supported".to_string()));
+}
+
+#[test]
+fn transact_blob() {
+    let (mut state, mut block, sender) = setup();
+    block.blob_base_fee = BigUint::from(3u64);
+    let mut hash = [0; 32];
+    hash[0] = 1;
+    let tx = Transaction {
+        tx_type: BLOB_TX_TYPE,
+        from: sender,
+        to: Some([0xaa; 20]),
+        gas_limit: 21000,
+        gas_price: BigUint::from(7u64),
+        max_priority_fee_per_gas: Some(BigUint::from(0u64)),
+        max_fee_per_blob_gas: Some(BigUint::from(3u64)),
+        blob_versioned_hashes: vec![hash, hash],
+        ..Transaction::default()
+    };
+    let r = transact(&Config::default(), &mut state, &block, &tx).unwrap();
+    assert!(r.success);
+    assert_eq!(
+        state.balance(&sender),
+        BigUint::from(1000000000000000000u64 - 21000 * 7 - 2 * 131072 * 3)
+    );
+
+    let tx = Transaction {
+        nonce: 1,
+        max_fee_per_blob_gas: Some(BigUint::from(2u64)),
+        ..tx
+    };
+    let r = transact(&Config::default(), &mut state, &block, &tx);
+    assert_eq!(
+        r,
+        Err("max fee per blob gas less than block blob gas fee".to_string())
+    );
+}
+
+#[test]
+fn transact_selfdestruct() {
+    let (mut state, block, sender) = setup();
+    // SELFDESTRUCT(0xbe)
+    let contract = [0xcc; 20];
+    state.account(&contract).code = hex::decode("60beff").unwrap();
+    state.account(&contract).balance = BigUint::from(5u64);
+    let tx = Transaction {
+        from: sender,
+        to: Some(contract),
+        gas_limit: 100000,
+        gas_price: BigUint::from(10u64),
+        ..Transaction::default()
+    };
+    let beneficiary = addr("00000000000000000000000000000000000000be");
+    // deleted at the end of the transaction before Cancun
+    let mut pre_cancun = state.clone();
+    let r = transact(&Config::new(Spec::Shanghai), &mut pre_cancun, &block, &tx).unwrap();
+    assert!(r.success);
+    assert!(pre_cancun.get(&contract).is_none());
+    assert_eq!(pre_cancun.balance(&beneficiary), BigUint::from(5u64));
+    // kept from Cancun, with its balance moved (EIP-6780)
+    let r = transact(&Config::new(Spec::Cancun), &mut state, &block, &tx).unwrap();
+    assert!(r.success);
+    assert_eq!(state.code(&contract), hex::decode("60beff").unwrap());
+    assert_eq!(state.balance(&contract), BigUint::from(0u64));
+    assert_eq!(state.balance(&beneficiary), BigUint::from(5u64));
+
+    // but for the accounts created in the transaction
+    let tx = Transaction {
+        from: sender,
+        to: None,
+        nonce: 1,
+        gas_limit: 100000,
+        gas_price: BigUint::from(10u64),
+        data: hex::decode("60beff").unwrap(),
+        ..Transaction::default()
+    };
+    let r = transact(&Config::new(Spec::Cancun), &mut state, &block, &tx).unwrap();
+    assert!(r.success);
+    assert!(state.get(&r.contract_address.unwrap()).is_none());
+}
+
+#[test]
+fn transact_tx_context() {
+    let (mut state, mut block, sender) = setup();
+    block.prev_randao = [0x5a; 32];
+    // returns GASPRICE and PREVRANDAO
+    let code = hex::decode("3a6000524460205260406000f3").unwrap();
+    state.account(&[0xcc; 20]).code = code;
+    let tx = Transaction {
+        from: sender,
+        to: Some([0xcc; 20]),
+        gas_limit: 100000,
+        gas_price: BigUint::from(10u64),
+        ..Transaction::default()
+    };
+    let r = transact(&Config::default(), &mut state, &block, &tx).unwrap();
+    assert!(r.success);
+    assert_eq!(r.output[31], 10);
+    assert_eq!(&r.output[32..], &[0x5a; 32]);
+}
@@ -0,0 +1,135 @@
+use evm::state::{Account, State, EMPTY_CODE_HASH};
+use evm::trie::{Trie, EMPTY_ROOT};
+use evm::Stack;
+use num_bigint::BigUint;
+
+// vectors from ethereum/tests TrieTests/trietest.json
+
+#[test]
+fn trie_empty() {
+    let trie = Trie::new();
+    assert_eq!(trie.root_hash(), EMPTY_ROOT);
+    assert_eq!(Trie::new_secure().root_hash(), EMPTY_ROOT);
+    assert_eq!(
+        hex::encode(EMPTY_ROOT),
+        "56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421"
+    );
+}
+
+#[test]
+fn trie_dogs() {
+    let mut trie = Trie::new();
+    trie.insert(b"doe", b"reindeer");
+    trie.insert(b"dog", b"puppy");
+    trie.insert(b"dogglesworth", b"cat");
+    assert_eq!(
+        hex::encode(trie.root_hash()),
+        "8aad789dff2f538bca5d8ea56e8abe10f4c7ba3a5dea95fea4cd6e7c3a1168d3"
+    );
+    assert_eq!(trie.get(b"dog"), Some(b"puppy".to_vec()));
+    assert_eq!(trie.get(b"dogglesworth"), Some(b"cat".to_vec()));
+    assert_eq!(trie.get(b"do"), None);
+    assert_eq!(trie.get(b"doggy"), None);
+}
+
+#[test]
+fn trie_puppy() {
+    let mut trie = Trie::new();
+    trie.insert(b"do", b"verb");
+    trie.insert(b"horse", b"stallion");
+    trie.insert(b"doge", b"coin");
+    trie.insert(b"dog", b"puppy");
+    assert_eq!(
+        hex::encode(trie.root_hash()),
+        "5991bb8c6514148a29db676a14ac506cd2cd5775ace63c30a4fe457715e9ac84"
+    );
+}
+
+#[test]
+fn trie_empty_values() {
+    // inserting an empty value deletes the key
+    let mut trie = Trie::new();
+    trie.insert(b"do", b"verb");
+    trie.insert(b"ether", b"wookiedoo");
+    trie.insert(b"horse", b"stallion");
+    trie.insert(b"shaman", b"horse");
+    trie.insert(b"doge", b"coin");
+    trie.insert(b"ether", b"");
+    trie.insert(b"dog", b"puppy");
+    trie.insert(b"shaman", b"");
+    assert_eq!(
+        hex::encode(trie.root_hash()),
+        "5991bb8c6514148a29db676a14ac506cd2cd5775ace63c30a4fe457715e9ac84"
+    );
+    assert_eq!(trie.get(b"ether"), None);
+}
+
+#[test]
+fn trie_delete() {
+    let mut trie = Trie::new();
+    let keys: Vec<&[u8]> = vec![
+        b"a",
+        b"ab",
+        b"abc",
+        b"b",
+        b"ba",
+        b"abcdefghijklmnopqrstuvwxyz",
+    ];
+    for (i, k) in keys.iter().enumerate() {
+        trie.insert(k, &[i as u8 + 1; 40]);
+    }
+    let root = trie.root_hash();
+
+    // the root does not depend on the insertion order
+    let mut reversed = Trie::new();
+    for (i, k) in keys.iter().enumerate().rev() {
+        reversed.insert(k, &[i as u8 + 1; 40]);
+    }
+    assert_eq!(reversed.root_hash(), root);
+
+    // deleting a key brings back the root of the trie without it
+    trie.insert(b"abd", b"x");
+    assert_ne!(trie.root_hash(), root);
+    trie.delete(b"abd");
+    assert_eq!(trie.root_hash(), root);
+
+    for k in keys.iter() {
+        trie.delete(k);
+    }
+    assert_eq!(trie.root_hash(), EMPTY_ROOT);
+}
+
+#[test]
+fn state_root() {
+    let account = Account::default();
+    assert_eq!(account.storage_root(), EMPTY_ROOT);
+    assert_eq!(account.code_hash(), EMPTY_CODE_HASH);
+    assert_eq!(State::new().root(), EMPTY_ROOT);
+
+    let address = [0x11; 20];
+    let mut state = State::new();
+    state.add_balance(&address, &BigUint::from(1000u64));
+    let root = state.root();
+    assert_ne!(root, EMPTY_ROOT);
+
+    // the storage written by the interpreter is part of the state root
+    let mut s = Stack::new();
+    s.address = address;
+    s.state = state.clone();
+    // sstore 2 at slot 0
+    let code = hex::decode("6002600055").unwrap();
+    s.execute(&code, &[], false).unwrap();
+    let storage_root = s.state.get(&address).unwrap().storage_root();
+    assert_ne!(storage_root, EMPTY_ROOT);
+    assert_ne!(s.state.root(), root);
+
+    let mut trie = Trie::new_secure();
+    trie.insert(&[0; 32], &[0x02]);
+    assert_eq!(trie.root_hash(), storage_root);
+
+    // clearing the slot removes it from the storage trie
+    let code = hex::decode("6000600055").unwrap();
+    s.execute(&code, &[], false).unwrap();
+    assert_eq!(s.state.get(&address).unwrap().storage_root(), EMPTY_ROOT);
+    assert_eq!(s.state.root(), root);
+}
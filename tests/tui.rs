@@ -0,0 +1,69 @@
+#![cfg(feature = "tui")]
+use evm::asm::assemble;
+use evm::breakpoint::Breakpoint;
+use evm::debugger::Debugger;
+use evm::tui::Tui;
+use ratatui::backend::TestBackend;
+use ratatui::crossterm::event::KeyCode;
+use ratatui::Terminal;
+
+fn render(tui: &Tui) -> String {
+    let mut terminal = Terminal::new(TestBackend::new(160, 30)).unwrap();
+    terminal.draw(|f| tui.draw(f)).unwrap();
+    let buffer = terminal.backend().buffer();
+    let mut lines = Vec::new();
+    for y in 0..buffer.area.height {
+        let line: String = (0..buffer.area.width)
+            .map(|x| buffer[(x, y)].symbol())
+            .collect();
+        lines.push(line);
+    }
+    lines.join("\n")
+}
+
+#[test]
+fn tui_step_and_render() {
+    let code = assemble("PUSH1 0x2a PUSH1 0 SSTORE PUSH1 0x41 PUSH1 0 MSTORE STOP").unwrap();
+    let mut tui = Tui::new(Debugger::new(&code, &[]));
+    let screen = render(&tui);
+    assert!(screen.contains("Disassembly"));
+    assert!(screen.contains("PUSH1 0x2a"));
+    assert!(screen.contains("step 0  pc 0  gas 10000000000"));
+
+    tui.key(KeyCode::Char('n'));
+    tui.key(KeyCode::Right);
+    let screen = render(&tui);
+    assert!(screen.contains("Stack (2)"));
+    assert!(screen.contains(&format!("  0  0x{}", "00".repeat(32))));
+
+    // breakpoint on the next instruction, toggled off and on again
+    tui.key(KeyCode::Char('b'));
+    tui.key(KeyCode::Char('b'));
+    assert!(tui.debugger.breakpoints.is_empty());
+    tui.key(KeyCode::Char('b'));
+    assert_eq!(tui.debugger.breakpoints, vec![Breakpoint::Pc(4)]);
+    assert!(render(&tui).contains("●    4  SSTORE"));
+
+    tui.key(KeyCode::Char('n'));
+    let screen = render(&tui);
+    assert!(screen.contains("0x0: 0x0 → 0x2a"));
+
+    // back to the breakpoint, where the slot is still unset
+    tui.key(KeyCode::Left);
+    assert_eq!(tui.debugger.steps, 2);
+    assert!(!render(&tui).contains("0x2a →"));
+    assert_eq!(tui.debugger.vm.state.storage(&[0; 20], &[0; 32]), [0; 32]);
+
+    tui.key(KeyCode::Char('c'));
+    let screen = render(&tui);
+    assert!(screen.contains("halted, output 0x"));
+    assert!(screen.contains("Memory (32 bytes)"));
+    assert!(
+        screen.contains("0010  00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 41  ...............A")
+    );
+
+    tui.key(KeyCode::Char('r'));
+    tui.key(KeyCode::Char('p'));
+    assert!(render(&tui).contains("(at the start of the execution)"));
+    assert!(!tui.key(KeyCode::Char('q')));
+}